@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 use clap::{Parser, Subcommand};
 use clap::ValueEnum;
+use serde::Deserialize;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -14,49 +15,179 @@ pub struct Args {
     /// how often to save state
     #[arg(long, default_value_t=1)]
     pub frames_per_save: usize,
+    /// write frames of solve and restart to binary trajectory ({file}/trajectory.bin) instead of
+    /// csv files. It is also used if it already exists
+    #[arg(long, default_value_t=false)]
+    pub trajectory: bool,
     #[command(subcommand)]
     pub command: Commands,
 }
 
-#[derive(Clone, ValueEnum)]
+#[derive(Clone, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum CrystalCellType {
     /// uniform grid like in gases
     U,
     /// Face-Centered Cubic grid like in metals.
+    #[serde(rename = "fcc")]
     FCC,
 }
 
-#[derive(Clone, ValueEnum)]
+#[derive(Clone, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BoundaryConditionChoose {
+    /// Particles leaving box enter it from the opposite side
+    Periodic,
+    /// Particles are reflected by walls
+    Reflective,
+    /// Particles can leave box
+    Open,
+}
+
+#[derive(Clone, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum IntegratorChoose {
     /// <https://doi.org/10.1103/PhysRev.159.98>
     VerletMethod,
+    /// Leapfrog method with velocities on half steps
+    Leapfrog,
+    /// <https://doi.org/10.1016/0021-9991(76)90059-0>
+    Beeman,
+    /// Multiple time step r-RESPA. Parameters: inner steps count, r_inner and r_outer (nm)
+    Respa,
     /// Custom method (doesn't implemented yet)
     Custom,
 }
 
-#[derive(Clone, ValueEnum)]
+#[derive(Clone, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum BarostatChoose {
     /// <https://pure.rug.nl/ws/files/64380902/1.448118.pdf>
     Berendsen,
+    /// Isotropic Martyna-Tobias-Klein barostat. Parameters: tau (ps) and temperature (K)
+    Mtk,
+    /// Parrinello-Rahman barostat with independent box axes and shear (see pressure coupling).
+    /// Parameters: tau (ps) and temperature (K)
+    ParrinelloRahman,
     /// Custom method (doesn't implemented yet)
     Custom,
 }
 
-#[derive(Clone, ValueEnum)]
+#[derive(Clone, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PressureCouplingChoose {
+    /// All box axes are scaled together
+    Isotropic,
+    /// x and y are scaled together, z independently
+    SemiIsotropic,
+    /// Every box axis is scaled independently
+    Anisotropic,
+    /// Every box axis is scaled independently and box is sheared by off-diagonal pressure
+    Triclinic,
+}
+
+#[derive(Clone, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum ThermostatChoose {
     /// <https://pure.rug.nl/ws/files/64380902/1.448118.pdf>
     Berendsen,
+    /// Nose-Hoover chains. Parameters: tau (ps) and chain length (1 if not set)
     NoseHoover,
+    /// Langevin (BAOAB). Parameters: friction gamma (1/ps)
+    Langevin,
+    /// Andersen collisions. Parameters: collision frequency nu (1/ps)
+    Andersen,
+    /// Bussi stochastic velocity rescaling. Parameters: tau (ps)
+    Csvr,
     /// Custom method (doesn't implemented yet)
     Custom,
 }
 
-#[derive(Clone, ValueEnum)]
+#[derive(Clone, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum PotentialChoose {
+    /// sigma (nm) and eps (10^-21 J)
     LennardJones,
+    /// D (10^-21 J), a (1/nm), r0 (nm) and r_cut (nm)
+    Morse,
+    /// A (10^-21 J), rho (nm), C (10^-21 J nm^6) and r_cut (nm)
+    Buckingham,
+    /// A (10^-21 J), rho (nm), sigma (nm), C (10^-21 J nm^6), D (10^-21 J nm^8) and r_cut (nm)
+    BornMayerHuggins,
+    /// sigma (nm), eps (10^-21 J), n and r_cut (nm)
+    SoftSphere,
+    /// sigma (nm) and eps (10^-21 J)
+    Wca,
+    /// Table in LAMMPS format, needs table file and keyword of section
+    Table,
     Custom,
 }
 
+#[derive(Clone, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CutoffChoose {
+    /// Potential is just cut
+    Truncated,
+    /// Energy is shifted to be zero at cutoff radius
+    Shifted,
+    /// Energy and force are shifted to be zero at cutoff radius
+    ShiftedForce,
+    /// CHARMM switching function from r_on to cutoff radius
+    Switched,
+}
+
+#[derive(Clone, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MixingRuleChoose {
+    /// Arithmetic mean of sigma and geometric mean of eps
+    LorentzBerthelot,
+    /// Geometric mean of sigma and eps
+    Geometric,
+    /// Sixth power mean of sigma, eps keeps sigma^6 eps
+    WaldmanHagler,
+}
+
+#[derive(Clone, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EamFormatChoose {
+    /// DYNAMO setfl file with one or several elements (`eam/alloy` in LAMMPS)
+    Setfl,
+    /// DYNAMO funcfl file with one element (`eam` in LAMMPS)
+    Funcfl,
+}
+
+#[derive(Clone, ValueEnum)]
+pub enum TrajectoryPrecisionChoose {
+    /// Single precision, half of file size
+    F32,
+    /// Double precision, the same values as in simulation
+    F64,
+}
+
+#[derive(Clone, ValueEnum)]
+pub enum StructureFormatChoose {
+    /// Plain XYZ: species and positions (angstroms)
+    Xyz,
+    /// Extended XYZ with lattice, boundary conditions and velocities
+    ExtXyz,
+    /// LAMMPS data file (`write_data` output), import only
+    LammpsData,
+    /// LAMMPS `dump custom` trajectory with ids, types, positions and velocities, export only
+    LammpsDump,
+    /// Protein Data Bank file: elements, positions (angstroms) and `CRYST1` cell, import only
+    Pdb,
+    /// GROMACS structure (nm, nm/ps), every frame of trajectory is imported or exported
+    Gro,
+}
+
+#[derive(Clone, ValueEnum)]
+pub enum LammpsUnitsChoose {
+    /// g/mol, angstroms, femtoseconds
+    Real,
+    /// g/mol, angstroms, picoseconds
+    Metal,
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// Generate file with default potentials
@@ -69,6 +200,69 @@ pub enum Commands {
         potential: PotentialChoose,
         #[arg(long, num_args = 1.., value_delimiter = ' ')]
         params: Vec<f64>,
+        /// file with table for table potential
+        #[arg(long)]
+        table: Option<PathBuf>,
+        /// keyword of section in table file
+        #[arg(long)]
+        keyword: Option<String>,
+        /// cutoff scheme of analytic potential (shifted energy if not set)
+        #[arg(long, value_enum)]
+        cutoff: Option<CutoffChoose>,
+        /// cutoff radius (nm) instead of the one from parameters (2.5 sigma for Lennard-Jones)
+        #[arg(long)]
+        r_cut: Option<f64>,
+        /// distance (nm) where switching function starts
+        #[arg(long)]
+        r_on: Option<f64>,
+    },
+    /// Sets Lennard-Jones parameters of particle type. Potentials of pairs with this type that
+    /// are not set are generated by mixing rule
+    SetSpeciesPotential {
+        #[arg(short = 'i', long)]
+        particle_type: u16,
+        /// sigma (nm)
+        #[arg(long)]
+        sigma: f64,
+        /// eps (10^-21 J)
+        #[arg(long)]
+        eps: f64,
+    },
+    /// Sets mixing rule for Lennard-Jones parameters of different particle types
+    SetMixingRule {
+        #[arg(short = 'r', long, value_enum)]
+        rule: MixingRuleChoose,
+        /// refuse to solve if potential of any pair of particle types isn't set (default argon
+        /// potential is used with warning otherwise)
+        #[arg(long)]
+        strict: bool,
+        /// cutoff scheme of mixed potentials (shifted energy if not set)
+        #[arg(long, value_enum)]
+        cutoff: Option<CutoffChoose>,
+        /// cutoff radius (nm) of mixed potentials (2.5 sigma of pair if not set)
+        #[arg(long)]
+        r_cut: Option<f64>,
+        /// distance (nm) where switching function starts
+        #[arg(long)]
+        r_on: Option<f64>,
+    },
+    /// Sets EAM potential from DYNAMO file (eV and angstroms). It is used instead of pair
+    /// potentials for all particle types
+    SetEam {
+        /// path to potential file
+        #[arg(short = 'i', long)]
+        input: PathBuf,
+        #[arg(long, value_enum, default_value_t = EamFormatChoose::Setfl)]
+        format: EamFormatChoose,
+        /// element of every particle type (funcfl file has one element, it is used for all of them)
+        #[arg(short = 'e', long, num_args = 1.., value_delimiter = ' ')]
+        elements: Vec<String>,
+    },
+    /// initialize particles state and run simulation described by TOML (or JSON) config.
+    /// Config is copied to output directory
+    Run {
+        /// path to config file
+        config: PathBuf,
     },
     /// initialize particles state
     Initialize {
@@ -78,21 +272,30 @@ pub enum Commands {
         /// size of this cell (unit cells count, x y z)
         #[arg(short = 's', long, num_args = 3, value_delimiter = ' ')]
         size: Vec<u32>,
-        /// name of particle to initialize
-        #[arg(short = 'n', long)]
-        particle_name: String,
-        /// mass of particle to initialize (10^-27 kg)
-        #[arg(short = 'm', long)]
-        particle_mass: f64,
-        /// radius of particle to initialize (nm)
-        #[arg(short = 'r', long)]
-        particle_radius: f64,
+        /// names of particles to initialize (one per particle type)
+        #[arg(short = 'n', long, num_args = 1.., value_delimiter = ' ')]
+        particle_name: Vec<String>,
+        /// masses of particles to initialize (10^-27 kg)
+        #[arg(short = 'm', long, num_args = 1.., value_delimiter = ' ')]
+        particle_mass: Vec<f64>,
+        /// radii of particles to initialize (nm)
+        #[arg(short = 'r', long, num_args = 1.., value_delimiter = ' ')]
+        particle_radius: Vec<f64>,
+        /// fractions of particle types in mixture (equal if not set)
+        #[arg(long, num_args = 1.., value_delimiter = ' ')]
+        fractions: Option<Vec<f64>>,
+        /// random seed to split lattice sites between particle types in mixture
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
         /// lattice cell (nm)
         #[arg(short = 'l', long)]
         lattice_cell: f64,
         /// temperature (K)
         #[arg(short = 'T', long)]
         temperature: f64,
+        /// boundary conditions along x y z (periodic on every axis if not set)
+        #[arg(long, num_args = 3, value_delimiter = ' ')]
+        boundary_conditions: Option<Vec<BoundaryConditionChoose>>,
     },
     /// run solver on particle state
     Solve {
@@ -106,12 +309,18 @@ pub enum Commands {
         /// if integrate method is custom, this parameter must be set
         #[arg(long)]
         custom_method: Option<String>,
+        /// Integrator parameters
+        #[arg(long, num_args = 1..5, value_delimiter = ' ')]
+        integrator_params: Option<Vec<f64>>,
         /// Barostat type
         #[arg(long)]
         barostat: Option<BarostatChoose>,
         /// Barostat parameters
         #[arg(long, num_args = 1..5, value_delimiter = ' ')]
         barostat_params: Option<Vec<f64>>,
+        /// How box axes are coupled by Parrinello-Rahman barostat (anisotropic if not set)
+        #[arg(long)]
+        pressure_coupling: Option<PressureCouplingChoose>,
         /// Barostat target pressure (Pa)
         #[arg(short = 'P', long)]
         pressure: Option<f64>,
@@ -121,6 +330,9 @@ pub enum Commands {
         /// Thermostat parameters
         #[arg(long, num_args = 1..5, value_delimiter = ' ')]
         thermostat_params: Option<Vec<f64>>,
+        /// Random seed of Langevin, Andersen and CSVR thermostats
+        #[arg(long, default_value_t = 0)]
+        thermostat_seed: u64,
         /// Thermostat target temperature (in K)
         #[arg(short = 'T', long)]
         temperature: Option<f64>,
@@ -128,6 +340,22 @@ pub enum Commands {
         /// If not it uses default potential for Argon
         #[arg(short = 'p', long)]
         use_potentials: bool,
+        /// Use Verlet neighbor list with this skin distance (nm) for force calculation.
+        /// If not set every pair of particles is checked
+        #[arg(long)]
+        neighbor_list_skin: Option<f64>,
+        /// Evaluate each pair of particles once and apply equal and opposite forces to both
+        #[arg(long)]
+        newton_third_law: bool,
+        /// Replace potentials with cubic spline tables from R_MIN (nm) to cutoff radius with
+        /// POINTS points. It is faster for expensive potentials
+        #[arg(long, num_args = 2, value_names = ["R_MIN", "POINTS"])]
+        tabulate_potentials: Option<Vec<f64>>,
+        /// Calculate Green-Kubo viscosity and thermal conductivity during simulation with
+        /// correlation length (in samples) and steps between samples (1 if not set).
+        /// Result is written to green_kubo.csv
+        #[arg(long, num_args = 1..=2, value_delimiter = ' ')]
+        green_kubo: Option<Vec<usize>>,
         /// how much iterations to count
         #[arg(short = 'c', long)]
         iteration_count: usize,
@@ -135,6 +363,18 @@ pub enum Commands {
         #[arg(short = 't', long)]
         delta_time: f64,
     },
+    /// continue simulation from checkpoint saved with the last frame of solve. Integrator,
+    /// thermostat, barostat, potentials and time step are taken from checkpoint
+    Restart {
+        #[arg(long)]
+        threads_count: Option<usize>,
+        /// number of frame with checkpoint
+        #[arg(short = 's', long)]
+        state_number: usize,
+        /// how much iterations to count
+        #[arg(short = 'c', long)]
+        iteration_count: usize,
+    },
     /// calculate macro parameters for solved state
     SolveMacroParameters {
         #[arg(short = 'k', long)]
@@ -147,6 +387,12 @@ pub enum Commands {
         temperature: bool,
         #[arg(short = 'P', long)]
         pressure: bool,
+        /// Pressure tensor components (Pxx, Pyy, Pzz, Pxy, Pxz, Pyz)
+        #[arg(long)]
+        pressure_tensor: bool,
+        /// Energy of particles, thermostat and barostat variables that should be conserved
+        #[arg(long)]
+        conserved_energy: bool,
         /// if you use custom macro parameter, set it true
         #[arg(short = 'c', long)]
         custom: bool,
@@ -160,6 +406,9 @@ pub enum Commands {
         /// If not it uses default potential for Argon
         #[arg(long)]
         use_potentials: bool,
+        /// Add long-range tail corrections to potential energy and pressure
+        #[arg(long)]
+        tail_correction: bool,
     },
     /// Prints impulse (momentum) on first and last step
     CheckImpulse,
@@ -173,4 +422,129 @@ pub enum Commands {
         #[arg(long, num_args = 1..65536, value_delimiter = ' ')]
         particle_types: Vec<u16>,
     },
+    /// Imports frames from file of other format (every frame of trajectory is imported)
+    Import {
+        /// path to file to import
+        #[arg(short = 'i', long)]
+        input: PathBuf,
+        /// format of file (detected by extension if not set)
+        #[arg(long)]
+        format: Option<StructureFormatChoose>,
+        /// number of the first imported frame
+        #[arg(short = 's', long, default_value_t = 0)]
+        state_number: usize,
+        /// units style of LAMMPS files
+        #[arg(long, value_enum, default_value_t = LammpsUnitsChoose::Metal)]
+        lammps_units: LammpsUnitsChoose,
+        /// names of particle types (particle database of directory is used if not set, unknown
+        /// elements of PDB and GRO files are added to it)
+        #[arg(short = 'n', long, num_args = 1.., value_delimiter = ' ')]
+        particle_name: Option<Vec<String>>,
+        /// masses of particle types (10^-27 kg)
+        #[arg(short = 'm', long, num_args = 1.., value_delimiter = ' ')]
+        particle_mass: Option<Vec<f64>>,
+        /// radii of particle types (nm)
+        #[arg(short = 'r', long, num_args = 1.., value_delimiter = ' ')]
+        particle_radius: Option<Vec<f64>>,
+    },
+    /// Exports frames to file of other format
+    Export {
+        /// path to output file
+        #[arg(short = 'o', long)]
+        output: PathBuf,
+        /// format of file (detected by extension if not set)
+        #[arg(long)]
+        format: Option<StructureFormatChoose>,
+        /// frame to export (all frames are exported as trajectory if not set)
+        #[arg(short = 's', long)]
+        state_number: Option<usize>,
+        /// units style of LAMMPS files
+        #[arg(long, value_enum, default_value_t = LammpsUnitsChoose::Metal)]
+        lammps_units: LammpsUnitsChoose,
+    },
+    /// Converts saved frames (bb.csv and data/*.csv) to binary trajectory file
+    ConvertTrajectory {
+        /// path to trajectory file
+        #[arg(short = 'o', long)]
+        output: PathBuf,
+        /// precision of positions and velocities
+        #[arg(long, value_enum, default_value_t = TrajectoryPrecisionChoose::F64)]
+        precision: TrajectoryPrecisionChoose,
+        /// compress frames with deflate
+        #[arg(long, default_value_t = false)]
+        compress: bool,
+    },
+    /// Calculates radial distribution function g(r) and coordination numbers averaged over
+    /// frames and writes them to csv
+    Rdf {
+        /// path to output csv file (rdf.csv in directory if not set)
+        #[arg(short = 'o', long)]
+        output: Option<PathBuf>,
+        /// largest distance (nm), half of the smallest box width of the first frame if not set
+        #[arg(short = 'r', long)]
+        r_max: Option<f64>,
+        /// number of bins
+        #[arg(short = 'b', long, default_value_t = 200)]
+        bins: usize,
+        /// first frame
+        #[arg(short = 's', long, default_value_t = 0)]
+        start: usize,
+        /// last frame (the last saved frame if not set)
+        #[arg(short = 'e', long)]
+        end: Option<usize>,
+    },
+    /// Calculates mean squared displacement of particles averaged over time origins, writes it to
+    /// csv and fits diffusion coefficient
+    Msd {
+        /// path to output csv file (msd.csv in directory if not set)
+        #[arg(short = 'o', long)]
+        output: Option<PathBuf>,
+        /// time between saved frames (ps)
+        #[arg(short = 't', long)]
+        frame_time: f64,
+        /// largest lag in frames (half of frames if not set)
+        #[arg(short = 'l', long)]
+        max_lag: Option<usize>,
+        /// frames between time origins
+        #[arg(long, default_value_t = 1)]
+        origin_interval: usize,
+        /// first frame
+        #[arg(short = 's', long, default_value_t = 0)]
+        start: usize,
+        /// last frame (the last saved frame if not set)
+        #[arg(short = 'e', long)]
+        end: Option<usize>,
+        /// start and end of time range (ps) for diffusion coefficient fit (from 20% to 100% of
+        /// the largest lag if not set)
+        #[arg(long, num_args = 2, value_delimiter = ' ')]
+        fit_range: Option<Vec<f64>>,
+    },
+    /// Calculates velocity autocorrelation function averaged over time origins and vibrational
+    /// density of states, writes them to csv and prints Green-Kubo diffusion coefficient
+    Vacf {
+        /// path to output csv file with VACF (vacf.csv in directory if not set)
+        #[arg(short = 'o', long)]
+        output: Option<PathBuf>,
+        /// path to output csv file with density of states (dos.csv in directory if not set)
+        #[arg(long)]
+        dos_output: Option<PathBuf>,
+        /// binary trajectory to read frames from (frames of directory are used if not set)
+        #[arg(short = 'i', long)]
+        trajectory: Option<PathBuf>,
+        /// time between saved frames (ps)
+        #[arg(short = 't', long)]
+        frame_time: f64,
+        /// largest lag in frames (half of frames if not set)
+        #[arg(short = 'l', long)]
+        max_lag: Option<usize>,
+        /// frames between time origins
+        #[arg(long, default_value_t = 1)]
+        origin_interval: usize,
+        /// first frame
+        #[arg(short = 's', long, default_value_t = 0)]
+        start: usize,
+        /// last frame (the last saved frame if not set)
+        #[arg(short = 'e', long)]
+        end: Option<usize>,
+    },
 }
@@ -1,14 +1,17 @@
 use std::fs;
 use std::fs::ReadDir;
-use std::io::BufWriter;
-use std::path::PathBuf;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use indicatif::{ProgressBar, ProgressStyle};
-use nalgebra::Vector3;
-use moldyn_core::{DataFileMacro, VectorData, MacroParameterType, ParticleDatabase, State, StateToSave, open_file_or_create};
-use moldyn_solver::initializer::UnitCell;
-use moldyn_solver::macro_parameters::get_momentum_of_system;
-use moldyn_solver::solver::{Integrator, Potential, PotentialsDatabase, update_force};
-use crate::args::{BarostatChoose, CrystalCellType, IntegratorChoose, PotentialChoose, ThermostatChoose};
+use nalgebra::{Matrix3, Vector3};
+use moldyn_core::formats::{self, LammpsUnits};
+use moldyn_core::{BoundaryCondition, DataFileMacro, VectorData, MacroParameterType, Particle, ParticleDatabase, State, StateToSave, TrajectoryCompression, TrajectoryPrecision, TrajectoryReader, TrajectoryWriter, convert_to_trajectory, open_file_or_create};
+use moldyn_solver::analysis::{GreenKubo, get_density_of_states, get_diffusion_coefficient, get_green_kubo_diffusion_coefficient, normalize_vacf, MeanSquaredDisplacement, RadialDistribution, VelocityAutocorrelation};
+use moldyn_solver::initializer::{get_barostat_energy, get_thermostat_energy, PressureCoupling, UnitCell};
+use moldyn_solver::macro_parameters::{get_kinetic_energy, get_momentum_of_system, get_particles_count, get_potential_energy, get_pressure, get_pressure_tensor, get_system_center_of_mass_velocity, get_system_kinetic_energy, get_system_potential_energy, get_system_pressure, get_system_pressure_tensor, get_system_thermal_energy, get_tail_corrections, get_temperature, get_thermal_energy};
+use moldyn_solver::solver::{Cutoff, Eam, Integrator, MixingRule, Potential, PotentialsDatabase, Simulation, update_force};
+use crate::config::{LatticeConfig, MacroParameterChoose, RunConfig};
+use crate::args::{BarostatChoose, BoundaryConditionChoose, CrystalCellType, CutoffChoose, EamFormatChoose, IntegratorChoose, LammpsUnitsChoose, MixingRuleChoose, PotentialChoose, PressureCouplingChoose, StructureFormatChoose, ThermostatChoose, TrajectoryPrecisionChoose};
 
 
 const PROGRESS_BAR_SYMBOLS: &str = "█▉▊▋▌▍▎▏  ";
@@ -25,34 +28,150 @@ pub fn generate_default_potentials(file: &PathBuf) {
     potentials_database.save_potentials_to_file(file);
 }
 
-pub fn add_potential_to_file(file: &PathBuf, particles: &Vec<u16>, potential: &PotentialChoose, params: &Vec<f64>) {
-    let mut potentials_database = PotentialsDatabase::new();
-    potentials_database.load_potentials_from_file(file);
+fn create_potential(potential: &PotentialChoose, params: &[f64], table: &Option<PathBuf>,
+                    keyword: &Option<String>, cutoff: &Option<CutoffChoose>, r_cut: &Option<f64>,
+                    r_on: &Option<f64>) -> Potential {
     let potential = match potential {
         PotentialChoose::LennardJones => {
+            assert!(params.len() >= 2, "Need sigma and eps for Lennard-Jones");
             Potential::new_lennard_jones(params[0], params[1])
         }
+        PotentialChoose::Morse => {
+            assert!(params.len() >= 4, "Need D, a, r0 and r_cut for Morse");
+            Potential::new_morse(params[0], params[1], params[2], params[3])
+        }
+        PotentialChoose::Buckingham => {
+            assert!(params.len() >= 4, "Need A, rho, C and r_cut for Buckingham");
+            Potential::new_buckingham(params[0], params[1], params[2], params[3])
+        }
+        PotentialChoose::BornMayerHuggins => {
+            assert!(params.len() >= 6, "Need A, rho, sigma, C, D and r_cut for Born-Mayer-Huggins");
+            Potential::new_born_mayer_huggins(params[0], params[1], params[2], params[3], params[4],
+                                              params[5])
+        }
+        PotentialChoose::SoftSphere => {
+            assert!(params.len() >= 4, "Need sigma, eps, n and r_cut for soft sphere");
+            Potential::new_soft_sphere(params[0], params[1], params[2], params[3])
+        }
+        PotentialChoose::Wca => {
+            assert!(params.len() >= 2, "Need sigma and eps for WCA");
+            Potential::new_wca(params[0], params[1])
+        }
+        PotentialChoose::Table => {
+            let table = table.as_ref().expect("Need table file for table potential");
+            let keyword = keyword.as_ref().expect("Need keyword of section for table potential");
+            Potential::load_table(table, keyword).expect("Can't load table")
+        }
         PotentialChoose::Custom => {
             todo!()
         }
     };
-    potentials_database.set_potential(particles[0], particles[1], potential);
+    if cutoff.is_none() && r_cut.is_none() {
+        return potential;
+    }
+    let r_cut = r_cut.unwrap_or_else(|| potential.get_radius_cut());
+    potential.with_cutoff(r_cut, get_cutoff(cutoff, r_on))
+}
+
+fn get_cutoff(cutoff: &Option<CutoffChoose>, r_on: &Option<f64>) -> Cutoff {
+    match cutoff {
+        Some(CutoffChoose::Truncated) => Cutoff::Truncated,
+        Some(CutoffChoose::Shifted) | None => Cutoff::Shifted,
+        Some(CutoffChoose::ShiftedForce) => Cutoff::ShiftedForce,
+        Some(CutoffChoose::Switched) => Cutoff::Switched {
+            r_on: r_on.expect("Need r_on for switching function"),
+        },
+    }
+}
+
+pub fn add_potential_to_file(file: &PathBuf, particles: &Vec<u16>, potential: &PotentialChoose, params: &Vec<f64>,
+                             table: &Option<PathBuf>, keyword: &Option<String>,
+                             cutoff: &Option<CutoffChoose>, r_cut: &Option<f64>, r_on: &Option<f64>) {
+    let mut potentials_database = PotentialsDatabase::new();
+    potentials_database.load_potentials_from_file(file);
+    potentials_database.set_potential(particles[0], particles[1], create_potential(potential, params, table, keyword, cutoff, r_cut, r_on));
+    potentials_database.save_potentials_to_file(file);
+}
+
+fn get_mixing_rule(rule: &MixingRuleChoose) -> MixingRule {
+    match rule {
+        MixingRuleChoose::LorentzBerthelot => MixingRule::LorentzBerthelot,
+        MixingRuleChoose::Geometric => MixingRule::Geometric,
+        MixingRuleChoose::WaldmanHagler => MixingRule::WaldmanHagler,
+    }
+}
+
+pub fn set_species_potential(file: &PathBuf, particle_type: u16, sigma: f64, eps: f64) {
+    let mut potentials_database = PotentialsDatabase::new();
+    potentials_database.load_potentials_from_file(file);
+    potentials_database.set_species_lennard_jones(particle_type, sigma, eps);
+    potentials_database.save_potentials_to_file(file);
+}
+
+pub fn set_mixing_rule(file: &PathBuf, rule: &MixingRuleChoose, strict: bool,
+                       cutoff: &Option<CutoffChoose>, r_cut: &Option<f64>, r_on: &Option<f64>) {
+    let mut potentials_database = PotentialsDatabase::new();
+    potentials_database.load_potentials_from_file(file);
+    potentials_database.set_mixing_rule(get_mixing_rule(rule));
+    potentials_database.set_mixed_cutoff(*r_cut, get_cutoff(cutoff, r_on));
+    potentials_database.use_strict_mode(strict);
+    potentials_database.save_potentials_to_file(file);
+}
+
+fn load_eam(input: &Path, format: &EamFormatChoose, elements: &[String]) -> Eam {
+    match format {
+        EamFormatChoose::Setfl => Eam::load_setfl(input, elements),
+        EamFormatChoose::Funcfl => Eam::load_funcfl(input, elements.len()),
+    }.expect("Can't load EAM potential")
+}
+
+pub fn set_eam(file: &PathBuf, input: &Path, format: &EamFormatChoose, elements: &[String]) {
+    let mut potentials_database = PotentialsDatabase::new();
+    potentials_database.load_potentials_from_file(file);
+    potentials_database.set_eam(load_eam(input, format, elements));
     potentials_database.save_potentials_to_file(file);
 }
 
+fn set_boundary_conditions(state: &mut State, boundary_conditions: &[BoundaryConditionChoose]) {
+    for (axis, condition) in boundary_conditions.iter().enumerate() {
+        state.boundary_conditions[axis] = match condition {
+            BoundaryConditionChoose::Periodic => BoundaryCondition::Periodic,
+            BoundaryConditionChoose::Reflective => BoundaryCondition::Reflective,
+            BoundaryConditionChoose::Open => BoundaryCondition::Open,
+        };
+    }
+}
+
+/// Amounts of particles of every type for `particles_count` sites split by `fractions`.
+/// Rounding remainder is given to the last type.
+fn get_species_counts(fractions: &[f64], particles_count: usize) -> Vec<usize> {
+    let sum: f64 = fractions.iter().sum();
+    let mut counts: Vec<usize> = fractions.iter()
+        .map(|fraction| (fraction / sum * particles_count as f64).floor() as usize)
+        .collect();
+    let rest = particles_count - counts.iter().sum::<usize>();
+    *counts.last_mut().expect("No particle types") += rest;
+    counts
+}
+
 pub fn initialize(file: &PathBuf,
                   crystal_cell_type: &CrystalCellType,
                   size: &Vec<u32>,
-                  particle_name: &String,
-                  particle_mass: &f64,
-                  particle_radius: &f64,
+                  particle_name: &Vec<String>,
+                  particle_mass: &Vec<f64>,
+                  particle_radius: &Vec<f64>,
+                  fractions: &Option<Vec<f64>>,
+                  seed: &u64,
                   lattice_cell: &f64,
-                  temperature: &f64) {
-    let unit_cell_type = match crystal_cell_type {
-        CrystalCellType::U => UnitCell::U,
-        CrystalCellType::FCC => UnitCell::FCC,
-    };
-    ParticleDatabase::add(0, particle_name.as_str(), particle_mass.clone(), particle_radius.clone());
+                  temperature: &f64,
+                  boundary_conditions: &Option<Vec<BoundaryConditionChoose>>) {
+    let unit_cell_type = get_unit_cell(crystal_cell_type);
+    assert!(particle_mass.len() == particle_name.len() && particle_radius.len() == particle_name.len(),
+            "Names, masses and radii should be set for every particle type");
+    for (particle_type, name) in particle_name.iter().enumerate() {
+        ParticleDatabase::add(particle_type as u16, name.as_str(),
+                              particle_mass[particle_type], particle_radius[particle_type]);
+    }
     let particles_count = match unit_cell_type {
         UnitCell::U => {
             (size[0] * size[1] * size[2]) as usize
@@ -72,43 +191,329 @@ pub fn initialize(file: &PathBuf,
         0, (0.0, 0.0, 0.0),
         (size[0] as _, size[1] as _, size[2] as _), lattice_cell.clone());
     res.expect("Can't init positions");
-    moldyn_solver::initializer::initialize_velocities_maxwell_boltzmann(&mut state,
-                                                              temperature.clone(), 0);
+    if particle_name.len() > 1 {
+        let fractions = fractions.clone().unwrap_or(vec![1.0; particle_name.len()]);
+        assert_eq!(fractions.len(), particle_name.len(), "Fraction should be set for every particle type");
+        let counts = get_species_counts(&fractions, particles_count);
+        moldyn_solver::initializer::split_particle_types(&mut state, 0, &counts, *seed)
+            .expect("Can't split particles between types");
+    }
+    for particle_type in 0..state.particles.len() {
+        moldyn_solver::initializer::initialize_velocities_maxwell_boltzmann(&mut state,
+                                                                  temperature.clone(),
+                                                                  particle_type as u16);
+    }
+    if let Some(boundary_conditions) = boundary_conditions {
+        set_boundary_conditions(&mut state, boundary_conditions);
+    }
     let data = StateToSave::from(&state);
     data.save_to_file(file, 0);
     ParticleDatabase::save_particles_data(file).expect("Can't save particles database");
 }
 
-pub fn solve(file: &PathBuf,
-             state_number: usize,
-             integrator: &IntegratorChoose,
-             _custom_method: &Option<String>,
-             use_potentials: &bool,
-             iteration_count: usize,
-             delta_time: &f64,
-             thermostat_choose: &Option<ThermostatChoose>,
-             thermostat_params: &Option<Vec<f64>>,
-             temperature: &Option<f64>,
-             barostat_choose: &Option<BarostatChoose>,
-             barostat_params: &Option<Vec<f64>>,
-             pressure: &Option<f64>) {
-    let data = StateToSave::load_from_file(file, state_number);
+fn get_unit_cell(crystal_cell_type: &CrystalCellType) -> UnitCell {
+    match crystal_cell_type {
+        CrystalCellType::U => UnitCell::U,
+        CrystalCellType::FCC => UnitCell::FCC,
+    }
+}
+
+fn get_sites_count(lattice: &LatticeConfig) -> usize {
+    let cells = (lattice.size[0] * lattice.size[1] * lattice.size[2]) as usize;
+    match lattice.cell_type {
+        CrystalCellType::U => cells,
+        CrystalCellType::FCC => cells * 4,
+    }
+}
+
+/// Far corner of `lattice` (nm)
+fn get_lattice_end(lattice: &LatticeConfig) -> Vector3<f64> {
+    Vector3::new(
+        lattice.start[0] + lattice.lattice_cell * lattice.size[0] as f64,
+        lattice.start[1] + lattice.lattice_cell * lattice.size[1] as f64,
+        lattice.start[2] + lattice.lattice_cell * lattice.size[2] as f64)
+}
+
+fn fill_lattice(state: &mut State, particle_type: u16, lattice: &LatticeConfig) {
+    moldyn_solver::initializer::initialize_particles_position(
+        get_unit_cell(&lattice.cell_type), state, particle_type,
+        (lattice.start[0], lattice.start[1], lattice.start[2]),
+        (lattice.size[0] as _, lattice.size[1] as _, lattice.size[2] as _), lattice.lattice_cell)
+        .expect("Can't init positions");
+}
+
+/// Creates start state described by `config`
+fn create_state(config: &RunConfig) -> State {
+    assert!(!config.species.is_empty(), "No species in config");
+    let lattices = config.lattice.iter()
+        .chain(config.species.iter().filter_map(|species| species.lattice.as_ref()));
+    let boundary_box = match config.boundary_box {
+        Some(boundary_box) => Vector3::from(boundary_box),
+        None => lattices.fold(Vector3::zeros(), |boundary_box: Vector3<f64>, lattice| {
+            boundary_box.sup(&get_lattice_end(lattice))
+        }),
+    };
+    // Sites of shared lattice are split between species without own lattice
+    let shared_fractions: Vec<f64> = config.species.iter().map(|species| {
+        if species.lattice.is_none() { species.fraction } else { 0.0 }
+    }).collect();
+    let mut state = match &config.lattice {
+        Some(lattice) if shared_fractions.iter().any(|fraction| *fraction > 0.0) => {
+            let mut state = moldyn_solver::initializer::initialize_particles(
+                &[get_sites_count(lattice)], &boundary_box).unwrap();
+            fill_lattice(&mut state, 0, lattice);
+            let counts = get_species_counts(&shared_fractions, get_sites_count(lattice));
+            moldyn_solver::initializer::split_particle_types(&mut state, 0, &counts,
+                                                            config.mixture_seed)
+                .expect("Can't split particles between types");
+            state
+        }
+        _ => {
+            moldyn_solver::initializer::initialize_particles(
+                &vec![0; config.species.len()], &boundary_box).unwrap()
+        }
+    };
+    for (particle_type, species) in config.species.iter().enumerate() {
+        if let Some(lattice) = &species.lattice {
+            let particle_type = particle_type as u16;
+            state.particles[particle_type as usize] = (0..get_sites_count(lattice)).map(|_| {
+                Particle::new(particle_type, Vector3::zeros(), Vector3::zeros())
+                    .expect("Can't create particle")
+            }).collect();
+            fill_lattice(&mut state, particle_type, lattice);
+        }
+    }
+    for particle_type in 0..state.particles.len() {
+        moldyn_solver::initializer::initialize_velocities_maxwell_boltzmann(&mut state,
+                                                                  config.temperature,
+                                                                  particle_type as u16);
+    }
+    if let Some(boundary_conditions) = &config.boundary_conditions {
+        set_boundary_conditions(&mut state, boundary_conditions);
+    }
+    state
+}
+
+/// Runs `f` in its own thread pool of `threads_count` threads (in global pool if not set).
+/// Global pool can't be rebuilt, so it isn't touched.
+pub fn in_thread_pool<R: Send>(threads_count: Option<usize>, f: impl FnOnce() -> R + Send) -> R {
+    match threads_count {
+        Some(threads_count) => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads_count)
+            .build()
+            .expect("Can't create thread pool")
+            .install(f),
+        None => f(),
+    }
+}
+
+/// Initializes state, potentials and runs simulation described by config file `config_path`.
+/// Results are saved to `file` together with copy of config.
+pub fn run(file: &PathBuf, config_path: &Path) {
+    let (config, text) = RunConfig::load_from_file(config_path);
+    fs::create_dir_all(file).expect("Can't create output directory");
+    fs::write(RunConfig::get_saved_path(file, config_path), text).expect("Can't save config");
+    for (particle_type, species) in config.species.iter().enumerate() {
+        ParticleDatabase::add(particle_type as u16, species.name.as_str(), species.mass, species.radius);
+    }
+    let state = create_state(&config);
+    StateToSave::from(&state).save_to_file(file, 0);
+    ParticleDatabase::save_particles_data(file).expect("Can't save particles database");
+    let mut potentials_database = PotentialsDatabase::new();
+    if let Some(rule) = &config.mixing_rule {
+        potentials_database.set_mixing_rule(get_mixing_rule(rule));
+    }
+    potentials_database.set_mixed_cutoff(config.mixing_r_cut, get_cutoff(&config.mixing_cutoff, &config.mixing_r_on));
+    potentials_database.use_strict_mode(config.strict_potentials);
+    for (particle_type, species) in config.species.iter().enumerate() {
+        if let Some([sigma, eps]) = species.lennard_jones {
+            potentials_database.set_species_lennard_jones(particle_type as u16, sigma, eps);
+        }
+    }
+    for potential in config.potentials.iter() {
+        let table = potential.table.as_ref().map(|table| config_path.parent().unwrap_or(Path::new("")).join(table));
+        potentials_database.set_potential(potential.particles[0], potential.particles[1],
+                                          create_potential(&potential.potential, &potential.params,
+                                                           &table, &potential.keyword, &potential.cutoff,
+                                                           &potential.r_cut, &potential.r_on));
+    }
+    if let Some(eam) = &config.eam {
+        let elements = eam.elements.clone().unwrap_or_else(|| {
+            config.species.iter().map(|species| species.name.clone()).collect()
+        });
+        let input = config_path.parent().unwrap_or(Path::new("")).join(&eam.file);
+        potentials_database.set_eam(load_eam(&input, &eam.format, &elements));
+    }
+    potentials_database.save_potentials_to_file(file);
+    let integrator = &config.integrator;
+    let thermostat = config.thermostat.as_ref();
+    let barostat = config.barostat.as_ref();
+    let options = SolveOptions {
+        integrator: integrator.method.clone(),
+        integrator_params: integrator.params.clone(),
+        use_potentials: true,
+        neighbor_list_skin: integrator.neighbor_list_skin,
+        newton_third_law: integrator.newton_third_law,
+        tabulate_potentials: integrator.tabulate_potentials.clone(),
+        green_kubo: config.output.green_kubo.as_ref()
+            .map(|green_kubo| vec![green_kubo.max_lag, green_kubo.sample_interval]),
+        iteration_count: integrator.iteration_count,
+        delta_time: integrator.delta_time,
+        thermostat: thermostat.map(|thermostat| thermostat.thermostat.clone()),
+        thermostat_params: thermostat.and_then(|thermostat| thermostat.params.clone()),
+        thermostat_seed: thermostat.map_or(0, |thermostat| thermostat.seed),
+        temperature: thermostat.map(|thermostat| thermostat.temperature),
+        barostat: barostat.map(|barostat| barostat.barostat.clone()),
+        barostat_params: barostat.and_then(|barostat| barostat.params.clone()),
+        pressure_coupling: barostat.and_then(|barostat| barostat.coupling.clone()),
+        pressure: barostat.map(|barostat| barostat.pressure),
+        frames_per_save: config.output.frames_per_save,
+        trajectory: config.output.trajectory,
+    };
+    in_thread_pool(integrator.threads_count, || solve(file, 0, &options));
+    let macro_parameters = &config.output.macro_parameters;
+    if !macro_parameters.is_empty() {
+        let has = |parameter: MacroParameterChoose| macro_parameters.contains(&parameter);
+        solve_macro(file,
+                    has(MacroParameterChoose::KineticEnergy),
+                    has(MacroParameterChoose::PotentialEnergy),
+                    has(MacroParameterChoose::ThermalEnergy),
+                    has(MacroParameterChoose::Temperature),
+                    has(MacroParameterChoose::Pressure),
+                    has(MacroParameterChoose::PressureTensor),
+                    has(MacroParameterChoose::ConservedEnergy),
+                    false, &None, &true, config.output.tail_correction);
+    }
+}
+
+/// Settings of `solve` command. Thermostat, barostat, Green-Kubo and other optional parts are
+/// not used if they are not set.
+#[derive(Clone)]
+pub struct SolveOptions {
+    pub integrator: IntegratorChoose,
+    pub integrator_params: Option<Vec<f64>>,
+    /// Load potentials from `potentials.json` instead of default argon potential
+    pub use_potentials: bool,
+    pub neighbor_list_skin: Option<f64>,
+    pub newton_third_law: bool,
+    /// Minimal distance (nm) and count of points of tables to replace potentials with
+    pub tabulate_potentials: Option<Vec<f64>>,
+    /// Correlation length and steps between samples of Green-Kubo coefficients
+    pub green_kubo: Option<Vec<usize>>,
+    pub iteration_count: usize,
+    /// Time step (ps)
+    pub delta_time: f64,
+    pub thermostat: Option<ThermostatChoose>,
+    pub thermostat_params: Option<Vec<f64>>,
+    /// Random seed of Langevin, Andersen and CSVR thermostats
+    pub thermostat_seed: u64,
+    /// Target temperature (K), needed for thermostat
+    pub temperature: Option<f64>,
+    pub barostat: Option<BarostatChoose>,
+    pub barostat_params: Option<Vec<f64>>,
+    pub pressure_coupling: Option<PressureCouplingChoose>,
+    /// Target pressure (Pa), needed for barostat
+    pub pressure: Option<f64>,
+    pub frames_per_save: usize,
+    /// Write frames to binary trajectory
+    pub trajectory: bool,
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        Self {
+            integrator: IntegratorChoose::VerletMethod,
+            integrator_params: None,
+            use_potentials: false,
+            neighbor_list_skin: None,
+            newton_third_law: false,
+            tabulate_potentials: None,
+            green_kubo: None,
+            iteration_count: 0,
+            delta_time: 0.002,
+            thermostat: None,
+            thermostat_params: None,
+            thermostat_seed: 0,
+            temperature: None,
+            barostat: None,
+            barostat_params: None,
+            pressure_coupling: None,
+            pressure: None,
+            frames_per_save: 1,
+            trajectory: false,
+        }
+    }
+}
+
+/// Solves `options.iteration_count` steps starting from frame `state_number`
+pub fn solve(file: &PathBuf, state_number: usize, options: &SolveOptions) {
+    let SolveOptions {
+        integrator,
+        integrator_params,
+        use_potentials,
+        neighbor_list_skin,
+        newton_third_law,
+        tabulate_potentials,
+        green_kubo,
+        iteration_count,
+        delta_time,
+        thermostat: thermostat_choose,
+        thermostat_params,
+        thermostat_seed,
+        temperature,
+        barostat: barostat_choose,
+        barostat_params,
+        pressure_coupling,
+        pressure,
+        frames_per_save,
+        trajectory,
+    } = options;
+    let data = SavedFrames::open(file).load(state_number);
     ParticleDatabase::load_particles_data(file).expect("Can't load particle database");
     let mut potentials_database = PotentialsDatabase::new();
-    let mut state = data.into();
+    let saved_thermostat = data.thermostat.clone();
+    let saved_barostat = data.barostat.clone();
+    let state: State = data.into();
     if *use_potentials {
         potentials_database.load_potentials_from_file(file);
     }
-    update_force(&potentials_database, &mut state);
+    let undefined_pairs = potentials_database.get_undefined_pairs(state.particles.len());
+    if !undefined_pairs.is_empty() {
+        if potentials_database.is_strict() {
+            panic!("Potentials aren't set for pairs of particle types {undefined_pairs:?}");
+        }
+        log::warn!("Default potential (argon) is used for pairs of particle types {undefined_pairs:?}");
+    }
+    if let Some(skin) = neighbor_list_skin {
+        potentials_database.use_neighbor_list(*skin);
+    }
+    potentials_database.use_newton_third_law(*newton_third_law);
+    if let Some(tabulate_potentials) = tabulate_potentials {
+        potentials_database.tabulate_potentials(tabulate_potentials[0], tabulate_potentials[1] as usize);
+    }
     let integrator = match integrator {
         IntegratorChoose::VerletMethod => {
             Integrator::VerletMethod
         }
+        IntegratorChoose::Leapfrog => {
+            Integrator::new_leapfrog()
+        }
+        IntegratorChoose::Beeman => {
+            Integrator::new_beeman()
+        }
+        IntegratorChoose::Respa => {
+            let params = integrator_params.clone()
+                .expect("No integrator parameters. Need inner steps, r_inner and r_outer for RESPA");
+            assert!(params.len() >= 3, "Need inner steps, r_inner and r_outer for RESPA");
+            assert!(params[0] >= 1.0 && params[0].fract() == 0.0,
+                    "Inner steps of RESPA should be positive integer, got {}", params[0]);
+            Integrator::new_respa(params[0] as usize, params[1], params[2])
+        }
         _ => {
             todo!()
         }
     };
-    let mut thermostat = if let Some(thermostat_choose) = thermostat_choose {
+    let thermostat = if let Some(thermostat_choose) = thermostat_choose {
         Some(match thermostat_choose {
                 ThermostatChoose::Berendsen => {
                     moldyn_solver::initializer::Thermostat::Berendsen {
@@ -119,13 +524,31 @@ pub fn solve(file: &PathBuf,
                     }
                 }
                 ThermostatChoose::NoseHoover => {
-                    moldyn_solver::initializer::Thermostat::NoseHoover {
-                        tau: thermostat_params.clone()
-                            .expect("No thermostat parameters. Need tau for Nose-Hoover")
-                            [0],
-                        psi: 0.0,
-                        lambda: 0.0,
-                    }
+                    let params = thermostat_params.clone()
+                        .expect("No thermostat parameters. Need tau and chain length for Nose-Hoover");
+                    let chain_length = params.get(1).map_or(1, |length| *length as usize);
+                    let mut thermostat =
+                        moldyn_solver::initializer::Thermostat::new_nose_hoover(params[0], chain_length);
+                    thermostat.load_saved(&saved_thermostat);
+                    thermostat
+                }
+                ThermostatChoose::Langevin => {
+                    let params = thermostat_params.clone()
+                        .expect("No thermostat parameters. Need gamma for Langevin");
+                    assert_eq!(params.len(), 1, "Need only gamma for Langevin, seed is set separately");
+                    moldyn_solver::initializer::Thermostat::new_langevin(params[0], *thermostat_seed)
+                }
+                ThermostatChoose::Andersen => {
+                    let params = thermostat_params.clone()
+                        .expect("No thermostat parameters. Need nu for Andersen");
+                    assert_eq!(params.len(), 1, "Need only nu for Andersen, seed is set separately");
+                    moldyn_solver::initializer::Thermostat::new_andersen(params[0], *thermostat_seed)
+                }
+                ThermostatChoose::Csvr => {
+                    let params = thermostat_params.clone()
+                        .expect("No thermostat parameters. Need tau for CSVR");
+                    assert_eq!(params.len(), 1, "Need only tau for CSVR, seed is set separately");
+                    moldyn_solver::initializer::Thermostat::new_csvr(params[0], *thermostat_seed)
                 }
                 ThermostatChoose::Custom => {
                     todo!()
@@ -134,7 +557,7 @@ pub fn solve(file: &PathBuf,
     } else {
         None
     };
-    let mut barostat = if let Some(barostat_choose) = barostat_choose {
+    let barostat = if let Some(barostat_choose) = barostat_choose {
         Some(match barostat_choose {
                 BarostatChoose::Berendsen => {
                     let params = barostat_params.clone()
@@ -145,6 +568,27 @@ pub fn solve(file: &PathBuf,
                         myu: 0.0,
                     }
                 }
+                BarostatChoose::Mtk => {
+                    let params = barostat_params.clone()
+                        .expect("No barostat parameters. Need tau and temperature for MTK");
+                    let mut barostat = moldyn_solver::initializer::Barostat::new_mtk(params[0], params[1]);
+                    barostat.load_saved(&saved_barostat);
+                    barostat
+                }
+                BarostatChoose::ParrinelloRahman => {
+                    let params = barostat_params.clone()
+                        .expect("No barostat parameters. Need tau and temperature for Parrinello-Rahman");
+                    let coupling = match pressure_coupling {
+                        Some(PressureCouplingChoose::Isotropic) => PressureCoupling::Isotropic,
+                        Some(PressureCouplingChoose::SemiIsotropic) => PressureCoupling::SemiIsotropic,
+                        Some(PressureCouplingChoose::Anisotropic) | None => PressureCoupling::Anisotropic,
+                        Some(PressureCouplingChoose::Triclinic) => PressureCoupling::Triclinic,
+                    };
+                    let mut barostat = moldyn_solver::initializer::Barostat::new_parrinello_rahman(
+                        params[0], params[1], coupling);
+                    barostat.load_saved(&saved_barostat);
+                    barostat
+                }
                 BarostatChoose::Custom => {
                     todo!()
                 }
@@ -152,13 +596,6 @@ pub fn solve(file: &PathBuf,
     } else {
         None
     };
-    let pb = ProgressBar::new(iteration_count as u64);
-    pb.set_style(
-        ProgressStyle::with_template(&PROGRESS_BAR_STYLE)
-            .expect("Can't set style for progress bar")
-            .progress_chars(PROGRESS_BAR_SYMBOLS)
-    );
-    pb.set_prefix("Solving steps: ");
     let pressure = if barostat.is_some() {
         pressure.expect("No pressure was passed")
     } else {
@@ -169,25 +606,113 @@ pub fn solve(file: &PathBuf,
     } else {
         0.0
     };
-    let mut barostat = if let Some(barostat) = &mut barostat {
-        Some((barostat, pressure))
-    } else {
-        None
-    };
-    let mut thermostat = if let Some(thermostat) = &mut thermostat {
-        Some((thermostat, temperature))
-    } else {
-        None
-    };
+    let thermostat = thermostat.map(|thermostat| (thermostat, temperature));
+    let barostat = barostat.map(|barostat| (barostat, pressure));
+    let mut simulation = Simulation::new(state, potentials_database, integrator,
+                                         thermostat, barostat, *delta_time);
+    simulation.green_kubo = green_kubo.as_ref().map(|green_kubo| {
+        GreenKubo::new(green_kubo[0], green_kubo.get(1).copied().unwrap_or(1))
+    });
+    make_steps(file, &mut simulation, state_number, *iteration_count, *frames_per_save, *trajectory);
+}
+
+/// Makes `iteration_count` steps of `simulation`. Every `frames_per_save` step is saved, frames
+/// are numbered without gaps from `state_number`. Checkpoint is saved together with the last
+/// frame. Frames are written to binary trajectory if `trajectory` is set or if `file` already
+/// has trajectory (see [open_trajectory_writer]).
+fn make_steps(file: &Path, simulation: &mut Simulation, state_number: usize,
+              iteration_count: usize, frames_per_save: usize, trajectory: bool) {
+    let mut writer = open_trajectory_writer(file, state_number, trajectory);
+    let pb = ProgressBar::new(iteration_count as u64);
+    pb.set_style(
+        ProgressStyle::with_template(&PROGRESS_BAR_STYLE)
+            .expect("Can't set style for progress bar")
+            .progress_chars(PROGRESS_BAR_SYMBOLS)
+    );
+    pb.set_prefix("Solving steps: ");
+    let frames_per_save = frames_per_save.max(1);
     for i in 0..iteration_count {
-        let data = StateToSave::from(&state);
-        data.save_to_file(file, state_number + i);
-        integrator.calculate(&potentials_database, &mut state, *delta_time, &mut barostat, &mut thermostat);
+        if i % frames_per_save == 0 {
+            save_frame(file, writer.as_mut(), &simulation.to_save(), state_number + i / frames_per_save);
+        }
+        simulation.step();
         pb.inc(1);
     }
     pb.finish_with_message("Calculated.");
-    let data = StateToSave::from(&state);
-    data.save_to_file(file, state_number + iteration_count);
+    let last_state_number = state_number + iteration_count.div_ceil(frames_per_save);
+    save_frame(file, writer.as_mut(), &simulation.to_save(), last_state_number);
+    if let Some(writer) = writer.as_mut() {
+        writer.flush().expect("Can't write trajectory");
+    }
+    simulation.save_checkpoint(&get_checkpoint_path(file, last_state_number))
+        .expect("Can't save checkpoint");
+    if let Some(green_kubo) = &simulation.green_kubo {
+        write_green_kubo(&file.join("green_kubo.csv"), green_kubo, simulation.delta_time);
+    }
+}
+
+/// Opens trajectory of `file` (see [get_trajectory_path]) to write frames from `state_number`
+/// if `trajectory` is set or trajectory already exists. Frames saved before to `bb.csv` and
+/// `data/*.csv` are converted to new trajectory, so frame numbers are the same.
+fn open_trajectory_writer(file: &Path, state_number: usize, trajectory: bool) -> Option<TrajectoryWriter> {
+    let path = get_trajectory_path(file);
+    if !path.is_file() {
+        if !trajectory {
+            return None;
+        }
+        convert_to_trajectory(file, &path, TrajectoryPrecision::F64, TrajectoryCompression::None)
+            .expect("Can't convert frames to trajectory");
+    }
+    Some(TrajectoryWriter::open_at(&path, state_number).expect("Can't open trajectory"))
+}
+
+/// Writes frame to trajectory if it is set, otherwise to `bb.csv` and `data/{state_number}.csv`
+fn save_frame(file: &Path, trajectory: Option<&mut TrajectoryWriter>, data: &StateToSave,
+              state_number: usize) {
+    match trajectory {
+        Some(trajectory) => trajectory.write_frame(data).expect("Can't write frame"),
+        None => data.save_to_file(file, state_number),
+    }
+}
+
+/// Writes pressure and heat flux autocorrelation functions with running integrals of viscosity
+/// and thermal conductivity and prints their last values
+fn write_green_kubo(output: &Path, green_kubo: &GreenKubo, delta_time: f64) {
+    let pressure_acf = green_kubo.get_pressure_acf();
+    let heat_flux_acf = green_kubo.get_heat_flux_acf();
+    let viscosity = green_kubo.get_viscosity(delta_time);
+    let thermal_conductivity = green_kubo.get_thermal_conductivity(delta_time);
+    let sample_time = delta_time * green_kubo.sample_interval as f64;
+    let times = (0..pressure_acf.len()).map(|lag| lag as f64 * sample_time).collect();
+    let header = ["t", "pressure_acf", "viscosity", "heat_flux_acf", "thermal_conductivity"]
+        .map(String::from);
+    write_columns(output, &header,
+                  &[times, pressure_acf, viscosity.clone(), heat_flux_acf, thermal_conductivity.clone()]);
+    println!("Green-Kubo: {} samples, T = {} K", green_kubo.get_samples_count(), green_kubo.get_temperature());
+    if let (Some(viscosity), Some(thermal_conductivity)) = (viscosity.last(), thermal_conductivity.last()) {
+        println!("Shear viscosity: {} mPa*s", viscosity * 1e-3);
+        println!("Thermal conductivity: {} W/(m*K)", thermal_conductivity);
+    }
+}
+
+/// Path to binary trajectory of frames saved by `solve`
+pub fn get_trajectory_path(file: &Path) -> PathBuf {
+    file.join("trajectory.bin")
+}
+
+/// Path to checkpoint saved with frame `state_number`
+pub fn get_checkpoint_path(file: &Path, state_number: usize) -> PathBuf {
+    file.join("checkpoint").join(format!("{state_number}.bin"))
+}
+
+/// Continues simulation from checkpoint of frame `state_number` with the same integrator,
+/// thermostat, barostat, potentials and time step. Result is bit-identical to the simulation
+/// that wasn't stopped.
+pub fn restart(file: &Path, state_number: usize, iteration_count: usize, frames_per_save: usize,
+               trajectory: bool) {
+    let mut simulation = Simulation::load_checkpoint(&get_checkpoint_path(file, state_number))
+        .expect("Can't load checkpoint");
+    make_steps(file, &mut simulation, state_number, iteration_count, frames_per_save, trajectory);
 }
 
 fn get_last_path (paths: ReadDir) -> usize {
@@ -201,23 +726,101 @@ fn get_last_path (paths: ReadDir) -> usize {
     }).max().unwrap()
 }
 
+/// Frames saved by `solve`. Binary trajectory (see [get_trajectory_path]) is read if it exists,
+/// otherwise frames are read from `bb.csv` and `data/*.csv`.
+enum SavedFrames<'a> {
+    Trajectory(TrajectoryReader),
+    Directory(&'a Path),
+}
+
+impl<'a> SavedFrames<'a> {
+    fn open(file: &'a Path) -> Self {
+        Self::open_trajectory(file, &None)
+    }
+
+    /// Frames are read from `trajectory` if it is set
+    fn open_trajectory(file: &'a Path, trajectory: &Option<PathBuf>) -> Self {
+        let path = trajectory.clone().unwrap_or_else(|| get_trajectory_path(file));
+        if trajectory.is_some() || path.is_file() {
+            Self::Trajectory(TrajectoryReader::open(&path).expect("Can't open trajectory"))
+        } else {
+            Self::Directory(file)
+        }
+    }
+
+    fn get_last_frame(&self) -> usize {
+        match self {
+            Self::Trajectory(reader) => reader.get_frames_count().checked_sub(1)
+                .expect("Trajectory has no frames"),
+            Self::Directory(file) => {
+                get_last_path(fs::read_dir(file.join("data")).expect("Can't read directory"))
+            }
+        }
+    }
+
+    fn load(&mut self, frame: usize) -> StateToSave {
+        match self {
+            Self::Trajectory(reader) => reader.read_frame(frame).expect("Can't read frame"),
+            Self::Directory(file) => StateToSave::load_from_file(file, frame),
+        }
+    }
+
+    /// Calls `add_state` for every frame from `start` to `end` showing progress bar with `prefix`
+    fn for_each_state(&mut self, start: usize, end: usize, prefix: &'static str,
+                      mut add_state: impl FnMut(&State)) {
+        let pb = ProgressBar::new((end + 1 - start) as u64);
+        pb.set_style(
+            ProgressStyle::with_template(PROGRESS_BAR_STYLE)
+                .expect("Can't set style for progress bar")
+                .progress_chars(PROGRESS_BAR_SYMBOLS)
+        );
+        pb.set_prefix(prefix);
+        for i in start..=end {
+            let state: State = self.load(i).into();
+            add_state(&state);
+            pb.inc(1);
+        }
+        pb.finish_with_message("Calculated.");
+    }
+}
+
+/// Name of particle type from database or its number
+fn get_particle_type_name(particle_type: usize) -> String {
+    ParticleDatabase::get_particle_name(particle_type as u16).unwrap_or(particle_type.to_string())
+}
+
+/// Column name suffixes (empty for all particles and `_name` for every particle type) and
+/// columns of all particles and of every particle type
+fn get_particle_type_columns(total: Vec<f64>, types_count: usize,
+                             get_column: impl Fn(usize) -> Vec<f64>) -> (Vec<String>, Vec<Vec<f64>>) {
+    let mut names = vec![String::new()];
+    let mut columns = vec![total];
+    for particle_type in 0..types_count {
+        names.push(format!("_{}", get_particle_type_name(particle_type)));
+        columns.push(get_column(particle_type));
+    }
+    (names, columns)
+}
+
 pub fn solve_macro(file: &PathBuf,
                    kinetic_energy: bool,
                    potential_energy: bool,
                    thermal_energy: bool,
                    temperature: bool,
                    pressure: bool,
+                   pressure_tensor: bool,
+                   conserved_energy: bool,
                    custom: bool,
                    _custom_name: &Option<String>,
-                   use_potentials: &bool) {
-    let paths = fs::read_dir(file.join("data"))
-        .expect("Can't read directory");
+                   use_potentials: &bool,
+                   tail_correction: bool) {
+    let mut frames = SavedFrames::open(file);
     let mut potentials_database = PotentialsDatabase::new();
     if *use_potentials {
         potentials_database.load_potentials_from_file(file);
     }
     let start = 0;
-    let end = get_last_path(paths);
+    let end = frames.get_last_frame();
     let pb = ProgressBar::new((end - start) as u64);
     pb.set_style(
         ProgressStyle::with_template(&PROGRESS_BAR_STYLE)
@@ -228,41 +831,92 @@ pub fn solve_macro(file: &PathBuf,
     let mut macro_data = DataFileMacro::new();
     ParticleDatabase::load_particles_data(file).expect("Can't load particle database");
     for i in start..=end {
-        let state_data = StateToSave::load_from_file(file, i);
+        let state_data = frames.load(i);
+        let saved_thermostat = state_data.thermostat.clone();
+        let saved_barostat = state_data.barostat.clone();
         let mut state: moldyn_core::State = state_data.into();
-        let particle_count = state.particles.iter().map( |t| t.len() ).sum();
+        let particle_count = get_particles_count(&state);
         update_force(&potentials_database, &mut state);
+        // Thermal energy, temperature and pressure of mixture and of every its species are
+        // calculated relative to center of mass of whole system
+        let mass_velocity =
+            if thermal_energy || temperature || pressure || pressure_tensor {
+                get_system_center_of_mass_velocity(&state)
+            } else {
+                Vector3::zeros()
+            };
+        let (energy_tail, pressure_tail) = if tail_correction {
+            get_tail_corrections(&potentials_database, &state)
+        } else {
+            (0.0, 0.0)
+        };
         let mut parameters = vec![];
         if kinetic_energy {
-            let value = moldyn_solver::macro_parameters::get_kinetic_energy(&state, 0);
-            parameters.push(MacroParameterType::KineticEnergy(value));
+            parameters.push(MacroParameterType::KineticEnergy(get_system_kinetic_energy(&state)));
         }
         if potential_energy {
-            let value = moldyn_solver::macro_parameters::get_potential_energy(&state, 0);
+            let value = get_system_potential_energy(&state) + energy_tail;
             parameters.push(MacroParameterType::PotentialEnergy(value));
         }
-        let mass_velocity =
-            if thermal_energy || temperature || pressure {
-                moldyn_solver::macro_parameters::get_center_of_mass_velocity(&state, 0)
-            } else {
-                Vector3::zeros()
-            };
-        let thermal_energy_value =
-            if thermal_energy || temperature {
-                let value = moldyn_solver::macro_parameters::get_thermal_energy(&state, 0, &mass_velocity);
+        if thermal_energy || temperature {
+            let value = get_system_thermal_energy(&state, &mass_velocity);
+            if thermal_energy {
                 parameters.push(MacroParameterType::ThermalEnergy(value));
-                value
-            } else {
-                0.0
-            };
-        if temperature {
-            let value = moldyn_solver::macro_parameters::get_temperature(thermal_energy_value, particle_count);
-            parameters.push(MacroParameterType::Temperature(value));
+            }
+            if temperature {
+                parameters.push(MacroParameterType::Temperature(get_temperature(value, particle_count)));
+            }
         }
         if pressure {
-            let value = moldyn_solver::macro_parameters::get_pressure(&state, 0, &mass_velocity);
+            let value = get_system_pressure(&state, &mass_velocity) + pressure_tail;
             parameters.push(MacroParameterType::Pressure(value));
         }
+        if pressure_tensor {
+            let value = get_system_pressure_tensor(&state, &mass_velocity)
+                + Matrix3::identity() * pressure_tail;
+            parameters.push(MacroParameterType::PressureTensor(value));
+        }
+        if conserved_energy {
+            let value = get_system_kinetic_energy(&state) + get_system_potential_energy(&state)
+                + energy_tail
+                + get_thermostat_energy(&state, &saved_thermostat)
+                + get_barostat_energy(&state, &saved_barostat);
+            parameters.push(MacroParameterType::ConservedEnergy(value));
+        }
+        if state.particles.len() > 1 {
+            for particle_type in 0..state.particles.len() {
+                let species_count = state.particles[particle_type].len();
+                let particle_type = particle_type as u16;
+                let mut species_parameters = vec![];
+                if kinetic_energy {
+                    let value = get_kinetic_energy(&state, particle_type);
+                    species_parameters.push(MacroParameterType::KineticEnergy(value));
+                }
+                if potential_energy {
+                    let value = get_potential_energy(&state, particle_type);
+                    species_parameters.push(MacroParameterType::PotentialEnergy(value));
+                }
+                if thermal_energy || temperature {
+                    let value = get_thermal_energy(&state, particle_type, &mass_velocity);
+                    if thermal_energy {
+                        species_parameters.push(MacroParameterType::ThermalEnergy(value));
+                    }
+                    if temperature {
+                        let value = get_temperature(value, species_count);
+                        species_parameters.push(MacroParameterType::Temperature(value));
+                    }
+                }
+                if pressure {
+                    let value = get_pressure(&state, particle_type, &mass_velocity);
+                    species_parameters.push(MacroParameterType::Pressure(value));
+                }
+                if pressure_tensor {
+                    let value = get_pressure_tensor(&state, particle_type, &mass_velocity);
+                    species_parameters.push(MacroParameterType::PressureTensor(value));
+                }
+                macro_data.add_species_macro_params(i, particle_type, &species_parameters, species_count);
+            }
+        }
         if custom {
             todo!()
         }
@@ -274,12 +928,11 @@ pub fn solve_macro(file: &PathBuf,
 }
 
 pub fn check_impulse (file: &PathBuf) {
-    let paths = fs::read_dir(file.join("data"))
-        .expect("Can't read directory");
+    let mut frames = SavedFrames::open(file);
     ParticleDatabase::load_particles_data(file).expect("Can't load particle database");
     let start = 0;
-    let end = get_last_path(paths);
-    let data = StateToSave::load_from_file(file, start);
+    let end = frames.get_last_frame();
+    let data = frames.load(start);
     {
         println!("First frame");
         let state: State = data.into();
@@ -289,7 +942,7 @@ pub fn check_impulse (file: &PathBuf) {
             println!("type = {particle_type};|p| = {p_abs:.15};p = {p:.15}");
         }
     }
-    let data = StateToSave::load_from_file(file, end);
+    let data = frames.load(end);
     {
         println!("Last frame");
         let state: State = data.into();
@@ -302,7 +955,7 @@ pub fn check_impulse (file: &PathBuf) {
 }
 
 pub fn particle_count(file: &PathBuf) {
-    let data = StateToSave::load_from_file(file, 0);
+    let data = SavedFrames::open(file).load(0);
     ParticleDatabase::load_particles_data(file).expect("Can't load particle database");
     let state: State = data.into();
     let count: usize = state.particles.iter().map(| type_data | {
@@ -314,7 +967,7 @@ pub fn particle_count(file: &PathBuf) {
 pub fn generate_histogram(in_file: &PathBuf,
                           state_number: usize,
                           particle_types: &[u16]) {
-    let data = StateToSave::load_from_file(in_file, state_number);
+    let data = SavedFrames::open(in_file).load(state_number);
     ParticleDatabase::load_particles_data(in_file).expect("Can't load particle database");
     let state: State = data.into();
     let mut hist_data = vec![];
@@ -336,4 +989,247 @@ pub fn generate_histogram(in_file: &PathBuf,
         wtr.serialize(value.clone()).expect("Can't serialize data");
     }
     wtr.flush().expect("Can't write");
-}
\ No newline at end of file
+}
+
+pub fn convert_trajectory(file: &Path, output: &Path, precision: &TrajectoryPrecisionChoose, compress: bool) {
+    let precision = match precision {
+        TrajectoryPrecisionChoose::F32 => TrajectoryPrecision::F32,
+        TrajectoryPrecisionChoose::F64 => TrajectoryPrecision::F64,
+    };
+    let compression = if compress {
+        TrajectoryCompression::Deflate
+    } else {
+        TrajectoryCompression::None
+    };
+    let frames_count = convert_to_trajectory(file, output, precision, compression)
+        .expect("Can't convert frames to trajectory");
+    println!("Converted frames: {frames_count}");
+}
+
+/// Writes g(r) and coordination numbers of all particles and of every pair of particle types
+/// averaged over frames from `start` to `end`
+pub fn radial_distribution(file: &Path, output: &Path, r_max: &Option<f64>, bins: usize,
+                           start: usize, end: &Option<usize>) {
+    ParticleDatabase::load_particles_data(file).expect("Can't load particle database");
+    let mut frames = SavedFrames::open(file);
+    let end = end.unwrap_or_else(|| frames.get_last_frame());
+    let r_max = r_max.unwrap_or_else(|| {
+        let state: State = frames.load(start).into();
+        let h = state.get_cell_matrix();
+        (0..3).map(|axis| {
+            let face = h.column((axis + 1) % 3).cross(&h.column((axis + 2) % 3));
+            state.get_volume() / face.norm() / 2.0
+        }).fold(f64::INFINITY, f64::min)
+    });
+    let mut rdf = RadialDistribution::new(r_max, bins);
+    frames.for_each_state(start, end, "Calculating g(r): ", |state| rdf.add_state(state));
+    let types_count = rdf.get_particle_types_count();
+    let mut header = vec![String::from("r"), String::from("g"), String::from("n")];
+    let mut columns = vec![rdf.get_r(), rdf.get_total_rdf(), rdf.get_total_coordination_number()];
+    for a in 0..types_count {
+        for b in 0..types_count {
+            header.push(format!("g_{}-{}", get_particle_type_name(a), get_particle_type_name(b)));
+            header.push(format!("n_{}-{}", get_particle_type_name(a), get_particle_type_name(b)));
+            columns.push(rdf.get_rdf(a, b));
+            columns.push(rdf.get_coordination_number(a, b));
+        }
+    }
+    write_columns(output, &header, &columns);
+}
+
+/// Writes MSD(t) of all particles and of every particle type averaged over time origins of
+/// frames from `start` to `end` and prints diffusion coefficients
+pub fn mean_squared_displacement(file: &Path, output: &Path, frame_time: f64, max_lag: &Option<usize>,
+                                 origin_interval: usize, start: usize, end: &Option<usize>,
+                                 fit_range: &Option<Vec<f64>>) {
+    ParticleDatabase::load_particles_data(file).expect("Can't load particle database");
+    let mut frames = SavedFrames::open(file);
+    let end = end.unwrap_or_else(|| frames.get_last_frame());
+    let max_lag = max_lag.unwrap_or((end - start) / 2);
+    let mut msd = MeanSquaredDisplacement::new(max_lag, origin_interval);
+    frames.for_each_state(start, end, "Calculating MSD: ", |state| msd.add_state(state));
+    let (names, values) = get_particle_type_columns(msd.get_total_msd(), msd.get_particle_types_count(),
+                                                    |particle_type| msd.get_msd(particle_type));
+    let times: Vec<f64> = (0..values[0].len()).map(|lag| lag as f64 * frame_time).collect();
+    let mut header = vec![String::from("t")];
+    header.extend(names.iter().map(|name| format!("msd{name}")));
+    let mut columns = vec![times.clone()];
+    columns.extend(values);
+    write_columns(output, &header, &columns);
+    let t_max = times.last().copied().unwrap_or(0.0);
+    let (fit_start, fit_end) = match fit_range {
+        Some(range) => (range[0], range[1]),
+        None => (0.2 * t_max, t_max),
+    };
+    let fit: Vec<usize> = (0..times.len())
+        .filter(|lag| times[*lag] >= fit_start && times[*lag] <= fit_end)
+        .collect();
+    if fit.len() < 2 {
+        println!("Not enough points to fit diffusion coefficient");
+        return;
+    }
+    let fit_times: Vec<f64> = fit.iter().map(|lag| times[*lag]).collect();
+    for (column, header) in columns.iter().zip(header.iter()).skip(1) {
+        let values: Vec<f64> = fit.iter().map(|lag| column[*lag]).collect();
+        let diffusion = get_diffusion_coefficient(&fit_times, &values);
+        // 1 nm^2/ps = 1e-2 cm^2/s
+        println!("Diffusion coefficient ({header}): {diffusion} nm^2/ps ({} cm^2/s)", diffusion * 1e-2);
+    }
+}
+
+/// Writes columns with `header` to csv file `output`
+fn write_columns(output: &Path, header: &[String], columns: &[Vec<f64>]) {
+    let mut wtr = csv::Writer::from_path(output).expect("Can't create file");
+    wtr.write_record(header).expect("Can't write header");
+    for row in 0..columns[0].len() {
+        wtr.write_record(columns.iter().map(|column| column[row].to_string()))
+            .expect("Can't write data");
+    }
+    wtr.flush().expect("Can't write");
+}
+
+/// Writes normalized and not normalized VACF and vibrational density of states of all particles
+/// and of every particle type averaged over time origins of frames from `start` to `end` and
+/// prints Green-Kubo diffusion coefficients. Frames are read from binary `trajectory` if it is
+/// set.
+pub fn velocity_autocorrelation(file: &Path, output: &Path, dos_output: &Path,
+                                trajectory: &Option<PathBuf>, frame_time: f64,
+                                max_lag: &Option<usize>, origin_interval: usize, start: usize,
+                                end: &Option<usize>) {
+    ParticleDatabase::load_particles_data(file).expect("Can't load particle database");
+    let mut frames = SavedFrames::open_trajectory(file, trajectory);
+    let end = end.unwrap_or_else(|| frames.get_last_frame());
+    let max_lag = max_lag.unwrap_or((end - start) / 2);
+    let mut vacf = VelocityAutocorrelation::new(max_lag, origin_interval);
+    frames.for_each_state(start, end, "Calculating VACF: ", |state| vacf.add_state(state));
+    let (names, values) = get_particle_type_columns(vacf.get_total_vacf(), vacf.get_particle_types_count(),
+                                                    |particle_type| vacf.get_vacf(particle_type));
+    let times: Vec<f64> = (0..values[0].len()).map(|lag| lag as f64 * frame_time).collect();
+    let mut header = vec![String::from("t")];
+    let mut columns = vec![times];
+    for (name, values) in names.iter().zip(values.iter()) {
+        header.push(format!("vacf{name}"));
+        header.push(format!("vacf_normalized{name}"));
+        columns.push(values.clone());
+        columns.push(normalize_vacf(values));
+    }
+    write_columns(output, &header, &columns);
+    let mut header = vec![String::from("frequency"), String::from("wavenumber")];
+    let mut columns = vec![];
+    for (name, values) in names.iter().zip(values.iter()) {
+        let (frequencies, dos) = get_density_of_states(values, frame_time);
+        if columns.is_empty() {
+            // 1 THz = 33.35641 cm^-1
+            columns.push(frequencies.iter().map(|frequency| frequency * 33.35641).collect());
+            columns.insert(0, frequencies);
+        }
+        header.push(format!("dos{name}"));
+        columns.push(dos);
+    }
+    write_columns(dos_output, &header, &columns);
+    for (name, values) in names.iter().zip(values.iter()) {
+        let diffusion = get_green_kubo_diffusion_coefficient(values, frame_time);
+        // 1 nm^2/ps = 1e-2 cm^2/s
+        println!("Green-Kubo diffusion coefficient (vacf{name}): {diffusion} nm^2/ps ({} cm^2/s)",
+                 diffusion * 1e-2);
+    }
+}
+
+fn get_structure_format(format: &Option<StructureFormatChoose>, path: &Path) -> StructureFormatChoose {
+    if let Some(format) = format {
+        return format.clone();
+    }
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("xyz") | Some("extxyz") => StructureFormatChoose::ExtXyz,
+        Some("data") | Some("lmp") => StructureFormatChoose::LammpsData,
+        Some("dump") | Some("lammpstrj") => StructureFormatChoose::LammpsDump,
+        Some("pdb") => StructureFormatChoose::Pdb,
+        Some("gro") => StructureFormatChoose::Gro,
+        _ => panic!("Can't detect format of {}, set it with --format", path.display()),
+    }
+}
+
+fn get_lammps_units(units: &LammpsUnitsChoose) -> LammpsUnits {
+    match units {
+        LammpsUnitsChoose::Real => LammpsUnits::Real,
+        LammpsUnitsChoose::Metal => LammpsUnits::Metal,
+    }
+}
+
+pub fn import(file: &Path,
+              input: &Path,
+              format: &Option<StructureFormatChoose>,
+              state_number: usize,
+              lammps_units: &LammpsUnitsChoose,
+              particle_name: &Option<Vec<String>>,
+              particle_mass: &Option<Vec<f64>>,
+              particle_radius: &Option<Vec<f64>>) {
+    let format = get_structure_format(format, input);
+    let mut reader = BufReader::new(fs::File::open(input).expect("Can't open file"));
+    // LAMMPS data file has its own particle types, they can be renamed by arguments
+    let lammps_data = match format {
+        StructureFormatChoose::LammpsData => Some(formats::read_lammps_data(&mut reader,
+            get_lammps_units(lammps_units)).expect("Can't read LAMMPS data file")),
+        _ => None,
+    };
+    if let Some(particle_name) = particle_name {
+        let particle_mass = particle_mass.as_ref().expect("No masses of particle types");
+        let particle_radius = particle_radius.as_ref().expect("No radii of particle types");
+        assert!(particle_mass.len() == particle_name.len() && particle_radius.len() == particle_name.len(),
+                "Names, masses and radii should be set for every particle type");
+        for (particle_type, name) in particle_name.iter().enumerate() {
+            ParticleDatabase::add(particle_type as u16, name.as_str(),
+                                  particle_mass[particle_type], particle_radius[particle_type]);
+        }
+    } else if lammps_data.is_none() {
+        // Elements of PDB and GRO files and species with masses of XYZ files are added to
+        // database if they aren't there
+        if file.join("db.csv").is_file() {
+            ParticleDatabase::load_particles_data(file).expect("Can't load particle database");
+        }
+    }
+    let frames = match format {
+        StructureFormatChoose::Xyz | StructureFormatChoose::ExtXyz => {
+            formats::read_xyz_frames(&mut reader).expect("Can't read frames")
+        }
+        StructureFormatChoose::Pdb => formats::read_pdb(&mut reader).expect("Can't read PDB file"),
+        StructureFormatChoose::Gro => formats::read_gro_frames(&mut reader).expect("Can't read frames"),
+        StructureFormatChoose::LammpsData => lammps_data.into_iter().collect(),
+        StructureFormatChoose::LammpsDump => panic!("Import of LAMMPS dump isn't supported"),
+    };
+    for (i, data) in frames.iter().enumerate() {
+        data.save_to_file(file, state_number + i);
+    }
+    ParticleDatabase::save_particles_data(file).expect("Can't save particles database");
+    println!("Imported frames: {}", frames.len());
+}
+
+pub fn export(file: &Path,
+              output: &Path,
+              format: &Option<StructureFormatChoose>,
+              state_number: &Option<usize>,
+              lammps_units: &LammpsUnitsChoose) {
+    ParticleDatabase::load_particles_data(file).expect("Can't load particle database");
+    let mut saved_frames = SavedFrames::open(file);
+    let frames = match state_number {
+        Some(state_number) => *state_number..=*state_number,
+        None => 0..=saved_frames.get_last_frame(),
+    };
+    let format = get_structure_format(format, output);
+    let mut writer = BufWriter::new(fs::File::create(output).expect("Can't create file"));
+    for i in frames {
+        let data = saved_frames.load(i);
+        match format {
+            StructureFormatChoose::Xyz => formats::write_xyz(&mut writer, &data, false),
+            StructureFormatChoose::ExtXyz => formats::write_xyz(&mut writer, &data, true),
+            StructureFormatChoose::LammpsDump => {
+                formats::write_lammps_dump(&mut writer, &data, i, get_lammps_units(lammps_units))
+            }
+            StructureFormatChoose::Gro => formats::write_gro(&mut writer, &data),
+            StructureFormatChoose::LammpsData | StructureFormatChoose::Pdb => {
+                panic!("Export to this format isn't supported")
+            }
+        }.expect("Can't write frame");
+    }
+    writer.flush().expect("Can't write");
+}
@@ -0,0 +1,233 @@
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use crate::args::{BarostatChoose, BoundaryConditionChoose, CrystalCellType, CutoffChoose, EamFormatChoose, IntegratorChoose, MixingRuleChoose, PotentialChoose, PressureCouplingChoose, ThermostatChoose};
+
+/// Simulation described by TOML or JSON file for `run` command
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunConfig {
+    /// Box size (nm). If not set, it is taken from lattices
+    pub boundary_box: Option<[f64; 3]>,
+    /// Boundary conditions along x y z (periodic if not set)
+    pub boundary_conditions: Option<Vec<BoundaryConditionChoose>>,
+    /// Start temperature (K) of all species
+    pub temperature: f64,
+    /// Lattice shared by species without their own lattice. Its sites are split between
+    /// species according to their fractions
+    pub lattice: Option<LatticeConfig>,
+    /// Random seed to split sites of shared lattice between species
+    #[serde(default)]
+    pub mixture_seed: u64,
+    /// Particle types. Id of particle type is its index in this list
+    pub species: Vec<SpeciesConfig>,
+    #[serde(default)]
+    pub potentials: Vec<PotentialConfig>,
+    /// Rule to mix Lennard-Jones parameters of species (Lorentz-Berthelot if not set)
+    pub mixing_rule: Option<MixingRuleChoose>,
+    /// Cutoff scheme of mixed potentials (shifted energy if not set)
+    pub mixing_cutoff: Option<CutoffChoose>,
+    /// Cutoff radius (nm) of mixed potentials (2.5 sigma of pair if not set)
+    pub mixing_r_cut: Option<f64>,
+    /// Distance (nm) where switching function of mixed potentials starts
+    pub mixing_r_on: Option<f64>,
+    /// Refuse to run if potential of any pair of species isn't set
+    #[serde(default)]
+    pub strict_potentials: bool,
+    /// Many-body potential that replaces pair potentials
+    pub eam: Option<EamConfig>,
+    pub integrator: IntegratorConfig,
+    pub thermostat: Option<ThermostatConfig>,
+    pub barostat: Option<BarostatConfig>,
+    #[serde(default)]
+    pub output: OutputConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LatticeConfig {
+    pub cell_type: CrystalCellType,
+    /// Unit cells count along x y z
+    pub size: [u32; 3],
+    /// Lattice cell (nm)
+    pub lattice_cell: f64,
+    /// Position of first site (nm)
+    #[serde(default)]
+    pub start: [f64; 3],
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpeciesConfig {
+    pub name: String,
+    /// Mass (10^-27 kg)
+    pub mass: f64,
+    /// Radius (nm)
+    pub radius: f64,
+    /// Part of shared lattice sites taken by this species
+    #[serde(default = "default_fraction")]
+    pub fraction: f64,
+    /// Own lattice of this species. Shared lattice is used if not set
+    pub lattice: Option<LatticeConfig>,
+    /// Lennard-Jones sigma (nm) and eps (10^-21 J) to generate potentials with other species
+    pub lennard_jones: Option<[f64; 2]>,
+}
+
+fn default_fraction() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PotentialConfig {
+    /// Pair of particle types
+    pub particles: [u16; 2],
+    pub potential: PotentialChoose,
+    #[serde(default)]
+    pub params: Vec<f64>,
+    /// File with table for table potential (relative to config)
+    pub table: Option<PathBuf>,
+    /// Keyword of section in table file
+    pub keyword: Option<String>,
+    /// Cutoff scheme of analytic potential (shifted energy if not set)
+    pub cutoff: Option<CutoffChoose>,
+    /// Cutoff radius (nm)
+    pub r_cut: Option<f64>,
+    /// Distance (nm) where switching function starts
+    pub r_on: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EamConfig {
+    /// DYNAMO file of potential (relative to config)
+    pub file: PathBuf,
+    pub format: EamFormatChoose,
+    /// Element of every species (names of species if not set)
+    pub elements: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IntegratorConfig {
+    pub method: IntegratorChoose,
+    pub params: Option<Vec<f64>>,
+    /// Time step (ps)
+    pub delta_time: f64,
+    pub iteration_count: usize,
+    /// Skin (nm) of neighbor list. Neighbor list isn't used if not set
+    pub neighbor_list_skin: Option<f64>,
+    #[serde(default)]
+    pub newton_third_law: bool,
+    /// Minimal distance (nm) and count of points of tables to replace potentials with
+    pub tabulate_potentials: Option<Vec<f64>>,
+    pub threads_count: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThermostatConfig {
+    #[serde(rename = "type")]
+    pub thermostat: ThermostatChoose,
+    pub params: Option<Vec<f64>>,
+    /// Random seed of Langevin, Andersen and CSVR thermostats
+    #[serde(default)]
+    pub seed: u64,
+    /// Target temperature (K)
+    pub temperature: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BarostatConfig {
+    #[serde(rename = "type")]
+    pub barostat: BarostatChoose,
+    pub params: Option<Vec<f64>>,
+    pub coupling: Option<PressureCouplingChoose>,
+    /// Target pressure
+    pub pressure: f64,
+}
+
+#[derive(Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MacroParameterChoose {
+    KineticEnergy,
+    PotentialEnergy,
+    ThermalEnergy,
+    Temperature,
+    Pressure,
+    PressureTensor,
+    ConservedEnergy,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// How often (in steps) to save state
+    #[serde(default = "default_frames_per_save")]
+    pub frames_per_save: usize,
+    /// Macro parameters to calculate for saved frames after simulation
+    #[serde(default)]
+    pub macro_parameters: Vec<MacroParameterChoose>,
+    /// Add long-range tail corrections to potential energy and pressure
+    #[serde(default)]
+    pub tail_correction: bool,
+    /// Write frames to binary trajectory instead of csv files
+    #[serde(default)]
+    pub trajectory: bool,
+    /// Green-Kubo transport coefficients to calculate during simulation
+    pub green_kubo: Option<GreenKuboConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GreenKuboConfig {
+    /// Correlation length (in samples)
+    pub max_lag: usize,
+    /// Steps between samples
+    #[serde(default = "default_frames_per_save")]
+    pub sample_interval: usize,
+}
+
+fn default_frames_per_save() -> usize {
+    1
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            frames_per_save: default_frames_per_save(),
+            macro_parameters: vec![],
+            tail_correction: false,
+            trajectory: false,
+            green_kubo: None,
+        }
+    }
+}
+
+impl RunConfig {
+    /// Loads config from TOML file, or from JSON file if it has `.json` extension. Text of file
+    /// is returned too, so exactly the same config can be saved with results.
+    pub fn load_from_file(path: &Path) -> (Self, String) {
+        let text = std::fs::read_to_string(path)
+            .unwrap_or_else(|_| panic!("Can't read config {}", path.display()));
+        let config = if Self::is_json(path) {
+            serde_json::from_str(&text).expect("Can't parse JSON config")
+        } else {
+            toml::from_str(&text).expect("Can't parse TOML config")
+        };
+        (config, text)
+    }
+
+    /// Path to copy of config in output directory `file`
+    pub fn get_saved_path(file: &Path, config_path: &Path) -> PathBuf {
+        if Self::is_json(config_path) {
+            file.join("config.json")
+        } else {
+            file.join("config.toml")
+        }
+    }
+
+    fn is_json(path: &Path) -> bool {
+        path.extension().is_some_and(|extension| extension == "json")
+    }
+}
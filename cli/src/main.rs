@@ -1,10 +1,11 @@
 use std::time::Instant;
 use clap::Parser;
 use crate::args::*;
-use crate::commands::{add_potential_to_file, check_impulse, generate_default_potentials, generate_histogram, initialize, particle_count, solve, solve_macro};
+use crate::commands::{add_potential_to_file, check_impulse, convert_trajectory, export, generate_default_potentials, generate_histogram, import, in_thread_pool, initialize, mean_squared_displacement, particle_count, radial_distribution, restart, run, set_eam, set_mixing_rule, set_species_potential, solve, solve_macro, SolveOptions, velocity_autocorrelation};
 
 mod args;
 mod commands;
+mod config;
 
 #[cfg(test)]
 mod tests;
@@ -14,43 +15,80 @@ fn main() {
     let args = Args::parse();
     let start = Instant::now();
     match &args.command {
+        Commands::Run {
+            config,
+        } => {
+            run(&args.file, config);
+        }
         Commands::Initialize {
             crystal_cell_type,
             size,
             particle_name,
             particle_mass,
             particle_radius,
+            fractions,
+            seed,
             lattice_cell,
-            temperature
+            temperature,
+            boundary_conditions,
         } => {
             initialize(&args.file, crystal_cell_type, size, particle_name,
-                       particle_mass, particle_radius, lattice_cell, temperature);
+                       particle_mass, particle_radius, fractions, seed, lattice_cell, temperature,
+                       boundary_conditions);
         }
         Commands::Solve {
             state_number,
             integrate_method,
             threads_count,
-            custom_method,
+            custom_method: _,
+            integrator_params,
             use_potentials,
+            neighbor_list_skin,
+            newton_third_law,
+            tabulate_potentials,
+            green_kubo,
             iteration_count,
             delta_time,
             thermostat,
             thermostat_params,
+            thermostat_seed,
             temperature,
             barostat,
             barostat_params,
+            pressure_coupling,
             pressure,
         } => {
-            if let Some(threads_count) = threads_count {
-                rayon::ThreadPoolBuilder::new()
-                    .num_threads(*threads_count)
-                    .build_global().unwrap();
-            }
-            solve(&args.file, *state_number, integrate_method,
-                  custom_method, use_potentials, *iteration_count,
-                  delta_time,
-                  thermostat, thermostat_params, temperature,
-                  barostat, barostat_params, pressure);
+            let options = SolveOptions {
+                integrator: integrate_method.clone(),
+                integrator_params: integrator_params.clone(),
+                use_potentials: *use_potentials,
+                neighbor_list_skin: *neighbor_list_skin,
+                newton_third_law: *newton_third_law,
+                tabulate_potentials: tabulate_potentials.clone(),
+                green_kubo: green_kubo.clone(),
+                iteration_count: *iteration_count,
+                delta_time: *delta_time,
+                thermostat: thermostat.clone(),
+                thermostat_params: thermostat_params.clone(),
+                thermostat_seed: *thermostat_seed,
+                temperature: *temperature,
+                barostat: barostat.clone(),
+                barostat_params: barostat_params.clone(),
+                pressure_coupling: pressure_coupling.clone(),
+                pressure: *pressure,
+                frames_per_save: args.frames_per_save,
+                trajectory: args.trajectory,
+            };
+            in_thread_pool(*threads_count, || solve(&args.file, *state_number, &options));
+        }
+        Commands::Restart {
+            threads_count,
+            state_number,
+            iteration_count,
+        } => {
+            in_thread_pool(*threads_count, || {
+                restart(&args.file, *state_number, *iteration_count, args.frames_per_save, args.trajectory)
+            });
         }
         Commands::SolveMacroParameters {
             kinetic_energy,
@@ -58,19 +96,23 @@ fn main() {
             thermal_energy,
             temperature,
             pressure,
+            pressure_tensor,
+            conserved_energy,
             custom,
             custom_name,
             all,
             use_potentials,
+            tail_correction,
         } => {
             if *all {
                 solve_macro(&args.file,true, true,
-                            true, true, true,
-                            *custom, custom_name, use_potentials);
+                            true, true, true, true, true,
+                            *custom, custom_name, use_potentials, *tail_correction);
             } else {
                 solve_macro(&args.file, *kinetic_energy, *potential_energy,
-                            *thermal_energy, *temperature, *pressure,
-                            *custom, custom_name, use_potentials);
+                            *thermal_energy, *temperature, *pressure, *pressure_tensor,
+                            *conserved_energy, *custom, custom_name, use_potentials,
+                            *tail_correction);
             }
         }
         Commands::CheckImpulse => {
@@ -86,8 +128,37 @@ fn main() {
             particle_types,
             potential,
             params,
+            table,
+            keyword,
+            cutoff,
+            r_cut,
+            r_on,
+        } => {
+            add_potential_to_file(&args.file, particle_types, potential, params, table, keyword,
+                                  cutoff, r_cut, r_on);
+        }
+        Commands::SetSpeciesPotential {
+            particle_type,
+            sigma,
+            eps,
+        } => {
+            set_species_potential(&args.file, *particle_type, *sigma, *eps);
+        }
+        Commands::SetMixingRule {
+            rule,
+            strict,
+            cutoff,
+            r_cut,
+            r_on,
+        } => {
+            set_mixing_rule(&args.file, rule, *strict, cutoff, r_cut, r_on);
+        }
+        Commands::SetEam {
+            input,
+            format,
+            elements,
         } => {
-            add_potential_to_file(&args.file, particle_types, potential, params);
+            set_eam(&args.file, input, format, elements);
         }
         Commands::GenerateVelocitiesHistogram {
             state_number,
@@ -95,6 +166,71 @@ fn main() {
         } => {
             generate_histogram(&args.file, *state_number, &particle_types[..]);
         }
+        Commands::Import {
+            input,
+            format,
+            state_number,
+            lammps_units,
+            particle_name,
+            particle_mass,
+            particle_radius,
+        } => {
+            import(&args.file, input, format, *state_number, lammps_units,
+                   particle_name, particle_mass, particle_radius);
+        }
+        Commands::Export {
+            output,
+            format,
+            state_number,
+            lammps_units,
+        } => {
+            export(&args.file, output, format, state_number, lammps_units);
+        }
+        Commands::ConvertTrajectory {
+            output,
+            precision,
+            compress,
+        } => {
+            convert_trajectory(&args.file, output, precision, *compress);
+        }
+        Commands::Rdf {
+            output,
+            r_max,
+            bins,
+            start,
+            end,
+        } => {
+            let output = output.clone().unwrap_or(args.file.join("rdf.csv"));
+            radial_distribution(&args.file, &output, r_max, *bins, *start, end);
+        }
+        Commands::Msd {
+            output,
+            frame_time,
+            max_lag,
+            origin_interval,
+            start,
+            end,
+            fit_range,
+        } => {
+            let output = output.clone().unwrap_or(args.file.join("msd.csv"));
+            mean_squared_displacement(&args.file, &output, *frame_time, max_lag, *origin_interval,
+                                      *start, end, fit_range);
+        }
+        Commands::Vacf {
+            output,
+            dos_output,
+            trajectory,
+            frame_time,
+            max_lag,
+            origin_interval,
+            start,
+            end,
+        } => {
+            let output = output.clone().unwrap_or(args.file.join("vacf.csv"));
+            let dos_output = dos_output.clone().unwrap_or(args.file.join("dos.csv"));
+            velocity_autocorrelation(&args.file, &output, &dos_output, trajectory, *frame_time,
+                                     max_lag, *origin_interval, *start, end);
+        }
     }
     let duration = start.elapsed();
     if args.time {
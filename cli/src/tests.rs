@@ -1,21 +1,22 @@
 use nalgebra::Vector3;
 use tempdir::TempDir;
-use moldyn_core::{ParticleDatabase, StateToSave};
-use moldyn_solver::solver::{PotentialsDatabase, update_force};
-use crate::args::{CrystalCellType, IntegratorChoose};
-use crate::commands::{initialize, solve};
+use moldyn_core::{DataFileMacro, ParticleDatabase, StateToSave, TrajectoryReader};
+use moldyn_solver::macro_parameters::get_tail_corrections;
+use moldyn_solver::solver::{Cutoff, MixingRule, Potential, PotentialsDatabase, update_force};
+use crate::args::{CrystalCellType, CutoffChoose, EamFormatChoose, IntegratorChoose, LammpsUnitsChoose, MixingRuleChoose, PotentialChoose, ThermostatChoose, TrajectoryPrecisionChoose};
+use crate::commands::{add_potential_to_file, convert_trajectory, export, generate_default_potentials, import, in_thread_pool, initialize, mean_squared_displacement, radial_distribution, restart, run, set_eam, set_mixing_rule, set_species_potential, solve, solve_macro, SolveOptions, velocity_autocorrelation};
 
 
 #[test]
 fn initialization() {
     let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
     let path = temp_dir.into_path();
-    let particle_name = String::from("Argon");
-    let mass = 66.335;
-    let radius = 0.071;
+    let particle_name = vec![String::from("Argon")];
+    let mass = vec![66.335];
+    let radius = vec![0.071];
     let lattice_cell = 3.338339;
     let temperature = 273.15;
-    initialize(&path, &CrystalCellType::U, &vec![10, 10, 10], &particle_name, &mass, &radius, &lattice_cell, &temperature);
+    initialize(&path, &CrystalCellType::U, &vec![10, 10, 10], &particle_name, &mass, &radius, &None, &0, &lattice_cell, &temperature, &None);
     let data = StateToSave::load_from_file(&path, 0);
     ParticleDatabase::load_particles_data(&path).unwrap();
     assert_ne!(ParticleDatabase::get_particle_name(0), None);
@@ -32,6 +33,40 @@ fn initialization() {
     assert_eq!(state.boundary_box.z, 33.38339);
 }
 
+#[test]
+fn mixture_initialization() {
+    let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
+    let path = temp_dir.into_path();
+    let particle_name = vec![String::from("Argon"), String::from("Krypton")];
+    let mass = vec![66.335, 139.154];
+    let radius = vec![0.071, 0.088];
+    initialize(&path, &CrystalCellType::FCC, &vec![3, 3, 3], &particle_name, &mass, &radius,
+               &Some(vec![3.0, 1.0]), &0, &0.56, &100.0, &None);
+    ParticleDatabase::load_particles_data(&path).unwrap();
+    assert_eq!(ParticleDatabase::get_particle_name(1).unwrap(), "Krypton");
+    let state: moldyn_core::State = StateToSave::load_from_file(&path, 0).into();
+    assert_eq!(state.particles[0].len(), 81);
+    assert_eq!(state.particles[1].len(), 27);
+    assert!(state.particles[1].iter().all(|particle| particle.mass == 139.154));
+    // Same seed gives same sites of every species
+    let same_seed_path = TempDir::new("test_dir").expect("Can't create temp directory").into_path();
+    initialize(&same_seed_path, &CrystalCellType::FCC, &vec![3, 3, 3], &particle_name, &mass, &radius,
+               &Some(vec![3.0, 1.0]), &0, &0.56, &100.0, &None);
+    let same_seed_state: moldyn_core::State = StateToSave::load_from_file(&same_seed_path, 0).into();
+    assert!(state.particles[1].iter().zip(same_seed_state.particles[1].iter())
+        .all(|(p1, p2)| p1.position == p2.position));
+    solve_macro(&path, true, false, true, true, true, false, false, false, &None, &false, false);
+    let data = DataFileMacro::load_from_file(&path.join("macro.csv"));
+    assert!(path.join("macro_0.csv").is_file());
+    assert!(path.join("macro_1.csv").is_file());
+    let system = &data.macro_parameters[&0];
+    let argon = &data.species_macro_parameters[&0][&0];
+    let krypton = &data.species_macro_parameters[&1][&0];
+    assert!((system.thermal_energy - argon.thermal_energy - krypton.thermal_energy).abs() < 1e-9);
+    assert!((system.temperature * 108.0 - argon.temperature * 81.0 - krypton.temperature * 27.0).abs() < 1e-6);
+    assert!((system.pressure - argon.pressure - krypton.pressure).abs() < 1e-9);
+}
+
 #[test]
 fn solvation() {
     let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
@@ -51,15 +86,17 @@ fn solvation() {
     let state = moldyn_core::State {
         particles: vec![vec![p1, p2]],
         boundary_box: Vector3::new(2.0, 2.0, 2.0),
+        tilt: Vector3::zeros(),
+        boundary_conditions: Default::default(),
     };
     let potentials_db = PotentialsDatabase::new();
     let data = StateToSave::from(&state);
     data.save_to_file(&path, 0);
     ParticleDatabase::save_particles_data(&path).expect("");
-    solve(&path, 0, &IntegratorChoose::VerletMethod,
-          &None, &false, 3, &0.002,
-          &None, &None, &None,
-          &None, &None, &None);
+    solve(&path, 0, &SolveOptions {
+        iteration_count: 3,
+        ..Default::default()
+    });
     let data = StateToSave::load_from_file(&path, 3);
     let mut state = data.into();
     update_force(&potentials_db, &mut state);
@@ -96,3 +133,519 @@ fn solvation() {
     assert_eq!(format!("{:.8}", v2.y), "1.00000000");
     assert_eq!(format!("{:.8}", v2.z), "0.00000000");
 }
+
+#[test]
+fn thread_pools() {
+    // Every command may set its own threads count
+    assert_eq!(in_thread_pool(Some(2), rayon::current_num_threads), 2);
+    assert_eq!(in_thread_pool(Some(3), rayon::current_num_threads), 3);
+    assert_eq!(in_thread_pool(None, rayon::current_num_threads), rayon::current_num_threads());
+}
+
+#[test]
+fn run_config() {
+    let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
+    let path = temp_dir.into_path();
+    let config_path = path.join("argon.toml");
+    let config = r#"
+temperature = 80.0
+
+[lattice]
+cell_type = "fcc"
+size = [3, 3, 3]
+lattice_cell = 0.5256
+
+[[species]]
+name = "Argon"
+mass = 66.335
+radius = 0.071
+
+[[potentials]]
+particles = [0, 0]
+potential = "lennard-jones"
+params = [0.3418, 1.712]
+
+[integrator]
+method = "verlet-method"
+delta_time = 0.002
+iteration_count = 4
+neighbor_list_skin = 0.1
+threads_count = 2
+
+[thermostat]
+type = "berendsen"
+params = [0.5]
+temperature = 80.0
+
+[output]
+frames_per_save = 2
+macro_parameters = ["temperature", "pressure"]
+"#;
+    std::fs::write(&config_path, config).expect("Can't write config");
+    let output = path.join("output");
+    run(&output, &config_path);
+    assert_eq!(std::fs::read_to_string(output.join("config.toml")).unwrap(), config);
+    assert!(output.join("data").join("2.csv").is_file());
+    assert!(!output.join("data").join("3.csv").is_file());
+    let state: moldyn_core::State = StateToSave::load_from_file(&output, 2).into();
+    assert_eq!(state.particles[0].len(), 108);
+    assert_eq!(state.boundary_box, Vector3::new(1.5768, 1.5768, 1.5768));
+    let data = DataFileMacro::load_from_file(&output.join("macro.csv"));
+    assert_eq!(data.macro_parameters.len(), 3);
+    assert!(data.macro_parameters[&0].temperature > 0.0);
+    assert!(output.join("potentials.json").is_file());
+}
+
+#[test]
+fn export_import_xyz() {
+    let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
+    let path = temp_dir.into_path();
+    let source = path.join("source");
+    let particle_name = vec![String::from("Argon")];
+    initialize(&source, &CrystalCellType::FCC, &vec![2, 2, 2], &particle_name, &vec![66.335],
+               &vec![0.071], &None, &0, &0.5256, &80.0, &None);
+    solve(&source, 0, &SolveOptions {
+        iteration_count: 2,
+        ..Default::default()
+    });
+    let xyz = path.join("trajectory.xyz");
+    export(&source, &xyz, &None, &None, &LammpsUnitsChoose::Metal);
+    let imported = path.join("imported");
+    import(&imported, &xyz, &None, 0, &LammpsUnitsChoose::Metal, &Some(particle_name), &Some(vec![66.335]), &Some(vec![0.071]));
+    for frame in 0..3 {
+        let expected: moldyn_core::State = StateToSave::load_from_file(&source, frame).into();
+        let loaded: moldyn_core::State = StateToSave::load_from_file(&imported, frame).into();
+        assert!((expected.boundary_box - loaded.boundary_box).norm() < 1e-12);
+        for (p1, p2) in expected.particles[0].iter().zip(loaded.particles[0].iter()) {
+            assert!((p1.position - p2.position).norm() < 1e-12);
+            assert!((p1.velocity - p2.velocity).norm() < 1e-12);
+        }
+    }
+    assert!(!imported.join("data").join("3.csv").is_file());
+}
+
+#[test]
+fn solve_restart() {
+    let continuous = TempDir::new("test_dir").expect("Can't create temp directory").into_path();
+    let stopped = TempDir::new("test_dir").expect("Can't create temp directory").into_path();
+    let particle_name = vec![String::from("Argon")];
+    initialize(&continuous, &CrystalCellType::FCC, &vec![3, 3, 3], &particle_name, &vec![66.335],
+               &vec![0.071], &None, &0, &0.5256, &80.0, &None);
+    StateToSave::load_from_file(&continuous, 0).save_to_file(&stopped, 0);
+    ParticleDatabase::save_particles_data(&stopped).expect("Can't save particle database");
+    let solve_steps = |path: &std::path::PathBuf, iteration_count: usize| {
+        solve(path, 0, &SolveOptions {
+            neighbor_list_skin: Some(0.1),
+            iteration_count,
+            thermostat: Some(ThermostatChoose::Langevin),
+            thermostat_params: Some(vec![5.0]),
+            thermostat_seed: 42,
+            temperature: Some(80.0),
+            frames_per_save: 2,
+            ..Default::default()
+        });
+    };
+    solve_steps(&continuous, 8);
+    solve_steps(&stopped, 4);
+    restart(&stopped, 2, 4, 2, false);
+    assert!(continuous.join("checkpoint").join("4.bin").is_file());
+    for state_number in 0..=4 {
+        let expected = StateToSave::load_from_file(&continuous, state_number);
+        let actual = StateToSave::load_from_file(&stopped, state_number);
+        assert_eq!(serde_json::to_string(&expected).unwrap(), serde_json::to_string(&actual).unwrap());
+    }
+}
+
+#[test]
+fn solve_restart_trajectory() {
+    let continuous = TempDir::new("test_dir").expect("Can't create temp directory").into_path();
+    let stopped = TempDir::new("test_dir").expect("Can't create temp directory").into_path();
+    let particle_name = vec![String::from("Argon")];
+    initialize(&continuous, &CrystalCellType::FCC, &vec![3, 3, 3], &particle_name, &vec![66.335],
+               &vec![0.071], &None, &0, &0.5256, &80.0, &None);
+    StateToSave::load_from_file(&continuous, 0).save_to_file(&stopped, 0);
+    ParticleDatabase::save_particles_data(&stopped).expect("Can't save particle database");
+    let solve_steps = |path: &std::path::PathBuf, iteration_count: usize| {
+        solve(path, 0, &SolveOptions {
+            neighbor_list_skin: Some(0.1),
+            iteration_count,
+            frames_per_save: 2,
+            trajectory: true,
+            ..Default::default()
+        });
+    };
+    solve_steps(&continuous, 8);
+    solve_steps(&stopped, 4);
+    // Frames after frame 0 are replaced by solve from it, and frame 3 is replaced by restart.
+    // Existing trajectory is continued without flag
+    solve_steps(&stopped, 6);
+    restart(&stopped, 2, 4, 2, false);
+    assert!(!stopped.join("data").join("1.csv").is_file());
+    let mut expected = TrajectoryReader::open(&continuous.join("trajectory.bin")).unwrap();
+    let mut actual = TrajectoryReader::open(&stopped.join("trajectory.bin")).unwrap();
+    assert_eq!(actual.get_frames_count(), 5);
+    for state_number in 0..=4 {
+        let expected = expected.read_frame(state_number).unwrap();
+        let actual = actual.read_frame(state_number).unwrap();
+        assert_eq!(serde_json::to_string(&expected).unwrap(), serde_json::to_string(&actual).unwrap());
+    }
+    // Analysis reads frames from trajectory
+    radial_distribution(&stopped, &stopped.join("rdf.csv"), &None, 50, 0, &None);
+    mean_squared_displacement(&stopped, &stopped.join("msd.csv"), 0.004, &None, 1, 0, &None, &None);
+    let mut reader = csv::Reader::from_path(stopped.join("msd.csv")).unwrap();
+    assert_eq!(reader.records().count(), 3);
+}
+
+#[test]
+fn rdf_csv() {
+    let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
+    let path = temp_dir.into_path();
+    initialize(&path, &CrystalCellType::FCC, &vec![3, 3, 3], &vec![String::from("Argon")],
+               &vec![66.335], &vec![0.071], &None, &0, &0.5256, &80.0, &None);
+    let output = path.join("rdf.csv");
+    radial_distribution(&path, &output, &None, 100, 0, &None);
+    let mut reader = csv::Reader::from_path(&output).expect("Can't open file");
+    let header = reader.headers().expect("Can't read header").clone();
+    assert_eq!(header.iter().collect::<Vec<_>>(), vec!["r", "g", "n", "g_Argon-Argon", "n_Argon-Argon"]);
+    let rows: Vec<Vec<f64>> = reader.records().map(|record| {
+        record.expect("Can't read record").iter().map(|value| value.parse().unwrap()).collect()
+    }).collect();
+    assert_eq!(rows.len(), 100);
+    // Box is 3 lattice cells wide, so default r_max is 1.5 lattice cells
+    assert!((rows[99][0] - 0.5256 * 1.5 * 0.995).abs() < 1e-9);
+    let nearest = rows.iter().find(|row| row[0] > 0.5256 / 2.0f64.sqrt() + 0.01).unwrap();
+    assert_eq!(nearest[2], 12.0);
+    assert_eq!(nearest[4], 12.0);
+}
+
+#[test]
+fn msd_csv() {
+    let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
+    let path = temp_dir.into_path();
+    initialize(&path, &CrystalCellType::FCC, &vec![3, 3, 3], &vec![String::from("Argon")],
+               &vec![66.335], &vec![0.071], &None, &0, &0.5256, &80.0, &None);
+    solve(&path, 0, &SolveOptions {
+        neighbor_list_skin: Some(0.1),
+        iteration_count: 10,
+        ..Default::default()
+    });
+    // Lattice sites are on cell faces, so some particles leave the cell on the first step
+    let last = StateToSave::load_from_file(&path, 10);
+    assert!(last.particles.iter().any(|particle| particle.image_x != 0 || particle.image_y != 0
+        || particle.image_z != 0));
+    let output = path.join("msd.csv");
+    mean_squared_displacement(&path, &output, 0.002, &Some(4), 1, 0, &None, &None);
+    let mut reader = csv::Reader::from_path(&output).expect("Can't open file");
+    let header = reader.headers().expect("Can't read header").clone();
+    assert_eq!(header.iter().collect::<Vec<_>>(), vec!["t", "msd", "msd_Argon"]);
+    let rows: Vec<Vec<f64>> = reader.records().map(|record| {
+        record.expect("Can't read record").iter().map(|value| value.parse().unwrap()).collect()
+    }).collect();
+    assert_eq!(rows.len(), 5);
+    assert_eq!(rows[0][1], 0.0);
+    // Motion is ballistic on short times: MSD = <v^2> t^2
+    let first = StateToSave::load_from_file(&path, 0);
+    let v_squared = first.particles.iter().map(|particle| {
+        particle.velocity_x.powi(2) + particle.velocity_y.powi(2) + particle.velocity_z.powi(2)
+    }).sum::<f64>() / first.particles.len() as f64;
+    assert!((rows[1][1] / (v_squared * 0.002 * 0.002) - 1.0).abs() < 0.05);
+    assert_eq!(rows[4][1], rows[4][2]);
+}
+
+#[test]
+fn vacf_csv() {
+    let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
+    let path = temp_dir.into_path();
+    initialize(&path, &CrystalCellType::FCC, &vec![3, 3, 3], &vec![String::from("Argon")],
+               &vec![66.335], &vec![0.071], &None, &0, &0.5256, &80.0, &None);
+    solve(&path, 0, &SolveOptions {
+        neighbor_list_skin: Some(0.1),
+        iteration_count: 20,
+        ..Default::default()
+    });
+    let trajectory = path.join("trajectory.bin");
+    convert_trajectory(&path, &trajectory, &TrajectoryPrecisionChoose::F64, false);
+    let read = |output: &str| -> (Vec<String>, Vec<Vec<f64>>) {
+        let mut reader = csv::Reader::from_path(path.join(output)).expect("Can't open file");
+        let header = reader.headers().expect("Can't read header").iter().map(String::from).collect();
+        let rows = reader.records().map(|record| {
+            record.expect("Can't read record").iter().map(|value| value.parse().unwrap()).collect()
+        }).collect();
+        (header, rows)
+    };
+    velocity_autocorrelation(&path, &path.join("vacf.csv"), &path.join("dos.csv"), &None, 0.002,
+                             &Some(8), 2, 0, &None);
+    velocity_autocorrelation(&path, &path.join("vacf_trajectory.csv"), &path.join("dos_trajectory.csv"),
+                             &Some(trajectory), 0.002, &Some(8), 2, 0, &None);
+    let (header, vacf) = read("vacf.csv");
+    assert_eq!(header, vec!["t", "vacf", "vacf_normalized", "vacf_Argon", "vacf_normalized_Argon"]);
+    assert_eq!(vacf.len(), 9);
+    assert_eq!(vacf[0][2], 1.0);
+    let (header, dos) = read("dos.csv");
+    assert_eq!(header, vec!["frequency", "wavenumber", "dos", "dos_Argon"]);
+    assert_eq!(dos.len(), 9);
+    assert!((dos[8][0] - 0.5 / 0.002).abs() < 1e-9);
+    assert_eq!(read("vacf_trajectory.csv").1, vacf);
+    assert_eq!(read("dos_trajectory.csv").1, dos);
+}
+
+#[test]
+fn green_kubo_csv() {
+    let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
+    let path = temp_dir.into_path();
+    initialize(&path, &CrystalCellType::FCC, &vec![3, 3, 3], &vec![String::from("Argon")],
+               &vec![66.335], &vec![0.071], &None, &0, &0.5256, &80.0, &None);
+    solve(&path, 0, &SolveOptions {
+        neighbor_list_skin: Some(0.1),
+        green_kubo: Some(vec![5, 2]),
+        iteration_count: 12,
+        frames_per_save: 4,
+        ..Default::default()
+    });
+    restart(&path, 3, 8, 4, false);
+    let mut reader = csv::Reader::from_path(path.join("green_kubo.csv")).expect("Can't open file");
+    let header = reader.headers().expect("Can't read header").clone();
+    assert_eq!(header.iter().collect::<Vec<_>>(),
+               vec!["t", "pressure_acf", "viscosity", "heat_flux_acf", "thermal_conductivity"]);
+    let rows: Vec<Vec<f64>> = reader.records().map(|record| {
+        record.expect("Can't read record").iter().map(|value| value.parse().unwrap()).collect()
+    }).collect();
+    // Correlators are continued after restart: 10 samples of 20 steps
+    assert_eq!(rows.len(), 6);
+    assert!((rows[5][0] - 0.02).abs() < 1e-12);
+    assert_eq!(rows[0][2], 0.0);
+    assert!(rows[0][1] > 0.0 && rows[0][3] > 0.0);
+}
+
+#[test]
+fn set_potentials() {
+    let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
+    let path = temp_dir.into_path();
+    let potentials = [
+        (PotentialChoose::Morse, vec![1.5, 13.0, 0.38, 0.9], Potential::new_morse(1.5, 13.0, 0.38, 0.9)),
+        (PotentialChoose::Buckingham, vec![1.5e5, 0.027, 1e-3, 0.9],
+         Potential::new_buckingham(1.5e5, 0.027, 1e-3, 0.9)),
+        (PotentialChoose::BornMayerHuggins, vec![20.0, 0.03, 0.3, 1e-3, 1e-4, 0.9],
+         Potential::new_born_mayer_huggins(20.0, 0.03, 0.3, 1e-3, 1e-4, 0.9)),
+        (PotentialChoose::SoftSphere, vec![0.3418, 1.712, 12.0, 0.9],
+         Potential::new_soft_sphere(0.3418, 1.712, 12.0, 0.9)),
+        (PotentialChoose::Wca, vec![0.3418, 1.712], Potential::new_wca(0.3418, 1.712)),
+    ];
+    generate_default_potentials(&path);
+    for (i, (choose, params, _)) in potentials.iter().enumerate() {
+        add_potential_to_file(&path, &vec![0, i as u16 + 1], choose, params, &None, &None, &None, &None, &None);
+    }
+    let morse = &potentials[0].2;
+    let table_path = path.join("morse.table");
+    let mut table = String::from("MORSE\nN 200\n\n");
+    for i in 0..200 {
+        let r = 0.3 + 0.6 * i as f64 / 199.0;
+        let (u, f) = morse.get_potential_and_force(r);
+        table += &format!("{} {} {} {}\n", i + 1, r, u, -f);
+    }
+    std::fs::write(&table_path, table).expect("Can't write table");
+    add_potential_to_file(&path, &vec![1, 1], &PotentialChoose::Table, &vec![],
+                          &Some(table_path), &Some(String::from("MORSE")), &None, &None, &None);
+    let mut potentials_database = PotentialsDatabase::new();
+    potentials_database.load_potentials_from_file(&path);
+    for (i, (_, _, expected)) in potentials.iter().enumerate() {
+        let potential = potentials_database.get_potential(i as u16 + 1, 0);
+        assert_eq!(potential.get_radius_cut(), expected.get_radius_cut());
+        assert_eq!(potential.get_potential_and_force(0.4), expected.get_potential_and_force(0.4));
+    }
+    let tabulated = potentials_database.get_potential(1, 1);
+    assert!((tabulated.get_radius_cut() - 0.9).abs() < 1e-12);
+    let (u, f) = tabulated.get_potential_and_force(0.4);
+    let (u_expected, f_expected) = morse.get_potential_and_force(0.4);
+    assert!((u - u_expected).abs() < 1e-4 && (f - f_expected).abs() < 1e-2);
+}
+
+#[test]
+fn mixing_rule_potentials() {
+    let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
+    let path = temp_dir.into_path();
+    initialize(&path, &CrystalCellType::FCC, &vec![2, 2, 2], &vec![String::from("Argon"), String::from("Krypton")],
+               &vec![66.335, 139.15], &vec![0.071, 0.088], &None, &0, &0.55, &80.0, &None);
+    generate_default_potentials(&path);
+    set_species_potential(&path, 1, 0.3624, 2.314);
+    set_mixing_rule(&path, &MixingRuleChoose::WaldmanHagler, true, &Some(CutoffChoose::ShiftedForce),
+                    &Some(1.0), &None);
+    let mut potentials_database = PotentialsDatabase::new();
+    potentials_database.load_potentials_from_file(&path);
+    assert_eq!(potentials_database.get_mixing_rule(), MixingRule::WaldmanHagler);
+    assert_eq!(potentials_database.get_mixed_cutoff(), (Some(1.0), Cutoff::ShiftedForce));
+    assert_eq!(potentials_database.get_potential(1, 1).get_radius_cut(), 1.0);
+    assert!(potentials_database.is_strict());
+    // Argon-argon potential is set explicitly, argon-krypton isn't
+    assert_eq!(potentials_database.get_undefined_pairs(2), vec![(0, 1)]);
+    set_species_potential(&path, 0, 0.3418, 1.712);
+    solve(&path, 0, &SolveOptions {
+        use_potentials: true,
+        iteration_count: 2,
+        ..Default::default()
+    });
+    assert!(path.join("data").join("2.csv").is_file());
+    // potentials.json of older versions has only map of potentials
+    let potentials = std::collections::HashMap::from([("0,1", Potential::new_wca(0.35, 2.0))]);
+    std::fs::write(path.join("potentials.json"), serde_json::to_string(&potentials).unwrap())
+        .expect("Can't write potentials");
+    let mut potentials_database = PotentialsDatabase::new();
+    potentials_database.load_potentials_from_file(&path);
+    assert_eq!(potentials_database.get_undefined_pairs(2), vec![(0, 0), (1, 1)]);
+}
+
+#[test]
+#[should_panic(expected = "Potentials aren't set for pairs of particle types [(0, 1), (1, 1)]")]
+fn strict_potentials() {
+    let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
+    let path = temp_dir.into_path();
+    initialize(&path, &CrystalCellType::FCC, &vec![2, 2, 2], &vec![String::from("Argon"), String::from("Krypton")],
+               &vec![66.335, 139.15], &vec![0.071, 0.088], &None, &0, &0.55, &80.0, &None);
+    generate_default_potentials(&path);
+    set_mixing_rule(&path, &MixingRuleChoose::LorentzBerthelot, true, &None, &None, &None);
+    solve(&path, 0, &SolveOptions {
+        use_potentials: true,
+        iteration_count: 2,
+        ..Default::default()
+    });
+}
+
+#[test]
+#[should_panic(expected = "Need inner steps, r_inner and r_outer for RESPA")]
+fn respa_parameters() {
+    let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
+    let path = temp_dir.into_path();
+    initialize(&path, &CrystalCellType::FCC, &vec![2, 2, 2], &vec![String::from("Argon")],
+               &vec![66.335], &vec![0.071], &None, &0, &0.5256, &80.0, &None);
+    generate_default_potentials(&path);
+    solve(&path, 0, &SolveOptions {
+        integrator: IntegratorChoose::Respa,
+        integrator_params: Some(vec![2.0]),
+        use_potentials: true,
+        iteration_count: 2,
+        ..Default::default()
+    });
+}
+
+#[test]
+#[should_panic(expected = "Inner steps of RESPA should be positive integer, got 2.5")]
+fn respa_inner_steps() {
+    let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
+    let path = temp_dir.into_path();
+    initialize(&path, &CrystalCellType::FCC, &vec![2, 2, 2], &vec![String::from("Argon")],
+               &vec![66.335], &vec![0.071], &None, &0, &0.5256, &80.0, &None);
+    generate_default_potentials(&path);
+    solve(&path, 0, &SolveOptions {
+        integrator: IntegratorChoose::Respa,
+        integrator_params: Some(vec![2.5, 0.6, 0.7]),
+        use_potentials: true,
+        iteration_count: 2,
+        ..Default::default()
+    });
+}
+
+#[test]
+#[should_panic(expected = "Need only gamma for Langevin, seed is set separately")]
+fn langevin_seed() {
+    let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
+    let path = temp_dir.into_path();
+    initialize(&path, &CrystalCellType::FCC, &vec![2, 2, 2], &vec![String::from("Argon")],
+               &vec![66.335], &vec![0.071], &None, &0, &0.5256, &80.0, &None);
+    generate_default_potentials(&path);
+    solve(&path, 0, &SolveOptions {
+        use_potentials: true,
+        iteration_count: 2,
+        thermostat: Some(ThermostatChoose::Langevin),
+        thermostat_params: Some(vec![5.0, 42.0]),
+        temperature: Some(80.0),
+        ..Default::default()
+    });
+}
+
+#[test]
+fn cutoff_and_tail_correction() {
+    let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
+    let path = temp_dir.into_path();
+    initialize(&path, &CrystalCellType::FCC, &vec![3, 3, 3], &vec![String::from("Argon")],
+               &vec![66.335], &vec![0.071], &None, &0, &0.5256, &80.0, &None);
+    generate_default_potentials(&path);
+    add_potential_to_file(&path, &vec![0, 0], &PotentialChoose::LennardJones, &vec![0.3418, 1.712],
+                          &None, &None, &Some(CutoffChoose::ShiftedForce), &Some(0.75), &None);
+    let mut potentials_database = PotentialsDatabase::new();
+    potentials_database.load_potentials_from_file(&path);
+    let potential = potentials_database.get_potential(0, 0);
+    assert_eq!(potential.get_radius_cut(), 0.75);
+    let (u, du) = potential.get_potential_and_force(0.75);
+    assert!(u.abs() < 1e-15 && du.abs() < 1e-15);
+    let load_macro = |tail_correction: bool| {
+        solve_macro(&path, false, true, false, false, true, true, false, false, &None, &true,
+                    tail_correction);
+        DataFileMacro::load_from_file(&path.join("macro.csv")).macro_parameters[&0].clone()
+    };
+    let without = load_macro(false);
+    let with = load_macro(true);
+    let state: moldyn_core::State = StateToSave::load_from_file(&path, 0).into();
+    let (energy, pressure) = get_tail_corrections(&potentials_database, &state);
+    assert!(energy < 0.0 && pressure < 0.0);
+    assert!((with.potential_energy - without.potential_energy - energy).abs() < 1e-9);
+    assert!((with.pressure - without.pressure - pressure).abs() < 1e-9);
+    assert!((with.pressure_xx - without.pressure_xx - pressure).abs() < 1e-9);
+    assert!((with.pressure_xy - without.pressure_xy).abs() < 1e-12);
+}
+
+#[test]
+fn eam_config() {
+    let temp_dir = TempDir::new("test_dir").expect("Can't create temp directory");
+    let path = temp_dir.into_path();
+    // funcfl file with simple model of copper (eV and angstroms)
+    let taper = |r: f64| if r < 4.0 { ((4.0 - r) / 1.45).powi(2) } else { 0.0 };
+    let mut funcfl = String::from("Test copper\n29 63.546 3.615 fcc\n500 0.05 500 0.01 4.0\n");
+    funcfl += &(0..500).map(|i| format!("{:e}\n", -2.0 * (i as f64 * 0.05).sqrt())).collect::<String>();
+    funcfl += &(0..500).map(|i| i as f64 * 0.01)
+        .map(|r| format!("{:e}\n", 0.2 * (-1.5 * (r - 2.55)).exp() * taper(r))).collect::<String>();
+    funcfl += &(0..500).map(|i| i as f64 * 0.01)
+        .map(|r| format!("{:e}\n", (-3.0 * (r - 2.55)).exp() * taper(r))).collect::<String>();
+    std::fs::write(path.join("cu.eam"), funcfl).expect("Can't write potential");
+    let config_path = path.join("copper.toml");
+    let config = r#"
+temperature = 300.0
+
+[lattice]
+cell_type = "fcc"
+size = [3, 3, 3]
+lattice_cell = 0.3615
+
+[[species]]
+name = "Copper"
+mass = 105.52
+radius = 0.128
+
+[eam]
+file = "cu.eam"
+format = "funcfl"
+
+[integrator]
+method = "verlet-method"
+delta_time = 0.002
+iteration_count = 4
+neighbor_list_skin = 0.05
+
+[output]
+macro_parameters = ["potential-energy"]
+"#;
+    std::fs::write(&config_path, config).expect("Can't write config");
+    let output = path.join("output");
+    run(&output, &config_path);
+    let mut potentials_database = PotentialsDatabase::new();
+    potentials_database.load_potentials_from_file(&output);
+    assert!((potentials_database.get_eam().unwrap().get_radius_cut() - 0.4).abs() < 1e-12);
+    assert!(potentials_database.get_undefined_pairs(1).is_empty());
+    let data = DataFileMacro::load_from_file(&output.join("macro.csv"));
+    assert!(data.macro_parameters[&4].potential_energy < 0.0);
+
+    generate_default_potentials(&path);
+    set_eam(&path, &path.join("cu.eam"), &EamFormatChoose::Funcfl, &[String::from("Cu")]);
+    let mut potentials_database = PotentialsDatabase::new();
+    potentials_database.load_potentials_from_file(&path);
+    assert_eq!(potentials_database.get_max_radius_cut(), potentials_database.get_eam().unwrap().get_radius_cut());
+}
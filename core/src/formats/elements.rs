@@ -0,0 +1,76 @@
+use std::io::Result;
+use crate::ParticleDatabase;
+use super::{invalid_data, ANGSTROM, ATOMIC_MASS_UNIT};
+
+/// Symbol, standard atomic weight (u) and covalent radius (angstroms) of chemical elements in
+/// order of atomic number. Elements without stable isotopes have mass number of their most
+/// stable isotope. Radii are single-bond radii of Pyykko and Atsumi (2009), they are known for
+/// every element unlike van der Waals radii. Radius is only used to draw particles.
+const ELEMENTS: [(&str, f64, f64); 118] = [
+    ("H", 1.008, 0.32), ("He", 4.0026, 0.46), ("Li", 6.94, 1.33), ("Be", 9.0122, 1.02),
+    ("B", 10.81, 0.85), ("C", 12.011, 0.75), ("N", 14.007, 0.71), ("O", 15.999, 0.63),
+    ("F", 18.998, 0.64), ("Ne", 20.180, 0.67), ("Na", 22.990, 1.55), ("Mg", 24.305, 1.39),
+    ("Al", 26.982, 1.26), ("Si", 28.085, 1.16), ("P", 30.974, 1.11), ("S", 32.06, 1.03),
+    ("Cl", 35.45, 0.99), ("Ar", 39.948, 0.96), ("K", 39.098, 1.96), ("Ca", 40.078, 1.71),
+    ("Sc", 44.956, 1.48), ("Ti", 47.867, 1.36), ("V", 50.942, 1.34), ("Cr", 51.996, 1.22),
+    ("Mn", 54.938, 1.19), ("Fe", 55.845, 1.16), ("Co", 58.933, 1.11), ("Ni", 58.693, 1.10),
+    ("Cu", 63.546, 1.12), ("Zn", 65.38, 1.18), ("Ga", 69.723, 1.24), ("Ge", 72.630, 1.21),
+    ("As", 74.922, 1.21), ("Se", 78.971, 1.16), ("Br", 79.904, 1.14), ("Kr", 83.798, 1.17),
+    ("Rb", 85.468, 2.10), ("Sr", 87.62, 1.85), ("Y", 88.906, 1.63), ("Zr", 91.224, 1.54),
+    ("Nb", 92.906, 1.47), ("Mo", 95.95, 1.38), ("Tc", 98.0, 1.28), ("Ru", 101.07, 1.25),
+    ("Rh", 102.91, 1.25), ("Pd", 106.42, 1.20), ("Ag", 107.87, 1.28), ("Cd", 112.41, 1.36),
+    ("In", 114.82, 1.42), ("Sn", 118.71, 1.40), ("Sb", 121.76, 1.40), ("Te", 127.60, 1.36),
+    ("I", 126.90, 1.33), ("Xe", 131.29, 1.31), ("Cs", 132.91, 2.32), ("Ba", 137.33, 1.96),
+    ("La", 138.91, 1.80), ("Ce", 140.12, 1.63), ("Pr", 140.91, 1.76), ("Nd", 144.24, 1.74),
+    ("Pm", 145.0, 1.73), ("Sm", 150.36, 1.72), ("Eu", 151.96, 1.68), ("Gd", 157.25, 1.69),
+    ("Tb", 158.93, 1.68), ("Dy", 162.50, 1.67), ("Ho", 164.93, 1.66), ("Er", 167.26, 1.65),
+    ("Tm", 168.93, 1.64), ("Yb", 173.05, 1.70), ("Lu", 174.97, 1.62), ("Hf", 178.49, 1.52),
+    ("Ta", 180.95, 1.46), ("W", 183.84, 1.37), ("Re", 186.21, 1.31), ("Os", 190.23, 1.29),
+    ("Ir", 192.22, 1.22), ("Pt", 195.08, 1.23), ("Au", 196.97, 1.24), ("Hg", 200.59, 1.33),
+    ("Tl", 204.38, 1.44), ("Pb", 207.2, 1.44), ("Bi", 208.98, 1.51), ("Po", 209.0, 1.45),
+    ("At", 210.0, 1.47), ("Rn", 222.0, 1.42), ("Fr", 223.0, 2.23), ("Ra", 226.0, 2.01),
+    ("Ac", 227.0, 1.86), ("Th", 232.04, 1.75), ("Pa", 231.04, 1.69), ("U", 238.03, 1.70),
+    ("Np", 237.0, 1.71), ("Pu", 244.0, 1.72), ("Am", 243.0, 1.66), ("Cm", 247.0, 1.66),
+    ("Bk", 247.0, 1.68), ("Cf", 251.0, 1.68), ("Es", 252.0, 1.65), ("Fm", 257.0, 1.67),
+    ("Md", 258.0, 1.73), ("No", 259.0, 1.76), ("Lr", 266.0, 1.61), ("Rf", 267.0, 1.57),
+    ("Db", 268.0, 1.49), ("Sg", 269.0, 1.43), ("Bh", 270.0, 1.41), ("Hs", 269.0, 1.34),
+    ("Mt", 278.0, 1.29), ("Ds", 281.0, 1.28), ("Rg", 282.0, 1.21), ("Cn", 285.0, 1.22),
+    ("Nh", 286.0, 1.36), ("Fl", 289.0, 1.43), ("Mc", 290.0, 1.62), ("Lv", 293.0, 1.75),
+    ("Ts", 294.0, 1.65), ("Og", 294.0, 1.57),
+];
+
+/// Mass (10^-27 kg) and radius (nm) of chemical element with `symbol` (case insensitive)
+pub fn get_element_data(symbol: &str) -> Option<(f64, f64)> {
+    ELEMENTS.iter()
+        .find(|(element, _, _)| element.eq_ignore_ascii_case(symbol))
+        .map(|(_, mass, radius)| (mass * ATOMIC_MASS_UNIT, radius * ANGSTROM))
+}
+
+/// Gets particle type for atom of structure file. Particle type with `atom_name` is used if it is
+/// in [ParticleDatabase], otherwise type is the `element`. Unknown element is added to database
+/// with its standard mass and radius.
+pub(crate) fn get_atom_type(atom_name: &str, element: &str) -> Result<u16> {
+    if let Some(id) = ParticleDatabase::get_particle_id(atom_name) {
+        return Ok(id);
+    }
+    let (symbol, _, _) = ELEMENTS.iter()
+        .find(|(symbol, _, _)| symbol.eq_ignore_ascii_case(element))
+        .ok_or_else(|| invalid_data(format!("Unknown element {element} of atom {atom_name}").as_str()))?;
+    let (mass, radius) = get_element_data(symbol).unwrap();
+    Ok(ParticleDatabase::get_or_add(symbol, mass, radius))
+}
+
+/// Guesses element from atom name: digits are skipped, two letters are used if they are an
+/// element and `two_letters` is set (e.g. name is the same as residue, like ions), otherwise
+/// the first letter.
+pub(crate) fn guess_element(atom_name: &str, two_letters: bool) -> String {
+    let letters: String = atom_name.chars()
+        .skip_while(|c| c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect();
+    if two_letters && letters.len() >= 2 && get_element_data(&letters[..2]).is_some() {
+        letters[..2].to_string()
+    } else {
+        letters.chars().take(1).collect()
+    }
+}
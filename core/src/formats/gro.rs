@@ -0,0 +1,115 @@
+use std::io::{BufRead, Result, Write};
+use na::{Matrix3, Vector3};
+use crate::{BoundaryCondition, ParticleToSave, StateToSave};
+use super::{get_atom_type, get_box_from_cell, get_particle_type_name, guess_element, invalid_data, transform_particles, wrap_particles};
+
+fn parse_number(value: Option<&str>) -> Result<f64> {
+    value.and_then(|value| value.trim().parse().ok())
+        .ok_or_else(|| invalid_data("Can't parse number in GRO file"))
+}
+
+/// Reads next frame of GROMACS GRO file. Returns `None` at the end of file.
+///
+/// Positions are in nm, velocities (if they are written) in nm/ps, so they aren't converted.
+/// Particle type is found by atom name in [ParticleDatabase](crate::ParticleDatabase), or it is
+/// the element guessed from atom name (two letters only if atom name is the same as residue
+/// name, like `NA` and `CL` ions, or second letter is lowercase) which is added to database with
+/// its standard mass and radius. Box is periodic, particles are wrapped into it.
+pub fn read_gro(reader: &mut impl BufRead) -> Result<Option<StateToSave>> {
+    let mut line = String::new();
+    // Title
+    if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+        return Ok(None);
+    }
+    line.clear();
+    reader.read_line(&mut line)?;
+    let particles_count: usize = line.trim().parse()
+        .map_err(|_| invalid_data("Can't parse particles count in GRO file"))?;
+    let mut particles = Vec::with_capacity(particles_count);
+    for _ in 0..particles_count {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("Unexpected end of GRO file"));
+        }
+        let line = line.trim_end();
+        let residue_name = line.get(5..10).unwrap_or("").trim();
+        let atom_name = line.get(10..15).unwrap_or("").trim();
+        // Width of number fields is distance between decimal points (8 for default precision)
+        let numbers = line.get(20..).ok_or_else(|| invalid_data("Not enough columns in GRO file"))?;
+        let first_point = numbers.find('.').unwrap_or(0);
+        let width = numbers[first_point + 1..].find('.').map_or(8, |point| point + 1);
+        let field = |i: usize| numbers.get(i * width..((i + 1) * width).min(numbers.len()));
+        let two_letters = atom_name.eq_ignore_ascii_case(residue_name)
+            || atom_name.chars().nth(1).is_some_and(|c| c.is_ascii_lowercase());
+        let has_velocity = numbers.len() > 3 * width;
+        let velocity = |i: usize| if has_velocity { parse_number(field(i)) } else { Ok(0.0) };
+        particles.push(ParticleToSave {
+            id: get_atom_type(atom_name, &guess_element(atom_name, two_letters))?,
+            position_x: parse_number(field(0))?,
+            position_y: parse_number(field(1))?,
+            position_z: parse_number(field(2))?,
+            velocity_x: velocity(3)?,
+            velocity_y: velocity(4)?,
+            velocity_z: velocity(5)?,
+            ..Default::default()
+        });
+    }
+    line.clear();
+    reader.read_line(&mut line)?;
+    let values: Vec<f64> = line.split_whitespace().map(|x| parse_number(Some(x)))
+        .collect::<Result<_>>()?;
+    // Box line: v1(x) v2(y) v3(z) [v1(y) v1(z) v2(x) v2(z) v3(x) v3(y)]
+    let cell = match values.as_slice() {
+        [x, y, z] => Matrix3::new(*x, 0.0, 0.0, 0.0, *y, 0.0, 0.0, 0.0, *z),
+        [v1x, v2y, v3z, v1y, v1z, v2x, v2z, v3x, v3y] => {
+            Matrix3::new(*v1x, *v2x, *v3x, *v1y, *v2y, *v3y, *v1z, *v2z, *v3z)
+        }
+        _ => return Err(invalid_data("Wrong box line in GRO file")),
+    };
+    let (boundary_box, tilt, rotation) = get_box_from_cell(&cell)
+        .ok_or_else(|| invalid_data("Box vectors are degenerate"))?;
+    let boundary_conditions = [BoundaryCondition::Periodic; 3];
+    transform_particles(&mut particles, &Vector3::zeros(), &rotation);
+    wrap_particles(&mut particles, &boundary_box, &tilt, &boundary_conditions);
+    Ok(Some(StateToSave {
+        particles,
+        boundary_box,
+        tilt,
+        boundary_conditions,
+        thermostat: vec![],
+        barostat: vec![],
+    }))
+}
+
+/// Reads all frames of GRO file (see [read_gro])
+pub fn read_gro_frames(reader: &mut impl BufRead) -> Result<Vec<StateToSave>> {
+    let mut frames = vec![];
+    while let Some(frame) = read_gro(reader)? {
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+/// Writes frame to GRO file with positions and velocities. Every particle is a residue, name of
+/// particle type is used as residue and atom name. Numbers of residues and atoms are wrapped at
+/// 100000 as GROMACS does.
+pub fn write_gro(writer: &mut impl Write, data: &StateToSave) -> Result<()> {
+    writeln!(writer, "Generated by moldyn")?;
+    writeln!(writer, "{:5}", data.particles.len())?;
+    for (i, particle) in data.particles.iter().enumerate() {
+        let name = get_particle_type_name(particle.id);
+        let name: String = name.chars().take(5).collect();
+        let number = (i + 1) % 100000;
+        writeln!(writer, "{number:>5}{name:<5}{name:>5}{number:>5}{:8.3}{:8.3}{:8.3}{:8.4}{:8.4}{:8.4}",
+                 particle.position_x, particle.position_y, particle.position_z,
+                 particle.velocity_x, particle.velocity_y, particle.velocity_z)?;
+    }
+    let bb = &data.boundary_box;
+    write!(writer, "{:10.5}{:10.5}{:10.5}", bb.x, bb.y, bb.z)?;
+    if data.tilt != Vector3::zeros() {
+        let tilt = &data.tilt;
+        write!(writer, "{:10.5}{:10.5}{:10.5}{:10.5}{:10.5}{:10.5}", 0.0, 0.0, tilt.x, 0.0, tilt.y, tilt.z)?;
+    }
+    writeln!(writer)?;
+    Ok(())
+}
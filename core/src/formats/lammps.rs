@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Result, Write};
+use na::Vector3;
+use crate::{BoundaryCondition, ParticleDatabase, ParticleToSave, StateToSave};
+use super::{get_particle_type_name, invalid_data, ANGSTROM, ATOMIC_MASS_UNIT};
+
+/// Radius (nm) of particle types created from LAMMPS data file, as it has no radii
+pub const LAMMPS_DEFAULT_RADIUS: f64 = 0.1;
+
+/// LAMMPS `units` style. Temperature is in Kelvin for both of them, like in program (where it
+/// is scaled by 0.01 only inside of `k_B T`, see [K_B](crate::K_B)).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LammpsUnits {
+    /// Mass in g/mol, distance in angstroms, time in femtoseconds, energy in kcal/mol
+    Real,
+    /// Mass in g/mol, distance in angstroms, time in picoseconds, energy in eV
+    Metal,
+}
+
+impl LammpsUnits {
+    /// Program units (nm) in one LAMMPS distance unit
+    pub fn distance(&self) -> f64 {
+        ANGSTROM
+    }
+
+    /// Program units (nm/ps) in one LAMMPS velocity unit
+    pub fn velocity(&self) -> f64 {
+        match self {
+            LammpsUnits::Real => ANGSTROM * 1000.0,
+            LammpsUnits::Metal => ANGSTROM,
+        }
+    }
+
+    /// Program units (10^-27 kg) in one LAMMPS mass unit
+    pub fn mass(&self) -> f64 {
+        ATOMIC_MASS_UNIT
+    }
+
+    /// Program units (10^-21 J) in one LAMMPS energy unit
+    pub fn energy(&self) -> f64 {
+        match self {
+            LammpsUnits::Real => 6.947695457055374,
+            LammpsUnits::Metal => 160.2176634,
+        }
+    }
+}
+
+/// Reads next non-empty line without comment. Returns line and its comment.
+fn read_data_line(reader: &mut impl BufRead, line: &mut String) -> Result<Option<(String, String)>> {
+    loop {
+        line.clear();
+        if reader.read_line(line)? == 0 {
+            return Ok(None);
+        }
+        let (data, comment) = line.split_once('#').unwrap_or((line.as_str(), ""));
+        if !data.trim().is_empty() {
+            return Ok(Some((data.trim().to_string(), comment.trim().to_string())));
+        }
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: Option<&str>) -> Result<T> {
+    value.and_then(|value| value.parse().ok())
+        .ok_or_else(|| invalid_data("Can't parse number in LAMMPS data file"))
+}
+
+/// Reads LAMMPS data file (`write_data` output). Atom types `1..N` become particle types
+/// `0..N-1`, they are added to [ParticleDatabase] with masses from `Masses` section, names from
+/// `Atom Type Labels` section or mass comment (type number if there is no name) and
+/// [LAMMPS_DEFAULT_RADIUS]. Atom styles `atomic`, `charge`, `molecular` and `full` are supported
+/// (`atomic` if style isn't written after `Atoms`). Particles are sorted by atom id, positions
+/// are moved so box starts at zero, periodic boundaries are set on all axes.
+pub fn read_lammps_data(reader: &mut impl BufRead, units: LammpsUnits) -> Result<StateToSave> {
+    let mut line = String::new();
+    // The first line is title
+    reader.read_line(&mut line)?;
+    let mut atoms_count = 0;
+    let mut lo = Vector3::zeros();
+    let mut hi = Vector3::zeros();
+    let mut tilt = Vector3::zeros();
+    let mut masses: HashMap<u16, f64> = HashMap::new();
+    let mut names: HashMap<u16, String> = HashMap::new();
+    let mut atoms: Vec<(u64, ParticleToSave)> = vec![];
+    let mut velocities: HashMap<u64, Vector3<f64>> = HashMap::new();
+    let mut section: Option<(String, String)> = None;
+    loop {
+        let (data, comment) = match section.take() {
+            Some(section) => section,
+            None => match read_data_line(reader, &mut line)? {
+                Some(data) => data,
+                None => break,
+            },
+        };
+        let words: Vec<&str> = data.split_whitespace().collect();
+        match words.as_slice() {
+            [count, "atoms"] => atoms_count = parse_number(Some(count))?,
+            [lo_value, hi_value, axis_lo, _] if axis_lo.ends_with("lo") => {
+                let axis = match *axis_lo {
+                    "xlo" => 0,
+                    "ylo" => 1,
+                    "zlo" => 2,
+                    _ => return Err(invalid_data("Wrong box bounds in LAMMPS data file")),
+                };
+                lo[axis] = parse_number::<f64>(Some(lo_value))? * units.distance();
+                hi[axis] = parse_number::<f64>(Some(hi_value))? * units.distance();
+            }
+            [xy, xz, yz, "xy", "xz", "yz"] => {
+                tilt = Vector3::new(parse_number(Some(xy))?, parse_number(Some(xz))?,
+                                    parse_number(Some(yz))?) * units.distance();
+            }
+            ["Masses"] | ["Atom", "Type", "Labels"] | ["Atoms"] | ["Velocities"] => {
+                let style = comment.split_whitespace().next().unwrap_or("atomic").to_string();
+                // Section lasts until the next line with a letter at the start
+                while let Some((data, comment)) = read_data_line(reader, &mut line)? {
+                    if data.starts_with(|c: char| c.is_alphabetic()) {
+                        section = Some((data, comment));
+                        break;
+                    }
+                    let mut values = data.split_whitespace();
+                    match words[0] {
+                        "Masses" => {
+                            let atom_type: u16 = parse_number(values.next())?;
+                            let mass: f64 = parse_number(values.next())?;
+                            masses.insert(atom_type, mass * units.mass());
+                            if !comment.is_empty() {
+                                names.entry(atom_type).or_insert(comment);
+                            }
+                        }
+                        "Atom" => {
+                            let atom_type: u16 = parse_number(values.next())?;
+                            let name = values.next()
+                                .ok_or_else(|| invalid_data("No type label in LAMMPS data file"))?;
+                            names.insert(atom_type, name.to_string());
+                        }
+                        "Atoms" => {
+                            let values: Vec<&str> = values.collect();
+                            let (type_column, position_column) = match style.as_str() {
+                                "atomic" => (1, 2),
+                                "charge" => (1, 3),
+                                "molecular" => (2, 3),
+                                "full" => (2, 4),
+                                _ => return Err(invalid_data(format!("Unsupported atom style {style}").as_str())),
+                            };
+                            let atom_type: u16 = parse_number(values.get(type_column).copied())?;
+                            let position = Vector3::new(
+                                parse_number::<f64>(values.get(position_column).copied())?,
+                                parse_number::<f64>(values.get(position_column + 1).copied())?,
+                                parse_number::<f64>(values.get(position_column + 2).copied())?)
+                                * units.distance();
+                            if atom_type == 0 {
+                                return Err(invalid_data("Atom types in LAMMPS start from 1"));
+                            }
+                            // Image flags are optional
+                            let image = |axis: usize| values.get(position_column + 3 + axis)
+                                .map_or(Ok(0), |value| parse_number::<i32>(Some(value)));
+                            atoms.push((parse_number(values.first().copied())?, ParticleToSave {
+                                id: atom_type - 1,
+                                position_x: position.x,
+                                position_y: position.y,
+                                position_z: position.z,
+                                image_x: image(0)?,
+                                image_y: image(1)?,
+                                image_z: image(2)?,
+                                ..Default::default()
+                            }));
+                        }
+                        _ => {
+                            let id: u64 = parse_number(values.next())?;
+                            let velocity = Vector3::new(parse_number::<f64>(values.next())?,
+                                                        parse_number::<f64>(values.next())?,
+                                                        parse_number::<f64>(values.next())?);
+                            velocities.insert(id, velocity * units.velocity());
+                        }
+                    }
+                }
+            }
+            // Other header lines (e.g. atom types count) and sections (e.g. pair coefficients)
+            // aren't needed
+            _ => {}
+        }
+    }
+    if atoms.len() != atoms_count {
+        return Err(invalid_data("Wrong amount of atoms in LAMMPS data file"));
+    }
+    for (atom_type, mass) in masses.iter() {
+        let id = atom_type - 1;
+        let name = names.get(atom_type).cloned().unwrap_or_else(|| atom_type.to_string());
+        ParticleDatabase::add(id, name.as_str(), *mass, LAMMPS_DEFAULT_RADIUS);
+    }
+    atoms.sort_by_key(|(id, _)| *id);
+    let particles = atoms.into_iter().map(|(id, mut particle)| {
+        particle.position_x -= lo.x;
+        particle.position_y -= lo.y;
+        particle.position_z -= lo.z;
+        if let Some(velocity) = velocities.get(&id) {
+            particle.velocity_x = velocity.x;
+            particle.velocity_y = velocity.y;
+            particle.velocity_z = velocity.z;
+        }
+        particle
+    }).collect();
+    Ok(StateToSave {
+        particles,
+        boundary_box: hi - lo,
+        tilt,
+        boundary_conditions: Default::default(),
+        thermostat: vec![],
+        barostat: vec![],
+    })
+}
+
+/// Writes frame in LAMMPS `dump custom` format with columns `id type x y z vx vy vz` and
+/// `element` (name of particle type). Particle types `0..N-1` are written as atom types `1..N`.
+pub fn write_lammps_dump(writer: &mut impl Write, data: &StateToSave, timestep: usize,
+                         units: LammpsUnits) -> Result<()> {
+    writeln!(writer, "ITEM: TIMESTEP\n{timestep}")?;
+    writeln!(writer, "ITEM: NUMBER OF ATOMS\n{}", data.particles.len())?;
+    let boundaries: Vec<&str> = data.boundary_conditions.iter().map(|condition| match condition {
+        BoundaryCondition::Periodic => "pp",
+        BoundaryCondition::Reflective => "ff",
+        BoundaryCondition::Open => "ss",
+    }).collect();
+    let bb = data.boundary_box / units.distance();
+    let tilt = data.tilt / units.distance();
+    if tilt == Vector3::zeros() {
+        writeln!(writer, "ITEM: BOX BOUNDS {}", boundaries.join(" "))?;
+        for axis in 0..3 {
+            writeln!(writer, "0 {}", bb[axis])?;
+        }
+    } else {
+        // Bounding box of triclinic cell
+        let (xy, xz, yz) = (tilt.x, tilt.y, tilt.z);
+        writeln!(writer, "ITEM: BOX BOUNDS xy xz yz {}", boundaries.join(" "))?;
+        writeln!(writer, "{} {} {xy}", f64::min(0.0, xy).min(xz).min(xy + xz),
+                 bb.x + f64::max(0.0, xy).max(xz).max(xy + xz))?;
+        writeln!(writer, "{} {} {xz}", f64::min(0.0, yz), bb.y + f64::max(0.0, yz))?;
+        writeln!(writer, "0 {} {yz}", bb.z)?;
+    }
+    writeln!(writer, "ITEM: ATOMS id type element x y z vx vy vz ix iy iz")?;
+    for (i, particle) in data.particles.iter().enumerate() {
+        writeln!(writer, "{} {} {} {} {} {} {} {} {} {} {} {}", i + 1, particle.id + 1,
+                 get_particle_type_name(particle.id),
+                 particle.position_x / units.distance(), particle.position_y / units.distance(),
+                 particle.position_z / units.distance(), particle.velocity_x / units.velocity(),
+                 particle.velocity_y / units.velocity(), particle.velocity_z / units.velocity(),
+                 particle.image_x, particle.image_y, particle.image_z)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,132 @@
+//! Readers and writers of structure formats used by other molecular dynamics tools. All of
+//! them work with [StateToSave](crate::StateToSave), particle types are mapped to names from
+//! [ParticleDatabase](crate::ParticleDatabase).
+
+mod elements;
+mod xyz;
+mod lammps;
+mod pdb;
+mod gro;
+
+use std::io::{Error, ErrorKind, Result};
+use na::{Matrix3, Vector3};
+use crate::{wrap_position, BoundaryCondition, ParticleDatabase, ParticleToSave};
+pub use elements::*;
+pub use xyz::*;
+pub use lammps::*;
+pub use pdb::*;
+pub use gro::*;
+
+/// Nanometers in one angstrom
+pub const ANGSTROM: f64 = 0.1;
+/// Atomic mass unit in program units (10^-27 kg)
+pub const ATOMIC_MASS_UNIT: f64 = 1.66053906660;
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Matrix with cell vectors as columns for box lengths `boundary_box` and `tilt` factors
+/// (see [State::tilt](crate::State::tilt))
+pub fn get_cell_matrix(boundary_box: &Vector3<f64>, tilt: &Vector3<f64>) -> Matrix3<f64> {
+    Matrix3::new(boundary_box.x, tilt.x, tilt.y,
+                 0.0, boundary_box.y, tilt.z,
+                 0.0, 0.0, boundary_box.z)
+}
+
+/// Converts arbitrary cell vectors `a`, `b` and `c` (columns of `cell`) to box lengths and
+/// tilt factors. Cell is rotated so `a` is along x and `b` is in xy plane, returned matrix
+/// rotates positions and velocities from old cell to the new one.
+pub fn get_box_from_cell(cell: &Matrix3<f64>) -> Option<(Vector3<f64>, Vector3<f64>, Matrix3<f64>)> {
+    let a = cell.column(0);
+    let b = cell.column(1);
+    let c = cell.column(2);
+    let x = a.norm();
+    let xy = b.dot(&a) / x;
+    let y = (b.norm_squared() - xy * xy).sqrt();
+    let xz = c.dot(&a) / x;
+    let yz = (b.dot(&c) - xy * xz) / y;
+    let z = (c.norm_squared() - xz * xz - yz * yz).sqrt();
+    let boundary_box = Vector3::new(x, y, z);
+    let tilt = Vector3::new(xy, xz, yz);
+    let rotation = get_cell_matrix(&boundary_box, &tilt) * cell.try_inverse()?;
+    Some((boundary_box, tilt, rotation))
+}
+
+/// Box lengths and tilt factors of cell with `lengths` of `a`, `b`, `c` vectors and `angles`
+/// (degrees) alpha (between `b` and `c`), beta (between `a` and `c`) and gamma (between `a`
+/// and `b`). Vector `a` is along x and `b` is in xy plane.
+pub fn get_box_from_parameters(lengths: &Vector3<f64>, angles: &Vector3<f64>) -> Option<(Vector3<f64>, Vector3<f64>)> {
+    let cos = angles.map(|angle| angle.to_radians().cos());
+    let xy = lengths.y * cos.z;
+    let xz = lengths.z * cos.y;
+    let y = (lengths.y * lengths.y - xy * xy).sqrt();
+    let yz = (lengths.y * lengths.z * cos.x - xy * xz) / y;
+    let z = (lengths.z * lengths.z - xz * xz - yz * yz).sqrt();
+    let boundary_box = Vector3::new(lengths.x, y, z);
+    if boundary_box.iter().any(|length| !(length.is_finite() && *length > 0.0)) {
+        return None;
+    }
+    Some((boundary_box, Vector3::new(xy, xz, yz)))
+}
+
+/// Gets id of particle type by its name in [ParticleDatabase] or by its number
+pub(crate) fn get_particle_type(name: &str) -> Result<u16> {
+    if let Some(id) = ParticleDatabase::get_particle_id(name) {
+        return Ok(id);
+    }
+    match name.parse::<u16>() {
+        Ok(id) if ParticleDatabase::get_particle_mass(id).is_some() => Ok(id),
+        _ => Err(invalid_data(format!("Unknown particle type {name}, add it to particle database").as_str())),
+    }
+}
+
+/// Name of particle type for other formats (its id if it has no name)
+pub(crate) fn get_particle_type_name(id: u16) -> String {
+    ParticleDatabase::get_particle_name(id).unwrap_or_else(|| id.to_string())
+}
+
+/// Moves particles to box origin and rotates them together with cell (see [get_box_from_cell])
+pub(crate) fn transform_particles(particles: &mut [ParticleToSave], origin: &Vector3<f64>,
+                                  rotation: &Matrix3<f64>) {
+    for particle in particles.iter_mut() {
+        let position = rotation * (Vector3::new(particle.position_x, particle.position_y,
+                                                particle.position_z) - origin);
+        let velocity = rotation * Vector3::new(particle.velocity_x, particle.velocity_y,
+                                               particle.velocity_z);
+        particle.position_x = position.x;
+        particle.position_y = position.y;
+        particle.position_z = position.z;
+        particle.velocity_x = velocity.x;
+        particle.velocity_y = velocity.y;
+        particle.velocity_z = velocity.z;
+    }
+}
+
+/// Moves particles so their bounding box starts at zero and returns size of the bounding box
+pub(crate) fn fit_bounding_box(particles: &mut [ParticleToSave]) -> Vector3<f64> {
+    if particles.is_empty() {
+        return Vector3::zeros();
+    }
+    let positions = particles.iter()
+        .map(|particle| Vector3::new(particle.position_x, particle.position_y, particle.position_z));
+    let min = positions.clone().fold(Vector3::repeat(f64::MAX), |min, position| min.inf(&position));
+    let max = positions.fold(Vector3::repeat(f64::MIN), |max, position| max.sup(&position));
+    transform_particles(particles, &min, &Matrix3::identity());
+    max - min
+}
+
+/// Wraps particles into cell on periodic axes (see [wrap_position]) and counts it in their images
+pub(crate) fn wrap_particles(particles: &mut [ParticleToSave], boundary_box: &Vector3<f64>,
+                             tilt: &Vector3<f64>, boundary_conditions: &[BoundaryCondition; 3]) {
+    for particle in particles.iter_mut() {
+        let mut position = Vector3::new(particle.position_x, particle.position_y, particle.position_z);
+        let image = wrap_position(&mut position, boundary_box, tilt, boundary_conditions);
+        particle.image_x += image.x;
+        particle.image_y += image.y;
+        particle.image_z += image.z;
+        particle.position_x = position.x;
+        particle.position_y = position.y;
+        particle.position_z = position.z;
+    }
+}
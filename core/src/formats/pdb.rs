@@ -0,0 +1,94 @@
+use std::io::{BufRead, Result};
+use na::Vector3;
+use crate::{BoundaryCondition, ParticleToSave, StateToSave};
+use super::{fit_bounding_box, get_atom_type, get_box_from_parameters, guess_element, invalid_data, wrap_particles, ANGSTROM};
+
+/// Columns (from 0) of fixed width field of PDB record
+fn get_field(line: &str, start: usize, end: usize) -> &str {
+    line.get(start..end.min(line.len())).unwrap_or("").trim()
+}
+
+fn parse_field(line: &str, start: usize, end: usize) -> Result<f64> {
+    get_field(line, start, end).parse()
+        .map_err(|_| invalid_data(format!("Can't parse number in PDB record {}", line.trim()).as_str()))
+}
+
+/// Creates frame from atoms of one model and cell from `CRYST1` record
+fn create_frame(mut particles: Vec<ParticleToSave>, cell: &Option<(Vector3<f64>, Vector3<f64>)>) -> StateToSave {
+    let (boundary_box, tilt, boundary_conditions) = match cell {
+        Some((boundary_box, tilt)) => {
+            let boundary_conditions = [BoundaryCondition::Periodic; 3];
+            wrap_particles(&mut particles, boundary_box, tilt, &boundary_conditions);
+            (*boundary_box, *tilt, boundary_conditions)
+        }
+        None => (fit_bounding_box(&mut particles), Vector3::zeros(), [BoundaryCondition::Open; 3]),
+    };
+    StateToSave {
+        particles,
+        boundary_box,
+        tilt,
+        boundary_conditions,
+        thermostat: vec![],
+        barostat: vec![],
+    }
+}
+
+/// Reads all models of PDB file as frames. Only `ATOM`, `HETATM`, `CRYST1`, `MODEL` and
+/// `ENDMDL` records are used.
+///
+/// Particle type is found by atom name in [ParticleDatabase](crate::ParticleDatabase), or it is
+/// the element (from element column or atom name) which is added to database with its standard
+/// mass and radius. Positions are in angstroms and velocities are zero. Box is taken from
+/// `CRYST1` with periodic boundaries and particles wrapped into it. Without `CRYST1` (or with
+/// `1 1 1` cell used by NMR structures) box is bounding box of particles with open boundaries.
+pub fn read_pdb(reader: &mut impl BufRead) -> Result<Vec<StateToSave>> {
+    let mut frames = vec![];
+    let mut particles = vec![];
+    let mut cell = None;
+    for line in reader.lines() {
+        let line = line?;
+        let record = get_field(&line, 0, 6);
+        match record {
+            "CRYST1" => {
+                let lengths = Vector3::new(parse_field(&line, 6, 15)?, parse_field(&line, 15, 24)?,
+                                           parse_field(&line, 24, 33)?);
+                let angles = Vector3::new(parse_field(&line, 33, 40)?, parse_field(&line, 40, 47)?,
+                                          parse_field(&line, 47, 54)?);
+                cell = if lengths == Vector3::repeat(1.0) {
+                    None
+                } else {
+                    Some(get_box_from_parameters(&(lengths * ANGSTROM), &angles)
+                        .ok_or_else(|| invalid_data("Wrong cell in CRYST1 record"))?)
+                };
+            }
+            "ATOM" | "HETATM" => {
+                let name_field = line.get(12..16).unwrap_or("");
+                let atom_name = name_field.trim();
+                let element = match get_field(&line, 76, 78) {
+                    "" => {
+                        // Two-letter elements start at the first column of atom name
+                        let two_letters = name_field.starts_with(|c: char| c.is_ascii_alphabetic());
+                        guess_element(atom_name, two_letters)
+                    }
+                    element => element.to_string(),
+                };
+                particles.push(ParticleToSave {
+                    id: get_atom_type(atom_name, &element)?,
+                    position_x: parse_field(&line, 30, 38)? * ANGSTROM,
+                    position_y: parse_field(&line, 38, 46)? * ANGSTROM,
+                    position_z: parse_field(&line, 46, 54)? * ANGSTROM,
+                    ..Default::default()
+                });
+            }
+            "ENDMDL" => frames.push(create_frame(std::mem::take(&mut particles), &cell)),
+            _ => {}
+        }
+    }
+    if !particles.is_empty() {
+        frames.push(create_frame(particles, &cell));
+    }
+    if frames.is_empty() {
+        return Err(invalid_data("No atoms in PDB file"));
+    }
+    Ok(frames)
+}
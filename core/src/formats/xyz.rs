@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Result, Write};
+use na::{Matrix3, Vector3};
+use crate::{BoundaryCondition, ParticleDatabase, ParticleToSave, StateToSave};
+use super::{fit_bounding_box, get_box_from_cell, get_cell_matrix, get_element_data, get_particle_type, get_particle_type_name, invalid_data, transform_particles, ANGSTROM, ATOMIC_MASS_UNIT, LAMMPS_DEFAULT_RADIUS};
+
+/// Splits comment line of extended XYZ to `key=value` pairs. Keys are in lower case, values
+/// can be quoted, keys without value are flags with value `T`.
+fn parse_header(line: &str) -> HashMap<String, String> {
+    let mut header = HashMap::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+        let key: String = std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace() && *c != '='))
+            .collect();
+        let value = if chars.next_if_eq(&'=').is_some() {
+            match chars.peek() {
+                Some('"') | Some('\'') => {
+                    let quote = chars.next().unwrap();
+                    let value = std::iter::from_fn(|| chars.next_if(|c| *c != quote)).collect();
+                    chars.next();
+                    value
+                }
+                _ => std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace())).collect(),
+            }
+        } else {
+            String::from("T")
+        };
+        header.insert(key.to_lowercase(), value);
+    }
+    header
+}
+
+fn parse_floats(value: &str) -> Result<Vec<f64>> {
+    value.split_whitespace()
+        .map(|x| x.parse::<f64>().map_err(|_| invalid_data("Can't parse number in XYZ header")))
+        .collect()
+}
+
+fn parse_flag(value: &str) -> bool {
+    matches!(value.to_lowercase().as_str(), "t" | "true" | "1")
+}
+
+fn boundary_condition_name(condition: BoundaryCondition) -> &'static str {
+    match condition {
+        BoundaryCondition::Periodic => "periodic",
+        BoundaryCondition::Reflective => "reflective",
+        BoundaryCondition::Open => "open",
+    }
+}
+
+fn parse_boundary_conditions(value: &str) -> Result<[BoundaryCondition; 3]> {
+    let conditions = value.split_whitespace().map(|condition| match condition.to_lowercase().as_str() {
+        "periodic" => Ok(BoundaryCondition::Periodic),
+        "reflective" => Ok(BoundaryCondition::Reflective),
+        "open" => Ok(BoundaryCondition::Open),
+        _ => Err(invalid_data("Unknown boundary condition in XYZ header")),
+    }).collect::<Result<Vec<_>>>()?;
+    conditions.try_into().map_err(|_| invalid_data("Boundary conditions should be set for 3 axes"))
+}
+
+/// Gets particle type of `species`. Species that isn't in [ParticleDatabase] is added with
+/// `mass` if it is set, its radius is radius of element with the same symbol or
+/// [LAMMPS_DEFAULT_RADIUS].
+fn get_species_type(species: &str, mass: Option<f64>) -> Result<u16> {
+    match (get_particle_type(species), mass) {
+        (Err(_), Some(mass)) if mass > 0.0 => {
+            let radius = get_element_data(species).map_or(LAMMPS_DEFAULT_RADIUS, |(_, radius)| radius);
+            Ok(ParticleDatabase::get_or_add(species, mass, radius))
+        }
+        (particle_type, _) => particle_type,
+    }
+}
+
+/// Reads next frame of XYZ or extended XYZ file. Returns `None` at the end of file.
+///
+/// Positions and `Lattice` are in angstroms, velocities (`velo`, `vel` or `velocities`
+/// property) in angstroms per picosecond, masses (`masses` or `mass`) in atomic mass units.
+/// Species are looked up in [ParticleDatabase] by name (or id), species that aren't there are
+/// added with their masses and radii of elements. Other per-particle properties are skipped.
+/// Boundary conditions are taken from `boundary_conditions` key (e.g. `"periodic reflective
+/// open"`) if it is set, otherwise from `pbc` (non-periodic axes are open). Without `Lattice` box
+/// is bounding box of particles with open boundaries.
+pub fn read_xyz(reader: &mut impl BufRead) -> Result<Option<StateToSave>> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.trim().is_empty() {
+            break;
+        }
+    }
+    let particles_count: usize = line.trim().parse()
+        .map_err(|_| invalid_data("Can't parse particles count in XYZ file"))?;
+    line.clear();
+    reader.read_line(&mut line)?;
+    let header = parse_header(line.trim());
+    let properties = header.get("properties").map_or("species:S:1:pos:R:3", |p| p.as_str());
+    let properties: Vec<&str> = properties.split(':').collect();
+    if !properties.len().is_multiple_of(3) {
+        return Err(invalid_data("Wrong Properties in XYZ header"));
+    }
+    let properties: Vec<(String, usize)> = properties.chunks(3).map(|property| {
+        let count = property[2].parse::<usize>()
+            .map_err(|_| invalid_data("Wrong Properties in XYZ header"))?;
+        Ok((property[0].to_lowercase(), count))
+    }).collect::<Result<_>>()?;
+    let mut particles = Vec::with_capacity(particles_count);
+    for _ in 0..particles_count {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("Unexpected end of XYZ file"));
+        }
+        let mut columns = line.split_whitespace();
+        let mut particle = ParticleToSave::default();
+        let mut species = None;
+        let mut mass = None;
+        for (name, count) in properties.iter() {
+            let values: Vec<&str> = columns.by_ref().take(*count).collect();
+            if values.len() != *count {
+                return Err(invalid_data("Not enough columns in XYZ file"));
+            }
+            let floats = || values.iter()
+                .map(|x| x.parse::<f64>().map_err(|_| invalid_data("Can't parse number in XYZ file")))
+                .collect::<Result<Vec<f64>>>();
+            match name.as_str() {
+                "species" | "element" => species = Some(values[0]),
+                "pos" | "positions" => {
+                    let position = floats()?;
+                    particle.position_x = position[0] * ANGSTROM;
+                    particle.position_y = position[1] * ANGSTROM;
+                    particle.position_z = position[2] * ANGSTROM;
+                }
+                "velo" | "vel" | "velocities" => {
+                    let velocity = floats()?;
+                    particle.velocity_x = velocity[0] * ANGSTROM;
+                    particle.velocity_y = velocity[1] * ANGSTROM;
+                    particle.velocity_z = velocity[2] * ANGSTROM;
+                }
+                "masses" | "mass" => mass = Some(floats()?[0] * ATOMIC_MASS_UNIT),
+                _ => {}
+            }
+        }
+        if let Some(species) = species {
+            particle.id = get_species_type(species, mass)?;
+        }
+        particles.push(particle);
+    }
+    let (boundary_box, tilt, boundary_conditions) = if let Some(lattice) = header.get("lattice") {
+        let lattice = parse_floats(lattice)?;
+        if lattice.len() != 9 {
+            return Err(invalid_data("Lattice should have 9 numbers"));
+        }
+        // Lattice vectors are rows in header and columns in cell matrix
+        let cell = Matrix3::from_column_slice(&lattice) * ANGSTROM;
+        let (boundary_box, tilt, rotation) = get_box_from_cell(&cell)
+            .ok_or_else(|| invalid_data("Lattice vectors are degenerate"))?;
+        let origin = match header.get("origin") {
+            Some(origin) => Vector3::from_iterator(parse_floats(origin)?.into_iter().take(3)) * ANGSTROM,
+            None => Vector3::zeros(),
+        };
+        transform_particles(&mut particles, &origin, &rotation);
+        let pbc: Vec<bool> = header.get("pbc").map_or(vec![true; 3], |pbc| {
+            pbc.split_whitespace().map(parse_flag).collect()
+        });
+        let conditions = match header.get("boundary_conditions") {
+            Some(conditions) => parse_boundary_conditions(conditions)?,
+            None => [0, 1, 2].map(|axis| {
+                if pbc.get(axis).copied().unwrap_or(true) {
+                    BoundaryCondition::Periodic
+                } else {
+                    BoundaryCondition::Open
+                }
+            }),
+        };
+        (boundary_box, tilt, conditions)
+    } else {
+        (fit_bounding_box(&mut particles), Vector3::zeros(), [BoundaryCondition::Open; 3])
+    };
+    Ok(Some(StateToSave {
+        particles,
+        boundary_box,
+        tilt,
+        boundary_conditions,
+        thermostat: vec![],
+        barostat: vec![],
+    }))
+}
+
+/// Reads all frames of XYZ or extended XYZ file (see [read_xyz])
+pub fn read_xyz_frames(reader: &mut impl BufRead) -> Result<Vec<StateToSave>> {
+    let mut frames = vec![];
+    while let Some(frame) = read_xyz(reader)? {
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+/// Writes frame to XYZ file. Plain XYZ has only species and positions. Extended XYZ also has
+/// `Lattice`, `pbc`, velocities and masses (in atomic mass units) of particles. Reflective walls
+/// can't be set by `pbc`, so boundary conditions are also written to `boundary_conditions` key.
+/// Other units are the same as in [read_xyz].
+pub fn write_xyz(writer: &mut impl Write, data: &StateToSave, extended: bool) -> Result<()> {
+    writeln!(writer, "{}", data.particles.len())?;
+    if extended {
+        let cell = get_cell_matrix(&data.boundary_box, &data.tilt) / ANGSTROM;
+        let lattice: Vec<String> = cell.as_slice().iter().map(|x| x.to_string()).collect();
+        let pbc: Vec<&str> = data.boundary_conditions.iter().map(|condition| {
+            if *condition == BoundaryCondition::Periodic { "T" } else { "F" }
+        }).collect();
+        let conditions: Vec<&str> = data.boundary_conditions.iter()
+            .map(|condition| boundary_condition_name(*condition))
+            .collect();
+        writeln!(writer, "Lattice=\"{}\" Properties=species:S:1:pos:R:3:velo:R:3:masses:R:1 pbc=\"{}\" boundary_conditions=\"{}\"",
+                 lattice.join(" "), pbc.join(" "), conditions.join(" "))?;
+    } else {
+        writeln!(writer, "Generated by moldyn")?;
+    }
+    for particle in data.particles.iter() {
+        write!(writer, "{} {} {} {}", get_particle_type_name(particle.id),
+               particle.position_x / ANGSTROM, particle.position_y / ANGSTROM,
+               particle.position_z / ANGSTROM)?;
+        if extended {
+            let mass = ParticleDatabase::get_particle_mass(particle.id).unwrap_or(0.0) / ATOMIC_MASS_UNIT;
+            write!(writer, " {} {} {} {}", particle.velocity_x / ANGSTROM,
+                   particle.velocity_y / ANGSTROM, particle.velocity_z / ANGSTROM, mass)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
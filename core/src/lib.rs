@@ -1,31 +1,37 @@
 mod particle;
 mod particles_database;
 mod save_data;
+mod trajectory;
+pub mod formats;
 
 extern crate lazy_static;
 extern crate nalgebra as na;
 extern crate serde;
+extern crate flate2;
 
 use std::fs::{File, OpenOptions};
 use std::path::PathBuf;
 pub use particle::*;
 pub use particles_database::*;
 pub use save_data::*;
+pub use trajectory::*;
 
 pub const K_B: f64 = 1.380648528;
 
+/// Opens file to write it from the beginning. Old content is removed, so shorter data doesn't
+/// leave the end of previous one.
 pub fn open_file_or_create(path: &PathBuf) -> File {
     if !path.exists() {
         File::create(path)
     } else {
-        OpenOptions::new().write(true).open(path)
+        OpenOptions::new().write(true).truncate(true).open(path)
     }.expect("Can't write to file")
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Particle, ParticleDatabase, ParticleToSave, State, StateToSave};
-    use na::Vector3;
+    use crate::{BoundaryCondition, DataFileMacro, MacroParameterType, Particle, ParticleDatabase, ParticleToSave, State, StateToSave, ThermostatLinkToSave, BoxVelocityToSave, TrajectoryCompression, TrajectoryPrecision, TrajectoryReader, TrajectoryWriter, convert_to_trajectory, formats};
+    use na::{Matrix3, Vector3};
     use rand::Rng;
     use std::path::Path;
 
@@ -58,6 +64,8 @@ mod tests {
         let state = State {
             particles: vec![vec![test_particle(), test_particle()]],
             boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
         };
         let state_data_to_save = StateToSave::from(&state);
         let serialized = serde_json::to_string(&state_data_to_save).unwrap();
@@ -137,9 +145,635 @@ mod tests {
         let mut state = State {
             particles: vec![vec![p]],
             boundary_box: Vector3::new(1.0, 1.0, 1.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
         };
         assert!(!check_boundary_conditions(&state));
         state.apply_boundary_conditions();
         assert!(check_boundary_conditions(&state));
     }
+
+    #[test]
+    fn reduce_tilt() {
+        let is_reduced = |state: &State| {
+            let bb = &state.boundary_box;
+            let tilt = &state.tilt;
+            tilt.x.abs() <= bb.x / 2.0 && tilt.y.abs() <= bb.x / 2.0 && tilt.z.abs() <= bb.y / 2.0
+        };
+        let particles: Vec<Particle> = [(0.1, 0.2, 0.3), (1.9, 1.5, 0.4), (0.7, 1.1, 1.8)].iter()
+            .map(|&(x, y, z)| Particle {
+                position: Vector3::new(x, y, z),
+                image: Vector3::new(1, -2, 3),
+                ..Default::default()
+            })
+            .collect();
+        let mut state = State {
+            particles: vec![particles],
+            boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            tilt: Vector3::new(1.3, -2.6, 3.1),
+            boundary_conditions: Default::default(),
+        };
+        let unwrapped: Vec<Vector3<f64>> = state.particles[0].iter()
+            .map(|particle| state.get_unwrapped_position(particle))
+            .collect();
+        assert!(state.reduce_tilt());
+        assert!(is_reduced(&state));
+        assert!((state.tilt - Vector3::new(-0.7, 0.8, -0.9)).norm() < 1e-12);
+        for (particle, unwrapped) in state.particles[0].iter().zip(unwrapped.iter()) {
+            assert!((state.get_unwrapped_position(particle) - unwrapped).norm() < 1e-12);
+            assert!(state.to_fractional(&particle.position).iter().all(|s| (0.0..1.0).contains(s)));
+        }
+        assert!(!state.reduce_tilt());
+        // Tilt with non-periodic axis isn't changed
+        state.tilt.x = 1.5;
+        state.boundary_conditions[0] = BoundaryCondition::Open;
+        assert!(!state.reduce_tilt());
+
+        // Cell from lattice vectors keeps distances between particles
+        let cell = Matrix3::new(0.0, 1.0, 1.0,
+                                1.0, 0.0, 3.0,
+                                1.0, 1.0, 0.0) * 0.5;
+        let positions = [Vector3::new(0.1, 0.2, 0.3), Vector3::new(0.4, 0.6, 0.2)];
+        let particles = positions.iter()
+            .map(|position| Particle { position: *position, ..Default::default() })
+            .collect();
+        let state = State::from_cell(vec![particles], &cell, Default::default()).unwrap();
+        assert!((state.get_volume() - cell.determinant().abs()).abs() < 1e-12);
+        assert!(is_reduced(&state));
+        let r = state.particles[0][1].position - state.particles[0][0].position;
+        assert!((r.norm() - (positions[1] - positions[0]).norm()).abs() < 1e-12);
+        assert!(State::from_cell(vec![], &Matrix3::zeros(), Default::default()).is_none());
+    }
+
+    #[test]
+    fn triclinic_boundary_conditions() {
+        let p = Particle {
+            position: Vector3::new(-0.3, 2.7, -0.2),
+            ..Default::default()
+        };
+        let mut state = State {
+            particles: vec![vec![p]],
+            boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            tilt: Vector3::new(0.5, -0.4, 0.8),
+            boundary_conditions: Default::default(),
+        };
+        let position = state.particles[0][0].position;
+        let fractional = state.to_fractional(&position);
+        assert!((state.from_fractional(&fractional) - position).norm() < 1e-12);
+        state.apply_boundary_conditions();
+        let wrapped = state.particles[0][0].position;
+        let wrapped_fractional = state.to_fractional(&wrapped);
+        assert!(wrapped_fractional.iter().all(|s| (0.0..1.0).contains(s)));
+        // Position is moved by integer number of cell vectors
+        let shift = fractional - wrapped_fractional;
+        assert!((shift - shift.map(f64::round)).norm() < 1e-12);
+        // Crossings are counted, so unwrapped position is the initial one
+        assert_eq!(state.particles[0][0].image, shift.map(|s| s.round() as i32));
+        assert!((state.get_unwrapped_position(&state.particles[0][0]) - position).norm() < 1e-12);
+        let p = Particle {
+            position: Vector3::new(-1e-17, 0.0, 0.0),
+            ..Default::default()
+        };
+        let mut state = State {
+            particles: vec![vec![p]],
+            boundary_box: Vector3::new(1.0, 1.0, 1.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
+        };
+        state.apply_boundary_conditions();
+        assert!(check_boundary_conditions(&state));
+    }
+
+    #[test]
+    fn per_axis_boundary_conditions() {
+        let p = Particle {
+            position: Vector3::new(5.3, -0.3, 2.4),
+            velocity: Vector3::new(1.0, -1.0, 1.0),
+            ..Default::default()
+        };
+        let p_open = Particle {
+            position: Vector3::new(-7.25, 1.5, -0.5),
+            velocity: Vector3::new(-1.0, 1.0, -1.0),
+            ..Default::default()
+        };
+        let mut state = State {
+            particles: vec![vec![p, p_open]],
+            boundary_box: Vector3::new(1.0, 1.0, 1.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: [BoundaryCondition::Periodic, BoundaryCondition::Reflective,
+                BoundaryCondition::Reflective],
+        };
+        state.apply_boundary_conditions();
+        let p = &state.particles[0][0];
+        assert!((p.position - Vector3::new(0.3, 0.3, 0.4)).norm() < 1e-12);
+        // Odd number of reflections along y and even along z
+        assert_eq!(p.velocity, Vector3::new(1.0, 1.0, 1.0));
+        state.boundary_conditions = [BoundaryCondition::Open; 3];
+        state.particles[0][1].position = Vector3::new(-7.25, 1.5, -0.5);
+        state.particles[0][1].velocity = Vector3::new(-1.0, 1.0, -1.0);
+        state.apply_boundary_conditions();
+        assert_eq!(state.particles[0][1].position, Vector3::new(-7.25, 1.5, -0.5));
+        assert_eq!(state.particles[0][1].velocity, Vector3::new(-1.0, 1.0, -1.0));
+    }
+
+    #[test]
+    fn save_load_triclinic_cell() {
+        let dir = TempDir::new("test_data").expect("Can't create temp directory");
+        let state = State {
+            particles: vec![vec![test_particle()]],
+            boundary_box: Vector3::new(2.0, 3.0, 4.0),
+            tilt: Vector3::new(0.5, -0.25, 1.0 / 3.0),
+            boundary_conditions: [BoundaryCondition::Periodic, BoundaryCondition::Periodic,
+                BoundaryCondition::Reflective],
+        };
+        StateToSave::from(&state).save_to_file(dir.path(), 0);
+        let loaded: State = StateToSave::load_from_file(dir.path(), 0).into();
+        assert_eq!(loaded.boundary_box, state.boundary_box);
+        assert_eq!(loaded.tilt, state.tilt);
+        assert_eq!(loaded.boundary_conditions, state.boundary_conditions);
+        // Files without tilt columns are loaded as rectangular boxes
+        std::fs::write(dir.path().join("bb.csv"), "x,y,z\n2.0,3.0,4.0\n").unwrap();
+        let loaded: State = StateToSave::load_from_file(dir.path(), 0).into();
+        assert_eq!(loaded.boundary_box, state.boundary_box);
+        assert!(!loaded.is_triclinic());
+        assert_eq!(loaded.boundary_conditions, [BoundaryCondition::Periodic; 3]);
+    }
+
+    #[test]
+    fn save_load_macro_parameters() {
+        let dir = TempDir::new("test_data").expect("Can't create temp directory");
+        let file_path = dir.path().join("macro.csv");
+        let mut data = DataFileMacro::new();
+        let pressure_tensor = Matrix3::new(1.0, 0.4, 0.5,
+                                           0.4, 2.0, 0.6,
+                                           0.5, 0.6, 3.0);
+        data.add_macro_params(0, &[MacroParameterType::Pressure(2.0),
+            MacroParameterType::PressureTensor(pressure_tensor)], 2);
+        data.add_macro_params(1, &[MacroParameterType::Pressure(1.0)], 2);
+        data.save_to_file(&file_path);
+        let loaded = DataFileMacro::load_from_file(&file_path);
+        let frame = &loaded.macro_parameters[&0];
+        assert_eq!(frame.pressure, 2.0);
+        assert_eq!(frame.pressure_xx, 1.0);
+        assert_eq!(frame.pressure_yy, 2.0);
+        assert_eq!(frame.pressure_zz, 3.0);
+        assert_eq!(frame.pressure_xy, 0.4);
+        assert_eq!(frame.pressure_xz, 0.5);
+        assert_eq!(frame.pressure_yz, 0.6);
+        assert_eq!(loaded.macro_parameters[&1].pressure_xx, 0.0);
+    }
+
+    #[test]
+    fn save_load_species_macro_parameters() {
+        let dir = TempDir::new("test_data").expect("Can't create temp directory");
+        let file_path = dir.path().join("macro.csv");
+        let mut data = DataFileMacro::new();
+        data.add_macro_params(0, &[MacroParameterType::Temperature(90.0)], 3);
+        data.add_species_macro_params(0, 0, &[MacroParameterType::Temperature(80.0)], 2);
+        data.add_species_macro_params(0, 1, &[MacroParameterType::Temperature(110.0)], 1);
+        data.save_to_file(&file_path);
+        assert!(dir.path().join("macro_0.csv").is_file());
+        assert!(dir.path().join("macro_1.csv").is_file());
+        let loaded = DataFileMacro::load_from_file(&file_path);
+        assert_eq!(loaded.macro_parameters[&0].temperature, 90.0);
+        assert_eq!(loaded.species_macro_parameters.len(), 2);
+        assert_eq!(loaded.species_macro_parameters[&0][&0].temperature, 80.0);
+        assert_eq!(loaded.species_macro_parameters[&1][&0].temperature, 110.0);
+    }
+
+    #[test]
+    fn save_load_extended_variables() {
+        let dir = TempDir::new("test_data").expect("Can't create temp directory");
+        let state = State {
+            particles: vec![vec![test_particle(), test_particle()]],
+            boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
+        };
+        let mut data = StateToSave::from(&state);
+        data.save_to_file(dir.path(), 0);
+        data.thermostat = (0..3).map(|link| ThermostatLinkToSave {
+            particle_type: 0,
+            link,
+            position: 0.1 * link as f64 + 1.0 / 3.0,
+            velocity: -0.7 / (link as f64 + 1.0),
+            mass: 12.5,
+            temperature: 120.0,
+        }).collect();
+        data.barostat = vec![BoxVelocityToSave {
+            axes: String::from("xy"),
+            velocity: 0.01 / 3.0,
+            mass: 1e5,
+            pressure: 1.5,
+        }, BoxVelocityToSave {
+            axes: String::from("z"),
+            velocity: -0.02,
+            mass: 1e5,
+            pressure: 1.5,
+        }];
+        data.save_to_file(dir.path(), 1);
+        let loaded = StateToSave::load_from_file(dir.path(), 0);
+        assert!(loaded.thermostat.is_empty());
+        assert!(loaded.barostat.is_empty());
+        let loaded = StateToSave::load_from_file(dir.path(), 1);
+        assert_eq!(loaded.thermostat, data.thermostat);
+        assert_eq!(loaded.barostat, data.barostat);
+    }
+
+    fn random_frame(rng: &mut impl Rng, particles_count: usize) -> StateToSave {
+        let particles = (0..particles_count).map(|i| ParticleToSave {
+            id: (i % 2) as u16,
+            position_x: rng.gen::<f64>() * 3.0,
+            position_y: rng.gen::<f64>() * 3.0,
+            position_z: rng.gen::<f64>() * 3.0,
+            velocity_x: rng.gen::<f64>() - 0.5,
+            velocity_y: rng.gen::<f64>() - 0.5,
+            velocity_z: rng.gen::<f64>() - 0.5,
+            image_x: rng.gen_range(-3..3),
+            image_y: rng.gen_range(-3..3),
+            image_z: 0,
+        }).collect();
+        StateToSave {
+            particles,
+            boundary_box: Vector3::new(3.0, 3.0, 3.0 + rng.gen::<f64>()),
+            tilt: Vector3::new(0.3, 0.0, -0.1),
+            boundary_conditions: [BoundaryCondition::Periodic, BoundaryCondition::Periodic,
+                BoundaryCondition::Reflective],
+            thermostat: vec![ThermostatLinkToSave {
+                particle_type: 1,
+                link: 2,
+                position: rng.gen(),
+                velocity: rng.gen(),
+                mass: 12.5,
+                temperature: 120.0,
+            }],
+            barostat: vec![BoxVelocityToSave {
+                axes: String::from("xy"),
+                velocity: rng.gen(),
+                mass: 1e5,
+                pressure: 1.5,
+            }],
+        }
+    }
+
+    fn check_frame_equality(frame: &StateToSave, loaded: &StateToSave, tolerance: f64) {
+        assert_eq!(frame.boundary_box, loaded.boundary_box);
+        assert_eq!(frame.tilt, loaded.tilt);
+        assert_eq!(frame.boundary_conditions, loaded.boundary_conditions);
+        assert_eq!(frame.thermostat, loaded.thermostat);
+        assert_eq!(frame.barostat, loaded.barostat);
+        assert_eq!(frame.particles.len(), loaded.particles.len());
+        for (p1, p2) in frame.particles.iter().zip(loaded.particles.iter()) {
+            assert_eq!(p1.id, p2.id);
+            assert_eq!((p1.image_x, p1.image_y, p1.image_z), (p2.image_x, p2.image_y, p2.image_z));
+            for (x1, x2) in [(p1.position_x, p2.position_x), (p1.position_y, p2.position_y),
+                (p1.position_z, p2.position_z), (p1.velocity_x, p2.velocity_x),
+                (p1.velocity_y, p2.velocity_y), (p1.velocity_z, p2.velocity_z)] {
+                assert!((x1 - x2).abs() <= tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn trajectory_write_read() {
+        let dir = TempDir::new("test_data").expect("Can't create temp directory");
+        let mut rng = rand::thread_rng();
+        let frames: Vec<StateToSave> = (0..5).map(|_| random_frame(&mut rng, 20)).collect();
+        for (precision, compression, tolerance) in [
+            (TrajectoryPrecision::F64, TrajectoryCompression::None, 0.0),
+            (TrajectoryPrecision::F32, TrajectoryCompression::Deflate, 1e-6)] {
+            let path = dir.path().join("trajectory.bin");
+            let mut writer = TrajectoryWriter::create(&path, precision, compression).unwrap();
+            for frame in frames[..3].iter() {
+                writer.write_frame(frame).unwrap();
+            }
+            drop(writer);
+            let mut writer = TrajectoryWriter::open(&path).unwrap();
+            assert_eq!(writer.get_frames_count(), 3);
+            for frame in frames[3..].iter() {
+                writer.write_frame(frame).unwrap();
+            }
+            writer.flush().unwrap();
+            let mut reader = TrajectoryReader::open(&path).unwrap();
+            assert_eq!(reader.get_precision(), precision);
+            assert_eq!(reader.get_compression(), compression);
+            assert_eq!(reader.get_frames_count(), 5);
+            for i in [4, 0, 2, 1, 3] {
+                check_frame_equality(&frames[i], &reader.read_frame(i).unwrap(), tolerance);
+            }
+            assert!(reader.read_frame(5).is_err());
+            // Continue from frame 2, later frames are replaced
+            let mut writer = TrajectoryWriter::open_at(&path, 2).unwrap();
+            assert_eq!(writer.get_frames_count(), 2);
+            writer.write_frame(&frames[4]).unwrap();
+            drop(writer);
+            let mut reader = TrajectoryReader::open(&path).unwrap();
+            assert_eq!(reader.get_frames_count(), 3);
+            check_frame_equality(&frames[1], &reader.read_frame(1).unwrap(), tolerance);
+            check_frame_equality(&frames[4], &reader.read_frame(2).unwrap(), tolerance);
+        }
+    }
+
+    #[test]
+    fn trajectory_incomplete_frame() {
+        let dir = TempDir::new("test_data").expect("Can't create temp directory");
+        let path = dir.path().join("trajectory.bin");
+        let mut rng = rand::thread_rng();
+        let mut writer = TrajectoryWriter::create(&path, TrajectoryPrecision::F64,
+                                                  TrajectoryCompression::None).unwrap();
+        for _ in 0..2 {
+            writer.write_frame(&random_frame(&mut rng, 10)).unwrap();
+        }
+        drop(writer);
+        // Simulate crash during writing of last frame
+        let size = std::fs::metadata(&path).unwrap().len();
+        std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(size - 7).unwrap();
+        assert_eq!(TrajectoryReader::open(&path).unwrap().get_frames_count(), 1);
+        let mut writer = TrajectoryWriter::open(&path).unwrap();
+        let frame = random_frame(&mut rng, 10);
+        writer.write_frame(&frame).unwrap();
+        drop(writer);
+        let mut reader = TrajectoryReader::open(&path).unwrap();
+        assert_eq!(reader.get_frames_count(), 2);
+        check_frame_equality(&frame, &reader.read_frame(1).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn trajectory_version_1() {
+        // Frame of version 1 has no particle images
+        let mut frame: Vec<u8> = vec![];
+        for value in [1.0f64, 1.0, 1.0, 0.0, 0.0, 0.0] {
+            frame.extend_from_slice(&value.to_le_bytes());
+        }
+        frame.extend_from_slice(&[0, 0, 0]);
+        frame.extend_from_slice(&1u64.to_le_bytes());
+        frame.extend_from_slice(&0u16.to_le_bytes());
+        for value in [0.5f64, 0.5, 0.5, 1.0, 0.0, 0.0] {
+            frame.extend_from_slice(&value.to_le_bytes());
+        }
+        frame.extend_from_slice(&0u64.to_le_bytes());
+        frame.extend_from_slice(&0u64.to_le_bytes());
+        let mut file = b"MOLDYNTR".to_vec();
+        file.extend_from_slice(&[1, 8, 0, 0]);
+        file.extend_from_slice(&(frame.len() as u64).to_le_bytes());
+        file.extend_from_slice(&frame);
+        let dir = TempDir::new("test_data").expect("Can't create temp directory");
+        let path = dir.path().join("trajectory.bin");
+        std::fs::write(&path, &file).unwrap();
+        let mut writer = TrajectoryWriter::open(&path).unwrap();
+        let mut appended = random_frame(&mut rand::thread_rng(), 3);
+        // Frames are appended in version of the file, so images are lost
+        appended.particles.iter_mut().for_each(|particle| (particle.image_x, particle.image_y) = (0, 0));
+        writer.write_frame(&appended).unwrap();
+        drop(writer);
+        let mut reader = TrajectoryReader::open(&path).unwrap();
+        assert_eq!(reader.get_frames_count(), 2);
+        let loaded = reader.read_frame(0).unwrap();
+        assert_eq!(loaded.particles[0].velocity_x, 1.0);
+        assert_eq!(loaded.particles[0].image_x, 0);
+        check_frame_equality(&appended, &reader.read_frame(1).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn convert_directory_to_trajectory() {
+        let dir = TempDir::new("test_data").expect("Can't create temp directory");
+        let mut rng = rand::thread_rng();
+        let frames: Vec<StateToSave> = (0..3).map(|_| {
+            let mut frame = random_frame(&mut rng, 4);
+            frame.particles.iter_mut().for_each(|particle| particle.id = 3);
+            frame
+        }).collect();
+        for (i, frame) in frames.iter().enumerate() {
+            frame.save_to_file(dir.path(), i);
+        }
+        let path = dir.path().join("trajectory.bin");
+        let frames_count = convert_to_trajectory(dir.path(), &path, TrajectoryPrecision::F64,
+                                                 TrajectoryCompression::Deflate).unwrap();
+        assert_eq!(frames_count, 3);
+        let mut reader = TrajectoryReader::open(&path).unwrap();
+        for (i, frame) in frames.iter().enumerate() {
+            check_frame_equality(frame, &reader.read_frame(i).unwrap(), 0.0);
+        }
+    }
+
+    #[test]
+    fn xyz_write_read() {
+        let mut rng = rand::thread_rng();
+        let mut frames: Vec<StateToSave> = (0..2).map(|_| random_frame(&mut rng, 6)).collect();
+        for frame in frames.iter_mut() {
+            // XYZ has no image flags
+            frame.particles.iter_mut().for_each(|particle| {
+                particle.id = 3;
+                (particle.image_x, particle.image_y) = (0, 0);
+            });
+            frame.boundary_conditions[1] = BoundaryCondition::Reflective;
+            frame.boundary_conditions[2] = BoundaryCondition::Open;
+            frame.thermostat.clear();
+            frame.barostat.clear();
+        }
+        let _ = test_particle();
+        let mut buffer = vec![];
+        for frame in frames.iter() {
+            formats::write_xyz(&mut buffer, frame, true).unwrap();
+        }
+        let loaded = formats::read_xyz_frames(&mut &buffer[..]).unwrap();
+        assert_eq!(loaded.len(), 2);
+        for (frame, loaded) in frames.iter().zip(loaded.iter()) {
+            assert!((frame.boundary_box - loaded.boundary_box).norm() < 1e-12);
+            assert!((frame.tilt - loaded.tilt).norm() < 1e-12);
+            assert_eq!(frame.boundary_conditions, loaded.boundary_conditions);
+            let loaded = StateToSave {
+                boundary_box: frame.boundary_box,
+                tilt: frame.tilt,
+                ..loaded.clone()
+            };
+            check_frame_equality(frame, &loaded, 1e-12);
+        }
+    }
+
+    #[test]
+    fn xyz_rotated_lattice() {
+        let _ = test_particle();
+        let text = "1\n\
+            Lattice=\"0.0 10.0 0.0 -10.0 0.0 0.0 0.0 0.0 10.0\" Origin=\"1 1 1\" \
+            Properties=species:S:1:pos:R:3:forces:R:3:velo:R:3 pbc=\"T T F\"\n\
+            test 2.0 6.0 4.0 0.5 0.5 0.5 1.0 0.0 0.0\n";
+        let frame = formats::read_xyz(&mut text.as_bytes()).unwrap().unwrap();
+        assert!((frame.boundary_box - Vector3::new(1.0, 1.0, 1.0)).norm() < 1e-12);
+        assert!(frame.tilt.norm() < 1e-12);
+        assert_eq!(frame.boundary_conditions, [BoundaryCondition::Periodic, BoundaryCondition::Periodic,
+            BoundaryCondition::Open]);
+        let particle = &frame.particles[0];
+        assert_eq!(particle.id, 3);
+        let position = Vector3::new(particle.position_x, particle.position_y, particle.position_z);
+        let velocity = Vector3::new(particle.velocity_x, particle.velocity_y, particle.velocity_z);
+        assert!((position - Vector3::new(0.5, -0.1, 0.3)).norm() < 1e-12);
+        assert!((velocity - Vector3::new(0.0, -0.1, 0.0)).norm() < 1e-12);
+        // Plain XYZ: box is bounding box of particles, types can be set by id
+        let text = "2\ncomment\ntest 1 2 3\n3 4 5 6\n";
+        let frame = formats::read_xyz(&mut text.as_bytes()).unwrap().unwrap();
+        assert!((frame.boundary_box - Vector3::new(0.3, 0.3, 0.3)).norm() < 1e-12);
+        assert_eq!(frame.boundary_conditions, [BoundaryCondition::Open; 3]);
+        assert_eq!(frame.particles[1].id, 3);
+        assert_eq!(frame.particles[0].position_x, 0.0);
+        assert!(formats::read_xyz(&mut "1\nc\nunknown_particle 0 0 0\n".as_bytes()).is_err());
+        // Unknown species is added with its mass, plain pbc has no reflective walls
+        let text = "1\nLattice=\"10 0 0 0 10 0 0 0 10\" Properties=species:S:1:pos:R:3:masses:R:1 \
+            pbc=\"T F F\"\nxyz_mass_test 1 2 3 12.5\n";
+        let frame = formats::read_xyz(&mut text.as_bytes()).unwrap().unwrap();
+        assert_eq!(frame.boundary_conditions, [BoundaryCondition::Periodic, BoundaryCondition::Open,
+            BoundaryCondition::Open]);
+        let id = ParticleDatabase::get_particle_id("xyz_mass_test").unwrap();
+        assert_eq!(frame.particles[0].id, id);
+        assert!((ParticleDatabase::get_particle_mass(id).unwrap() - 12.5 * formats::ATOMIC_MASS_UNIT).abs() < 1e-12);
+    }
+
+    #[test]
+    fn lammps_data_and_dump() {
+        let text = "LAMMPS data file via write_data\n\n\
+            2 atoms\n7 atom types\n\n\
+            -1.0 9.0 xlo xhi\n0.0 10.0 ylo yhi\n0.0 20.0 zlo zhi\n2.0 0.0 0.0 xy xz yz\n\n\
+            Masses\n\n6 39.948 # lammps_ar\n7 20.18 # lammps_ne\n\n\
+            Pair Coeffs # lj/cut\n\n6 0.0104 3.4\n7 0.0031 2.8\n\n\
+            Atoms # atomic\n\n2 7 4.0 5.0 6.0 0 0 0\n1 6 0.0 1.0 2.0 0 0 1\n\n\
+            Velocities\n\n1 10.0 0.0 0.0\n2 -5.0 0.0 0.0\n";
+        let frame = formats::read_lammps_data(&mut text.as_bytes(), formats::LammpsUnits::Metal).unwrap();
+        assert!((frame.boundary_box - Vector3::new(1.0, 1.0, 2.0)).norm() < 1e-12);
+        assert!((frame.tilt - Vector3::new(0.2, 0.0, 0.0)).norm() < 1e-12);
+        assert_eq!(frame.particles.len(), 2);
+        let (first, second) = (&frame.particles[0], &frame.particles[1]);
+        assert_eq!((first.id, second.id), (5, 6));
+        assert!((first.position_x - 0.1).abs() < 1e-12 && (first.position_z - 0.2).abs() < 1e-12);
+        assert!((second.position_x - 0.5).abs() < 1e-12);
+        assert_eq!((first.image_x, first.image_y, first.image_z), (0, 0, 1));
+        assert!((first.velocity_x - 1.0).abs() < 1e-12 && (second.velocity_x + 0.5).abs() < 1e-12);
+        assert_eq!(ParticleDatabase::get_particle_name(5).unwrap(), "lammps_ar");
+        let mass_ar = ParticleDatabase::get_particle_mass(5).unwrap();
+        let mass_ne = ParticleDatabase::get_particle_mass(6).unwrap();
+        assert!((mass_ar - 39.948 * formats::ATOMIC_MASS_UNIT).abs() < 1e-12);
+        // Temperature with program units and the 0.01 scale of k_B T is the same as in SI
+        let kinetic = mass_ar * first.velocity_x.powi(2) + mass_ne * second.velocity_x.powi(2);
+        let temperature = kinetic / (6.0 * crate::K_B * 0.01);
+        let amu = 1.66053906660e-27;
+        let kinetic_si = 39.948 * amu * 1000.0f64.powi(2) + 20.18 * amu * 500.0f64.powi(2);
+        let temperature_si = kinetic_si / (6.0 * 1.380648528e-23);
+        assert!((temperature - temperature_si).abs() < 1e-9 * temperature_si);
+        let mut dump = vec![];
+        formats::write_lammps_dump(&mut dump, &frame, 10, formats::LammpsUnits::Real).unwrap();
+        let dump = String::from_utf8(dump).unwrap();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines[1], "10");
+        assert_eq!(lines[3], "2");
+        assert_eq!(lines[4], "ITEM: BOX BOUNDS xy xz yz pp pp pp");
+        assert_eq!(lines[8], "ITEM: ATOMS id type element x y z vx vy vz ix iy iz");
+        assert!(lines[9].ends_with(" 0 0 1"));
+        let values: Vec<f64> = lines[9].split_whitespace().skip(3)
+            .map(|x| x.parse().unwrap()).collect();
+        assert_eq!(lines[9].split_whitespace().nth(1), Some("6"));
+        assert!((values[0] - 1.0).abs() < 1e-9 && (values[3] - 0.01).abs() < 1e-12);
+        assert!(formats::read_lammps_data(&mut "title\n\n3 atoms\n".as_bytes(),
+                                          formats::LammpsUnits::Metal).is_err());
+    }
+
+    #[test]
+    fn elements() {
+        let (mass, radius) = formats::get_element_data("fe").unwrap();
+        assert!((mass - 55.845 * formats::ATOMIC_MASS_UNIT).abs() < 1e-12);
+        assert!((radius - 0.116).abs() < 1e-12);
+        // Elements without stable isotopes and the last one are there too
+        assert!(["Tc", "Pm", "Po", "Og"].iter().all(|symbol| formats::get_element_data(symbol).is_some()));
+        assert!(formats::get_element_data("Xx").is_none());
+    }
+
+    /// Adds elements with ids that aren't used by other tests, so readers don't add them with
+    /// the smallest free ids
+    fn test_elements(first_id: u16, elements: &[&str]) {
+        for (i, element) in elements.iter().enumerate() {
+            let (mass, radius) = formats::get_element_data(element).unwrap();
+            ParticleDatabase::add(first_id + i as u16, element, mass, radius);
+        }
+    }
+
+    #[test]
+    fn pdb_read() {
+        test_elements(20, &["N", "C", "Cl"]);
+        ParticleDatabase::add(25, "tst", 2.0, 0.2);
+        let atom = |record: &str, number: usize, name: &str, residue: &str, position: [f64; 3], element: &str| {
+            format!("{record:<6}{number:>5} {name:<4} {residue:>3} A{number:>4}    {:8.3}{:8.3}{:8.3}  1.00  0.00          {element:>2}\n",
+                    position[0], position[1], position[2])
+        };
+        let text = String::from("CRYST1   20.000   20.000   30.000  90.00  90.00 120.00 P 1           1\n")
+            + &atom("ATOM", 1, " N", "ALA", [11.104, 6.134, -6.504], "N")
+            + &atom("ATOM", 2, " CA", "ALA", [11.639, 6.071, 5.147], "C")
+            + &atom("HETATM", 3, "CL", "CL", [1.0, 2.0, 3.0], "")
+            + &atom("HETATM", 4, "tst", "TST", [5.0, 5.0, 25.0], "")
+            + "END\n";
+        let frames = formats::read_pdb(&mut text.as_bytes()).unwrap();
+        assert_eq!(frames.len(), 1);
+        let frame = &frames[0];
+        assert!((frame.boundary_box - Vector3::new(2.0, 3.0f64.sqrt(), 3.0)).norm() < 1e-9);
+        assert!((frame.tilt - Vector3::new(-1.0, 0.0, 0.0)).norm() < 1e-9);
+        assert_eq!(frame.boundary_conditions, [BoundaryCondition::Periodic; 3]);
+        let names: Vec<String> = frame.particles.iter()
+            .map(|particle| ParticleDatabase::get_particle_name(particle.id).unwrap()).collect();
+        assert_eq!(names, ["N", "C", "Cl", "tst"]);
+        let mass = ParticleDatabase::get_particle_mass(frame.particles[0].id).unwrap();
+        assert!((mass - 14.007 * formats::ATOMIC_MASS_UNIT).abs() < 1e-9);
+        // Negative z is wrapped into cell
+        assert!((frame.particles[0].position_z - 2.3496).abs() < 1e-9);
+        assert!((frame.particles[2].position_x - 0.1).abs() < 1e-9);
+        // Without cell box is bounding box of particles
+        let text = atom("ATOM", 1, "tst", "TST", [1.0, 1.0, 1.0], "")
+            + &atom("ATOM", 2, "tst", "TST", [2.0, 3.0, 4.0], "");
+        let frames = formats::read_pdb(&mut text.as_bytes()).unwrap();
+        assert!((frames[0].boundary_box - Vector3::new(0.1, 0.2, 0.3)).norm() < 1e-9);
+        assert_eq!(frames[0].boundary_conditions, [BoundaryCondition::Open; 3]);
+        assert!(formats::read_pdb(&mut atom("ATOM", 1, "Q1", "UNK", [0.0; 3], "Qq").as_bytes()).is_err());
+    }
+
+    #[test]
+    fn gro_write_read() {
+        test_elements(30, &["O", "H", "Na"]);
+        let mut rng = rand::thread_rng();
+        let mut frame = random_frame(&mut rng, 10);
+        frame.boundary_conditions = [BoundaryCondition::Periodic; 3];
+        frame.thermostat.clear();
+        frame.barostat.clear();
+        let cell = formats::get_cell_matrix(&frame.boundary_box, &frame.tilt);
+        for particle in frame.particles.iter_mut() {
+            particle.id = 30;
+            // GRO has no image flags
+            (particle.image_x, particle.image_y) = (0, 0);
+            // Particles aren't near cell faces, so rounding doesn't move them to other side
+            let position = cell * Vector3::from_fn(|_, _| 0.1 + 0.8 * rng.gen::<f64>());
+            particle.position_x = position.x;
+            particle.position_y = position.y;
+            particle.position_z = position.z;
+        }
+        let mut buffer = vec![];
+        formats::write_gro(&mut buffer, &frame).unwrap();
+        formats::write_gro(&mut buffer, &frame).unwrap();
+        let loaded = formats::read_gro_frames(&mut &buffer[..]).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert!((frame.boundary_box - loaded[1].boundary_box).norm() < 1e-5);
+        assert!((frame.tilt - loaded[1].tilt).norm() < 1e-5);
+        check_frame_equality(&frame, &StateToSave {
+            boundary_box: frame.boundary_box,
+            tilt: frame.tilt,
+            ..loaded[1].clone()
+        }, 1e-3);
+        // Elements are guessed from atom names of GROMACS files
+        let text = "water and ion\n    3\n\
+            \x20   1SOL     OW    1   0.126   1.624   1.679  0.1227 -0.0580  0.0434\n\
+            \x20   1SOL    HW1    2   0.190   1.661   1.747\n\
+            \x20   2NA      NA    3   2.100   0.500   0.500\n\
+            \x20  2.00000   2.00000   2.00000\n";
+        let frame = formats::read_gro(&mut text.as_bytes()).unwrap().unwrap();
+        let names: Vec<String> = frame.particles.iter()
+            .map(|particle| ParticleDatabase::get_particle_name(particle.id).unwrap()).collect();
+        assert_eq!(names, ["O", "H", "Na"]);
+        assert!((frame.particles[0].velocity_y + 0.058).abs() < 1e-12);
+        assert_eq!(frame.particles[1].velocity_x, 0.0);
+        assert!((frame.particles[2].position_x - 0.1).abs() < 1e-9);
+    }
 }
@@ -1,8 +1,10 @@
 use crate::ParticleDatabase;
-use na::Vector3;
+use crate::formats::get_box_from_cell;
+use na::{Matrix3, Vector3};
+use serde::{Deserialize, Serialize};
 
 /// Structure that keeps all data for particle
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Particle {
     /// position of particle in 3d space
     pub position: Vector3<f64>,
@@ -14,21 +16,47 @@ pub struct Particle {
     pub potential: f64,
     /// Sum of F(i,j) * r(i, j) for every other particle
     pub temp: f64,
+    /// Sum of outer products F(i,j) ⊗ r(i, j) for every other particle. Its trace is `temp`
+    pub virial: Matrix3<f64>,
     /// Mass of particle
     pub mass: f64,
     /// Radius of particle
     pub radius: f64,
     /// ID of particle. Defines type of particle
     pub id: u16,
+    /// How many times particle crossed periodic cell along cell vectors `a`, `b` and `c`.
+    /// It is used to get unwrapped position ([State::get_unwrapped_position]).
+    pub image: Vector3<i32>,
+}
+
+/// Boundary condition along one axis of cell
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum BoundaryCondition {
+    /// Particle that leaves cell enters it from the opposite side. Minimum image convention is
+    /// used for forces.
+    #[default]
+    Periodic,
+    /// Particle is elastically reflected by walls at `0` and box length
+    Reflective,
+    /// Particles can leave cell, they are not wrapped and interact only directly
+    Open,
 }
 
 /// Structure that keeps current state
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct State {
     /// Particles that exists right now
     pub particles: Vec<Vec<Particle>>,
-    /// Boundary conditions for current state
+    /// Boundary conditions for current state. For triclinic cell it keeps lengths of cell along
+    /// axes (diagonal of [State::get_cell_matrix])
     pub boundary_box: Vector3<f64>,
+    /// Tilt factors `(xy, xz, yz)` of triclinic cell like in LAMMPS. Cell vectors are
+    /// `a = (x, 0, 0)`, `b = (xy, y, 0)` and `c = (xz, yz, z)`. Zero for rectangular box.
+    /// Factors greater than half of box length are reduced by [State::reduce_tilt].
+    pub tilt: Vector3<f64>,
+    /// Boundary conditions along x, y and z. Non-periodic axes should have zero tilt factors
+    /// with other axes.
+    pub boundary_conditions: [BoundaryCondition; 3],
 }
 
 impl Particle {
@@ -72,9 +100,11 @@ impl Particle {
             force: Vector3::new(0.0, 0.0, 0.0),
             potential: 0.0,
             temp: 0.0,
+            virial: Matrix3::zeros(),
             id: particle_id,
             mass,
             radius,
+            image: Vector3::zeros(),
         })
     }
 }
@@ -89,9 +119,11 @@ impl Default for Particle {
             force: Vector3::new(0.0, 0.0, 0.0),
             potential: 0.0,
             temp: 0.0,
+            virial: Matrix3::zeros(),
             id: 0,
             mass: 1.0,
             radius: 0.1,
+            image: Vector3::zeros(),
         }
     }
 }
@@ -99,6 +131,8 @@ impl Default for Particle {
 impl Clone for State {
     fn clone(&self) -> Self {
         let boundary_box = self.boundary_box;
+        let tilt = self.tilt;
+        let boundary_conditions = self.boundary_conditions;
         let mut particles: Vec<Vec<Particle>> = vec![];
         for particle_type in &self.particles {
             let mut pt = vec![];
@@ -111,35 +145,150 @@ impl Clone for State {
         Self {
             particles,
             boundary_box,
+            tilt,
+            boundary_conditions,
         }
     }
 }
 
 impl State {
-    /// Makes every particle to satisfy periodic boundary conditions.
+    /// Creates state with cell made of lattice vectors `a`, `b` and `c` (columns of `cell`).
+    /// Cell is rotated so `a` is along x and `b` is in xy plane, positions and velocities of
+    /// `particles` are rotated with it. Tilt is reduced ([State::reduce_tilt]) and particles
+    /// are wrapped into cell.
+    ///
+    /// # Returns
+    ///
+    /// None if vectors don't make a cell
+    pub fn from_cell(mut particles: Vec<Vec<Particle>>, cell: &Matrix3<f64>,
+                     boundary_conditions: [BoundaryCondition; 3]) -> Option<Self> {
+        let (boundary_box, tilt, rotation) = get_box_from_cell(cell)?;
+        if boundary_box.iter().any(|length| !(length.is_finite() && *length > 0.0)) {
+            return None;
+        }
+        particles.iter_mut().flatten().for_each(|particle| {
+            particle.position = rotation * particle.position;
+            particle.velocity = rotation * particle.velocity;
+        });
+        let mut state = State {
+            particles,
+            boundary_box,
+            tilt,
+            boundary_conditions,
+        };
+        state.reduce_tilt();
+        state.apply_boundary_conditions();
+        Some(state)
+    }
+
+    /// Reduces tilt factors that are greater than half of corresponding box length by choosing
+    /// other vectors of the same lattice (`c - n b`, `c - n a` and `b - n a`) like flips in
+    /// LAMMPS. Minimum image convention needs it. Particles are wrapped into new cell and
+    /// their [Particle::image] are converted to new cell vectors, so unwrapped positions are
+    /// kept. Only factors between periodic axes are reduced.
+    ///
+    /// # Returns
+    ///
+    /// true if cell was changed
+    pub fn reduce_tilt(&mut self) -> bool {
+        let periodic = self.boundary_conditions.map(|condition| condition == BoundaryCondition::Periodic);
+        let old_cell = self.get_cell_matrix();
+        let bb = self.boundary_box;
+        let steps = |tilt: f64, length: f64| {
+            if tilt.abs() > length / 2.0 { (tilt / length).round() } else { 0.0 }
+        };
+        let mut changed = false;
+        if periodic[1] {
+            let n = steps(self.tilt.z, bb.y);
+            self.tilt.z -= n * bb.y;
+            self.tilt.y -= n * self.tilt.x;
+            changed |= n != 0.0;
+        }
+        if periodic[0] {
+            let n = steps(self.tilt.y, bb.x);
+            self.tilt.y -= n * bb.x;
+            let m = steps(self.tilt.x, bb.x);
+            self.tilt.x -= m * bb.x;
+            changed |= n != 0.0 || m != 0.0;
+        }
+        if !changed {
+            return false;
+        }
+        // New cell vectors are integer combinations of old ones
+        let transform = (old_cell.try_inverse().expect("Can't invert cell matrix")
+            * self.get_cell_matrix()).map(f64::round);
+        let inverse = transform.try_inverse().expect("Can't invert cell transform").map(f64::round);
+        let bb = &self.boundary_box;
+        let tilt = &self.tilt;
+        let boundary_conditions = &self.boundary_conditions;
+        self.particles.iter_mut().flatten().for_each(|particle| {
+            particle.image = (inverse * particle.image.cast::<f64>()).map(|image| image as i32);
+            particle.image += wrap_position(&mut particle.position, bb, tilt, boundary_conditions);
+        });
+        true
+    }
+
+    /// Makes every particle to satisfy boundary conditions. Periodic axes are wrapped in
+    /// fractional coordinates (so it works for triclinic cell too), on reflective axes particles
+    /// are reflected by walls. It works for any displacement of particles. Crossings of periodic
+    /// cell are counted in [Particle::image]. Tilt of cell is reduced first
+    /// ([State::reduce_tilt]).
     pub fn apply_boundary_conditions(&mut self) {
+        self.reduce_tilt();
         let bb = &self.boundary_box;
+        let tilt = &self.tilt;
+        let boundary_conditions = &self.boundary_conditions;
         self.particles.iter_mut().for_each(|particle_type| {
             particle_type.iter_mut().for_each(|particle| {
-                if particle.position.x < 0.0 {
-                    particle.position.x += bb.x;
-                } else if particle.position.x >= bb.x {
-                    particle.position.x -= bb.x;
-                }
-                if particle.position.y < 0.0 {
-                    particle.position.y += bb.y;
-                } else if particle.position.y >= bb.y {
-                    particle.position.y -= bb.y;
-                }
-                if particle.position.z < 0.0 {
-                    particle.position.z += bb.z;
-                } else if particle.position.z >= bb.z {
-                    particle.position.z -= bb.z;
+                for axis in 0..3 {
+                    if boundary_conditions[axis] == BoundaryCondition::Reflective {
+                        reflect(&mut particle.position[axis], &mut particle.velocity[axis], bb[axis]);
+                    }
                 }
+                particle.image += wrap_position(&mut particle.position, bb, tilt, boundary_conditions);
             })
         });
     }
 
+    /// Matrix with cell vectors `a`, `b` and `c` as columns (h-matrix)
+    pub fn get_cell_matrix(&self) -> Matrix3<f64> {
+        let bb = &self.boundary_box;
+        let tilt = &self.tilt;
+        Matrix3::new(bb.x, tilt.x, tilt.y,
+                     0.0, bb.y, tilt.z,
+                     0.0, 0.0, bb.z)
+    }
+
+    /// Checks if cell is not rectangular
+    pub fn is_triclinic(&self) -> bool {
+        self.tilt != Vector3::zeros()
+    }
+
+    /// Volume of cell
+    pub fn get_volume(&self) -> f64 {
+        self.boundary_box.x * self.boundary_box.y * self.boundary_box.z
+    }
+
+    /// Converts `position` to fractional coordinates of cell
+    pub fn to_fractional(&self, position: &Vector3<f64>) -> Vector3<f64> {
+        let bb = &self.boundary_box;
+        let tilt = &self.tilt;
+        let z = position.z / bb.z;
+        let y = (position.y - tilt.z * z) / bb.y;
+        let x = (position.x - tilt.x * y - tilt.y * z) / bb.x;
+        Vector3::new(x, y, z)
+    }
+
+    /// Converts fractional coordinates of cell to position
+    pub fn from_fractional(&self, fractional: &Vector3<f64>) -> Vector3<f64> {
+        self.get_cell_matrix() * fractional
+    }
+
+    /// Position of `particle` without wrapping into periodic cell
+    pub fn get_unwrapped_position(&self, particle: &Particle) -> Vector3<f64> {
+        particle.position + self.get_cell_matrix() * particle.image.cast::<f64>()
+    }
+
     /// Get minimal and maximum and maximum velocity of particles with type `particle_type_id`.
     /// > **Warning**
     /// > This function doesn't check if particle with `particle_type_id` exists!
@@ -179,6 +328,65 @@ impl Default for State {
         State {
             particles: vec![vec![p1, p2, p3]],
             boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
+        }
+    }
+}
+
+/// Reflects `position` (and `velocity`) along one axis from walls at `0` and `length`.
+/// Any number of reflections is possible.
+fn reflect(position: &mut f64, velocity: &mut f64, length: f64) {
+    if *position >= 0.0 && *position <= length {
+        return;
+    }
+    let reflections = (*position / length).floor();
+    let folded = position.rem_euclid(2.0 * length);
+    *position = if folded > length { 2.0 * length - folded } else { folded };
+    if reflections.rem_euclid(2.0) == 1.0 {
+        *velocity = -*velocity;
+    }
+}
+
+/// Shifts `position` by cell vectors to get it inside of cell with lengths `bb` and `tilt`
+/// (see [State::tilt]). It is done in fractional coordinates from `c` to `a` vector.
+/// Only axes with periodic `boundary_conditions` are wrapped.
+///
+/// # Returns
+///
+/// How many cell vectors `a`, `b` and `c` were subtracted from `position`
+pub fn wrap_position(position: &mut Vector3<f64>, bb: &Vector3<f64>, tilt: &Vector3<f64>,
+                     boundary_conditions: &[BoundaryCondition; 3]) -> Vector3<i32> {
+    let mut image = Vector3::zeros();
+    let periodic = boundary_conditions.map(|condition| condition == BoundaryCondition::Periodic);
+    let fractional_z = |position: &Vector3<f64>| position.z / bb.z;
+    let fractional_y = |position: &Vector3<f64>| (position.y - tilt.z * position.z / bb.z) / bb.y;
+    for _ in 0..2 {
+        // Second pass is needed only if rounding of small negative value gives 1.0
+        let shift = fractional_z(position).floor();
+        if periodic[2] && shift != 0.0 {
+            position.z -= shift * bb.z;
+            position.y -= shift * tilt.z;
+            position.x -= shift * tilt.y;
+            image.z += shift as i32;
+        }
+    }
+    for _ in 0..2 {
+        let shift = fractional_y(position).floor();
+        if periodic[1] && shift != 0.0 {
+            position.y -= shift * bb.y;
+            position.x -= shift * tilt.x;
+            image.y += shift as i32;
+        }
+    }
+    for _ in 0..2 {
+        let fractional_x = (position.x - tilt.x * fractional_y(position)
+            - tilt.y * fractional_z(position)) / bb.x;
+        let shift = fractional_x.floor();
+        if periodic[0] && shift != 0.0 {
+            position.x -= shift * bb.x;
+            image.x += shift as i32;
         }
     }
+    image
 }
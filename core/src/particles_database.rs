@@ -135,6 +135,57 @@ impl ParticleDatabase {
         }
     }
 
+    /// Gets id of particle with `name`
+    ///
+    /// # Returns
+    ///
+    /// The smallest id of particle with this name if it exists in particle database else it
+    /// returns None
+    ///
+    /// # Panics
+    ///
+    /// This function can panic if it can't lock particle database.
+    pub fn get_particle_id(name: &str) -> Option<u16> {
+        let particle_data_locked = PARTICLE_DATA.read().expect("Can't lock mutex");
+        particle_data_locked.iter()
+            .filter(|(_, particle_data)| particle_data.name == name)
+            .map(|(id, _)| *id)
+            .min()
+    }
+
+    /// Gets id of particle with `name` or adds it with the smallest free id.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - particle name
+    /// * `mass` - particle mass in 10^(-27) kg, used only if particle is added
+    /// * `radius` - particle radius in nm, used only if particle is added
+    ///
+    /// # Panics
+    ///
+    /// This function can panic if it can't lock particle database.
+    pub fn get_or_add(name: &str, mass: f64, radius: f64) -> u16 {
+        let mut particle_data_locked = PARTICLE_DATA.write().expect("Can't lock mutex");
+        let existing = particle_data_locked.iter()
+            .filter(|(_, particle_data)| particle_data.name == name)
+            .map(|(id, _)| *id)
+            .min();
+        if let Some(id) = existing {
+            return id;
+        }
+        let id = (0..=u16::MAX).find(|id| !particle_data_locked.contains_key(id))
+            .expect("Particle database is full");
+        particle_data_locked.insert(
+            id,
+            ParticleData {
+                name: String::from(name),
+                mass,
+                radius,
+            },
+        );
+        id
+    }
+
     /// Remove all particles from database.
     ///
     /// # Panics
@@ -3,12 +3,13 @@ use std::fs::{File, OpenOptions};
 use std::io::BufWriter;
 use std::path::{Path, PathBuf};
 use itertools::Itertools;
-use na::Vector3;
+use na::{Matrix3, Vector3};
 use serde::{Deserialize, Serialize, Serializer};
-use crate::{open_file_or_create, Particle, ParticleDatabase, State};
+use serde::de::DeserializeOwned;
+use crate::{open_file_or_create, BoundaryCondition, Particle, ParticleDatabase, State};
 
 /// Serialization struct for [Particle]
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct ParticleToSave {
     pub id: u16,
     pub position_x: f64,
@@ -17,6 +18,40 @@ pub struct ParticleToSave {
     pub velocity_x: f64,
     pub velocity_y: f64,
     pub velocity_z: f64,
+    /// Image counters of particle (see [Particle::image])
+    #[serde(default)]
+    pub image_x: i32,
+    #[serde(default)]
+    pub image_y: i32,
+    #[serde(default)]
+    pub image_z: i32,
+}
+
+/// Serialization struct for one link of thermostat chain (e.g. Nose-Hoover chain)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ThermostatLinkToSave {
+    /// Type of particles that are coupled to this chain
+    pub particle_type: u16,
+    /// Index of link in chain
+    pub link: usize,
+    pub position: f64,
+    pub velocity: f64,
+    pub mass: f64,
+    /// Target temperature (K) of thermostat
+    pub temperature: f64,
+}
+
+/// Serialization struct for one box degree of freedom of barostat (e.g. Parrinello-Rahman)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BoxVelocityToSave {
+    /// Box axes that are scaled together by this degree of freedom (e.g. "xyz", "xy" or "z"),
+    /// or tilt factor changed by it ("tilt_xy", "tilt_xz" or "tilt_yz")
+    pub axes: String,
+    /// Logarithmic box velocity (1/ps)
+    pub velocity: f64,
+    pub mass: f64,
+    /// Target pressure of barostat
+    pub pressure: f64,
 }
 
 /// Serialization struct for [State]
@@ -24,6 +59,18 @@ pub struct ParticleToSave {
 pub struct StateToSave {
     pub particles: Vec<ParticleToSave>,
     pub boundary_box: Vector3<f64>,
+    /// Tilt factors of triclinic cell (see [State::tilt])
+    #[serde(default)]
+    pub tilt: Vector3<f64>,
+    /// Boundary conditions along x, y and z
+    #[serde(default)]
+    pub boundary_conditions: [BoundaryCondition; 3],
+    /// Thermostat variables. Empty if thermostat has no state to keep between steps
+    #[serde(default)]
+    pub thermostat: Vec<ThermostatLinkToSave>,
+    /// Box degrees of freedom of barostat. Empty if barostat has no state to keep between steps
+    #[serde(default)]
+    pub barostat: Vec<BoxVelocityToSave>,
 }
 
 pub enum MacroParameterType {
@@ -32,6 +79,10 @@ pub enum MacroParameterType {
     ThermalEnergy(f64),
     Temperature(f64),
     Pressure(f64),
+    /// Full pressure tensor (kinetic and virial parts)
+    PressureTensor(Matrix3<f64>),
+    /// Energy of extended system (with thermostat and barostat variables) that should be conserved
+    ConservedEnergy(f64),
     Custom(usize, f64),
 }
 
@@ -47,6 +98,20 @@ pub struct MacroParameters {
     pub unit_thermal_energy: f64,
     pub temperature: f64,
     pub pressure: f64,
+    #[serde(default)]
+    pub pressure_xx: f64,
+    #[serde(default)]
+    pub pressure_yy: f64,
+    #[serde(default)]
+    pub pressure_zz: f64,
+    #[serde(default)]
+    pub pressure_xy: f64,
+    #[serde(default)]
+    pub pressure_xz: f64,
+    #[serde(default)]
+    pub pressure_yz: f64,
+    #[serde(default)]
+    pub conserved_energy: f64,
     pub custom: f64,
 }
 
@@ -57,6 +122,30 @@ pub struct DataFileMacro {
     pub macro_parameters: HashMap<usize, MacroParameters>,
     pub start_frame: usize,
     pub frame_count: usize,
+    /// Macro parameters of every particle type for mixtures. They are saved next to main file
+    /// with particle type added to file name, e.g. `macro_0.csv`, `macro_1.csv`.
+    #[serde(skip)]
+    pub species_macro_parameters: BTreeMap<u16, HashMap<usize, MacroParameters>>,
+}
+
+/// Row of `bb.csv`: box lengths, tilt factors (see [State::tilt]) and boundary conditions
+#[derive(Serialize, Deserialize, Clone)]
+struct BoundaryBoxData {
+    x: f64,
+    y: f64,
+    z: f64,
+    #[serde(default)]
+    xy: f64,
+    #[serde(default)]
+    xz: f64,
+    #[serde(default)]
+    yz: f64,
+    #[serde(default)]
+    boundary_x: BoundaryCondition,
+    #[serde(default)]
+    boundary_y: BoundaryCondition,
+    #[serde(default)]
+    boundary_z: BoundaryCondition,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -90,9 +179,11 @@ impl ParticleToSave {
                 force: Default::default(),
                 potential: 0.0,
                 temp: 0.0,
+                virial: Matrix3::zeros(),
                 mass,
                 radius,
                 id,
+                image: Vector3::new(self.image_x, self.image_y, self.image_z),
             }
         )
     }
@@ -106,6 +197,9 @@ impl ParticleToSave {
             velocity_y: particle.velocity.y,
             velocity_z: particle.velocity.z,
             id: particle.id,
+            image_x: particle.image.x,
+            image_y: particle.image.y,
+            image_z: particle.image.z,
         }
     }
 }
@@ -113,6 +207,8 @@ impl ParticleToSave {
 impl From<&State> for StateToSave {
     fn from(state: &State) -> Self {
         let boundary_box = state.boundary_box;
+        let tilt = state.tilt;
+        let boundary_conditions = state.boundary_conditions;
         let mut particles: Vec<ParticleToSave> = vec![];
         state.particles.iter().for_each(|t| {
             t.iter().for_each(|particle| {
@@ -122,6 +218,10 @@ impl From<&State> for StateToSave {
         Self {
             particles,
             boundary_box,
+            tilt,
+            boundary_conditions,
+            thermostat: vec![],
+            barostat: vec![],
         }
     }
 }
@@ -143,21 +243,25 @@ impl Into<State> for StateToSave {
             particles[particle.id as usize].push(particle);
         }
         let boundary_box = self.boundary_box;
+        let tilt = self.tilt;
+        let boundary_conditions = self.boundary_conditions;
         State {
             particles,
             boundary_box,
+            tilt,
+            boundary_conditions,
         }
     }
 }
 
 impl StateToSave {
 
-    fn get_bbs(path: &Path) -> Vec<Vector3<f64>> {
-        let mut bbs: Vec<Vector3<f64>> = vec![];
+    fn get_bbs(path: &Path) -> Vec<BoundaryBoxData> {
+        let mut bbs: Vec<BoundaryBoxData> = vec![];
         let mut reader = csv::Reader::from_path(path).expect("Can't open file");
         for data in reader.deserialize() {
-            let bb: VectorData = data.expect("Can't deserialize");
-            bbs.push(Vector3::new(bb.x, bb.y, bb.z));
+            let bb: BoundaryBoxData = data.expect("Can't deserialize");
+            bbs.push(bb);
         }
         bbs
     }
@@ -169,26 +273,30 @@ impl StateToSave {
             OpenOptions::new().write(true).open(path)
         }.expect("Can't write to file");
         let mut bbs = Self::get_bbs(path);
+        let bb = BoundaryBoxData {
+            x: self.boundary_box.x,
+            y: self.boundary_box.y,
+            z: self.boundary_box.z,
+            xy: self.tilt.x,
+            xz: self.tilt.y,
+            yz: self.tilt.z,
+            boundary_x: self.boundary_conditions[0],
+            boundary_y: self.boundary_conditions[1],
+            boundary_z: self.boundary_conditions[2],
+        };
         if bbs.len() > state_number {
-            bbs[state_number] = self.boundary_box;
+            bbs[state_number] = bb;
         } else {
-            bbs.push(self.boundary_box);
+            bbs.push(bb);
         }
         let buf_writer = BufWriter::with_capacity(1073741824, bb_file);
         let mut wtr = csv::Writer::from_writer(buf_writer);
         for value in bbs {
-            wtr.serialize(VectorData { x: value.x, y: value.y, z: value.z })
-                .expect("Can't serialize data");
+            wtr.serialize(value).expect("Can't serialize data");
         }
         wtr.flush().expect("Can't write");
     }
 
-    fn load_bb(path:&Path, state_number: usize) -> Vector3<f64> {
-        let bbs = Self::get_bbs(path);
-        let bb = bbs[state_number];
-        bb
-    }
-
     pub fn save_to_file(&self, path: &Path, state_number: usize) {
         if !path.is_dir() {
             std::fs::create_dir_all(path).expect(format!("Can't create directory in {}",
@@ -197,58 +305,98 @@ impl StateToSave {
 
         let bb_path = path.join("bb.csv");
         self.save_bb(&bb_path, state_number);
-        let path = path.join("data");
-        if !path.is_dir() {
-            std::fs::create_dir_all(&path).expect(format!("Can't create directory in {}",
-                                                         path.to_str().unwrap()).as_str());
+        let data_path = path.join("data");
+        if !data_path.is_dir() {
+            std::fs::create_dir_all(&data_path).expect(format!("Can't create directory in {}",
+                                                              data_path.to_str().unwrap()).as_str());
         }
-        let path = path.join(format!("{state_number}.csv"));
-        let file = open_file_or_create(&path);
+        let data_path = data_path.join(format!("{state_number}.csv"));
+        let file = open_file_or_create(&data_path);
         let buf_writer = BufWriter::with_capacity(1073741824, file);
         let mut wtr = csv::Writer::from_writer(buf_writer);
         for value in self.particles.iter() {
             wtr.serialize(value.clone()).expect("Can't serialize data");
         }
         wtr.flush().expect("Can't write");
+        Self::save_rows(&path.join("thermostat"), state_number, &self.thermostat);
+        Self::save_rows(&path.join("barostat"), state_number, &self.barostat);
+    }
+
+    /// Saves additional per-frame data (e.g. thermostat variables) to `{path}/{state_number}.csv`.
+    /// Nothing is written for empty `rows`.
+    fn save_rows<T: Serialize>(path: &Path, state_number: usize, rows: &[T]) {
+        if rows.is_empty() {
+            return;
+        }
+        if !path.is_dir() {
+            std::fs::create_dir_all(path).unwrap_or_else(|_| panic!("Can't create directory in {}",
+                                                                    path.to_str().unwrap()));
+        }
+        let path = path.join(format!("{state_number}.csv"));
+        let mut wtr = csv::Writer::from_path(path).expect("Can't open file");
+        for value in rows {
+            wtr.serialize(value).expect("Can't serialize data");
+        }
+        wtr.flush().expect("Can't write");
+    }
+
+    fn load_rows<T: DeserializeOwned>(path: &Path, state_number: usize) -> Vec<T> {
+        let path = path.join(format!("{state_number}.csv"));
+        if !path.exists() {
+            return vec![];
+        }
+        let mut reader = csv::Reader::from_path(path).expect("Can't open file");
+        reader.deserialize().map(|data| data.expect("Can't parse row")).collect()
     }
 
     pub fn load_from_file(path: &Path, state_number: usize) -> Self {
         let bb_path = path.join("bb.csv");
-        let bb = Self::load_bb(&bb_path, state_number);
-        let path = path.join("data").join(format!("{state_number}.csv"));
-        let mut reader = csv::Reader::from_path(path).expect("Can't open file");
+        let bbs = Self::get_bbs(&bb_path);
+        Self::load_frame(path, state_number, &bbs[state_number])
+    }
+
+    /// Calls `f` for every frame saved in `path` in order. `bb.csv` is read only once.
+    /// Returns amount of frames.
+    pub(crate) fn for_each_frame(path: &Path,
+                                 mut f: impl FnMut(StateToSave) -> std::io::Result<()>) -> std::io::Result<usize> {
+        let bbs = Self::get_bbs(&path.join("bb.csv"));
+        let mut state_number = 0;
+        while state_number < bbs.len()
+            && path.join("data").join(format!("{state_number}.csv")).is_file() {
+            f(Self::load_frame(path, state_number, &bbs[state_number]))?;
+            state_number += 1;
+        }
+        Ok(state_number)
+    }
+
+    fn load_frame(path: &Path, state_number: usize, bb: &BoundaryBoxData) -> Self {
+        let (bb, tilt, boundary_conditions) = (Vector3::new(bb.x, bb.y, bb.z),
+                                               Vector3::new(bb.xy, bb.xz, bb.yz),
+                                               [bb.boundary_x, bb.boundary_y, bb.boundary_z]);
+        let data_path = path.join("data").join(format!("{state_number}.csv"));
+        let mut reader = csv::Reader::from_path(data_path).expect("Can't open file");
         let mut particles = vec![];
         for data in reader.deserialize() {
             let data: ParticleToSave = data.expect("Can't parse row");
             particles.push(data);
         }
+        let thermostat = Self::load_rows(&path.join("thermostat"), state_number);
+        let barostat = Self::load_rows(&path.join("barostat"), state_number);
         Self {
             particles,
             boundary_box: bb,
+            tilt,
+            boundary_conditions,
+            thermostat,
+            barostat,
         }
     }
 }
 
-impl DataFileMacro {
-    /// Creates empty structure
-    pub fn new() -> Self {
+impl MacroParameters {
+    fn new(iteration: usize) -> Self {
         Self {
-            macro_parameters: HashMap::new(),
-            start_frame: 0,
-            frame_count: 0,
-        }
-    }
-
-    /// Add multiple macro parameters
-    ///
-    /// # Arguments
-    /// * `frame` - frame number to add macro parameter
-    /// * `parameters` - slice of [MacroParameterType] each keeps data to save
-    /// * `particles_count` - amount of particles in system
-    ///
-    pub fn add_macro_params(&mut self, frame: usize, parameters: &[MacroParameterType], particles_count: usize) {
-        let macro_parameters = self.macro_parameters.entry(frame).or_insert(MacroParameters {
-            iteration: frame,
+            iteration,
             kinetic_energy: 0.0,
             potential_energy: 0.0,
             thermal_energy: 0.0,
@@ -257,51 +405,143 @@ impl DataFileMacro {
             unit_thermal_energy: 0.0,
             temperature: 0.0,
             pressure: 0.0,
+            pressure_xx: 0.0,
+            pressure_yy: 0.0,
+            pressure_zz: 0.0,
+            pressure_xy: 0.0,
+            pressure_xz: 0.0,
+            pressure_yz: 0.0,
+            conserved_energy: 0.0,
             custom: 0.0,
-        });
+        }
+    }
+
+    fn set(&mut self, parameters: &[MacroParameterType], particles_count: usize) {
         for parameter in parameters {
             match parameter {
                 MacroParameterType::KineticEnergy(value) => {
-                    macro_parameters.kinetic_energy = *value;
-                    macro_parameters.unit_kinetic_energy = *value / particles_count as f64;
+                    self.kinetic_energy = *value;
+                    self.unit_kinetic_energy = *value / particles_count as f64;
                 }
                 MacroParameterType::PotentialEnergy(value) => {
-                    macro_parameters.potential_energy = *value;
-                    macro_parameters.unit_potential_energy = *value / particles_count as f64;
+                    self.potential_energy = *value;
+                    self.unit_potential_energy = *value / particles_count as f64;
                 }
                 MacroParameterType::ThermalEnergy(value) => {
-                    macro_parameters.thermal_energy = *value;
-                    macro_parameters.unit_thermal_energy = *value / particles_count as f64;
+                    self.thermal_energy = *value;
+                    self.unit_thermal_energy = *value / particles_count as f64;
                 }
                 MacroParameterType::Pressure(value) => {
-                    macro_parameters.pressure = *value;
+                    self.pressure = *value;
+                }
+                MacroParameterType::PressureTensor(value) => {
+                    self.pressure_xx = value[(0, 0)];
+                    self.pressure_yy = value[(1, 1)];
+                    self.pressure_zz = value[(2, 2)];
+                    self.pressure_xy = value[(0, 1)];
+                    self.pressure_xz = value[(0, 2)];
+                    self.pressure_yz = value[(1, 2)];
+                }
+                MacroParameterType::ConservedEnergy(value) => {
+                    self.conserved_energy = *value;
                 }
                 MacroParameterType::Temperature(value) => {
-                    macro_parameters.temperature = *value;
+                    self.temperature = *value;
                 }
                 MacroParameterType::Custom(_id, _value) => {
                     todo!()
                 }
             }
         }
+    }
+}
+
+impl DataFileMacro {
+    /// Creates empty structure
+    pub fn new() -> Self {
+        Self {
+            macro_parameters: HashMap::new(),
+            start_frame: 0,
+            frame_count: 0,
+            species_macro_parameters: BTreeMap::new(),
+        }
+    }
+
+    /// Add multiple macro parameters
+    ///
+    /// # Arguments
+    /// * `frame` - frame number to add macro parameter
+    /// * `parameters` - slice of [MacroParameterType] each keeps data to save
+    /// * `particles_count` - amount of particles in system
+    ///
+    pub fn add_macro_params(&mut self, frame: usize, parameters: &[MacroParameterType], particles_count: usize) {
+        self.macro_parameters.entry(frame)
+            .or_insert_with(|| MacroParameters::new(frame))
+            .set(parameters, particles_count);
         self.frame_count += 1;
     }
 
-    /// Save all macro parameters to file. It uses CSV format.
-    pub fn save_to_file (&self, path: &PathBuf) {
+    /// Add multiple macro parameters of one particle type. Frame count isn't changed, so it
+    /// should be called together with [DataFileMacro::add_macro_params] for the whole system.
+    ///
+    /// # Arguments
+    /// * `frame` - frame number to add macro parameter
+    /// * `particle_type` - id of particle type
+    /// * `parameters` - slice of [MacroParameterType] each keeps data to save
+    /// * `particles_count` - amount of particles of this type
+    ///
+    pub fn add_species_macro_params(&mut self, frame: usize, particle_type: u16,
+                                    parameters: &[MacroParameterType], particles_count: usize) {
+        self.species_macro_parameters.entry(particle_type).or_default()
+            .entry(frame)
+            .or_insert_with(|| MacroParameters::new(frame))
+            .set(parameters, particles_count);
+    }
+
+    /// Path of file with macro parameters of `particle_type` next to file `path`
+    pub fn get_species_path(path: &Path, particle_type: u16) -> PathBuf {
+        let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("macro");
+        let file_name = match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) => format!("{stem}_{particle_type}.{extension}"),
+            None => format!("{stem}_{particle_type}"),
+        };
+        path.with_file_name(file_name)
+    }
+
+    fn save_rows(path: &PathBuf, macro_parameters: &HashMap<usize, MacroParameters>) {
         let file = open_file_or_create(path);
         let buf_writer = BufWriter::with_capacity(1073741824, file);
         let mut wtr = csv::Writer::from_writer(buf_writer);
-        for (_key, value) in self.macro_parameters.iter()
+        for (_key, value) in macro_parameters.iter()
             .sorted_by_key(|x| *x.0) {
             wtr.serialize(value.clone()).expect("Can't serialize data");
         }
         wtr.flush().expect("Can't write");
     }
 
+    fn load_rows(path: &Path) -> HashMap<usize, MacroParameters> {
+        let mut reader = csv::Reader::from_path(path).expect("Can't open file");
+        let mut macro_parameters = HashMap::new();
+        for data in reader.deserialize() {
+            let data: MacroParameters = data.expect("Can't parse row");
+            let _ = macro_parameters.insert(data.iteration, data);
+        }
+        macro_parameters
+    }
+
+    /// Save all macro parameters to file. It uses CSV format. Macro parameters of particle
+    /// types are saved to files from [DataFileMacro::get_species_path].
+    pub fn save_to_file (&self, path: &PathBuf) {
+        Self::save_rows(path, &self.macro_parameters);
+        for (particle_type, macro_parameters) in self.species_macro_parameters.iter() {
+            Self::save_rows(&Self::get_species_path(path, *particle_type), macro_parameters);
+        }
+    }
+
     /// Removes old frames. This function is used when you want to keep your data in separate files.
     pub fn reset_old(&mut self) {
         self.macro_parameters.clear();
+        self.species_macro_parameters.clear();
         self.start_frame += self.frame_count;
         self.frame_count = 0;
     }
@@ -311,28 +551,33 @@ impl DataFileMacro {
     /// > After this function `start_frame` and `frame_count` become incorrect.
     pub fn append_data(&mut self, another: &DataFileMacro) {
         self.macro_parameters.extend(another.macro_parameters.clone());
+        for (particle_type, macro_parameters) in another.species_macro_parameters.iter() {
+            self.species_macro_parameters.entry(*particle_type).or_default()
+                .extend(macro_parameters.clone());
+        }
     }
 
-    /// Loads from CSV file macro parameters data.
+    /// Loads from CSV file macro parameters data. Macro parameters of particle types are loaded
+    /// if files from [DataFileMacro::get_species_path] exist.
     pub fn load_from_file (path: &Path) -> Self {
-        let mut reader = csv::Reader::from_path(path).expect("Can't open file");
-        let mut macro_parameters = HashMap::new();
-        let mut min_iter = usize::MAX;
-        let mut max_iter = 0;
-        for data in reader.deserialize() {
-            let data: MacroParameters = data.expect("Can't parse row");
-            if data.iteration > max_iter {
-                max_iter = data.iteration;
-            }
-            if data.iteration < min_iter {
-                min_iter = data.iteration;
+        let macro_parameters = Self::load_rows(path);
+        let min_iter = macro_parameters.keys().copied().min().unwrap_or(usize::MAX);
+        let max_iter = macro_parameters.keys().copied().max().unwrap_or(0);
+        let mut species_macro_parameters = BTreeMap::new();
+        let mut particle_type = 0;
+        loop {
+            let species_path = Self::get_species_path(path, particle_type);
+            if !species_path.is_file() {
+                break;
             }
-            let _ = macro_parameters.insert(data.iteration, data);
+            species_macro_parameters.insert(particle_type, Self::load_rows(&species_path));
+            particle_type += 1;
         }
         Self {
             macro_parameters,
             start_frame: min_iter,
             frame_count: max_iter - min_iter,
+            species_macro_parameters,
         }
     }
 }
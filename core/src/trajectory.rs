@@ -0,0 +1,428 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::path::Path;
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use na::Vector3;
+use crate::{BoundaryCondition, BoxVelocityToSave, ParticleToSave, StateToSave, ThermostatLinkToSave};
+
+const MAGIC: &[u8; 8] = b"MOLDYNTR";
+const VERSION: u8 = 2;
+/// The first version with particle images
+const IMAGE_VERSION: u8 = 2;
+const HEADER_SIZE: u64 = 12;
+
+/// Precision of particle positions and velocities in trajectory. Box and thermostat/barostat
+/// variables are always saved in `f64`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrajectoryPrecision {
+    F32,
+    F64,
+}
+
+/// Compression of trajectory frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrajectoryCompression {
+    None,
+    Deflate,
+}
+
+/// Append-only binary trajectory. File has short header and frames one after another, every
+/// frame starts with its size in bytes, so frame index for random access is built by skipping
+/// frames when file is opened.
+///
+/// Frame layout (little endian): box (3 `f64`), tilt (3 `f64`), boundary conditions (3 `u8`),
+/// particles count (`u64`) and particles (`u16` id, position and velocity with trajectory
+/// precision, image as 3 `i32`), thermostat links and box velocities (count and rows).
+/// Frames of version 1 files have no particle images, they are read as zero.
+pub struct TrajectoryWriter {
+    file: BufWriter<File>,
+    version: u8,
+    precision: TrajectoryPrecision,
+    compression: TrajectoryCompression,
+    frames_count: usize,
+}
+
+/// Reader of trajectory written by [TrajectoryWriter]
+pub struct TrajectoryReader {
+    file: BufReader<File>,
+    version: u8,
+    precision: TrajectoryPrecision,
+    compression: TrajectoryCompression,
+    offsets: Vec<u64>,
+}
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn write_header(file: &mut impl Write, precision: TrajectoryPrecision,
+                compression: TrajectoryCompression) -> Result<()> {
+    let precision = match precision {
+        TrajectoryPrecision::F32 => 4,
+        TrajectoryPrecision::F64 => 8,
+    };
+    let compression = match compression {
+        TrajectoryCompression::None => 0,
+        TrajectoryCompression::Deflate => 1,
+    };
+    file.write_all(MAGIC)?;
+    file.write_all(&[VERSION, precision, compression, 0])
+}
+
+fn read_header(file: &mut impl Read) -> Result<(u8, TrajectoryPrecision, TrajectoryCompression)> {
+    let mut header = [0u8; HEADER_SIZE as usize];
+    file.read_exact(&mut header)?;
+    if &header[..8] != MAGIC {
+        return Err(invalid_data("Not a trajectory file"));
+    }
+    if header[8] == 0 || header[8] > VERSION {
+        return Err(invalid_data("Unsupported trajectory version"));
+    }
+    let precision = match header[9] {
+        4 => TrajectoryPrecision::F32,
+        8 => TrajectoryPrecision::F64,
+        _ => return Err(invalid_data("Unknown trajectory precision")),
+    };
+    let compression = match header[10] {
+        0 => TrajectoryCompression::None,
+        1 => TrajectoryCompression::Deflate,
+        _ => return Err(invalid_data("Unknown trajectory compression")),
+    };
+    Ok((header[8], precision, compression))
+}
+
+/// Offsets of complete frames and end of last complete frame
+fn scan_frames(file: &mut (impl Read + Seek), file_size: u64) -> Result<(Vec<u64>, u64)> {
+    let mut offsets = vec![];
+    let mut offset = file.seek(SeekFrom::Start(HEADER_SIZE))?;
+    let mut size = [0u8; 8];
+    while offset + 8 <= file_size {
+        file.read_exact(&mut size)?;
+        let end = offset + 8 + u64::from_le_bytes(size);
+        if end > file_size {
+            break;
+        }
+        offsets.push(offset);
+        offset = file.seek(SeekFrom::Start(end))?;
+    }
+    Ok((offsets, offset))
+}
+
+fn boundary_condition_to_byte(condition: BoundaryCondition) -> u8 {
+    match condition {
+        BoundaryCondition::Periodic => 0,
+        BoundaryCondition::Reflective => 1,
+        BoundaryCondition::Open => 2,
+    }
+}
+
+fn boundary_condition_from_byte(byte: u8) -> Result<BoundaryCondition> {
+    match byte {
+        0 => Ok(BoundaryCondition::Periodic),
+        1 => Ok(BoundaryCondition::Reflective),
+        2 => Ok(BoundaryCondition::Open),
+        _ => Err(invalid_data("Unknown boundary condition")),
+    }
+}
+
+struct FrameEncoder {
+    data: Vec<u8>,
+    version: u8,
+    precision: TrajectoryPrecision,
+}
+
+impl FrameEncoder {
+    fn f64(&mut self, value: f64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn real(&mut self, value: f64) {
+        match self.precision {
+            TrajectoryPrecision::F32 => self.data.extend_from_slice(&(value as f32).to_le_bytes()),
+            TrajectoryPrecision::F64 => self.f64(value),
+        }
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn vector(&mut self, value: &Vector3<f64>) {
+        value.iter().for_each(|x| self.f64(*x));
+    }
+
+    fn encode(mut self, data: &StateToSave) -> Vec<u8> {
+        self.vector(&data.boundary_box);
+        self.vector(&data.tilt);
+        for condition in data.boundary_conditions {
+            self.data.push(boundary_condition_to_byte(condition));
+        }
+        self.u64(data.particles.len() as u64);
+        for particle in data.particles.iter() {
+            self.data.extend_from_slice(&particle.id.to_le_bytes());
+            for value in [particle.position_x, particle.position_y, particle.position_z,
+                particle.velocity_x, particle.velocity_y, particle.velocity_z] {
+                self.real(value);
+            }
+            if self.version >= IMAGE_VERSION {
+                for value in [particle.image_x, particle.image_y, particle.image_z] {
+                    self.data.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+        self.u64(data.thermostat.len() as u64);
+        for link in data.thermostat.iter() {
+            self.data.extend_from_slice(&link.particle_type.to_le_bytes());
+            self.u64(link.link as u64);
+            for value in [link.position, link.velocity, link.mass, link.temperature] {
+                self.f64(value);
+            }
+        }
+        self.u64(data.barostat.len() as u64);
+        for degree in data.barostat.iter() {
+            self.u64(degree.axes.len() as u64);
+            self.data.extend_from_slice(degree.axes.as_bytes());
+            for value in [degree.velocity, degree.mass, degree.pressure] {
+                self.f64(value);
+            }
+        }
+        self.data
+    }
+}
+
+struct FrameDecoder<'a> {
+    data: &'a [u8],
+    version: u8,
+    precision: TrajectoryPrecision,
+}
+
+impl FrameDecoder<'_> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N]> {
+        if self.data.len() < N {
+            return Err(invalid_data("Trajectory frame is too short"));
+        }
+        let (bytes, rest) = self.data.split_at(N);
+        self.data = rest;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.bytes()?))
+    }
+
+    fn real(&mut self) -> Result<f64> {
+        match self.precision {
+            TrajectoryPrecision::F32 => Ok(f32::from_le_bytes(self.bytes()?) as f64),
+            TrajectoryPrecision::F64 => self.f64(),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes()?))
+    }
+
+    fn image(&mut self) -> Result<i32> {
+        if self.version < IMAGE_VERSION {
+            return Ok(0);
+        }
+        Ok(i32::from_le_bytes(self.bytes()?))
+    }
+
+    fn vector(&mut self) -> Result<Vector3<f64>> {
+        Ok(Vector3::new(self.f64()?, self.f64()?, self.f64()?))
+    }
+
+    fn decode(mut self) -> Result<StateToSave> {
+        let boundary_box = self.vector()?;
+        let tilt = self.vector()?;
+        let conditions: [u8; 3] = self.bytes()?;
+        let boundary_conditions = [
+            boundary_condition_from_byte(conditions[0])?,
+            boundary_condition_from_byte(conditions[1])?,
+            boundary_condition_from_byte(conditions[2])?,
+        ];
+        let particles = (0..self.u64()?).map(|_| {
+            Ok(ParticleToSave {
+                id: self.u16()?,
+                position_x: self.real()?,
+                position_y: self.real()?,
+                position_z: self.real()?,
+                velocity_x: self.real()?,
+                velocity_y: self.real()?,
+                velocity_z: self.real()?,
+                image_x: self.image()?,
+                image_y: self.image()?,
+                image_z: self.image()?,
+            })
+        }).collect::<Result<Vec<_>>>()?;
+        let thermostat = (0..self.u64()?).map(|_| {
+            Ok(ThermostatLinkToSave {
+                particle_type: self.u16()?,
+                link: self.u64()? as usize,
+                position: self.f64()?,
+                velocity: self.f64()?,
+                mass: self.f64()?,
+                temperature: self.f64()?,
+            })
+        }).collect::<Result<Vec<_>>>()?;
+        let barostat = (0..self.u64()?).map(|_| {
+            let length = self.u64()? as usize;
+            if self.data.len() < length {
+                return Err(invalid_data("Trajectory frame is too short"));
+            }
+            let (axes, rest) = self.data.split_at(length);
+            self.data = rest;
+            Ok(BoxVelocityToSave {
+                axes: String::from_utf8(axes.to_vec()).map_err(|_| invalid_data("Wrong box axes"))?,
+                velocity: self.f64()?,
+                mass: self.f64()?,
+                pressure: self.f64()?,
+            })
+        }).collect::<Result<Vec<_>>>()?;
+        Ok(StateToSave {
+            particles,
+            boundary_box,
+            tilt,
+            boundary_conditions,
+            thermostat,
+            barostat,
+        })
+    }
+}
+
+impl TrajectoryWriter {
+    /// Creates new trajectory file (existing file is overwritten)
+    pub fn create(path: &Path, precision: TrajectoryPrecision,
+                  compression: TrajectoryCompression) -> Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        write_header(&mut file, precision, compression)?;
+        Ok(Self {
+            file,
+            version: VERSION,
+            precision,
+            compression,
+            frames_count: 0,
+        })
+    }
+
+    /// Opens existing trajectory to append frames. Incomplete last frame (e.g. after crash)
+    /// is removed. Frames are written in version of the file.
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::open_at(path, usize::MAX)
+    }
+
+    /// Opens existing trajectory to write frames starting from frame `frame`. This frame and
+    /// frames after it are removed (e.g. to continue simulation from earlier frame). If
+    /// trajectory has less than `frame` frames, frames are appended to its end.
+    pub fn open_at(path: &Path, frame: usize) -> Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let (version, precision, compression) = read_header(&mut file)?;
+        let file_size = file.metadata()?.len();
+        let (mut offsets, mut end) = scan_frames(&mut file, file_size)?;
+        if frame < offsets.len() {
+            end = offsets[frame];
+            offsets.truncate(frame);
+        }
+        file.set_len(end)?;
+        file.seek(SeekFrom::Start(end))?;
+        Ok(Self {
+            file: BufWriter::new(file),
+            version,
+            precision,
+            compression,
+            frames_count: offsets.len(),
+        })
+    }
+
+    /// Appends frame to the end of trajectory
+    pub fn write_frame(&mut self, data: &StateToSave) -> Result<()> {
+        let frame = FrameEncoder { data: vec![], version: self.version, precision: self.precision }
+            .encode(data);
+        let frame = match self.compression {
+            TrajectoryCompression::None => frame,
+            TrajectoryCompression::Deflate => {
+                let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+                encoder.write_all(&frame)?;
+                encoder.finish()?
+            }
+        };
+        self.file.write_all(&(frame.len() as u64).to_le_bytes())?;
+        self.file.write_all(&frame)?;
+        self.frames_count += 1;
+        Ok(())
+    }
+
+    /// Writes buffered frames to disk
+    pub fn flush(&mut self) -> Result<()> {
+        self.file.flush()
+    }
+
+    pub fn get_frames_count(&self) -> usize {
+        self.frames_count
+    }
+}
+
+impl TrajectoryReader {
+    /// Opens trajectory and builds index of its frames
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let (version, precision, compression) = read_header(&mut file)?;
+        let file_size = file.get_ref().metadata()?.len();
+        let (offsets, _) = scan_frames(&mut file, file_size)?;
+        Ok(Self {
+            file,
+            version,
+            precision,
+            compression,
+            offsets,
+        })
+    }
+
+    pub fn get_frames_count(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn get_precision(&self) -> TrajectoryPrecision {
+        self.precision
+    }
+
+    pub fn get_compression(&self) -> TrajectoryCompression {
+        self.compression
+    }
+
+    /// Reads frame with number `frame`
+    pub fn read_frame(&mut self, frame: usize) -> Result<StateToSave> {
+        let offset = *self.offsets.get(frame)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "No such frame in trajectory"))?;
+        self.file.seek(SeekFrom::Start(offset))?;
+        let mut size = [0u8; 8];
+        self.file.read_exact(&mut size)?;
+        let mut frame = vec![0u8; u64::from_le_bytes(size) as usize];
+        self.file.read_exact(&mut frame)?;
+        let frame = match self.compression {
+            TrajectoryCompression::None => frame,
+            TrajectoryCompression::Deflate => {
+                let mut decoded = vec![];
+                DeflateDecoder::new(&frame[..]).read_to_end(&mut decoded)?;
+                decoded
+            }
+        };
+        FrameDecoder { data: &frame, version: self.version, precision: self.precision }.decode()
+    }
+}
+
+/// Converts frames saved with [StateToSave::save_to_file] in directory `directory` (`bb.csv`
+/// and `data/{n}.csv`) to trajectory file `path`. Returns amount of converted frames.
+pub fn convert_to_trajectory(directory: &Path, path: &Path, precision: TrajectoryPrecision,
+                             compression: TrajectoryCompression) -> Result<usize> {
+    let mut writer = TrajectoryWriter::create(path, precision, compression)?;
+    let frames_count = StateToSave::for_each_frame(directory, |data| writer.write_frame(&data))?;
+    writer.flush()?;
+    Ok(frames_count)
+}
@@ -2,7 +2,7 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use nalgebra::Vector3;
 use moldyn_core::ParticleDatabase;
 use moldyn_solver::initializer::{initialize_particles, randomize_positions};
-use moldyn_solver::solver::{Potential, update_force};
+use moldyn_solver::solver::{Potential, PotentialsDatabase, update_force};
 
 static UNIT_CELL: f64 = 3.338339;
 
@@ -18,9 +18,18 @@ pub fn update_force_10_10_10_bench(c: &mut Criterion) {
     let mut state = initialize_particles(&[size.0 * size.1 * size.2],
                                          &(size_v * UNIT_CELL)).unwrap();
     randomize_positions(&mut state, 0, size, UNIT_CELL);
+    let potentials_db = PotentialsDatabase::new();
     c.bench_function("update force 1000", |b| b.iter(|| {
         let mut new_state = state.clone();
-        update_force(black_box(&mut new_state))
+        update_force(&potentials_db, black_box(&mut new_state))
+    })
+    );
+    let mut potentials_db = PotentialsDatabase::new();
+    potentials_db.use_neighbor_list(0.1);
+    update_force(&potentials_db, &mut state);
+    c.bench_function("update force 1000 neighbor list", |b| b.iter(|| {
+        let mut new_state = state.clone();
+        update_force(&potentials_db, black_box(&mut new_state))
     })
     );
 }
@@ -32,9 +41,18 @@ pub fn update_force_20_20_20_bench(c: &mut Criterion) {
     let mut state = initialize_particles(&[size.0 * size.1 * size.2],
                                          &(size_v * UNIT_CELL)).unwrap();
     randomize_positions(&mut state, 0, size, UNIT_CELL);
+    let potentials_db = PotentialsDatabase::new();
     c.bench_function("update force 8000", |b| b.iter(|| {
         let mut new_state = state.clone();
-        update_force(black_box(&mut new_state))
+        update_force(&potentials_db, black_box(&mut new_state))
+    })
+    );
+    let mut potentials_db = PotentialsDatabase::new();
+    potentials_db.use_neighbor_list(0.1);
+    update_force(&potentials_db, &mut state);
+    c.bench_function("update force 8000 neighbor list", |b| b.iter(|| {
+        let mut new_state = state.clone();
+        update_force(&potentials_db, black_box(&mut new_state))
     })
     );
 }
@@ -46,9 +64,18 @@ pub fn update_force_30_30_30_bench(c: &mut Criterion) {
     let mut state = initialize_particles(&[size.0 * size.1 * size.2],
                                          &(size_v * UNIT_CELL)).unwrap();
     randomize_positions(&mut state, 0, size, UNIT_CELL);
+    let potentials_db = PotentialsDatabase::new();
     c.bench_function("update force 27000", |b| b.iter(|| {
         let mut new_state = state.clone();
-        update_force(black_box(&mut new_state))
+        update_force(&potentials_db, black_box(&mut new_state))
+    })
+    );
+    let mut potentials_db = PotentialsDatabase::new();
+    potentials_db.use_neighbor_list(0.1);
+    update_force(&potentials_db, &mut state);
+    c.bench_function("update force 27000 neighbor list", |b| b.iter(|| {
+        let mut new_state = state.clone();
+        update_force(&potentials_db, black_box(&mut new_state))
     })
     );
 }
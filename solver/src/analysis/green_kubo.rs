@@ -0,0 +1,148 @@
+use std::collections::VecDeque;
+use moldyn_core::{State, K_B};
+use serde::{Deserialize, Serialize};
+use crate::analysis::get_running_integral;
+use crate::macro_parameters::{get_heat_flux, get_particles_count, get_system_center_of_mass_velocity, get_system_pressure_tensor, get_system_thermal_energy, get_temperature};
+
+/// Streaming time correlation function `C(t) = <A(t0) A(t0 + t)>` of vector signal `A`, averaged
+/// over components of signal and every time origin. Only last `max_lag` samples are kept, so it
+/// can run during simulation.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TimeCorrelator {
+    /// Largest lag (in samples)
+    pub max_lag: usize,
+    history: VecDeque<Vec<f64>>,
+    sums: Vec<f64>,
+    counts: Vec<usize>,
+}
+
+impl TimeCorrelator {
+    pub fn new(max_lag: usize) -> Self {
+        Self {
+            max_lag,
+            history: VecDeque::with_capacity(max_lag + 1),
+            sums: vec![0.0; max_lag + 1],
+            counts: vec![0; max_lag + 1],
+        }
+    }
+
+    /// Adds next sample of signal
+    pub fn add_sample(&mut self, sample: Vec<f64>) {
+        if self.history.len() > self.max_lag {
+            self.history.pop_front();
+        }
+        let components = sample.len().max(1) as f64;
+        for (lag, previous) in self.history.iter().rev().enumerate() {
+            self.sums[lag + 1] += previous.iter().zip(sample.iter()).map(|(a, b)| a * b).sum::<f64>()
+                / components;
+            self.counts[lag + 1] += 1;
+        }
+        self.sums[0] += sample.iter().map(|a| a * a).sum::<f64>() / components;
+        self.counts[0] += 1;
+        self.history.push_back(sample);
+    }
+
+    /// Count of added samples
+    pub fn get_samples_count(&self) -> usize {
+        self.counts[0]
+    }
+
+    /// Correlation function for lags that were reached by at least one time origin
+    pub fn get_correlation(&self) -> Vec<f64> {
+        self.sums.iter().zip(self.counts.iter())
+            .take_while(|(_, count)| **count > 0)
+            .map(|(sum, count)| sum / *count as f64)
+            .collect()
+    }
+}
+
+/// Green-Kubo transport coefficients calculated during simulation.
+///
+/// Shear viscosity `eta = V / (k T) ∫ <P_ab(0) P_ab(t)> dt` uses off-diagonal elements of
+/// pressure tensor (xy, xz and yz), thermal conductivity `kappa = V / (3 k T^2) ∫ <J(0) J(t)> dt`
+/// uses heat flux ([get_heat_flux]). Temperature and volume are averaged over samples. Units are
+/// 10^-6 Pa s for viscosity and W / (m K) for thermal conductivity. For mixtures heat flux doesn't
+/// exclude enthalpy carried by interdiffusion.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GreenKubo {
+    /// Steps between samples
+    pub sample_interval: usize,
+    steps: usize,
+    pressure: TimeCorrelator,
+    heat_flux: TimeCorrelator,
+    temperature_sum: f64,
+    volume_sum: f64,
+}
+
+impl GreenKubo {
+    /// Creates correlators with lags up to `max_lag` samples. State is sampled every
+    /// `sample_interval` steps.
+    pub fn new(max_lag: usize, sample_interval: usize) -> Self {
+        Self {
+            sample_interval: sample_interval.max(1),
+            steps: 0,
+            pressure: TimeCorrelator::new(max_lag),
+            heat_flux: TimeCorrelator::new(max_lag),
+            temperature_sum: 0.0,
+            volume_sum: 0.0,
+        }
+    }
+
+    /// Adds state after step. Forces should be calculated for current positions. Only every
+    /// `sample_interval` state is sampled.
+    pub fn add_state(&mut self, state: &State) {
+        let sample = self.steps.is_multiple_of(self.sample_interval);
+        self.steps += 1;
+        if !sample {
+            return;
+        }
+        let center_of_mass_velocity = get_system_center_of_mass_velocity(state);
+        let pressure = get_system_pressure_tensor(state, &center_of_mass_velocity);
+        self.pressure.add_sample(vec![pressure[(0, 1)], pressure[(0, 2)], pressure[(1, 2)]]);
+        self.heat_flux.add_sample(get_heat_flux(state, &center_of_mass_velocity).as_slice().to_vec());
+        let thermal_energy = get_system_thermal_energy(state, &center_of_mass_velocity);
+        self.temperature_sum += get_temperature(thermal_energy, get_particles_count(state));
+        self.volume_sum += state.get_volume();
+    }
+
+    /// Count of samples
+    pub fn get_samples_count(&self) -> usize {
+        self.pressure.get_samples_count()
+    }
+
+    /// Average temperature (K) of samples
+    pub fn get_temperature(&self) -> f64 {
+        self.temperature_sum / self.get_samples_count().max(1) as f64
+    }
+
+    /// Average volume of samples
+    pub fn get_volume(&self) -> f64 {
+        self.volume_sum / self.get_samples_count().max(1) as f64
+    }
+
+    /// Autocorrelation function of off-diagonal elements of pressure tensor
+    pub fn get_pressure_acf(&self) -> Vec<f64> {
+        self.pressure.get_correlation()
+    }
+
+    /// Autocorrelation function of heat flux (averaged over components)
+    pub fn get_heat_flux_acf(&self) -> Vec<f64> {
+        self.heat_flux.get_correlation()
+    }
+
+    /// Running integral of shear viscosity for time step `delta_time` of simulation
+    pub fn get_viscosity(&self, delta_time: f64) -> Vec<f64> {
+        let kt = K_B * self.get_temperature() * 0.01;
+        get_running_integral(&self.get_pressure_acf(), delta_time * self.sample_interval as f64)
+            .iter().map(|integral| integral * self.get_volume() / kt).collect()
+    }
+
+    /// Running integral of thermal conductivity for time step `delta_time` of simulation
+    pub fn get_thermal_conductivity(&self, delta_time: f64) -> Vec<f64> {
+        let temperature = self.get_temperature();
+        let kt2 = K_B * 0.01 * temperature * temperature;
+        // Heat flux ACF is already averaged over components
+        get_running_integral(&self.get_heat_flux_acf(), delta_time * self.sample_interval as f64)
+            .iter().map(|integral| integral * self.get_volume() / kt2).collect()
+    }
+}
@@ -0,0 +1,11 @@
+mod green_kubo;
+mod msd;
+mod rdf;
+mod time_origins;
+mod vacf;
+
+pub use green_kubo::*;
+pub use msd::*;
+pub use rdf::*;
+pub use time_origins::*;
+pub use vacf::*;
@@ -0,0 +1,69 @@
+use moldyn_core::State;
+use na::Vector3;
+use crate::analysis::TimeOriginAverage;
+
+/// Mean squared displacement `MSD(t) = <|r(t0 + t) - r(t0)|^2>` of every particle type and of
+/// all particles, averaged over time origins `t0`.
+///
+/// Positions are unwrapped with particle images ([moldyn_core::Particle::image]), so frames
+/// should be added in order and particles shouldn't be added or removed. Only origins that are
+/// not older than `max_lag` frames are kept.
+pub struct MeanSquaredDisplacement {
+    displacements: TimeOriginAverage,
+}
+
+fn get_unwrapped_positions(state: &State) -> Vec<Vec<Vector3<f64>>> {
+    state.particles.iter().map(|particles| {
+        particles.iter().map(|particle| state.get_unwrapped_position(particle)).collect()
+    }).collect()
+}
+
+impl MeanSquaredDisplacement {
+    /// Creates empty MSD with lags up to `max_lag` frames and time origin every
+    /// `origin_interval` frames
+    pub fn new(max_lag: usize, origin_interval: usize) -> Self {
+        Self {
+            displacements: TimeOriginAverage::new(max_lag, origin_interval,
+                                                  |origin, position| (position - origin).norm_squared()),
+        }
+    }
+
+    /// Count of added frames
+    pub fn get_frames_count(&self) -> usize {
+        self.displacements.get_frames_count()
+    }
+
+    /// Count of particle types in added frames
+    pub fn get_particle_types_count(&self) -> usize {
+        self.displacements.get_particle_types_count()
+    }
+
+    /// Adds next frame
+    pub fn add_state(&mut self, state: &State) {
+        self.displacements.add_frame(get_unwrapped_positions(state));
+    }
+
+    /// MSD (nm^2) of particles with `particle_type` for lags from 0 to `max_lag` frames
+    /// (or less if not enough frames were added)
+    pub fn get_msd(&self, particle_type: usize) -> Vec<f64> {
+        self.displacements.get_average(particle_type)
+    }
+
+    /// MSD (nm^2) of all particles
+    pub fn get_total_msd(&self) -> Vec<f64> {
+        self.displacements.get_total_average()
+    }
+}
+
+/// Diffusion coefficient from Einstein relation `MSD(t) = 6 D t + c`. Slope is found with least
+/// squares fit of `msd` over `times`. Units are nm^2/ps for nm^2 and ps.
+pub fn get_diffusion_coefficient(times: &[f64], msd: &[f64]) -> f64 {
+    let n = times.len() as f64;
+    let mean_t = times.iter().sum::<f64>() / n;
+    let mean_msd = msd.iter().sum::<f64>() / n;
+    let (covariance, variance) = times.iter().zip(msd.iter())
+        .fold((0.0, 0.0), |(covariance, variance), (t, msd)| {
+            (covariance + (t - mean_t) * (msd - mean_msd), variance + (t - mean_t).powi(2))
+        });
+    covariance / variance / 6.0
+}
@@ -0,0 +1,172 @@
+use std::f64::consts::PI;
+use moldyn_core::State;
+use rayon::prelude::*;
+use crate::solver::minimum_image;
+
+/// Radial distribution function g(r) of every pair of particle types and of all particles,
+/// averaged over added frames.
+///
+/// Distances are taken with minimum image convention, so `r_max` shouldn't be greater than half
+/// of the smallest width of periodic box.
+pub struct RadialDistribution {
+    /// Largest distance (nm)
+    pub r_max: f64,
+    /// Number of bins of histogram
+    pub bins: usize,
+    frames: usize,
+    /// `rdf[a][b][bin]` - sum of g(r) of types `a` and `b` over frames
+    rdf: Vec<Vec<Vec<f64>>>,
+    /// `neighbors[a][b][bin]` - sum over frames of average count of particles of type `b`
+    /// in shell `bin` around particle of type `a`
+    neighbors: Vec<Vec<Vec<f64>>>,
+    total_rdf: Vec<f64>,
+    total_neighbors: Vec<f64>,
+}
+
+impl RadialDistribution {
+    /// Creates empty g(r) with `bins` bins up to `r_max` (nm)
+    pub fn new(r_max: f64, bins: usize) -> Self {
+        Self {
+            r_max,
+            bins,
+            frames: 0,
+            rdf: vec![],
+            neighbors: vec![],
+            total_rdf: vec![0.0; bins],
+            total_neighbors: vec![0.0; bins],
+        }
+    }
+
+    /// Calculates g(r) of single `state`
+    pub fn from_state(state: &State, r_max: f64, bins: usize) -> Self {
+        let mut rdf = Self::new(r_max, bins);
+        rdf.add_state(state);
+        rdf
+    }
+
+    /// Width of bin (nm)
+    pub fn get_bin_width(&self) -> f64 {
+        self.r_max / self.bins as f64
+    }
+
+    /// Centers of bins (nm)
+    pub fn get_r(&self) -> Vec<f64> {
+        let dr = self.get_bin_width();
+        (0..self.bins).map(|bin| (bin as f64 + 0.5) * dr).collect()
+    }
+
+    /// Count of added frames
+    pub fn get_frames_count(&self) -> usize {
+        self.frames
+    }
+
+    /// Count of particle types in added frames
+    pub fn get_particle_types_count(&self) -> usize {
+        self.rdf.len()
+    }
+
+    fn resize(&mut self, particle_types_count: usize) {
+        if particle_types_count <= self.rdf.len() {
+            return;
+        }
+        let bins = self.bins;
+        for data in [&mut self.rdf, &mut self.neighbors] {
+            data.iter_mut().for_each(|row| row.resize(particle_types_count, vec![0.0; bins]));
+            data.resize(particle_types_count, vec![vec![0.0; bins]; particle_types_count]);
+        }
+    }
+
+    /// Adds pairs distances of `state` to histogram
+    pub fn add_state(&mut self, state: &State) {
+        let types_count = state.particles.len();
+        self.resize(types_count);
+        let bins = self.bins;
+        let dr = self.get_bin_width();
+        let bb = &state.boundary_box;
+        let tilt = &state.tilt;
+        let boundary_conditions = &state.boundary_conditions;
+        let particles: Vec<_> = state.particles.iter().enumerate()
+            .flat_map(|(particle_type, particles)| {
+                particles.iter().map(move |particle| (particle_type, particle.position))
+            }).collect();
+        // Pair counts are integers, so result doesn't depend on order of summation
+        let histogram = particles.par_iter().enumerate()
+            .fold(|| vec![0.0; types_count * types_count * bins], |mut histogram, (i, (a, position))| {
+                for (b, other_position) in &particles[i + 1..] {
+                    let mut r = other_position - position;
+                    minimum_image(&mut r, bb, tilt, boundary_conditions);
+                    let bin = (r.norm() / dr) as usize;
+                    if bin < bins {
+                        histogram[(a * types_count + b) * bins + bin] += 1.0;
+                        histogram[(b * types_count + a) * bins + bin] += 1.0;
+                    }
+                }
+                histogram
+            })
+            .reduce(|| vec![0.0; types_count * types_count * bins], |mut a, b| {
+                a.iter_mut().zip(b.iter()).for_each(|(a, b)| *a += b);
+                a
+            });
+        let volume = state.get_volume();
+        let shell_volumes: Vec<f64> = (0..bins).map(|bin| {
+            4.0 / 3.0 * PI * (((bin + 1) as f64 * dr).powi(3) - (bin as f64 * dr).powi(3))
+        }).collect();
+        let counts: Vec<f64> = state.particles.iter().map(|particles| particles.len() as f64).collect();
+        let count: f64 = counts.iter().sum();
+        let mut total = vec![0.0; bins];
+        for a in 0..types_count {
+            for b in 0..types_count {
+                let pairs = counts[a] * (counts[b] - if a == b { 1.0 } else { 0.0 });
+                let histogram = &histogram[(a * types_count + b) * bins..(a * types_count + b + 1) * bins];
+                for bin in 0..bins {
+                    total[bin] += histogram[bin];
+                    if pairs > 0.0 {
+                        self.rdf[a][b][bin] += histogram[bin] * volume / pairs / shell_volumes[bin];
+                        self.neighbors[a][b][bin] += histogram[bin] / counts[a];
+                    }
+                }
+            }
+        }
+        if count > 1.0 {
+            for bin in 0..bins {
+                self.total_rdf[bin] += total[bin] * volume / (count * (count - 1.0)) / shell_volumes[bin];
+                self.total_neighbors[bin] += total[bin] / count;
+            }
+        }
+        self.frames += 1;
+    }
+
+    fn average(&self, data: &[f64]) -> Vec<f64> {
+        let frames = self.frames.max(1) as f64;
+        data.iter().map(|value| value / frames).collect()
+    }
+
+    fn cumulative(&self, data: &[f64]) -> Vec<f64> {
+        let frames = self.frames.max(1) as f64;
+        data.iter().scan(0.0, |sum, value| {
+            *sum += value / frames;
+            Some(*sum)
+        }).collect()
+    }
+
+    /// g(r) of particle types `a` and `b`
+    pub fn get_rdf(&self, a: usize, b: usize) -> Vec<f64> {
+        self.average(&self.rdf[a][b])
+    }
+
+    /// g(r) of all particles regardless of their types
+    pub fn get_total_rdf(&self) -> Vec<f64> {
+        self.average(&self.total_rdf)
+    }
+
+    /// Coordination number: average count of particles of type `b` closer than the upper
+    /// bound of bin to particle of type `a`
+    pub fn get_coordination_number(&self, a: usize, b: usize) -> Vec<f64> {
+        self.cumulative(&self.neighbors[a][b])
+    }
+
+    /// Coordination number of all particles regardless of their types
+    pub fn get_total_coordination_number(&self) -> Vec<f64> {
+        self.cumulative(&self.total_neighbors)
+    }
+}
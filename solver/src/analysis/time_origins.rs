@@ -0,0 +1,100 @@
+use std::collections::VecDeque;
+use na::Vector3;
+
+/// Average of `pair(a(t0), a(t0 + t))` of every particle type and of all particles over time
+/// origins `t0`, where `a` is vector per-particle value (unwrapped position, velocity and so on).
+///
+/// Frames should be added in order and particles shouldn't be added or removed. Only origins
+/// that are not older than `max_lag` frames are kept.
+pub struct TimeOriginAverage {
+    /// Largest lag (in frames)
+    pub max_lag: usize,
+    /// Frames between time origins
+    pub origin_interval: usize,
+    /// Function of value at time origin and value after lag
+    pair: fn(&Vector3<f64>, &Vector3<f64>) -> f64,
+    frames: usize,
+    /// Frame numbers and values of time origins
+    origins: VecDeque<(usize, Vec<Vec<Vector3<f64>>>)>,
+    /// `sums[type][lag]` - sum of `pair` of particles of `type` over time origins
+    sums: Vec<Vec<f64>>,
+    /// Count of time origins for every lag
+    origins_counts: Vec<usize>,
+    particles_counts: Vec<usize>,
+}
+
+impl TimeOriginAverage {
+    /// Creates empty average of `pair` with lags up to `max_lag` frames and time origin every
+    /// `origin_interval` frames
+    pub fn new(max_lag: usize, origin_interval: usize,
+               pair: fn(&Vector3<f64>, &Vector3<f64>) -> f64) -> Self {
+        Self {
+            max_lag,
+            origin_interval: origin_interval.max(1),
+            pair,
+            frames: 0,
+            origins: VecDeque::new(),
+            sums: vec![],
+            origins_counts: vec![0; max_lag + 1],
+            particles_counts: vec![],
+        }
+    }
+
+    /// Count of added frames
+    pub fn get_frames_count(&self) -> usize {
+        self.frames
+    }
+
+    /// Count of particle types in added frames
+    pub fn get_particle_types_count(&self) -> usize {
+        self.particles_counts.len()
+    }
+
+    /// Adds values of particles (grouped by particle type) in next frame
+    pub fn add_frame(&mut self, values: Vec<Vec<Vector3<f64>>>) {
+        if self.frames == 0 {
+            self.particles_counts = values.iter().map(|particles| particles.len()).collect();
+            self.sums = vec![vec![0.0; self.max_lag + 1]; values.len()];
+        }
+        if self.frames.is_multiple_of(self.origin_interval) {
+            self.origins.push_back((self.frames, values.clone()));
+        }
+        for (origin, origin_values) in self.origins.iter() {
+            let lag = self.frames - origin;
+            for (particle_type, (values, origin_values)) in
+                values.iter().zip(origin_values.iter()).enumerate() {
+                self.sums[particle_type][lag] += values.iter().zip(origin_values.iter())
+                    .map(|(value, origin)| (self.pair)(origin, value))
+                    .sum::<f64>();
+            }
+            self.origins_counts[lag] += 1;
+        }
+        if self.origins.front().is_some_and(|(origin, _)| self.frames - origin == self.max_lag) {
+            self.origins.pop_front();
+        }
+        self.frames += 1;
+    }
+
+    /// Lags (in frames) that were reached by at least one time origin
+    fn get_lags_count(&self) -> usize {
+        self.frames.min(self.max_lag + 1)
+    }
+
+    /// Average of particles with `particle_type` for lags from 0 to `max_lag` frames (or less
+    /// if not enough frames were added)
+    pub fn get_average(&self, particle_type: usize) -> Vec<f64> {
+        let particles_count = self.particles_counts[particle_type].max(1) as f64;
+        (0..self.get_lags_count()).map(|lag| {
+            self.sums[particle_type][lag] / (self.origins_counts[lag] as f64 * particles_count)
+        }).collect()
+    }
+
+    /// Average of all particles
+    pub fn get_total_average(&self) -> Vec<f64> {
+        let particles_count = self.particles_counts.iter().sum::<usize>().max(1) as f64;
+        (0..self.get_lags_count()).map(|lag| {
+            let sum: f64 = self.sums.iter().map(|sums| sums[lag]).sum();
+            sum / (self.origins_counts[lag] as f64 * particles_count)
+        }).collect()
+    }
+}
@@ -0,0 +1,102 @@
+use std::f64::consts::PI;
+use moldyn_core::State;
+use crate::analysis::TimeOriginAverage;
+
+/// Velocity autocorrelation function `C(t) = <v(t0) v(t0 + t)>` of every particle type and of
+/// all particles, averaged over time origins `t0`.
+///
+/// Frames should be added in order. Only origins that are not older than `max_lag` frames are
+/// kept.
+pub struct VelocityAutocorrelation {
+    correlations: TimeOriginAverage,
+}
+
+impl VelocityAutocorrelation {
+    /// Creates empty VACF with lags up to `max_lag` frames and time origin every
+    /// `origin_interval` frames
+    pub fn new(max_lag: usize, origin_interval: usize) -> Self {
+        Self {
+            correlations: TimeOriginAverage::new(max_lag, origin_interval,
+                                                 |origin, velocity| velocity.dot(origin)),
+        }
+    }
+
+    /// Count of added frames
+    pub fn get_frames_count(&self) -> usize {
+        self.correlations.get_frames_count()
+    }
+
+    /// Count of particle types in added frames
+    pub fn get_particle_types_count(&self) -> usize {
+        self.correlations.get_particle_types_count()
+    }
+
+    /// Adds next frame
+    pub fn add_state(&mut self, state: &State) {
+        self.correlations.add_frame(state.particles.iter().map(|particles| {
+            particles.iter().map(|particle| particle.velocity).collect()
+        }).collect());
+    }
+
+    /// VACF (nm^2/ps^2) of particles with `particle_type` for lags from 0 to `max_lag` frames
+    /// (or less if not enough frames were added)
+    pub fn get_vacf(&self, particle_type: usize) -> Vec<f64> {
+        self.correlations.get_average(particle_type)
+    }
+
+    /// VACF (nm^2/ps^2) of all particles
+    pub fn get_total_vacf(&self) -> Vec<f64> {
+        self.correlations.get_total_average()
+    }
+}
+
+/// VACF divided by its value at zero lag
+pub fn normalize_vacf(vacf: &[f64]) -> Vec<f64> {
+    let c0 = vacf.first().copied().unwrap_or(1.0);
+    vacf.iter().map(|c| c / c0).collect()
+}
+
+/// Running integral of `values` sampled every `delta_time` with trapezoidal rule
+pub fn get_running_integral(values: &[f64], delta_time: f64) -> Vec<f64> {
+    let mut integral = 0.0;
+    let mut previous = None;
+    values.iter().map(|value| {
+        if let Some(previous) = previous {
+            integral += (previous + value) * delta_time / 2.0;
+        }
+        previous = Some(*value);
+        integral
+    }).collect()
+}
+
+/// Diffusion coefficient from Green-Kubo relation `D = 1/3 ∫ C(t) dt` where `vacf` is not
+/// normalized VACF sampled every `delta_time`. Units are nm^2/ps for nm/ps and ps.
+pub fn get_green_kubo_diffusion_coefficient(vacf: &[f64], delta_time: f64) -> f64 {
+    get_running_integral(vacf, delta_time).last().copied().unwrap_or(0.0) / 3.0
+}
+
+/// Vibrational density of states: cosine transform of normalized VACF with Hann window
+/// `S(f) = 4 ∫ c(t) w(t) cos(2 pi f t) dt`. Spectrum is normalized so its integral over
+/// frequency is 1.
+///
+/// # Returns
+///
+/// Frequencies (THz for ps) from zero to Nyquist frequency `1 / (2 delta_time)` and DOS (ps)
+pub fn get_density_of_states(vacf: &[f64], delta_time: f64) -> (Vec<f64>, Vec<f64>) {
+    if vacf.len() < 2 {
+        return (vec![0.0], vec![0.0]);
+    }
+    let lags = vacf.len() - 1;
+    let t_max = lags as f64 * delta_time;
+    let windowed: Vec<f64> = normalize_vacf(vacf).iter().enumerate().map(|(lag, c)| {
+        c * 0.5 * (1.0 + (PI * lag as f64 / lags as f64).cos())
+    }).collect();
+    let frequencies: Vec<f64> = (0..=lags).map(|k| k as f64 / (2.0 * t_max)).collect();
+    let dos = frequencies.iter().map(|frequency| {
+        let integrand: Vec<f64> = windowed.iter().enumerate().map(|(lag, c)| {
+            c * (2.0 * PI * frequency * lag as f64 * delta_time).cos()
+        }).collect();
+        4.0 * get_running_integral(&integrand, delta_time).last().unwrap()
+    }).collect();
+    (frequencies, dos)
+}
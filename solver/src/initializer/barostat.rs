@@ -1,6 +1,38 @@
-use crate::macro_parameters::{get_center_of_mass_velocity, get_pressure};
+use moldyn_core::{BoxVelocityToSave, State, K_B};
+use na::{Matrix3, Vector3};
+use serde::{Deserialize, Serialize};
+use crate::macro_parameters::{get_particles_count, get_system_center_of_mass_velocity, get_system_kinetic_energy, get_system_pressure, get_system_pressure_tensor};
+
+/// Which box axes are scaled together by barostat
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PressureCoupling {
+    /// All axes are scaled by the same factor
+    Isotropic,
+    /// x and y are scaled together, z independently (membranes, slabs)
+    SemiIsotropic,
+    /// Every axis is scaled independently
+    Anisotropic,
+    /// Every axis is scaled independently and tilt factors xy, xz and yz are driven by
+    /// off-diagonal pressure, so box is sheared
+    Triclinic,
+}
+
+impl PressureCoupling {
+    /// Groups of axes that have common box velocity
+    fn groups(&self) -> &'static [&'static [usize]] {
+        match self {
+            PressureCoupling::Isotropic => &[&[0, 1, 2]],
+            PressureCoupling::SemiIsotropic => &[&[0, 1], &[2]],
+            PressureCoupling::Anisotropic | PressureCoupling::Triclinic => &[&[0], &[1], &[2]],
+        }
+    }
+}
+
+const AXES_NAMES: [&str; 3] = ["x", "y", "z"];
+const TILT_NAMES: [&str; 3] = ["tilt_xy", "tilt_xz", "tilt_yz"];
 
 /// Barostat enum object
+#[derive(Serialize, Deserialize)]
 pub enum Barostat {
     /// Paper: <https://pure.rug.nl/ws/files/64380902/1.448118.pdf>
     Berendsen {
@@ -8,6 +40,27 @@ pub enum Barostat {
         tau: f64,
         myu: f64,
     },
+    /// Isotropic Martyna-Tobias-Klein barostat: <https://doi.org/10.1063/1.467468>.
+    /// `velocity` is logarithmic box velocity, `mass` is derived from `tau` and `temperature`.
+    Mtk {
+        tau: f64,
+        temperature: f64,
+        velocity: f64,
+        mass: f64,
+    },
+    /// Parrinello-Rahman barostat in MTK form: <https://doi.org/10.1063/1.328693>.
+    /// Box axes are coupled according to `coupling`, `velocity` keeps logarithmic velocity of
+    /// every axis. With [PressureCoupling::Triclinic] `shear_velocity` keeps velocities of
+    /// xy, xz and yz elements of upper triangular box velocity matrix. Box isn't flipped, so
+    /// tilt factors should stay small compared with box lengths.
+    ParrinelloRahman {
+        tau: f64,
+        temperature: f64,
+        coupling: PressureCoupling,
+        velocity: Vector3<f64>,
+        shear_velocity: Vector3<f64>,
+        mass: f64,
+    },
     /// Doesn't implemented yet
     Custom {
         name: String,
@@ -15,38 +68,209 @@ pub enum Barostat {
     }
 }
 
+fn get_degrees_of_freedom(state: &State) -> f64 {
+    3.0 * get_particles_count(state) as f64
+}
 
 impl Barostat {
+    /// Creates isotropic MTK barostat with relaxation time `tau` (ps). Box mass is
+    /// `(Nf + 3) kT tau^2` for `temperature` (K).
+    pub fn new_mtk(tau: f64, temperature: f64) -> Self {
+        Barostat::Mtk {
+            tau,
+            temperature,
+            velocity: 0.0,
+            mass: 0.0,
+        }
+    }
+
+    /// Creates Parrinello-Rahman barostat with relaxation time `tau` (ps) and box axes
+    /// `coupling`. Mass of every box degree of freedom is `(Nf + 3) kT tau^2` for `temperature` (K).
+    pub fn new_parrinello_rahman(tau: f64, temperature: f64, coupling: PressureCoupling) -> Self {
+        Barostat::ParrinelloRahman {
+            tau,
+            temperature,
+            coupling,
+            velocity: Vector3::zeros(),
+            shear_velocity: Vector3::zeros(),
+            mass: 0.0,
+        }
+    }
+
+    fn coupling(&self) -> PressureCoupling {
+        match self {
+            Barostat::ParrinelloRahman { coupling, .. } => *coupling,
+            _ => PressureCoupling::Isotropic,
+        }
+    }
+
+    /// Logarithmic velocity of every box axis. Zero for barostats without box degrees of freedom.
+    pub fn get_box_velocity(&self) -> Vector3<f64> {
+        match self {
+            Barostat::Mtk { velocity, .. } => Vector3::new(*velocity, *velocity, *velocity),
+            Barostat::ParrinelloRahman { velocity, .. } => *velocity,
+            _ => Vector3::zeros(),
+        }
+    }
+
+    /// Velocity of xy, xz and yz elements of box velocity matrix. Zero if box isn't sheared
+    /// by barostat.
+    pub fn get_shear_velocity(&self) -> Vector3<f64> {
+        match self {
+            Barostat::ParrinelloRahman { shear_velocity, .. } => *shear_velocity,
+            _ => Vector3::zeros(),
+        }
+    }
+
+    /// Upper triangular box velocity matrix: `dh/dt = V h` for cell matrix `h`
+    fn get_box_velocity_matrix(&self) -> Matrix3<f64> {
+        let velocity = self.get_box_velocity();
+        let shear = self.get_shear_velocity();
+        Matrix3::new(velocity.x, shear.x, shear.y,
+                     0.0, velocity.y, shear.z,
+                     0.0, 0.0, velocity.z)
+    }
+
+    /// Exponent of upper triangular box velocity matrix multiplied by `time`
+    fn get_box_scale_matrix(&self, time: f64) -> Matrix3<f64> {
+        let mut scale = (self.get_box_velocity_matrix() * time).exp();
+        scale.fill_lower_triangle(0.0, 1);
+        scale
+    }
+
+    /// Half step of box velocity for `time` driven by difference between current and target pressure
+    fn kick_box(&mut self, state: &State, time: f64, target_pressure: f64) {
+        let coupling = self.coupling();
+        let (tau, temperature, mass) = match self {
+            Barostat::Mtk { tau, temperature, mass, .. }
+            | Barostat::ParrinelloRahman { tau, temperature, mass, .. } => (tau, temperature, mass),
+            _ => return,
+        };
+        let degrees_of_freedom = get_degrees_of_freedom(state);
+        if *mass == 0.0 {
+            let k_t = K_B * *temperature * 0.01;
+            *mass = (degrees_of_freedom + 3.0) * k_t * *tau * *tau;
+        }
+        let mass = *mass;
+        // Barostat scales full velocities, so center of mass velocity isn't subtracted
+        let pressure_tensor = get_system_pressure_tensor(state, &Vector3::zeros());
+        let pressure = pressure_tensor.diagonal();
+        let kinetic_energy = get_system_kinetic_energy(state);
+        let volume = state.get_volume();
+        let force = pressure.map(|p| volume * (p - target_pressure) + 2.0 * kinetic_energy / degrees_of_freedom);
+        let mut velocity = self.get_box_velocity();
+        for group in coupling.groups() {
+            let group_force: f64 = group.iter().map(|axis| force[*axis]).sum();
+            for axis in group.iter() {
+                velocity[*axis] += group_force / mass * time;
+            }
+        }
+        match self {
+            Barostat::Mtk { velocity: v, .. } => *v = velocity.x,
+            Barostat::ParrinelloRahman { velocity: v, shear_velocity, .. } => {
+                *v = velocity;
+                if coupling == PressureCoupling::Triclinic {
+                    // Target pressure is hydrostatic, so shear is driven by off-diagonal pressure only
+                    let shear_pressure = Vector3::new(pressure_tensor[(0, 1)], pressure_tensor[(0, 2)],
+                                                      pressure_tensor[(1, 2)]);
+                    *shear_velocity += shear_pressure * (volume / mass * time);
+                }
+            }
+            _ => {}
+        }
+    }
+
     /// Calculate resize coefficient
-    pub fn calculate_myu (&mut self, state: &moldyn_core::State, delta_time: f64,
-                          particle_type_id: u16, target_pressure: f64) {
-        let mv = get_center_of_mass_velocity(&state, particle_type_id);
-        let pressure = get_pressure(&state, particle_type_id, &mv);
+    pub fn calculate_myu (&mut self, state: &State, delta_time: f64, target_pressure: f64) {
         match self {
             Barostat::Berendsen {
                 beta, tau, myu
             } => {
+                let mv = get_system_center_of_mass_velocity(state);
+                let pressure = get_system_pressure(state, &mv);
                 let myu_cubed = 1.0 + delta_time * *beta / *tau * (pressure - target_pressure);
                 *myu = myu_cubed.cbrt();
             }
+            Barostat::Mtk { .. } | Barostat::ParrinelloRahman { .. } => {
+                self.kick_box(state, delta_time / 2.0, target_pressure);
+            }
             Barostat::Custom { .. } => {
                 todo!()
             }
         }
     }
 
+    /// Scales velocities of all particles by box motion for `time`
+    pub fn scale_velocities(&self, state: &mut State, time: f64) {
+        let velocity = self.get_box_velocity();
+        if velocity == Vector3::zeros() && self.get_shear_velocity() == Vector3::zeros() {
+            return;
+        }
+        let trace = velocity.sum() / get_degrees_of_freedom(state);
+        if self.get_shear_velocity() != Vector3::zeros() {
+            let scale = self.get_box_scale_matrix(-time) * f64::exp(-trace * time);
+            state.particles.iter_mut().for_each(|particle_type| {
+                particle_type.iter_mut().for_each(|particle| {
+                    particle.velocity = scale * particle.velocity;
+                });
+            });
+            return;
+        }
+        let scale = velocity.map(|v| f64::exp(-(v + trace) * time));
+        state.particles.iter_mut().for_each(|particle_type| {
+            particle_type.iter_mut().for_each(|particle| {
+                particle.velocity.component_mul_assign(&scale);
+            });
+        });
+    }
+
+    /// Scales box and positions of all particles by box motion for `time`
+    pub fn scale_positions(&self, state: &mut State, time: f64) {
+        let velocity = self.get_box_velocity();
+        if velocity == Vector3::zeros() && self.get_shear_velocity() == Vector3::zeros() {
+            return;
+        }
+        if self.get_shear_velocity() != Vector3::zeros() {
+            let scale = self.get_box_scale_matrix(time);
+            let h = scale * state.get_cell_matrix();
+            state.boundary_box = h.diagonal();
+            state.tilt = Vector3::new(h[(0, 1)], h[(0, 2)], h[(1, 2)]);
+            state.particles.iter_mut().for_each(|particle_type| {
+                particle_type.iter_mut().for_each(|particle| {
+                    particle.position = scale * particle.position;
+                });
+            });
+            return;
+        }
+        let scale = velocity.map(|v| f64::exp(v * time));
+        state.boundary_box.component_mul_assign(&scale);
+        // Rows of cell matrix are scaled: xy and xz by x factor, yz by y factor
+        state.tilt.component_mul_assign(&Vector3::new(scale.x, scale.x, scale.y));
+        state.particles.iter_mut().for_each(|particle_type| {
+            particle_type.iter_mut().for_each(|particle| {
+                particle.position.component_mul_assign(&scale);
+            });
+        });
+    }
+
     /// Resize current state
-    pub fn update(&mut self, state: &mut moldyn_core::State, _delta_time: f64,
-                  particle_type_id: u16, _target_pressure: f64) {
+    pub fn update(&mut self, state: &mut State, delta_time: f64, target_pressure: f64) {
         match self {
             Barostat::Berendsen {
                 myu, ..
             } => {
                 state.boundary_box *= *myu;
-                state.particles[particle_type_id as usize].iter_mut().for_each(|particle| {
-                    particle.position *= *myu;
+                state.tilt *= *myu;
+                state.particles.iter_mut().for_each(|particle_type| {
+                    particle_type.iter_mut().for_each(|particle| {
+                        particle.position *= *myu;
+                    });
                 });
             }
+            Barostat::Mtk { .. } | Barostat::ParrinelloRahman { .. } => {
+                self.scale_velocities(state, delta_time / 2.0);
+                self.kick_box(state, delta_time / 2.0, target_pressure);
+            }
             Barostat::Custom {
                 ..
             } => {
@@ -54,4 +278,75 @@ impl Barostat {
             }
         }
     }
-}
\ No newline at end of file
+
+    /// Energy of barostat variables: kinetic energy of box and `P V` work. Sum of it with
+    /// energy of particles (and thermostat) is conserved during simulation.
+    pub fn get_energy(&self, state: &State, target_pressure: f64) -> f64 {
+        get_barostat_energy(state, &self.to_save(target_pressure))
+    }
+
+    /// Box degrees of freedom to save together with state
+    pub fn to_save(&self, target_pressure: f64) -> Vec<BoxVelocityToSave> {
+        let mass = match self {
+            Barostat::Mtk { mass, .. } | Barostat::ParrinelloRahman { mass, .. } => *mass,
+            _ => return vec![],
+        };
+        let velocity = self.get_box_velocity();
+        let mut saved: Vec<BoxVelocityToSave> = self.coupling().groups().iter().map(|group| BoxVelocityToSave {
+            axes: group.iter().map(|axis| AXES_NAMES[*axis]).collect(),
+            velocity: velocity[group[0]],
+            mass,
+            pressure: target_pressure,
+        }).collect();
+        if self.coupling() == PressureCoupling::Triclinic {
+            let shear_velocity = self.get_shear_velocity();
+            saved.extend(TILT_NAMES.iter().zip(shear_velocity.iter()).map(|(name, velocity)| BoxVelocityToSave {
+                axes: name.to_string(),
+                velocity: *velocity,
+                mass,
+                pressure: target_pressure,
+            }));
+        }
+        saved
+    }
+
+    /// Restores box degrees of freedom saved with [Barostat::to_save]
+    pub fn load_saved(&mut self, data: &[BoxVelocityToSave]) {
+        let mut loaded_velocity = Vector3::zeros();
+        let mut loaded_shear_velocity = Vector3::zeros();
+        for degree in data {
+            if let Some(element) = TILT_NAMES.iter().position(|name| degree.axes == *name) {
+                loaded_shear_velocity[element] = degree.velocity;
+                continue;
+            }
+            for (axis, name) in AXES_NAMES.iter().enumerate() {
+                if degree.axes.contains(name) {
+                    loaded_velocity[axis] = degree.velocity;
+                }
+            }
+        }
+        match self {
+            Barostat::Mtk { velocity, mass, .. } => {
+                if let Some(degree) = data.first() {
+                    *velocity = loaded_velocity.x;
+                    *mass = degree.mass;
+                }
+            }
+            Barostat::ParrinelloRahman { velocity, shear_velocity, mass, .. } => {
+                if let Some(degree) = data.first() {
+                    *velocity = loaded_velocity;
+                    *shear_velocity = loaded_shear_velocity;
+                    *mass = degree.mass;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Energy of saved box degrees of freedom for `state`: `sum(W v^2 / 2) + P V`
+pub fn get_barostat_energy(state: &State, barostat: &[BoxVelocityToSave]) -> f64 {
+    let volume = state.get_volume();
+    barostat.iter().map(|degree| degree.mass * degree.velocity * degree.velocity / 2.0).sum::<f64>()
+        + barostat.first().map_or(0.0, |degree| degree.pressure * volume)
+}
@@ -1,6 +1,7 @@
 use moldyn_core::{Particle, ParticleDatabase, State};
 use na::Vector3;
 use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
 
 /// Particle creation errors
 /// * particle ID didn't found
@@ -133,6 +134,8 @@ pub fn initialize_particles(number_particles: &[usize], boundary: &Vector3<f64>)
     Ok(State {
         particles,
         boundary_box: boundary.clone(),
+        tilt: Vector3::zeros(),
+        boundary_conditions: Default::default(),
     })
 }
 
@@ -172,3 +175,30 @@ pub fn initialize_particles_position(
     unit_cell_type.initialize_particles_position(state, particle_id, start_position,
                                                  grid_size, unit_cell_size)
 }
+
+/// Randomly distributes particles with id `particle_id` (e.g. sites of lattice made by
+/// [initialize_particles_position]) between particle types to make mixture. `particles_counts[i]`
+/// particles get id=`i`, their positions and velocities are kept. Sum of `particles_counts` must
+/// be equal to amount of particles with `particle_id`. Particles of other types are removed.
+/// Same `seed` gives same mixture.
+pub fn split_particle_types(state: &mut State, particle_id: u16,
+                            particles_counts: &[usize], seed: u64) -> Result<(), InitError> {
+    let mut sites = std::mem::take(&mut state.particles[particle_id as usize]);
+    if particles_counts.iter().sum::<usize>() != sites.len() {
+        state.particles[particle_id as usize] = sites;
+        return Err(InitError::TooBig);
+    }
+    if (0..particles_counts.len())
+        .any(|particle_type| ParticleDatabase::get_particle_mass(particle_type as u16).is_none()) {
+        state.particles[particle_id as usize] = sites;
+        return Err(InitError::ParticleIdDidNotFound);
+    }
+    sites.shuffle(&mut ChaCha12Rng::seed_from_u64(seed));
+    let mut sites = sites.into_iter();
+    state.particles = particles_counts.iter().enumerate().map(|(particle_type, count)| {
+        sites.by_ref().take(*count).map(|site| {
+            Particle::new(particle_type as u16, site.position, site.velocity).unwrap()
+        }).collect()
+    }).collect();
+    Ok(())
+}
@@ -1,16 +1,54 @@
-use crate::macro_parameters::{get_center_of_mass_velocity, get_temperature, get_thermal_energy};
+use moldyn_core::{State, ThermostatLinkToSave, K_B};
+use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
+use rand_distr::{ChiSquared, StandardNormal};
+use serde::{Deserialize, Serialize};
+use crate::macro_parameters::{get_kinetic_energy, get_potential_energy, get_system_center_of_mass_velocity, get_temperature, get_thermal_energy};
 
-/// Thermostat enum object
+/// One link of Nose-Hoover chain
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NoseHooverLink {
+    /// Thermostat coordinate
+    pub position: f64,
+    /// Thermostat velocity
+    pub velocity: f64,
+    /// Thermostat mass
+    pub mass: f64,
+}
+
+/// Thermostat enum object. Random generators of stochastic thermostats are saved together
+/// with it, so restarted simulation gets the same random numbers.
+#[derive(Serialize, Deserialize)]
 pub enum Thermostat {
     /// Paper: <https://pure.rug.nl/ws/files/64380902/1.448118.pdf>
     Berendsen {
         tau: f64,
         lambda: f64,
     },
+    /// Nose-Hoover chains: <https://doi.org/10.1063/1.463940>.
+    /// `chains[particle_type]` keeps `chain_length` links, masses are derived from `tau`.
     NoseHoover {
         tau: f64,
-        psi: f64,
+        chain_length: usize,
+        chains: Vec<Vec<NoseHooverLink>>,
+    },
+    /// Langevin dynamics with BAOAB splitting: <https://doi.org/10.1093/amrx/abs010>.
+    /// `gamma` is friction coefficient (1/ps)
+    Langevin {
+        gamma: f64,
+        rng: ChaCha12Rng,
+    },
+    /// Andersen collision thermostat: <https://doi.org/10.1063/1.439486>.
+    /// `nu` is collision frequency (1/ps) of each particle
+    Andersen {
+        nu: f64,
+        rng: ChaCha12Rng,
+    },
+    /// Stochastic velocity rescaling (CSVR): <https://doi.org/10.1063/1.2408420>
+    Csvr {
+        tau: f64,
         lambda: f64,
+        rng: ChaCha12Rng,
     },
     /// Doesn't implemented yet
     Custom {
@@ -20,11 +58,49 @@ pub enum Thermostat {
 }
 
 impl Thermostat {
+    /// Creates Nose-Hoover chains thermostat with relaxation time `tau` (ps) and `chain_length`
+    /// links for every particle type
+    pub fn new_nose_hoover(tau: f64, chain_length: usize) -> Self {
+        Thermostat::NoseHoover {
+            tau,
+            chain_length: chain_length.max(1),
+            chains: vec![],
+        }
+    }
+
+    /// Creates Langevin thermostat with friction `gamma` and random generator seeded with `seed`
+    pub fn new_langevin(gamma: f64, seed: u64) -> Self {
+        Thermostat::Langevin {
+            gamma,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Creates Andersen thermostat with collision frequency `nu` and random generator seeded
+    /// with `seed`
+    pub fn new_andersen(nu: f64, seed: u64) -> Self {
+        Thermostat::Andersen {
+            nu,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Creates Bussi stochastic velocity rescaling thermostat with relaxation time `tau` and
+    /// random generator seeded with `seed`
+    pub fn new_csvr(tau: f64, seed: u64) -> Self {
+        Thermostat::Csvr {
+            tau,
+            lambda: 1.0,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+        }
+    }
+
     /// Calculate velocity scaling coefficient
     pub fn calculate_lambda(&mut self, state: &moldyn_core::State, delta_time: f64,
                             particle_type_id: u16, target_temperature: f64) {
         let particles_count = state.particles[particle_type_id as usize].len();
-        let mv = get_center_of_mass_velocity(&state, particle_type_id);
+        // Temperature of one species of mixture is measured relative to center of mass of system
+        let mv = get_system_center_of_mass_velocity(state);
         let thermal_energy = get_thermal_energy(&state, particle_type_id, &mv);
         let temperature = get_temperature(thermal_energy, particles_count);
         match self {
@@ -32,10 +108,27 @@ impl Thermostat {
                 let lambda_squared = 1.0 + delta_time / *tau * (target_temperature / temperature - 1.0);
                 *lambda = lambda_squared.sqrt();
             }
-            Thermostat::NoseHoover { tau,  psi, lambda} => {
-                let psi_dot = - ((target_temperature / temperature) - 1.0) / *tau;
-                *psi += psi_dot * (delta_time / 2.0);
-                *lambda = f64::exp(-*psi * delta_time / 2.0);
+            Thermostat::NoseHoover { .. } | Thermostat::Langevin { .. } | Thermostat::Andersen { .. } => {}
+            Thermostat::Csvr { lambda, .. } if thermal_energy <= 0.0 => {
+                *lambda = 1.0;
+            }
+            Thermostat::Csvr { tau, lambda, rng } => {
+                let degrees_of_freedom = 3 * particles_count;
+                let target_thermal_energy = thermal_energy * target_temperature / temperature;
+                let c = f64::exp(-delta_time / *tau);
+                let r1: f64 = rng.sample(StandardNormal);
+                let sum_r_squared = if degrees_of_freedom > 1 {
+                    let chi_squared = ChiSquared::new((degrees_of_freedom - 1) as f64)
+                        .expect("Can't create chi-squared distribution");
+                    rng.sample(chi_squared)
+                } else {
+                    0.0
+                };
+                let ratio = target_thermal_energy / (degrees_of_freedom as f64 * thermal_energy);
+                let lambda_squared = c + (1.0 - c) * (sum_r_squared + r1 * r1) * ratio
+                    + 2.0 * r1 * f64::sqrt(c * (1.0 - c) * ratio);
+                let sign = r1 + f64::sqrt(c / ((1.0 - c) * ratio));
+                *lambda = lambda_squared.sqrt().copysign(sign);
             }
             Thermostat::Custom { .. } => {
                 todo!()
@@ -47,21 +140,66 @@ impl Thermostat {
     pub fn update(&mut self, state: &mut moldyn_core::State, delta_time: f64,
                   particle_type_id: u16, target_temperature: f64) {
         let particles_count = state.particles[particle_type_id as usize].len();
-        let mv = get_center_of_mass_velocity(&state, particle_type_id);
-        let thermal_energy = get_thermal_energy(&state, particle_type_id, &mv);
-        let temperature = get_temperature(thermal_energy, particles_count);
+        let mv = get_system_center_of_mass_velocity(state);
+        let k_t = K_B * target_temperature * 0.01; // Scale temperature from Kelvin to program units
         match self {
             Thermostat::Berendsen {lambda, ..} => {
                 state.particles[particle_type_id as usize].iter_mut().for_each(|particle| {
                     particle.velocity *= *lambda;
                 });
             }
-            Thermostat::NoseHoover { tau, lambda, psi } => {
+            Thermostat::NoseHoover { tau, chain_length, chains } => {
+                if chains.len() <= particle_type_id as usize {
+                    chains.resize(particle_type_id as usize + 1, vec![]);
+                }
+                let chain = &mut chains[particle_type_id as usize];
+                chain.resize(*chain_length, NoseHooverLink { position: 0.0, velocity: 0.0, mass: 0.0 });
+                let degrees_of_freedom = 3.0 * particles_count as f64;
+                let q = k_t * *tau * *tau;
+                chain.iter_mut().enumerate().for_each(|(i, link)| {
+                    link.mass = if i == 0 { degrees_of_freedom * q } else { q };
+                });
+                let kinetic_energy = get_kinetic_energy(state, particle_type_id);
+                // Same splitting as for Langevin: half position step, thermostat step for
+                // whole `delta_time` and second half position step made by integrator.
+                let scale = propagate_chain(chain, 2.0 * kinetic_energy, degrees_of_freedom,
+                                            k_t, delta_time);
                 state.particles[particle_type_id as usize].iter_mut().for_each(|particle| {
-                    particle.velocity *= *lambda;
+                    particle.position += particle.velocity * (delta_time / 2.0);
+                    particle.velocity *= scale;
+                    particle.position -= particle.velocity * (delta_time / 2.0);
+                });
+            }
+            Thermostat::Langevin { gamma, rng } => {
+                // It is called between first velocity half step (B) and position step, so
+                // BAOAB is done as half position step (A), Ornstein-Uhlenbeck step (O) and
+                // second half step (A) that integrator makes with full step minus half of it.
+                let c1 = f64::exp(-*gamma * delta_time);
+                let c2 = f64::sqrt(1.0 - c1 * c1);
+                state.particles[particle_type_id as usize].iter_mut().for_each(|particle| {
+                    particle.position += particle.velocity * (delta_time / 2.0);
+                    let sigma = f64::sqrt(k_t / particle.mass);
+                    particle.velocity.iter_mut().for_each(|v| {
+                        *v = c1 * *v + c2 * sigma * rng.sample::<f64, _>(StandardNormal);
+                    });
+                    particle.position -= particle.velocity * (delta_time / 2.0);
+                });
+            }
+            Thermostat::Andersen { nu, rng } => {
+                let probability = *nu * delta_time;
+                state.particles[particle_type_id as usize].iter_mut().for_each(|particle| {
+                    if rng.gen::<f64>() < probability {
+                        let sigma = f64::sqrt(k_t / particle.mass);
+                        particle.velocity.iter_mut().for_each(|v| {
+                            *v = sigma * rng.sample::<f64, _>(StandardNormal);
+                        });
+                    }
+                });
+            }
+            Thermostat::Csvr { lambda, .. } => {
+                state.particles[particle_type_id as usize].iter_mut().for_each(|particle| {
+                    particle.velocity = mv + (particle.velocity - mv) * *lambda;
                 });
-                let psi_dot = - ((target_temperature / temperature) - 1.0) / *tau;
-                *psi += psi_dot * (delta_time / 2.0);
             }
             Thermostat::Custom {
                 ..
@@ -70,5 +208,114 @@ impl Thermostat {
             }
         }
     }
+
+    /// Energy of thermostat variables. Sum of it with kinetic and potential energy of particles
+    /// is conserved during simulation. It is zero for thermostats without extended system.
+    pub fn get_energy(&self, state: &State, target_temperature: f64) -> f64 {
+        get_thermostat_energy(state, &self.to_save(target_temperature))
+    }
+
+    /// Energy of extended system that should be conserved: kinetic and potential energy of all
+    /// particles plus [Thermostat::get_energy]
+    pub fn get_conserved_energy(&self, state: &State, target_temperature: f64) -> f64 {
+        (0..state.particles.len()).map(|particle_type| {
+            get_kinetic_energy(state, particle_type as u16)
+                + get_potential_energy(state, particle_type as u16)
+        }).sum::<f64>() + self.get_energy(state, target_temperature)
+    }
+
+    /// Thermostat variables to save together with state
+    pub fn to_save(&self, target_temperature: f64) -> Vec<ThermostatLinkToSave> {
+        match self {
+            Thermostat::NoseHoover { chains, .. } => {
+                chains.iter().enumerate().flat_map(|(particle_type, chain)| {
+                    chain.iter().enumerate().map(move |(link, data)| ThermostatLinkToSave {
+                        particle_type: particle_type as u16,
+                        link,
+                        position: data.position,
+                        velocity: data.velocity,
+                        mass: data.mass,
+                        temperature: target_temperature,
+                    })
+                }).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Restores thermostat variables saved with [Thermostat::to_save]
+    pub fn load_saved(&mut self, data: &[ThermostatLinkToSave]) {
+        if let Thermostat::NoseHoover { chains, .. } = self {
+            chains.clear();
+            for link in data {
+                let particle_type = link.particle_type as usize;
+                if chains.len() <= particle_type {
+                    chains.resize(particle_type + 1, vec![]);
+                }
+                let chain = &mut chains[particle_type];
+                if chain.len() <= link.link {
+                    chain.resize(link.link + 1, NoseHooverLink { position: 0.0, velocity: 0.0, mass: 0.0 });
+                }
+                chain[link.link] = NoseHooverLink {
+                    position: link.position,
+                    velocity: link.velocity,
+                    mass: link.mass,
+                };
+            }
+        }
+    }
 }
 
+/// Energy of saved thermostat variables for `state`: kinetic energy of chain links and
+/// `Nf * kT * position` for the first link, `kT * position` for others.
+pub fn get_thermostat_energy(state: &State, thermostat: &[ThermostatLinkToSave]) -> f64 {
+    thermostat.iter().map(|link| {
+        let k_t = K_B * link.temperature * 0.01;
+        let coefficient = if link.link == 0 {
+            3.0 * state.particles[link.particle_type as usize].len() as f64 * k_t
+        } else {
+            k_t
+        };
+        link.mass * link.velocity * link.velocity / 2.0 + coefficient * link.position
+    }).sum()
+}
+
+/// Propagates Nose-Hoover chain for `time` with symmetric Trotter splitting.
+/// `twice_kinetic_energy` is 2K of coupled particles.
+///
+/// # Returns
+///
+/// Velocity scaling factor for coupled particles
+fn propagate_chain(chain: &mut [NoseHooverLink], twice_kinetic_energy: f64,
+                   degrees_of_freedom: f64, k_t: f64, time: f64) -> f64 {
+    let m = chain.len();
+    let force = |chain: &[NoseHooverLink], i: usize, twice_kinetic_energy: f64| -> f64 {
+        if i == 0 {
+            (twice_kinetic_energy - degrees_of_freedom * k_t) / chain[0].mass
+        } else {
+            (chain[i - 1].mass * chain[i - 1].velocity * chain[i - 1].velocity - k_t) / chain[i].mass
+        }
+    };
+    let update_link = |chain: &mut [NoseHooverLink], i: usize, twice_kinetic_energy: f64| {
+        if i + 1 < m {
+            let damping = f64::exp(-chain[i + 1].velocity * time / 4.0);
+            chain[i].velocity *= damping;
+            chain[i].velocity += force(chain, i, twice_kinetic_energy) * time / 2.0;
+            chain[i].velocity *= damping;
+        } else {
+            chain[i].velocity += force(chain, i, twice_kinetic_energy) * time / 2.0;
+        }
+    };
+    for i in (0..m).rev() {
+        update_link(chain, i, twice_kinetic_energy);
+    }
+    let scale = f64::exp(-chain[0].velocity * time);
+    let twice_kinetic_energy = twice_kinetic_energy * scale * scale;
+    chain.iter_mut().for_each(|link| {
+        link.position += link.velocity * time;
+    });
+    for i in 0..m {
+        update_link(chain, i, twice_kinetic_energy);
+    }
+    scale
+}
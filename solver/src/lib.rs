@@ -1,6 +1,7 @@
 extern crate moldyn_core;
 extern crate nalgebra as na;
 extern crate rand_distr;
+pub mod analysis;
 pub mod initializer;
 pub mod macro_parameters;
 pub mod solver;
@@ -8,14 +9,30 @@ pub mod solver;
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::initializer::{Barostat, InitError, initialize_particles, initialize_particles_position, initialize_velocities_maxwell_boltzmann, Thermostat, UnitCell};
-    use crate::macro_parameters::{get_center_of_mass_velocity, get_kinetic_energy, get_potential_energy, get_pressure, get_temperature, get_thermal_energy};
-    use moldyn_core::{Particle, ParticleDatabase, State};
+    use crate::analysis::{GreenKubo, TimeCorrelator, get_density_of_states, get_diffusion_coefficient, get_green_kubo_diffusion_coefficient, get_running_integral, normalize_vacf, MeanSquaredDisplacement, RadialDistribution, VelocityAutocorrelation};
+    use crate::initializer::{Barostat, InitError, PressureCoupling, initialize_particles, initialize_particles_position, initialize_velocities_maxwell_boltzmann, Thermostat, UnitCell};
+    use crate::macro_parameters::{get_center_of_mass_velocity, get_heat_flux, get_tail_corrections, get_kinetic_energy, get_particles_count, get_potential_energy, get_pressure, get_pressure_tensor, get_system_center_of_mass_velocity, get_system_kinetic_energy, get_system_pressure, get_system_pressure_tensor, get_system_thermal_energy, get_temperature, get_thermal_energy};
+    use moldyn_core::{BoundaryCondition, Particle, ParticleDatabase, State};
     use crate::solver::*;
     use na::Vector3;
+    use rand::prelude::*;
+    use std::sync::{PoisonError, RwLock, RwLockReadGuard};
+
+    /// Particle database is global. [initialize_uniform_grid] clears it under write lock, other
+    /// tests register argon as particle 0 and use database under read lock ([register_argon]).
+    static PARTICLE_DATABASE: RwLock<()> = RwLock::new(());
+
+    /// Adds argon as particle 0. Database can be used while returned guard is alive.
+    fn register_argon() -> RwLockReadGuard<'static, ()> {
+        let guard = PARTICLE_DATABASE.read().unwrap_or_else(PoisonError::into_inner);
+        ParticleDatabase::add(0, "Argon", 66.335, 0.071);
+        guard
+    }
 
     #[test]
     fn initialize_uniform_grid() {
+        let _database = PARTICLE_DATABASE.write().unwrap_or_else(PoisonError::into_inner);
+        ParticleDatabase::clear_particles();
         let res = initialize_particles(&[8],
                                        &Vector3::new(4.0, 4.0, 4.0));
         assert_eq!(res.unwrap_err(), InitError::ParticleIdDidNotFound);
@@ -59,13 +76,14 @@ mod tests {
     #[test]
     fn momentum () {
         let bounding_box = Vector3::new(2.0, 2.0, 2.0) * 3.338339;
-        let verlet_method = Integrator::VerletMethod;
-        ParticleDatabase::add(0, "Argon", 66.335, 0.071);
+        let mut verlet_method = Integrator::VerletMethod;
+        let database = register_argon();
         let mut state = initialize_particles(&[8], &bounding_box).unwrap();
         let potentials_db = PotentialsDatabase::new();
         initialize_particles_position(UnitCell::U, &mut state, 0, (0.0, 0.0, 0.0), (2, 2, 2), 3.338339)
             .expect("Can't initialize particles");
         initialize_velocities_maxwell_boltzmann(&mut state, 273.15, 0);
+        drop(database);
         update_force(&potentials_db, &mut state);
         check_momentum(&state);
         for _ in 0..100000 {
@@ -74,6 +92,78 @@ mod tests {
         }
     }
 
+    fn argon_fcc_state() -> (State, PotentialsDatabase) {
+        let _database = register_argon();
+        let lattice_cell = 0.5256;
+        let bb = Vector3::new(4.0, 4.0, 4.0) * lattice_cell;
+        let mut state = initialize_particles(&[256], &bb).unwrap();
+        initialize_particles_position(UnitCell::FCC, &mut state, 0, (0.0, 0.0, 0.0),
+                                      (4, 4, 4), lattice_cell)
+            .expect("Can't initialize particles");
+        initialize_velocities_maxwell_boltzmann(&mut state, 80.0, 0);
+        let mut potentials_db = PotentialsDatabase::new();
+        potentials_db.use_neighbor_list(0.1);
+        potentials_db.use_newton_third_law(true);
+        update_force(&potentials_db, &mut state);
+        (state, potentials_db)
+    }
+
+    fn nve_argon_energy_drift(mut integrator: Integrator, delta_time: f64, steps: usize) -> f64 {
+        let (mut state, potentials_db) = argon_fcc_state();
+        let energy = |state: &State| get_kinetic_energy(state, 0) + get_potential_energy(state, 0);
+        let start_energy = energy(&state);
+        let mut max_deviation: f64 = 0.0;
+        for _ in 0..steps {
+            integrator.calculate(&potentials_db, &mut state, delta_time, &mut None, &mut None);
+            max_deviation = max_deviation.max((energy(&state) - start_energy).abs());
+        }
+        max_deviation / start_energy.abs()
+    }
+
+    #[test]
+    fn energy_drift_verlet() {
+        assert!(nve_argon_energy_drift(Integrator::VerletMethod, 0.002, 300) < 1e-4);
+    }
+
+    #[test]
+    fn energy_drift_leapfrog() {
+        assert!(nve_argon_energy_drift(Integrator::new_leapfrog(), 0.002, 300) < 1e-4);
+    }
+
+    #[test]
+    fn energy_drift_beeman() {
+        assert!(nve_argon_energy_drift(Integrator::new_beeman(), 0.002, 300) < 1e-4);
+    }
+
+    #[test]
+    fn energy_drift_respa() {
+        assert!(nve_argon_energy_drift(Integrator::new_respa(4, 0.45, 0.55), 0.008, 75) < 1e-4);
+    }
+
+    #[test]
+    #[should_panic(expected = "RESPA needs at least one inner step")]
+    fn respa_without_inner_steps() {
+        Integrator::new_respa(0, 0.45, 0.55);
+    }
+
+    #[test]
+    #[should_panic(expected = "RESPA needs 0 < r_inner < r_outer")]
+    fn respa_switching_range() {
+        Integrator::new_respa(2, 0.55, 0.45);
+    }
+
+    #[test]
+    #[should_panic(expected = "Need 0 <= r_on < r_cut for switched cutoff")]
+    fn switched_cutoff_range() {
+        Potential::new_lennard_jones(0.3418, 1.712).with_cutoff(0.8, Cutoff::Switched { r_on: 0.9 });
+    }
+
+    #[test]
+    #[should_panic(expected = "Need 0 <= r_on < r_cut for switched cutoff")]
+    fn mixed_switched_cutoff_range() {
+        PotentialsDatabase::new().set_mixed_cutoff(Some(0.8), Cutoff::Switched { r_on: -0.1 });
+    }
+
     #[test]
     fn lennard_jones() {
         let lennard_jones_potential = Potential::new_lennard_jones(0.3418, 1.712);
@@ -88,6 +178,336 @@ mod tests {
         );
     }
 
+    #[test]
+    fn pair_potentials() {
+        let potentials = [
+            Potential::new_lennard_jones(0.3418, 1.712),
+            Potential::new_morse(1.5, 13.0, 0.38, 0.9),
+            Potential::new_buckingham(1.5e5, 0.027, 1e-3, 0.9),
+            Potential::new_born_mayer_huggins(20.0, 0.03, 0.3, 1e-3, 1e-4, 0.9),
+            Potential::new_soft_sphere(0.3418, 1.712, 12.0, 0.9),
+            Potential::new_wca(0.3418, 1.712),
+        ];
+        for potential in potentials {
+            let r_cut = potential.get_radius_cut();
+            // Force is negative numeric derivative of energy (second value is dU/dr)
+            for i in 0..20 {
+                let r = 0.3 + (r_cut - 0.3) * (i as f64 + 0.5) / 20.0;
+                let h = 1e-6;
+                let (u_plus, _) = potential.get_potential_and_force(r + h);
+                let (u_minus, _) = potential.get_potential_and_force(r - h);
+                let (_, force) = potential.get_potential_and_force(r);
+                let derivative = (u_plus - u_minus) / (2.0 * h);
+                assert!((force - derivative).abs() < 1e-5 * (1.0 + derivative.abs()),
+                        "r = {r}: dU/dr = {force}, numeric {derivative}");
+            }
+            // Energy is shifted to be zero at cutoff
+            assert!(potential.get_potential_and_force(r_cut).0.abs() < 1e-12);
+            assert_eq!(potential.get_potential_and_force(r_cut * 1.01), (0.0, 0.0));
+            let json = serde_json::to_string(&potential).unwrap();
+            let loaded: Potential = serde_json::from_str(&json).unwrap();
+            assert_eq!(loaded.get_potential_and_force(0.35), potential.get_potential_and_force(0.35));
+        }
+        let wca = Potential::new_wca(0.3418, 1.712);
+        assert!((wca.get_radius_cut() - 0.3418 * 2.0f64.powf(1.0 / 6.0)).abs() < 1e-12);
+        assert!(wca.get_potential_and_force(0.3).1 < 0.0);
+    }
+
+    #[test]
+    fn tabulated_potential() {
+        // Clamped cubic spline reproduces cubic polynomial exactly
+        let x: Vec<f64> = (0..7).map(|i| (i as f64 * 0.3).powf(1.3)).collect();
+        let y: Vec<f64> = x.iter().map(|x| x * x * x - 2.0 * x).collect();
+        let spline = CubicSpline::new(x.clone(), y, Some((-2.0, 3.0 * x[6] * x[6] - 2.0)));
+        for x in [0.05, 0.4, 1.1, 1.9] {
+            let (value, derivative) = spline.get_value_and_derivative(x);
+            assert!((value - (x * x * x - 2.0 * x)).abs() < 1e-12);
+            assert!((derivative - (3.0 * x * x - 2.0)).abs() < 1e-12);
+        }
+
+        let lennard_jones = Potential::new_lennard_jones(0.3418, 1.712);
+        let tabulated = lennard_jones.tabulate(0.3, 1000);
+        assert_eq!(tabulated.get_radius_cut(), lennard_jones.get_radius_cut());
+        let compare = |potential: &Potential| {
+            for i in 0..50 {
+                let r = 0.31 + 0.5 * i as f64 / 50.0;
+                let (u, f) = potential.get_potential_and_force(r);
+                let (u_expected, f_expected) = lennard_jones.get_potential_and_force(r);
+                assert!((u - u_expected).abs() < 1e-5, "r = {r}: U = {u}, expected {u_expected}");
+                assert!((f - f_expected).abs() < 1e-4, "r = {r}: dU/dr = {f}, expected {f_expected}");
+            }
+        };
+        compare(&tabulated);
+
+        // The same table in LAMMPS format with distances recomputed from R parameter
+        let mut table = String::from("# Lennard-Jones for argon\n\nOTHER\nN 2\n\n1 0.1 1 1\n2 0.2 0 0\n\nLJ_AR\n");
+        table += &format!("N 1000 R 0.3 {}\n\n", lennard_jones.get_radius_cut());
+        for i in 0..1000 {
+            let r = 0.3 + (lennard_jones.get_radius_cut() - 0.3) * i as f64 / 999.0;
+            let (u, f) = lennard_jones.get_potential_and_force(r);
+            table += &format!("{} {:.6} {:e} {:e}\n", i + 1, r, u, -f);
+        }
+        let loaded = read_lammps_table(&mut table.as_bytes(), "LJ_AR").unwrap();
+        compare(&loaded);
+        assert!(read_lammps_table(&mut table.as_bytes(), "NONE").is_err());
+        assert!(read_lammps_table(&mut &table.as_bytes()[..200], "LJ_AR").is_err());
+
+        // Forces with tabulated potentials are the same as with analytic ones
+        let state = perturbed_argon_state(4, 0.5256, 22);
+        let mut state_analytic = state.clone();
+        let mut state_tabulated = state;
+        let potentials_db = PotentialsDatabase::new();
+        let mut tabulated_db = PotentialsDatabase::new();
+        tabulated_db.tabulate_potentials(0.25, 2000);
+        update_force(&potentials_db, &mut state_analytic);
+        update_force(&tabulated_db, &mut state_tabulated);
+        for (p1, p2) in state_analytic.particles[0].iter().zip(state_tabulated.particles[0].iter()) {
+            assert!((p1.force - p2.force).norm() < 1e-3 * (1.0 + p1.force.norm()));
+            assert!((p1.potential - p2.potential).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn mixing_rules() {
+        let (argon, krypton) = ((0.3418, 1.712), (0.3624, 2.314));
+        let assert_close = |(sigma, eps): (f64, f64), (sigma_expected, eps_expected): (f64, f64)| {
+            assert!((sigma - sigma_expected).abs() < 1e-12 && (eps - eps_expected).abs() < 1e-12,
+                    "({sigma}, {eps}) != ({sigma_expected}, {eps_expected})");
+        };
+        let eps = (1.712f64 * 2.314).sqrt();
+        assert_close(MixingRule::LorentzBerthelot.mix(argon, krypton), (0.3521, eps));
+        assert_close(MixingRule::Geometric.mix(argon, krypton), ((0.3418f64 * 0.3624).sqrt(), eps));
+        let sigma_6 = (0.3418f64.powi(6) + 0.3624f64.powi(6)) / 2.0;
+        assert_close(MixingRule::WaldmanHagler.mix(argon, krypton),
+                     (sigma_6.powf(1.0 / 6.0), eps * (0.3418f64 * 0.3624).powi(3) / sigma_6));
+        for rule in [MixingRule::LorentzBerthelot, MixingRule::Geometric, MixingRule::WaldmanHagler] {
+            assert_close(rule.mix(krypton, krypton), krypton);
+        }
+
+        let same = |potential1: &Potential, potential2: &Potential| {
+            potential1.get_radius_cut() == potential2.get_radius_cut()
+                && potential1.get_potential_and_force(0.4) == potential2.get_potential_and_force(0.4)
+        };
+        let mut potentials_db = PotentialsDatabase::new();
+        assert_eq!(potentials_db.get_undefined_pairs(2), vec![(0, 0), (0, 1), (1, 1)]);
+        potentials_db.set_species_lennard_jones(0, argon.0, argon.1);
+        potentials_db.set_species_lennard_jones(1, krypton.0, krypton.1);
+        let (sigma, eps) = MixingRule::LorentzBerthelot.mix(argon, krypton);
+        assert!(same(potentials_db.get_potential(1, 0), &Potential::new_lennard_jones(sigma, eps)));
+        assert!(same(potentials_db.get_potential(1, 1), &Potential::new_lennard_jones(krypton.0, krypton.1)));
+        assert!((potentials_db.get_max_radius_cut() - 0.3624 * 2.5).abs() < 1e-12);
+        potentials_db.set_mixing_rule(MixingRule::Geometric);
+        let (sigma, eps) = MixingRule::Geometric.mix(argon, krypton);
+        assert!(same(potentials_db.get_potential(0, 1), &Potential::new_lennard_jones(sigma, eps)));
+        potentials_db.set_mixed_cutoff(Some(1.2), Cutoff::ShiftedForce);
+        assert!(same(potentials_db.get_potential(0, 1),
+                     &Potential::new_lennard_jones(sigma, eps).with_cutoff(1.2, Cutoff::ShiftedForce)));
+        assert!((potentials_db.get_max_radius_cut() - 1.2).abs() < 1e-12);
+        potentials_db.set_mixed_cutoff(None, Cutoff::Truncated);
+        assert!(same(potentials_db.get_potential(1, 1), &Potential::new_lennard_jones(krypton.0, krypton.1)
+            .with_cutoff(0.3624 * 2.5, Cutoff::Truncated)));
+        // Explicit potential wins over mixed one
+        let morse = Potential::new_morse(1.5, 13.0, 0.38, 0.9);
+        potentials_db.set_potential(0, 1, morse.clone());
+        assert!(same(potentials_db.get_potential(1, 0), &morse));
+        assert!(potentials_db.get_undefined_pairs(2).is_empty());
+        assert_eq!(potentials_db.get_undefined_pairs(3), vec![(0, 2), (1, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn cutoff_schemes() {
+        let (sigma, eps, r_cut) = (0.3418, 1.712, 0.3418 * 3.0);
+        let lennard_jones = Potential::new_lennard_jones(sigma, eps);
+        let raw = lennard_jones.clone().with_cutoff(r_cut, Cutoff::Truncated);
+        let schemes = [Cutoff::Truncated, Cutoff::Shifted, Cutoff::ShiftedForce, Cutoff::Switched { r_on: 0.8 }];
+        for scheme in schemes {
+            let potential = lennard_jones.clone().with_cutoff(r_cut, scheme);
+            assert_eq!(potential.get_radius_cut(), r_cut);
+            for i in 0..40 {
+                let r = 0.3 + (r_cut - 0.3) * (i as f64 + 0.5) / 40.0;
+                let h = 1e-6;
+                let derivative = (potential.get_potential_and_force(r + h).0
+                    - potential.get_potential_and_force(r - h).0) / (2.0 * h);
+                let (_, force) = potential.get_potential_and_force(r);
+                assert!((force - derivative).abs() < 1e-5 * (1.0 + derivative.abs()),
+                        "{scheme:?}, r = {r}: dU/dr = {force}, numeric {derivative}");
+            }
+            let (u_cut, du_cut) = potential.get_potential_and_force(r_cut);
+            let (u_raw, du_raw) = raw.get_potential_and_force(r_cut);
+            match scheme {
+                Cutoff::Truncated => assert_eq!((u_cut, du_cut), (u_raw, du_raw)),
+                Cutoff::Shifted => assert!(u_cut.abs() < 1e-15 && du_cut == du_raw),
+                _ => assert!(u_cut.abs() < 1e-15 && du_cut.abs() < 1e-15),
+            }
+        }
+        // Switching function doesn't change potential before r_on
+        let switched = lennard_jones.clone().with_cutoff(r_cut, Cutoff::Switched { r_on: 0.8 });
+        assert_eq!(switched.get_potential_and_force(0.7), raw.get_potential_and_force(0.7));
+        // Default Lennard-Jones potential is shifted at 2.5 sigma
+        let shifted = lennard_jones.clone().with_cutoff(sigma * 2.5, Cutoff::Shifted);
+        assert_eq!(lennard_jones.get_potential_and_force(0.5), shifted.get_potential_and_force(0.5));
+
+        // Analytic tail integrals are equal to numeric ones, r = 1 / x
+        let potentials = [
+            lennard_jones.clone().with_cutoff(r_cut, Cutoff::Shifted),
+            Potential::new_morse(1.5, 13.0, 0.38, 0.5),
+            Potential::new_buckingham(1.5e5, 0.027, 1e-3, 0.9),
+            Potential::new_born_mayer_huggins(20.0, 0.03, 0.3, 1e-3, 1e-4, 0.9),
+            Potential::new_soft_sphere(0.3418, 1.712, 12.0, 0.9),
+        ];
+        for potential in potentials {
+            let r_cut = potential.get_radius_cut();
+            let unlimited = potential.clone().with_cutoff(f64::MAX, Cutoff::Truncated);
+            let intervals = 4000;
+            let step = 1.0 / r_cut / intervals as f64;
+            let (mut energy, mut pressure) = (0.0, 0.0);
+            for i in 1..=intervals {
+                let x = i as f64 * step;
+                let weight = if i == intervals { 1.0 } else if i % 2 == 1 { 4.0 } else { 2.0 };
+                let (u, du) = unlimited.get_potential_and_force(1.0 / x);
+                energy += weight * u / x.powi(4) * step / 3.0;
+                pressure += weight * du / x.powi(5) * step / 3.0;
+            }
+            let (energy_expected, pressure_expected) = potential.get_tail_integrals();
+            assert!((energy - energy_expected).abs() < 1e-6 * (1e-6 + energy_expected.abs()),
+                    "{energy} != {energy_expected}");
+            assert!((pressure - pressure_expected).abs() < 1e-6 * (1e-6 + pressure_expected.abs()),
+                    "{pressure} != {pressure_expected}");
+        }
+        assert_eq!(Potential::new_wca(sigma, eps).get_tail_integrals(), (0.0, 0.0));
+        let custom = Potential::Custom { name: String::from("custom"), custom_data: vec![] };
+        assert_eq!(custom.get_tail_integrals(), (0.0, 0.0));
+
+        // Standard Lennard-Jones tail corrections
+        let (state, _) = argon_fcc_state();
+        let potentials_db = PotentialsDatabase::new();
+        let (energy, pressure) = get_tail_corrections(&potentials_db, &state);
+        let density = 256.0 / state.get_volume();
+        let sigma_r_3 = (1.0f64 / 2.5).powi(3);
+        let sigma_3 = sigma.powi(3);
+        let expected_energy = 256.0 * 8.0 / 3.0 * std::f64::consts::PI * density * eps * sigma_3
+            * (sigma_r_3.powi(3) / 3.0 - sigma_r_3);
+        let expected_pressure = 16.0 / 3.0 * std::f64::consts::PI * density * density * eps * sigma_3
+            * (2.0 / 3.0 * sigma_r_3.powi(3) - sigma_r_3);
+        assert!((energy / expected_energy - 1.0).abs() < 1e-12);
+        assert!((pressure / expected_pressure - 1.0).abs() < 1e-12);
+        assert!(energy < 0.0 && pressure < 0.0);
+    }
+
+    fn copper_fcc_state(lattice_cell: f64) -> State {
+        let size = 4;
+        let basis = [[0.0, 0.0, 0.0], [0.5, 0.5, 0.0], [0.5, 0.0, 0.5], [0.0, 0.5, 0.5]];
+        let mut particles = vec![];
+        for (i, j, k) in (0..size * size * size).map(|n| (n % size, n / size % size, n / size / size)) {
+            for site in basis {
+                let position = Vector3::new(i as f64 + site[0], j as f64 + site[1], k as f64 + site[2]);
+                particles.push(Particle { position: position * lattice_cell, ..Default::default() });
+            }
+        }
+        State {
+            particles: vec![particles],
+            boundary_box: Vector3::new(1.0, 1.0, 1.0) * size as f64 * lattice_cell,
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
+        }
+    }
+
+    #[test]
+    fn eam_copper() {
+        // Cu EAM of Zhou, Johnson and Wadley (Phys. Rev. B 69, 144113) in setfl format
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/Cu_Zhou04.eam.alloy");
+        let setfl = std::fs::read(&path).expect("Can't read EAM file");
+        let eam = Eam::load_setfl(&path, &["Cu".to_string()]).unwrap();
+        assert!((eam.get_radius_cut() - 0.5715751993501819).abs() < 1e-12);
+        assert!(read_setfl(&mut setfl.as_slice(), &["Ni".to_string()]).is_err());
+        assert!(read_setfl(&mut &setfl[..5000], &["Cu".to_string()]).is_err());
+        let mut potentials_db = PotentialsDatabase::new();
+        potentials_db.set_eam(eam);
+        assert!(potentials_db.get_undefined_pairs(1).is_empty());
+
+        // Energy per atom and its minimum reproduce cohesive energy and lattice constant
+        let energy_per_atom = |potentials_db: &PotentialsDatabase, lattice_cell: f64| {
+            let mut state = copper_fcc_state(lattice_cell);
+            update_force(potentials_db, &mut state);
+            let atom = &state.particles[0][0];
+            assert!(atom.force.norm() < 1e-9);
+            assert!((atom.potential / 2.0 - get_potential_energy(&state, 0) / 256.0).abs() < 1e-9);
+            get_potential_energy(&state, 0) / 256.0
+        };
+        let (lattice_cell, step) = (0.3615, 0.001);
+        let energies = [lattice_cell - step, lattice_cell, lattice_cell + step]
+            .map(|lattice_cell| energy_per_atom(&potentials_db, lattice_cell));
+        let cohesive_energy = energies[1] / 160.2176634;
+        assert!((cohesive_energy + 3.54).abs() < 0.005, "{cohesive_energy} != -3.54");
+        let minimum = lattice_cell
+            - step * (energies[2] - energies[0]) / (2.0 * (energies[2] - 2.0 * energies[1] + energies[0]));
+        assert!((minimum - 0.3615).abs() < 2e-4, "{minimum} != 0.3615");
+        let mut state = copper_fcc_state(lattice_cell);
+        update_force(&potentials_db, &mut state);
+        assert!(get_system_pressure(&state, &Vector3::zeros()).abs() < 10.0);
+
+        // Forces are derivatives of energy, neighbor list gives the same forces
+        let mut state = copper_fcc_state(lattice_cell);
+        let mut rng = StdRng::seed_from_u64(25);
+        for particle in state.particles[0].iter_mut() {
+            particle.position += Vector3::new(rng.gen_range(-0.01..0.01),
+                                              rng.gen_range(-0.01..0.01),
+                                              rng.gen_range(-0.01..0.01));
+        }
+        state.apply_boundary_conditions();
+        update_force(&potentials_db, &mut state);
+        let total_energy = |state: &State| {
+            let mut state = state.clone();
+            update_force(&potentials_db, &mut state);
+            get_potential_energy(&state, 0)
+        };
+        let h = 1e-6;
+        for i in [0, 100] {
+            for axis in 0..3 {
+                let mut forward = state.clone();
+                forward.particles[0][i].position[axis] += h;
+                let mut backward = state.clone();
+                backward.particles[0][i].position[axis] -= h;
+                let numeric = -(total_energy(&forward) - total_energy(&backward)) / (2.0 * h);
+                let force = state.particles[0][i].force[axis];
+                assert!((force - numeric).abs() < 1e-3 * (1.0 + numeric.abs()), "{force} != {numeric}");
+            }
+        }
+        let mut neighbor_list_db = PotentialsDatabase::new();
+        neighbor_list_db.set_eam(potentials_db.get_eam().unwrap().clone());
+        neighbor_list_db.use_neighbor_list(0.05);
+        let mut neighbor_list_state = state.clone();
+        update_force(&neighbor_list_db, &mut neighbor_list_state);
+        assert_same_forces(&state, &neighbor_list_state);
+        assert_eq!(get_tail_corrections(&potentials_db, &state), (0.0, 0.0));
+    }
+
+    #[test]
+    fn eam_funcfl_units() {
+        // Constant effective charge `Z` without density and embedding: pair potential is
+        // `27.2 * 0.529 * Z^2 / r` eV
+        let (z, r) = (0.5, 2.5);
+        let mut funcfl = String::from("constant charge\n1 1.0 1.0 fcc\n100 0.01 500 0.01 4.0\n");
+        funcfl += &"0.0\n".repeat(100);
+        funcfl += &format!("{z}\n").repeat(500);
+        funcfl += &"0.0\n".repeat(500);
+        let mut potentials_db = PotentialsDatabase::new();
+        potentials_db.set_eam(read_funcfl(&mut funcfl.as_bytes(), 1).unwrap());
+        assert!((potentials_db.get_max_radius_cut() - 0.4).abs() < 1e-12);
+        let mut p2 = Particle::default();
+        p2.position.x = r * 0.1;
+        let mut state = State {
+            particles: vec![vec![Particle::default(), p2]],
+            boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
+        };
+        update_force(&potentials_db, &mut state);
+        let expected = 27.2 * 0.529 * z * z / r * 160.2176634;
+        let energy = get_potential_energy(&state, 0);
+        assert!((energy / expected - 1.0).abs() < 1e-6, "{energy} != {expected}");
+    }
+
     #[test]
     fn update_force_lennard_jones() {
         let p1 = Particle::default();
@@ -96,6 +516,8 @@ mod tests {
         let mut state = State {
             particles: vec![vec![p1, p2]],
             boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
         };
         let potentials_db = PotentialsDatabase::new();
         update_force(&potentials_db, &mut state);
@@ -106,6 +528,285 @@ mod tests {
         assert_eq!(format!("{:.8}", force_p1.z), "0.00000000");
     }
 
+    fn perturbed_argon_state(size: usize, lattice_cell: f64, seed: u64) -> State {
+        let _database = register_argon();
+        let bb = Vector3::new(size as f64, size as f64, size as f64) * lattice_cell;
+        let mut state = initialize_particles(&[size * size * size], &bb).unwrap();
+        initialize_particles_position(UnitCell::U, &mut state, 0, (0.0, 0.0, 0.0),
+                                      (size, size, size), lattice_cell)
+            .expect("Can't initialize particles");
+        let mut rng = StdRng::seed_from_u64(seed);
+        for particle in state.particles[0].iter_mut() {
+            particle.position += Vector3::new(rng.gen_range(-0.05..0.05),
+                                              rng.gen_range(-0.05..0.05),
+                                              rng.gen_range(-0.05..0.05));
+            particle.mass = 66.335;
+        }
+        state.apply_boundary_conditions();
+        state
+    }
+
+    fn assert_same_forces(state1: &State, state2: &State) {
+        for (p1, p2) in state1.particles[0].iter().zip(state2.particles[0].iter()) {
+            assert!((p1.force - p2.force).norm() < 1e-9 * p1.force.norm().max(1.0));
+            assert!((p1.potential - p2.potential).abs() < 1e-9 * p1.potential.abs().max(1.0));
+            assert!((p1.temp - p2.temp).abs() < 1e-9 * p1.temp.abs().max(1.0));
+            assert!((p1.virial - p2.virial).norm() < 1e-9 * p1.virial.norm().max(1.0));
+        }
+    }
+
+    #[test]
+    fn neighbor_list_forces() {
+        let mut state = perturbed_argon_state(10, 0.35, 42);
+        let mut state_neighbor_list = state.clone();
+        let potentials_db = PotentialsDatabase::new();
+        let mut potentials_db_neighbor_list = PotentialsDatabase::new();
+        potentials_db_neighbor_list.use_neighbor_list(0.1);
+        update_force(&potentials_db, &mut state);
+        update_force(&potentials_db_neighbor_list, &mut state_neighbor_list);
+        assert_same_forces(&state, &state_neighbor_list);
+        let mut verlet = Integrator::VerletMethod;
+        for _ in 0..20 {
+            verlet.calculate(&potentials_db, &mut state, 0.002, &mut None, &mut None);
+            verlet.calculate(&potentials_db_neighbor_list, &mut state_neighbor_list, 0.002,
+                             &mut None, &mut None);
+        }
+        assert_same_forces(&state, &state_neighbor_list);
+    }
+
+    #[test]
+    fn neighbor_list_rebuild() {
+        let mut state = perturbed_argon_state(6, 0.35, 7);
+        let potentials_db = PotentialsDatabase::new();
+        let mut neighbor_list = NeighborList::new(0.1);
+        assert!(neighbor_list.update(&potentials_db, &state));
+        assert!(!neighbor_list.update(&potentials_db, &state));
+        state.particles[0][0].position.x += 0.04;
+        assert!(!neighbor_list.update(&potentials_db, &state));
+        state.particles[0][0].position.x += 0.02;
+        assert!(neighbor_list.update(&potentials_db, &state));
+        assert_eq!(neighbor_list.get_rebuild_count(), 2);
+        // Reference positions follow the cell, list is kept until deformation eats the skin
+        let scale = |state: &mut State, factor: f64| {
+            state.boundary_box *= factor;
+            for particle in state.particles[0].iter_mut() {
+                particle.position *= factor;
+            }
+        };
+        scale(&mut state, 1.01);
+        assert!(!neighbor_list.needs_rebuild(&state));
+        scale(&mut state, 0.95 / 1.01);
+        assert!(!neighbor_list.needs_rebuild(&state));
+        state.particles[0][0].position.x += 0.03;
+        assert!(neighbor_list.needs_rebuild(&state));
+        state.particles[0][0].position.x -= 0.03;
+        scale(&mut state, 0.85 / 0.95);
+        assert!(neighbor_list.needs_rebuild(&state));
+    }
+
+    #[test]
+    fn neighbor_list_npt() {
+        let (mut state_neighbor_list, potentials_db_neighbor_list) = argon_fcc_state();
+        let mut state = state_neighbor_list.clone();
+        let mut potentials_db = PotentialsDatabase::new();
+        potentials_db.use_newton_third_law(true);
+        let mut barostat = Barostat::new_parrinello_rahman(0.5, 80.0, PressureCoupling::Triclinic);
+        let mut barostat_neighbor_list = Barostat::new_parrinello_rahman(0.5, 80.0, PressureCoupling::Triclinic);
+        let mut verlet = Integrator::VerletMethod;
+        let start_box = state.boundary_box;
+        let steps = 100;
+        for _ in 0..steps {
+            verlet.calculate(&potentials_db, &mut state, 0.002, &mut Some((&mut barostat, 100.0)), &mut None);
+            verlet.calculate(&potentials_db_neighbor_list, &mut state_neighbor_list, 0.002,
+                             &mut Some((&mut barostat_neighbor_list, 100.0)), &mut None);
+        }
+        assert_ne!(state.boundary_box, start_box);
+        assert_ne!(state.tilt, Vector3::zeros());
+        assert_same_forces(&state, &state_neighbor_list);
+        // List isn't rebuilt on every change of the box
+        let rebuild_count = potentials_db_neighbor_list.get_neighbor_list_rebuild_count().unwrap();
+        assert!(rebuild_count < steps / 4, "List was rebuilt {rebuild_count} times");
+    }
+
+    fn sheared_argon_state() -> State {
+        let mut state = perturbed_argon_state(8, 0.35, 21);
+        let fractional: Vec<Vector3<f64>> = state.particles[0].iter()
+            .map(|particle| state.to_fractional(&particle.position))
+            .collect();
+        state.tilt = Vector3::new(0.8, 0.4, -0.6);
+        let h = state.get_cell_matrix();
+        for (particle, fractional) in state.particles[0].iter_mut().zip(fractional.iter()) {
+            particle.position = h * fractional;
+        }
+        state
+    }
+
+    #[test]
+    fn triclinic_forces() {
+        let mut state = sheared_argon_state();
+        let potentials_db = PotentialsDatabase::new();
+        update_force(&potentials_db, &mut state);
+        // Nearest image is searched through all neighbor cells
+        let potential = potentials_db.get_potential(0, 0);
+        let h = state.get_cell_matrix();
+        let particles = &state.particles[0];
+        let mut expected_energy = 0.0;
+        for i in 0..particles.len() {
+            for j in (i + 1)..particles.len() {
+                let r = particles[j].position - particles[i].position;
+                let mut r_min = f64::MAX;
+                for x in -1..=1 {
+                    for y in -1..=1 {
+                        for z in -1..=1 {
+                            let image = r + h * Vector3::new(x as f64, y as f64, z as f64);
+                            r_min = r_min.min(image.norm());
+                        }
+                    }
+                }
+                if r_min <= potential.get_radius_cut() {
+                    expected_energy += potential.get_potential_and_force(r_min).0;
+                }
+            }
+        }
+        let energy: f64 = particles.iter().map(|particle| particle.potential).sum::<f64>() / 2.0;
+        assert!((energy - expected_energy).abs() < 1e-9 * expected_energy.abs());
+        let total_force: Vector3<f64> = particles.iter().map(|particle| particle.force).sum();
+        assert!(total_force.norm() < 1e-9);
+        let mut state_neighbor_list = state.clone();
+        let mut potentials_db_neighbor_list = PotentialsDatabase::new();
+        potentials_db_neighbor_list.use_neighbor_list(0.1);
+        potentials_db_neighbor_list.use_newton_third_law(true);
+        update_force(&potentials_db_neighbor_list, &mut state_neighbor_list);
+        assert_same_forces(&state, &state_neighbor_list);
+        // The same lattice with vectors b + a and c + 2a has tilt beyond half of box, it is
+        // reduced back
+        let mut state_sheared = state.clone();
+        state_sheared.tilt.x += state.boundary_box.x;
+        state_sheared.tilt.y += 2.0 * state.boundary_box.x;
+        update_force(&potentials_db, &mut state_sheared);
+        assert!((state_sheared.tilt - state.tilt).norm() < 1e-12);
+        assert_same_forces(&state, &state_sheared);
+        let mut verlet = Integrator::VerletMethod;
+        for _ in 0..5 {
+            verlet.calculate(&potentials_db, &mut state, 0.002, &mut None, &mut None);
+            verlet.calculate(&potentials_db_neighbor_list, &mut state_neighbor_list, 0.002,
+                             &mut None, &mut None);
+        }
+        assert_same_forces(&state, &state_neighbor_list);
+        for particle in state.particles[0].iter() {
+            let fractional = state.to_fractional(&particle.position);
+            assert!(fractional.iter().all(|s| (0.0..1.0).contains(s)));
+        }
+    }
+
+    #[test]
+    fn slab_boundary_conditions() {
+        let database = register_argon();
+        let p1 = Particle::new(0, Vector3::new(0.1, 0.1, 0.1), Vector3::zeros()).unwrap();
+        let p2 = Particle::new(0, Vector3::new(1.9, 0.1, 1.9), Vector3::zeros()).unwrap();
+        drop(database);
+        let mut state = State {
+            particles: vec![vec![p1, p2]],
+            boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
+        };
+        let potentials_db = PotentialsDatabase::new();
+        update_force(&potentials_db, &mut state);
+        assert_ne!(state.particles[0][0].force, Vector3::zeros());
+        // Particles interact only through periodic axes
+        state.boundary_conditions[2] = BoundaryCondition::Reflective;
+        update_force(&potentials_db, &mut state);
+        assert_eq!(state.particles[0][0].force, Vector3::zeros());
+
+        let mut state = perturbed_argon_state(6, 0.35, 5);
+        state.boundary_conditions[2] = BoundaryCondition::Reflective;
+        let _database = register_argon();
+        initialize_velocities_maxwell_boltzmann(&mut state, 300.0, 0);
+        let mut state_neighbor_list = state.clone();
+        let mut potentials_db_neighbor_list = PotentialsDatabase::new();
+        potentials_db_neighbor_list.use_neighbor_list(0.1);
+        potentials_db_neighbor_list.use_newton_third_law(true);
+        update_force(&potentials_db, &mut state);
+        update_force(&potentials_db_neighbor_list, &mut state_neighbor_list);
+        let mut verlet = Integrator::VerletMethod;
+        for _ in 0..20 {
+            verlet.calculate(&potentials_db, &mut state, 0.002, &mut None, &mut None);
+            verlet.calculate(&potentials_db_neighbor_list, &mut state_neighbor_list, 0.002,
+                             &mut None, &mut None);
+        }
+        assert_same_forces(&state, &state_neighbor_list);
+        assert!(state.particles[0].iter().all(|particle| {
+            particle.position.z >= 0.0 && particle.position.z <= state.boundary_box.z
+        }));
+    }
+
+    #[test]
+    fn newton_third_law_forces() {
+        let mut state = perturbed_argon_state(8, 0.35, 13);
+        let potentials_db = PotentialsDatabase::new();
+        update_force(&potentials_db, &mut state);
+        for use_neighbor_list in [false, true] {
+            let mut state_newton = state.clone();
+            let mut potentials_db_newton = PotentialsDatabase::new();
+            potentials_db_newton.use_newton_third_law(true);
+            if use_neighbor_list {
+                potentials_db_newton.use_neighbor_list(0.1);
+            }
+            update_force(&potentials_db_newton, &mut state_newton);
+            assert_same_forces(&state, &state_newton);
+            // Sums don't depend on count of threads
+            for threads_count in [1, 3] {
+                let mut state_threads = state.clone();
+                rayon::ThreadPoolBuilder::new().num_threads(threads_count).build().unwrap()
+                    .install(|| update_force(&potentials_db_newton, &mut state_threads));
+                for (p1, p2) in state_newton.particles[0].iter().zip(state_threads.particles[0].iter()) {
+                    assert_eq!((p1.force, p1.potential, p1.virial), (p2.force, p2.potential, p2.virial));
+                }
+            }
+            let mv = get_center_of_mass_velocity(&state, 0);
+            let pressure = get_pressure(&state, 0, &mv);
+            let pressure_newton = get_pressure(&state_newton, 0, &mv);
+            assert!((pressure - pressure_newton).abs() < 1e-9 * pressure.abs());
+            let potential = get_potential_energy(&state, 0);
+            let potential_newton = get_potential_energy(&state_newton, 0);
+            assert!((potential - potential_newton).abs() < 1e-9 * potential.abs());
+        }
+    }
+
+    #[test]
+    fn newton_third_law_macro_parameters() {
+        let mut p1 = Particle::default();
+        let mut p2 = Particle::default();
+        p1.position = Vector3::new(0.75, 0.75, 0.5);
+        p2.position = Vector3::new(1.25, 0.75, 0.5);
+        p1.velocity = Vector3::new(1.0, 1.0, 0.0);
+        p2.velocity = Vector3::new(-1.0, 1.0, 0.0);
+        p1.mass = 66.335;
+        p2.mass = 66.335;
+        let mut state = State {
+            particles: vec![vec![p1, p2]],
+            boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
+        };
+        let mut potentials_db = PotentialsDatabase::new();
+        potentials_db.use_newton_third_law(true);
+        let mut verlet = Integrator::VerletMethod;
+        update_force(&potentials_db, &mut state);
+        assert_eq!(format!("{:.8}", get_potential_energy(&state, 0)), "-0.59958655");
+        for _ in 0..999 {
+            verlet.calculate(&potentials_db, &mut state, 0.002, &mut None, &mut None);
+        }
+        let mv = get_center_of_mass_velocity(&state, 0);
+        let thermal = get_thermal_energy(&state, 0, &mv);
+        assert_eq!(format!("{:.8}", get_kinetic_energy(&state, 0)), "132.07134835");
+        assert_eq!(format!("{:.8}", thermal), "65.73634835");
+        assert_eq!(format!("{:.8}", get_potential_energy(&state, 0)), "0.00000000");
+        assert_eq!(format!("{:.8}", get_temperature(thermal, 2) / 100.0), "15.87088652");
+        assert_eq!(format!("{:.8}", get_pressure(&state, 0, &mv)), "5.47802903");
+    }
+
     #[test]
     fn verlet_with_lennard_jones() {
         let mut p1 = Particle::default();
@@ -119,8 +820,10 @@ mod tests {
         let mut state = State {
             particles: vec![vec![p1, p2]],
             boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
         };
-        let verlet = Integrator::VerletMethod;
+        let mut verlet = Integrator::VerletMethod;
         let potentials_db = PotentialsDatabase::new();
         update_force(&potentials_db, &mut state); // Initialize forces
         {
@@ -274,9 +977,11 @@ mod tests {
         let mut state = State {
             particles: vec![vec![p1, p2]],
             boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
         };
         let potentials_db = PotentialsDatabase::new();
-        let verlet = Integrator::VerletMethod;
+        let mut verlet = Integrator::VerletMethod;
         update_force(&potentials_db, &mut state); // Initialize forces
         for _ in 0..999 {
             verlet.calculate(&potentials_db, &mut state, 0.002, &mut None, &mut None);
@@ -344,6 +1049,8 @@ mod tests {
         let mut state = State {
             particles: vec![vec![p1, p2]],
             boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
         };
         let potentials_db = PotentialsDatabase::new();
         update_force(&potentials_db, &mut state);
@@ -389,6 +1096,8 @@ mod tests {
         let mut state = State {
             particles: vec![vec![p1, p2]],
             boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
         };
         let potentials_db = PotentialsDatabase::new();
         update_force(&potentials_db, &mut state);
@@ -415,6 +1124,8 @@ mod tests {
         let mut state = State {
             particles: vec![vec![p1, p2]],
             boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
         };
         let potentials_db = PotentialsDatabase::new();
         update_force(&potentials_db, &mut state);
@@ -426,11 +1137,312 @@ mod tests {
         );
     }
 
+    #[test]
+    fn pressure_tensor() {
+        let mut p1 = Particle::default();
+        let mut p2 = Particle::default();
+        p1.position = Vector3::new(0.75, 0.75, 0.5);
+        p2.position = Vector3::new(1.05, 1.05, 0.5);
+        p1.velocity = Vector3::new(1.0, 1.0, 0.0);
+        p2.velocity = Vector3::new(-1.0, 1.0, 0.0);
+        p1.mass = 66.335;
+        p2.mass = 66.335;
+        let mut state = State {
+            particles: vec![vec![p1, p2]],
+            boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            tilt: Vector3::zeros(),
+            boundary_conditions: Default::default(),
+        };
+        let potentials_db = PotentialsDatabase::new();
+        update_force(&potentials_db, &mut state);
+        for particle in &state.particles[0] {
+            assert!((particle.virial.trace() - particle.temp).abs() < 1e-12);
+        }
+        let mv = get_center_of_mass_velocity(&state, 0);
+        let pressure = get_pressure(&state, 0, &mv);
+        let pressure_tensor = get_pressure_tensor(&state, 0, &mv);
+        assert!((pressure_tensor.trace() / 3.0 - pressure).abs() < 1e-12);
+        assert!((pressure_tensor - pressure_tensor.transpose()).norm() < 1e-12);
+        // Particles are placed along the xy diagonal, so virial part of xy-component is non-zero
+        assert!(pressure_tensor[(0, 1)].abs() > 1e-3);
+        assert_eq!(pressure_tensor[(0, 2)], 0.0);
+    }
+
+    #[test]
+    fn mixture_macro_parameters() {
+        let particle = |position: Vector3<f64>, velocity: Vector3<f64>, mass: f64| {
+            Particle { position, velocity, mass, ..Default::default() }
+        };
+        // Species move in opposite directions, so system is at rest and each species has
+        // non-zero thermal energy relative to system center of mass
+        let light = vec![
+            particle(Vector3::new(0.5, 0.5, 0.5), Vector3::new(1.0, 0.0, 0.0), 66.335),
+            particle(Vector3::new(1.5, 0.5, 0.5), Vector3::new(1.0, 0.0, 0.0), 66.335),
+        ];
+        let heavy = vec![
+            particle(Vector3::new(1.0, 1.4, 0.5), Vector3::new(-1.0, 0.0, 0.0), 132.67),
+        ];
+        let mut state = State {
+            particles: vec![light, heavy],
+            boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            ..Default::default()
+        };
+        let potentials_db = PotentialsDatabase::new();
+        update_force(&potentials_db, &mut state);
+        let mv = get_system_center_of_mass_velocity(&state);
+        assert!(mv.norm() < 1e-12);
+        assert_eq!(get_center_of_mass_velocity(&state, 0), Vector3::new(1.0, 0.0, 0.0));
+        let thermal_energy = get_system_thermal_energy(&state, &mv);
+        assert!((thermal_energy - get_system_kinetic_energy(&state)).abs() < 1e-9);
+        let temperature = get_temperature(thermal_energy, get_particles_count(&state));
+        let species_temperature: f64 = (0..2).map(|particle_type| {
+            let e = get_thermal_energy(&state, particle_type as u16, &mv);
+            get_temperature(e, state.particles[particle_type].len())
+                * state.particles[particle_type].len() as f64
+        }).sum::<f64>() / 3.0;
+        assert!((temperature - species_temperature).abs() < 1e-9);
+        assert!(get_thermal_energy(&state, 1, &mv) > 0.0);
+        let pressure = get_system_pressure(&state, &mv);
+        let pressure_tensor = get_system_pressure_tensor(&state, &mv);
+        assert!((pressure_tensor.trace() / 3.0 - pressure).abs() < 1e-9);
+        // For one species system values are the same as values of this species
+        let single = State {
+            particles: vec![state.particles[0].clone()],
+            ..state.clone()
+        };
+        assert_eq!(get_system_center_of_mass_velocity(&single), get_center_of_mass_velocity(&single, 0));
+        assert_eq!(get_system_pressure(&single, &mv), get_pressure(&single, 0, &mv));
+    }
+
+    fn stochastic_thermostat_run(thermostat: &mut Thermostat, state: &mut State, steps: usize) -> f64 {
+        let potentials_db = PotentialsDatabase::new();
+        let mut verlet = Integrator::VerletMethod;
+        update_force(&potentials_db, state);
+        let mut temperature_sum = 0.0;
+        for step in 0..steps {
+            verlet.calculate(&potentials_db, state, 0.002, &mut None, &mut Some((thermostat, 120.0)));
+            if step >= steps / 4 {
+                let mv = get_center_of_mass_velocity(state, 0);
+                let thermal_energy = get_thermal_energy(state, 0, &mv);
+                temperature_sum += get_temperature(thermal_energy, state.particles[0].len());
+            }
+        }
+        temperature_sum / (steps - steps / 4) as f64
+    }
+
+    fn stochastic_thermostat_test(new_thermostat: fn(u64) -> Thermostat) {
+        let mut state = perturbed_argon_state(3, 1.0, 3);
+        let _database = register_argon();
+        initialize_velocities_maxwell_boltzmann(&mut state, 300.0, 0);
+        let mut state1 = state.clone();
+        let mut state2 = state.clone();
+        let temperature = stochastic_thermostat_run(&mut new_thermostat(1), &mut state1, 6000);
+        assert!((temperature - 120.0).abs() < 12.0, "Average temperature is {temperature}");
+        let _ = stochastic_thermostat_run(&mut new_thermostat(1), &mut state2, 6000);
+        for (p1, p2) in state1.particles[0].iter().zip(state2.particles[0].iter()) {
+            assert_eq!(p1.position, p2.position);
+            assert_eq!(p1.velocity, p2.velocity);
+        }
+    }
+
+    #[test]
+    fn langevin_thermostat() {
+        stochastic_thermostat_test(|seed| Thermostat::new_langevin(5.0, seed));
+    }
+
+    #[test]
+    fn andersen_thermostat() {
+        stochastic_thermostat_test(|seed| Thermostat::new_andersen(5.0, seed));
+    }
+
+    #[test]
+    fn csvr_thermostat() {
+        stochastic_thermostat_test(|seed| Thermostat::new_csvr(0.1, seed));
+    }
+
+    #[test]
+    fn nose_hoover_chain_conserved_energy() {
+        let (mut state, potentials_db) = argon_fcc_state();
+        let mut thermostat = Thermostat::new_nose_hoover(0.1, 3);
+        let mut verlet = Integrator::VerletMethod;
+        let start_energy = thermostat.get_conserved_energy(&state, 120.0);
+        let start_kinetic_energy = get_kinetic_energy(&state, 0);
+        let mut max_deviation: f64 = 0.0;
+        for _ in 0..300 {
+            verlet.calculate(&potentials_db, &mut state, 0.002, &mut None,
+                             &mut Some((&mut thermostat, 120.0)));
+            let energy = thermostat.get_conserved_energy(&state, 120.0);
+            max_deviation = max_deviation.max((energy - start_energy).abs());
+        }
+        // Thermostat heats the system, but energy of extended system stays the same
+        assert!((get_kinetic_energy(&state, 0) - start_kinetic_energy).abs()
+            > 0.1 * start_kinetic_energy);
+        assert!(thermostat.get_energy(&state, 120.0).abs() > 10.0 * max_deviation);
+        assert!(max_deviation / start_energy.abs() < 5e-4, "Relative drift is {}",
+                max_deviation / start_energy.abs());
+    }
+
+    #[test]
+    fn nose_hoover_chain_restart() {
+        let (mut state, potentials_db) = argon_fcc_state();
+        let mut thermostat = Thermostat::new_nose_hoover(0.1, 3);
+        let mut verlet = Integrator::VerletMethod;
+        for _ in 0..50 {
+            verlet.calculate(&potentials_db, &mut state, 0.002, &mut None,
+                             &mut Some((&mut thermostat, 120.0)));
+        }
+        let saved = thermostat.to_save(120.0);
+        assert_eq!(saved.len(), 3);
+        let mut restarted_state = state.clone();
+        let mut restarted_thermostat = Thermostat::new_nose_hoover(0.1, 3);
+        restarted_thermostat.load_saved(&saved);
+        assert_eq!(restarted_thermostat.get_energy(&restarted_state, 120.0),
+                   thermostat.get_energy(&state, 120.0));
+        for _ in 0..50 {
+            verlet.calculate(&potentials_db, &mut state, 0.002, &mut None,
+                             &mut Some((&mut thermostat, 120.0)));
+            verlet.calculate(&potentials_db, &mut restarted_state, 0.002, &mut None,
+                             &mut Some((&mut restarted_thermostat, 120.0)));
+        }
+        assert_eq!(thermostat.to_save(120.0), restarted_thermostat.to_save(120.0));
+        for (p1, p2) in state.particles[0].iter().zip(restarted_state.particles[0].iter()) {
+            assert_eq!(p1.position, p2.position);
+            assert_eq!(p1.velocity, p2.velocity);
+        }
+    }
+
+    /// Integrator, thermostat, barostat and whether Newton's third law is used
+    type SimulationSetup = (fn() -> Integrator, fn() -> Option<(Thermostat, f64)>, fn() -> Option<(Barostat, f64)>, bool);
+
+    fn argon_simulation(state: &State, (integrator, thermostat, barostat, newton_third_law): SimulationSetup) -> Simulation {
+        let mut potentials_db = PotentialsDatabase::new();
+        potentials_db.use_neighbor_list(0.1);
+        potentials_db.use_newton_third_law(newton_third_law);
+        Simulation::new(state.clone(), potentials_db, integrator(), thermostat(), barostat(), 0.002)
+    }
+
+    #[test]
+    fn checkpoint_restart() {
+        let setups: [SimulationSetup; 4] = [
+            (|| Integrator::VerletMethod, || Some((Thermostat::new_langevin(5.0, 1), 100.0)),
+             || Some((Barostat::new_mtk(0.5, 100.0), 0.1)), false),
+            (Integrator::new_leapfrog, || Some((Thermostat::new_csvr(0.1, 2), 100.0)), || None, true),
+            (Integrator::new_beeman, || Some((Thermostat::new_nose_hoover(0.1, 3), 100.0)), || None, false),
+            (|| Integrator::new_respa(2, 0.6, 0.7), || Some((Thermostat::new_andersen(5.0, 3), 100.0)),
+             || Some((Barostat::new_parrinello_rahman(0.5, 100.0, PressureCoupling::Triclinic), 0.1)), true),
+        ];
+        for setup in setups {
+            // N + M steps without stop and N steps, checkpoint and M steps after restart
+            let (state, _) = argon_fcc_state();
+            let mut continuous = argon_simulation(&state, setup);
+            let mut stopped = argon_simulation(&state, setup);
+            continuous.green_kubo = Some(GreenKubo::new(4, 2));
+            stopped.green_kubo = Some(GreenKubo::new(4, 2));
+            for _ in 0..20 {
+                continuous.step();
+            }
+            for _ in 0..10 {
+                stopped.step();
+            }
+            let mut checkpoint = vec![];
+            stopped.write_checkpoint(&mut checkpoint).unwrap();
+            drop(stopped);
+            let mut restarted = Simulation::read_checkpoint(&mut &checkpoint[..]).unwrap();
+            // Restarted simulation may use another amount of threads
+            rayon::ThreadPoolBuilder::new().num_threads(3).build().unwrap().install(|| {
+                for _ in 0..10 {
+                    restarted.step();
+                }
+            });
+            assert_eq!(restarted.iteration, 20);
+            assert_eq!(continuous.state.boundary_box, restarted.state.boundary_box);
+            assert_eq!(continuous.state.tilt, restarted.state.tilt);
+            for (p1, p2) in continuous.state.particles[0].iter().zip(restarted.state.particles[0].iter()) {
+                assert_eq!(p1.position, p2.position);
+                assert_eq!(p1.velocity, p2.velocity);
+                assert_eq!(p1.force, p2.force);
+            }
+            let (saved1, saved2) = (continuous.to_save(), restarted.to_save());
+            assert_eq!(saved1.thermostat, saved2.thermostat);
+            assert_eq!(saved1.barostat, saved2.barostat);
+            let (green_kubo1, green_kubo2) = (continuous.green_kubo.unwrap(), restarted.green_kubo.unwrap());
+            assert_eq!(green_kubo1.get_samples_count(), 10);
+            assert_eq!(green_kubo1.get_pressure_acf(), green_kubo2.get_pressure_acf());
+            assert_eq!(green_kubo1.get_heat_flux_acf(), green_kubo2.get_heat_flux_acf());
+        }
+        assert!(Simulation::read_checkpoint(&mut &b"MOLDYNTR\x01\0\0\0"[..]).is_err());
+    }
+
+    fn npt_argon_energy_drift(barostat: &mut Barostat, target_pressure: f64, steps: usize) -> (State, f64) {
+        let (mut state, potentials_db) = argon_fcc_state();
+        let mut verlet = Integrator::VerletMethod;
+        let energy = |state: &State, barostat: &Barostat| get_kinetic_energy(state, 0)
+            + get_potential_energy(state, 0) + barostat.get_energy(state, target_pressure);
+        verlet.calculate(&potentials_db, &mut state, 0.002, &mut Some((barostat, target_pressure)), &mut None);
+        let start_energy = energy(&state, barostat);
+        let mut max_deviation: f64 = 0.0;
+        for _ in 1..steps {
+            verlet.calculate(&potentials_db, &mut state, 0.002, &mut Some((barostat, target_pressure)), &mut None);
+            max_deviation = max_deviation.max((energy(&state, barostat) - start_energy).abs());
+        }
+        (state, max_deviation / start_energy.abs())
+    }
+
+    #[test]
+    fn mtk_barostat() {
+        let mut barostat = Barostat::new_mtk(0.5, 80.0);
+        let (state, drift) = npt_argon_energy_drift(&mut barostat, 100.0, 150);
+        assert!(drift < 1e-4, "Relative drift is {drift}");
+        let bb = state.boundary_box;
+        assert!((bb.x - 4.0 * 0.5256).abs() > 1e-3);
+        assert_eq!(bb.x, bb.y);
+        assert_eq!(bb.x, bb.z);
+        let mut restored = Barostat::new_mtk(0.5, 80.0);
+        restored.load_saved(&barostat.to_save(100.0));
+        assert_eq!(restored.get_box_velocity(), barostat.get_box_velocity());
+        assert_eq!(restored.get_energy(&state, 100.0), barostat.get_energy(&state, 100.0));
+    }
+
+    #[test]
+    fn parrinello_rahman_barostat() {
+        let mut barostat = Barostat::new_parrinello_rahman(0.5, 80.0, PressureCoupling::SemiIsotropic);
+        let (state, drift) = npt_argon_energy_drift(&mut barostat, 100.0, 150);
+        assert!(drift < 1e-4, "Relative drift is {drift}");
+        let bb = state.boundary_box;
+        assert_eq!(bb.x, bb.y);
+        assert_ne!(bb.x, bb.z);
+        let saved = barostat.to_save(100.0);
+        assert_eq!(saved.len(), 2);
+        let mut restored = Barostat::new_parrinello_rahman(0.5, 80.0, PressureCoupling::SemiIsotropic);
+        restored.load_saved(&saved);
+        assert_eq!(restored.get_box_velocity(), barostat.get_box_velocity());
+
+        let mut barostat = Barostat::new_parrinello_rahman(0.5, 80.0, PressureCoupling::Anisotropic);
+        let (state, drift) = npt_argon_energy_drift(&mut barostat, 100.0, 150);
+        assert!(drift < 1e-4, "Relative drift is {drift}");
+        let bb = state.boundary_box;
+        assert_ne!(bb.x, bb.y);
+        assert_ne!(bb.y, bb.z);
+        assert_eq!(state.tilt, Vector3::zeros());
+
+        let mut barostat = Barostat::new_parrinello_rahman(0.5, 80.0, PressureCoupling::Triclinic);
+        let (state, drift) = npt_argon_energy_drift(&mut barostat, 100.0, 150);
+        assert!(drift < 1e-4, "Relative drift is {drift}");
+        assert!(state.tilt.iter().all(|tilt| *tilt != 0.0));
+        let saved = barostat.to_save(100.0);
+        assert_eq!(saved.len(), 6);
+        let mut restored = Barostat::new_parrinello_rahman(0.5, 80.0, PressureCoupling::Triclinic);
+        restored.load_saved(&saved);
+        assert_eq!(restored.get_box_velocity(), barostat.get_box_velocity());
+        assert_eq!(restored.get_shear_velocity(), barostat.get_shear_velocity());
+        assert_eq!(restored.get_energy(&state, 100.0), barostat.get_energy(&state, 100.0));
+    }
+
     #[ignore]
     #[test]
     fn berendsen_thermostat () {
         let bb = Vector3::new(2.0, 2.0, 2.0) * 3.338339;
-        ParticleDatabase::add(0, "Argon", 66.335, 0.071);
+        let _database = register_argon();
         let mut state = initialize_particles(&[8], &bb).unwrap();
         initialize_particles_position(UnitCell::U, &mut state, 0,
                                       (0.0, 0.0, 0.0), (2, 2, 2), 3.338339)
@@ -442,7 +1454,7 @@ mod tests {
             tau: 0.5,
             lambda: 0.0,
         };
-        let verlet = Integrator::VerletMethod;
+        let mut verlet = Integrator::VerletMethod;
         for _ in 0..100000 {
             verlet.calculate(&potentials_db, &mut state, 0.002, &mut None, &mut Some((&mut berendsen, 273.15)));
         }
@@ -457,7 +1469,7 @@ mod tests {
     #[test]
     fn berendsen_barostat () {
         let bb = Vector3::new(2.0, 2.0, 2.0) * 3.338339;
-        ParticleDatabase::add(0, "Argon", 66.335, 0.071);
+        let _database = register_argon();
         let mut state = initialize_particles(&[8], &bb).unwrap();
         let potentials_db = PotentialsDatabase::new();
         initialize_particles_position(UnitCell::U, &mut state, 0,
@@ -470,7 +1482,7 @@ mod tests {
             tau: 0.1,
             myu: 0.0,
         };
-        let verlet = Integrator::VerletMethod;
+        let mut verlet = Integrator::VerletMethod;
         for _ in 0..100000 {
             verlet.calculate(&potentials_db, &mut state, 0.002, &mut Some((&mut berendsen, 0.101325)), &mut None);
         }
@@ -480,4 +1492,241 @@ mod tests {
         println!("{:.15} ", pressure);
         assert!((pressure - 0.101325).abs() < 1e-5);
     }
+
+    #[test]
+    fn radial_distribution() {
+        // Every particle of FCC lattice has 12 nearest neighbors at distance a / sqrt(2)
+        let (state, _) = argon_fcc_state();
+        let rdf = RadialDistribution::from_state(&state, 0.5, 100);
+        let first_shell = 0.5256 / 2.0f64.sqrt();
+        let bin = (first_shell / rdf.get_bin_width()) as usize;
+        let coordination_number = rdf.get_coordination_number(0, 0);
+        assert_eq!(coordination_number[bin - 1], 0.0);
+        assert!((coordination_number[bin] - 12.0).abs() < 1e-12);
+        assert!((coordination_number[99] - 12.0).abs() < 1e-12);
+        assert!(rdf.get_rdf(0, 0)[bin] > 10.0);
+        assert_eq!(rdf.get_total_rdf(), rdf.get_rdf(0, 0));
+
+        // Ideal gas mixture: g(r) is close to 1 for every pair of types
+        let mut rng = StdRng::seed_from_u64(17);
+        let bb = Vector3::new(3.0, 3.0, 3.0);
+        let mut random_particles = |count: usize| -> Vec<Particle> {
+            (0..count).map(|_| Particle {
+                position: Vector3::new(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>()).component_mul(&bb),
+                mass: 1.0,
+                ..Default::default()
+            }).collect()
+        };
+        let mut rdf = RadialDistribution::new(1.5, 30);
+        for _ in 0..4 {
+            let state = State {
+                particles: vec![random_particles(600), random_particles(300)],
+                boundary_box: bb,
+                ..Default::default()
+            };
+            rdf.add_state(&state);
+        }
+        assert_eq!(rdf.get_frames_count(), 4);
+        assert_eq!(rdf.get_particle_types_count(), 2);
+        let average = |g: Vec<f64>| g[10..].iter().sum::<f64>() / 20.0;
+        for (a, b) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            assert!((average(rdf.get_rdf(a, b)) - 1.0).abs() < 0.05);
+        }
+        assert!((average(rdf.get_total_rdf()) - 1.0).abs() < 0.02);
+        let coordination_number = |a: usize, b: usize| rdf.get_coordination_number(a, b)[29];
+        let total = (coordination_number(0, 0) + coordination_number(0, 1)) * 600.0 / 900.0
+            + (coordination_number(1, 0) + coordination_number(1, 1)) * 300.0 / 900.0;
+        assert!((rdf.get_total_coordination_number()[29] - total).abs() < 1e-9);
+        let ideal = 899.0 / 27.0 * 4.0 / 3.0 * std::f64::consts::PI * 1.5f64.powi(3);
+        assert!((total / ideal - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn mean_squared_displacement() {
+        // Ballistic motion through small periodic box: MSD = (v t)^2, particles cross the box
+        // many times, so positions should be unwrapped
+        let particle = |velocity: Vector3<f64>| Particle {
+            position: Vector3::new(0.5, 0.5, 0.5),
+            velocity,
+            ..Default::default()
+        };
+        let mut state = State {
+            particles: vec![vec![particle(Vector3::new(3.0, 0.0, 0.0)), particle(Vector3::new(0.0, -4.0, 0.0))],
+                            vec![particle(Vector3::new(1.0, 2.0, 2.0))]],
+            boundary_box: Vector3::new(1.0, 1.0, 1.0),
+            tilt: Vector3::new(0.2, -0.1, 0.3),
+            ..Default::default()
+        };
+        let delta_time = 0.1;
+        let mut msd = MeanSquaredDisplacement::new(10, 3);
+        for _ in 0..30 {
+            msd.add_state(&state);
+            state.particles.iter_mut().flatten().for_each(|particle| {
+                particle.position += particle.velocity * delta_time;
+            });
+            state.apply_boundary_conditions();
+        }
+        assert_eq!(msd.get_frames_count(), 30);
+        let (first, second, total) = (msd.get_msd(0), msd.get_msd(1), msd.get_total_msd());
+        assert_eq!(total.len(), 11);
+        for lag in 0..=10 {
+            let t = lag as f64 * delta_time;
+            assert!((first[lag] - 12.5 * t * t).abs() < 1e-9);
+            assert!((second[lag] - 9.0 * t * t).abs() < 1e-9);
+            assert!((total[lag] - (25.0 + 9.0) / 3.0 * t * t).abs() < 1e-9);
+        }
+
+        // Random walk with known diffusion coefficient
+        let mut rng = StdRng::seed_from_u64(18);
+        let normal = rand_distr::Normal::new(0.0, 0.05).unwrap();
+        let mut state = State {
+            particles: vec![(0..500).map(|_| particle(Vector3::zeros())).collect()],
+            boundary_box: Vector3::new(1.0, 1.0, 1.0),
+            ..Default::default()
+        };
+        let mut msd = MeanSquaredDisplacement::new(20, 5);
+        for _ in 0..200 {
+            msd.add_state(&state);
+            state.particles[0].iter_mut().for_each(|particle| {
+                particle.position += Vector3::from_fn(|_, _| normal.sample(&mut rng));
+            });
+            state.apply_boundary_conditions();
+        }
+        let times: Vec<f64> = (0..=20).map(|lag| lag as f64 * delta_time).collect();
+        let diffusion = get_diffusion_coefficient(&times[5..], &msd.get_total_msd()[5..]);
+        let expected = 0.05 * 0.05 / (2.0 * delta_time);
+        assert!((diffusion / expected - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn velocity_autocorrelation() {
+        // Ornstein-Uhlenbeck velocities: C(t) = 3 sigma^2 exp(-t / tau) and D = sigma^2 tau.
+        // Green-Kubo and Einstein (MSD) diffusion coefficients should agree.
+        let (delta_time, tau, sigma): (f64, f64, f64) = (0.01, 0.1, 0.5);
+        let a = (-delta_time / tau).exp();
+        let mut rng = StdRng::seed_from_u64(19);
+        let normal = rand_distr::Normal::new(0.0, sigma).unwrap();
+        let mut state = State {
+            particles: vec![(0..400).map(|_| Particle {
+                velocity: Vector3::from_fn(|_, _| normal.sample(&mut rng)),
+                ..Default::default()
+            }).collect()],
+            boundary_box: Vector3::new(1.0, 1.0, 1.0),
+            ..Default::default()
+        };
+        let mut vacf = VelocityAutocorrelation::new(60, 2);
+        let mut msd = MeanSquaredDisplacement::new(60, 2);
+        for _ in 0..1000 {
+            vacf.add_state(&state);
+            msd.add_state(&state);
+            state.particles[0].iter_mut().for_each(|particle| {
+                particle.position += particle.velocity * delta_time;
+                particle.velocity = particle.velocity * a
+                    + Vector3::from_fn(|_, _| normal.sample(&mut rng)) * (1.0 - a * a).sqrt();
+            });
+            state.apply_boundary_conditions();
+        }
+        let c = vacf.get_total_vacf();
+        assert_eq!(c, vacf.get_vacf(0));
+        assert!((c[0] / (3.0 * sigma * sigma) - 1.0).abs() < 0.02);
+        assert!((normalize_vacf(&c)[10] - (-1.0f64).exp()).abs() < 0.02);
+        let expected = sigma * sigma * tau;
+        let green_kubo = get_green_kubo_diffusion_coefficient(&c, delta_time);
+        let times: Vec<f64> = (0..=60).map(|lag| lag as f64 * delta_time).collect();
+        let einstein = get_diffusion_coefficient(&times[30..], &msd.get_total_msd()[30..]);
+        assert!((green_kubo / expected - 1.0).abs() < 0.05);
+        assert!((einstein / expected - 1.0).abs() < 0.05);
+        assert!((green_kubo / einstein - 1.0).abs() < 0.05);
+
+        // Velocities oscillating with frequency 5 THz give peak of DOS at 5 THz
+        let frequency = 5.0;
+        let phases: Vec<f64> = (0..200).map(|_| rng.gen::<f64>() * 2.0 * std::f64::consts::PI).collect();
+        let mut vacf = VelocityAutocorrelation::new(100, 1);
+        for frame in 0..400 {
+            let t = frame as f64 * delta_time;
+            state.particles[0] = phases.iter().map(|phase| Particle {
+                velocity: Vector3::repeat((2.0 * std::f64::consts::PI * frequency * t + phase).cos()),
+                ..Default::default()
+            }).collect();
+            vacf.add_state(&state);
+        }
+        let (frequencies, dos) = get_density_of_states(&vacf.get_total_vacf(), delta_time);
+        assert_eq!(frequencies.len(), 101);
+        assert!((frequencies[100] - 0.5 / delta_time).abs() < 1e-9);
+        let peak = (0..dos.len()).max_by(|i, j| dos[*i].total_cmp(&dos[*j])).unwrap();
+        assert!((frequencies[peak] - frequency).abs() < 1e-9);
+        let df = frequencies[1] - frequencies[0];
+        let integral = get_running_integral(&dos, df).last().copied().unwrap();
+        assert!((integral - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn green_kubo() {
+        // Streaming correlator gives the same values as direct sum over time origins
+        let mut rng = StdRng::seed_from_u64(20);
+        let samples: Vec<Vec<f64>> = (0..50).map(|_| (0..3).map(|_| rng.gen_range(-1.0..1.0)).collect())
+            .collect();
+        let mut correlator = TimeCorrelator::new(10);
+        samples.iter().for_each(|sample| correlator.add_sample(sample.clone()));
+        let correlation = correlator.get_correlation();
+        assert_eq!(correlation.len(), 11);
+        for (lag, value) in correlation.iter().enumerate() {
+            let direct = (0..50 - lag).map(|t0| {
+                (0..3).map(|i| samples[t0][i] * samples[t0 + lag][i]).sum::<f64>() / 3.0
+            }).sum::<f64>() / (50 - lag) as f64;
+            assert!((value - direct).abs() < 1e-12);
+        }
+        let mut short = TimeCorrelator::new(10);
+        short.add_sample(vec![2.0]);
+        short.add_sample(vec![-2.0]);
+        assert_eq!(short.get_correlation(), vec![4.0, -4.0]);
+
+        // Heat flux of two particles: J V = sum(e_i v_i) + 1/2 sum((F_ij v_i) r_ij)
+        let mut state = State {
+            particles: vec![vec![
+                Particle { velocity: Vector3::new(0.3, -0.2, 0.1), ..Default::default() },
+                Particle {
+                    position: Vector3::new(0.4, 0.2, 0.0),
+                    velocity: Vector3::new(-0.3, 0.2, -0.1),
+                    ..Default::default()
+                },
+            ]],
+            boundary_box: Vector3::new(2.0, 2.0, 2.0),
+            ..Default::default()
+        };
+        update_force(&PotentialsDatabase::new(), &mut state);
+        let r = state.particles[0][1].position - state.particles[0][0].position;
+        let (potential, force) = Potential::new_lennard_jones(0.3418, 1.712)
+            .get_potential_and_force(r.norm());
+        let force = r.normalize() * force;
+        let expected: Vector3<f64> = state.particles[0].iter().enumerate().map(|(i, particle)| {
+            let (force, r) = if i == 0 { (force, -r) } else { (-force, r) };
+            let energy = particle.mass * particle.velocity.norm_squared() / 2.0 + potential / 2.0;
+            particle.velocity * energy + r * force.dot(&particle.velocity) / 2.0
+        }).sum::<Vector3<f64>>() / state.get_volume();
+        let heat_flux = get_heat_flux(&state, &Vector3::zeros());
+        assert!((heat_flux - expected).norm() < 1e-12);
+        // Motion of the whole system doesn't carry heat
+        let shift = Vector3::new(1.0, 2.0, 3.0);
+        state.particles[0].iter_mut().for_each(|particle| particle.velocity = shift);
+        assert!(get_heat_flux(&state, &shift).norm() < 1e-12);
+
+        // Transport coefficients of argon crystal are positive at start of correlation
+        let (state, potentials_db) = argon_fcc_state();
+        let mut simulation = Simulation::new(state, potentials_db, Integrator::VerletMethod,
+                                             None, None, 0.002);
+        simulation.green_kubo = Some(GreenKubo::new(20, 2));
+        for _ in 0..100 {
+            simulation.step();
+        }
+        let green_kubo = simulation.green_kubo.unwrap();
+        assert_eq!(green_kubo.get_samples_count(), 50);
+        assert!(green_kubo.get_temperature() > 10.0);
+        let viscosity = green_kubo.get_viscosity(0.002);
+        let thermal_conductivity = green_kubo.get_thermal_conductivity(0.002);
+        assert_eq!(viscosity.len(), 21);
+        assert_eq!(viscosity[0], 0.0);
+        assert!(viscosity[1] > 0.0 && viscosity[20].is_finite());
+        assert!(thermal_conductivity[1] > 0.0 && thermal_conductivity[20].is_finite());
+    }
 }
@@ -47,3 +47,25 @@ pub fn get_potential_energy(state: &State, particle_type_id: u16) -> f64 {
         .sum();
     res / 2.0
 }
+
+/// Get summary kinetic energy of all particles
+pub fn get_system_kinetic_energy(state: &State) -> f64 {
+    (0..state.particles.len())
+        .map(|particle_type| get_kinetic_energy(state, particle_type as u16))
+        .sum()
+}
+
+/// Get summary thermal energy of all particles. `center_of_mass_velocity` should be velocity of
+/// center of mass of all particles.
+pub fn get_system_thermal_energy(state: &State, center_of_mass_velocity: &Vector3<f64>) -> f64 {
+    (0..state.particles.len())
+        .map(|particle_type| get_thermal_energy(state, particle_type as u16, center_of_mass_velocity))
+        .sum()
+}
+
+/// Get summary potential energy of all particles
+pub fn get_system_potential_energy(state: &State) -> f64 {
+    (0..state.particles.len())
+        .map(|particle_type| get_potential_energy(state, particle_type as u16))
+        .sum()
+}
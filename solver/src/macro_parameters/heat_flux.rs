@@ -0,0 +1,20 @@
+use moldyn_core::State;
+use na::Vector3;
+
+/// Get heat flux of all particles in Irving-Kirkwood form for pair forces
+/// `J = (sum(e_i v_i) + 1/2 sum((F_ij v_i) r_ij)) / V`, where `e_i` is kinetic energy and half of
+/// potential energy of particle `i` and `r_ij = r_i - r_j`. The pair sum is taken from virials of
+/// particles ([moldyn_core::Particle::virial]).
+///
+/// Forces, potentials and virials should be calculated by
+/// [update_force](crate::solver::update_force) for current positions. Velocities are taken
+/// relative to `center_of_mass_velocity`, it should be velocity of center of mass of all
+/// particles.
+pub fn get_heat_flux(state: &State, center_of_mass_velocity: &Vector3<f64>) -> Vector3<f64> {
+    let flux: Vector3<f64> = state.particles.iter().flatten().map(|particle| {
+        let velocity = particle.velocity - center_of_mass_velocity;
+        let energy = particle.mass * velocity.norm_squared() / 2.0 + particle.potential / 2.0;
+        velocity * energy - particle.virial.transpose() * velocity / 2.0
+    }).sum();
+    flux / state.get_volume()
+}
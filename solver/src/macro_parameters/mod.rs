@@ -1,12 +1,16 @@
 mod energy;
+mod heat_flux;
 mod temperature;
 mod pressure;
+mod tail_correction;
 
 pub use energy::*;
+pub use heat_flux::*;
 use moldyn_core::State;
 use na::{Vector3, Vector4};
 pub use temperature::*;
 pub use pressure::*;
+pub use tail_correction::*;
 
 /// Get velocity of center of mass of particles with `particle_type_id`
 pub fn get_center_of_mass_velocity(
@@ -24,6 +28,25 @@ pub fn get_center_of_mass_velocity(
     Vector3::new(res.x, res.y, res.z) / res.w
 }
 
+/// Get velocity of center of mass of all particles. It should be used for temperature and
+/// pressure of mixtures.
+pub fn get_system_center_of_mass_velocity(state: &State) -> Vector3<f64> {
+    let res: Vector4<f64> = state.particles
+        .iter()
+        .flatten()
+        .map(|particle| {
+            let v = particle.velocity;
+            Vector4::new(v.x, v.y, v.z, 1.0) * particle.mass
+        })
+        .sum();
+    Vector3::new(res.x, res.y, res.z) / res.w
+}
+
+/// Get amount of particles of all types
+pub fn get_particles_count(state: &State) -> usize {
+    state.particles.iter().map(|particle_type| particle_type.len()).sum()
+}
+
 /// Get summary momentum of particles with `particle_type_id`
 pub fn get_momentum_of_system (state: &State, particle_type_id: u16) -> Vector3<f64> {
     let mut p = Vector3::new(0.0, 0.0, 0.0);
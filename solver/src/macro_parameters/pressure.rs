@@ -1,4 +1,4 @@
-use na::Vector3;
+use na::{Matrix3, Vector3};
 use moldyn_core::State;
 
 /// Get pressure of particles with `particle_type_id`
@@ -6,7 +6,7 @@ pub fn get_pressure(state: &State,
                     particle_type_id: u16,
                     center_of_mass_velocity: &Vector3<f64>,) -> f64 {
     let slice = &state.particles[particle_type_id as usize][..];
-    let volume = state.boundary_box.x * state.boundary_box.y * state.boundary_box.z;
+    let volume = state.get_volume();
     let mut result1 = 0.0;
     let mut result2 = 0.0;
     for particle in slice {
@@ -18,3 +18,36 @@ pub fn get_pressure(state: &State,
     }
     (result1 + result2 * 0.5) / volume / 3.0
 }
+
+/// Get pressure tensor (kinetic and virial parts) of particles with `particle_type_id`.
+/// Its trace divided by 3 is equal to [get_pressure].
+pub fn get_pressure_tensor(state: &State,
+                           particle_type_id: u16,
+                           center_of_mass_velocity: &Vector3<f64>,) -> Matrix3<f64> {
+    let slice = &state.particles[particle_type_id as usize][..];
+    let volume = state.get_volume();
+    let mut kinetic = Matrix3::zeros();
+    let mut virial = Matrix3::zeros();
+    for particle in slice {
+        let dv = particle.velocity - center_of_mass_velocity;
+        kinetic += particle.mass * dv * dv.transpose();
+        virial -= particle.virial;
+    }
+    (kinetic + virial * 0.5) / volume
+}
+
+/// Get pressure of all particles. `center_of_mass_velocity` should be velocity of center of mass
+/// of all particles.
+pub fn get_system_pressure(state: &State, center_of_mass_velocity: &Vector3<f64>) -> f64 {
+    (0..state.particles.len())
+        .map(|particle_type| get_pressure(state, particle_type as u16, center_of_mass_velocity))
+        .sum()
+}
+
+/// Get pressure tensor of all particles. `center_of_mass_velocity` should be velocity of center
+/// of mass of all particles.
+pub fn get_system_pressure_tensor(state: &State, center_of_mass_velocity: &Vector3<f64>) -> Matrix3<f64> {
+    (0..state.particles.len())
+        .map(|particle_type| get_pressure_tensor(state, particle_type as u16, center_of_mass_velocity))
+        .sum()
+}
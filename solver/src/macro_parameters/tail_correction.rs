@@ -0,0 +1,33 @@
+use std::f64::consts::PI;
+use moldyn_core::State;
+use crate::solver::PotentialsDatabase;
+
+/// Get analytic long-range tail corrections `(energy, pressure)` of all particles for
+/// potentials that are cut at their cutoff radius. Pair distribution function is assumed to be
+/// 1 after cutoff: `E = 2 pi / V sum(N_a N_b ∫ r^2 U_ab dr)` and
+/// `P = -2 pi / (3 V^2) sum(N_a N_b ∫ r^3 U_ab' dr)` for every pair of particle types
+/// ([Potential::get_tail_integrals](crate::solver::Potential::get_tail_integrals)). They should
+/// be added to potential energy and pressure. Switched potentials differ from unshifted ones
+/// before cutoff too, it isn't corrected. EAM potential has no tail corrections.
+pub fn get_tail_corrections(potentials_database: &PotentialsDatabase, state: &State) -> (f64, f64) {
+    if potentials_database.get_eam().is_some() {
+        return (0.0, 0.0);
+    }
+    let volume = state.get_volume();
+    let mut energy = 0.0;
+    let mut pressure = 0.0;
+    for (type1, particles1) in state.particles.iter().enumerate() {
+        for (type2, particles2) in state.particles.iter().enumerate() {
+            let pairs = (particles1.len() * particles2.len()) as f64;
+            if pairs == 0.0 {
+                continue;
+            }
+            let (energy_integral, pressure_integral) = potentials_database
+                .get_potential(type1 as u16, type2 as u16)
+                .get_tail_integrals();
+            energy += 2.0 * PI * pairs * energy_integral / volume;
+            pressure -= 2.0 * PI * pairs * pressure_integral / (3.0 * volume * volume);
+        }
+    }
+    (energy, pressure)
+}
@@ -1,6 +1,7 @@
 use moldyn_core::K_B;
 
-/// Get temperature of particles with `particle_type_id`
+/// Get temperature of `number_particles` particles with `thermal_energy`. For one species of
+/// mixture thermal energy should be calculated with center of mass velocity of all particles.
 pub fn get_temperature(thermal_energy: f64, number_particles: usize) -> f64 {
     let t = (2.0 * thermal_energy) / (3.0 * number_particles as f64 * K_B);
     t * 100.0 // Convert from program units to Kelvin
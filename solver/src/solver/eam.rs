@@ -0,0 +1,283 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Result};
+use std::path::Path;
+use moldyn_core::State;
+use moldyn_core::formats::{LammpsUnits, ANGSTROM};
+use na::{Matrix3, Vector3};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::solver::{minimum_image, CubicSpline, NeighborList};
+use super::table::invalid_data;
+
+/// `27.2 * 0.529` (eV Å) converts product of effective charges of funcfl file to `r * phi`
+const FUNCFL_CHARGE_FACTOR: f64 = 27.2 * 0.529;
+
+/// Embedded-atom method many-body potential. Energy of atom `i` is
+/// `F(rho_i) + 1/2 sum(phi(r_ij))`, where `rho_i = sum(rho_j(r_ij))` is electron density made by
+/// its neighbors. Functions are tabulated in DYNAMO `setfl` ([read_setfl]) or `funcfl`
+/// ([read_funcfl]) files and interpolated with cubic splines.
+///
+/// When it is set in [PotentialsDatabase](crate::solver::PotentialsDatabase) it replaces pair
+/// potentials of all particle types. Like for pair potentials [moldyn_core::Particle::potential]
+/// is twice energy of atom.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Eam {
+    /// Embedding energy `F(rho)` (10^-21 J) of every element
+    embedding: Vec<CubicSpline>,
+    /// Electron density `rho(r)` made by atom of every element at distance `r` (nm)
+    density: Vec<CubicSpline>,
+    /// `r * phi(r)` (10^-21 J nm) for every pair of elements, `pair[i][j]` with `j <= i`
+    pair: Vec<Vec<CubicSpline>>,
+    r_cut: f64,
+    /// Element of every particle type
+    elements: Vec<usize>,
+}
+
+impl Eam {
+    /// Loads `setfl` file (`eam/alloy` in LAMMPS). `elements` are names of elements from file
+    /// for every particle type.
+    pub fn load_setfl(path: &Path, elements: &[String]) -> Result<Eam> {
+        read_setfl(&mut BufReader::new(File::open(path)?), elements)
+    }
+
+    /// Loads `funcfl` file (`eam` in LAMMPS). Its element is used for `particle_types_count`
+    /// particle types.
+    pub fn load_funcfl(path: &Path, particle_types_count: usize) -> Result<Eam> {
+        read_funcfl(&mut BufReader::new(File::open(path)?), particle_types_count)
+    }
+
+    pub fn get_radius_cut(&self) -> f64 {
+        self.r_cut
+    }
+
+    /// Element of particle type. Panics if particle type isn't described by potential.
+    pub fn get_element(&self, particle_type: usize) -> usize {
+        *self.elements.get(particle_type)
+            .unwrap_or_else(|| panic!("Particle type {particle_type} has no element in EAM potential"))
+    }
+
+    /// Electron density made by atom of `element` at distance `r` and its derivative
+    pub fn get_density(&self, element: usize, r: f64) -> (f64, f64) {
+        self.density[element].get_value_and_derivative(r)
+    }
+
+    /// Embedding energy of atom of `element` in electron density `rho` and its derivative
+    pub fn get_embedding_energy(&self, element: usize, rho: f64) -> (f64, f64) {
+        self.embedding[element].get_value_and_derivative(rho)
+    }
+
+    /// Pair potential of two elements at distance `r` and its derivative
+    pub fn get_pair_potential(&self, element1: usize, element2: usize, r: f64) -> (f64, f64) {
+        let (i, j) = if element1 > element2 { (element1, element2) } else { (element2, element1) };
+        let (z, dz) = self.pair[i][j].get_value_and_derivative(r);
+        let phi = z / r;
+        (phi, (dz - phi) / r)
+    }
+}
+
+/// Numbers of file separated by whitespace
+struct Tokens {
+    tokens: std::vec::IntoIter<String>,
+}
+
+impl Tokens {
+    fn new(reader: &mut impl BufRead) -> Result<Self> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        let tokens: Vec<String> = text.split_whitespace().map(String::from).collect();
+        Ok(Self { tokens: tokens.into_iter() })
+    }
+
+    fn next_string(&mut self) -> Result<String> {
+        self.tokens.next().ok_or_else(|| invalid_data("Unexpected end of EAM file"))
+    }
+
+    fn next_f64(&mut self) -> Result<f64> {
+        self.next_string()?.parse().map_err(|_| invalid_data("Can't parse number in EAM file"))
+    }
+
+    fn next_usize(&mut self) -> Result<usize> {
+        self.next_string()?.parse().map_err(|_| invalid_data("Can't parse count in EAM file"))
+    }
+
+    fn next_values(&mut self, count: usize) -> Result<Vec<f64>> {
+        (0..count).map(|_| self.next_f64()).collect()
+    }
+}
+
+/// Grids of tables: `Nrho drho Nr dr cutoff` (in eV and Å)
+struct Grids {
+    rho: Vec<f64>,
+    r: Vec<f64>,
+    r_cut: f64,
+}
+
+impl Grids {
+    fn read(tokens: &mut Tokens) -> Result<Self> {
+        let rho_count = tokens.next_usize()?;
+        let rho_step = tokens.next_f64()?;
+        let r_count = tokens.next_usize()?;
+        let r_step = tokens.next_f64()? * ANGSTROM;
+        let r_cut = tokens.next_f64()? * ANGSTROM;
+        if rho_count < 2 || r_count < 2 {
+            return Err(invalid_data("EAM tables should have at least two points"));
+        }
+        Ok(Self {
+            rho: (0..rho_count).map(|i| i as f64 * rho_step).collect(),
+            r: (0..r_count).map(|i| i as f64 * r_step).collect(),
+            r_cut,
+        })
+    }
+
+    /// Reads embedding energy table (eV)
+    fn read_embedding(&self, tokens: &mut Tokens) -> Result<CubicSpline> {
+        let energy = LammpsUnits::Metal.energy();
+        let values = tokens.next_values(self.rho.len())?;
+        Ok(CubicSpline::new(self.rho.clone(), values.iter().map(|f| f * energy).collect(), None))
+    }
+
+    /// Reads table of function of distance, its values are multiplied by `factor`
+    fn read_distance_function(&self, tokens: &mut Tokens, factor: f64) -> Result<Vec<f64>> {
+        Ok(tokens.next_values(self.r.len())?.iter().map(|value| value * factor).collect())
+    }
+}
+
+/// Reads DYNAMO `setfl` file: three comment lines, line with count and names of elements,
+/// `Nrho drho Nr dr cutoff`, then for every element line `number mass lattice_constant lattice`
+/// with tables of embedding energy `F(rho)` and density `rho(r)`, and at last tables of
+/// `r * phi(r)` for pairs of elements `(i, j)` with `j <= i`. Units are eV and Å.
+/// `elements` are names of elements for every particle type.
+pub fn read_setfl(reader: &mut impl BufRead, elements: &[String]) -> Result<Eam> {
+    for _ in 0..3 {
+        reader.read_line(&mut String::new())?;
+    }
+    let mut tokens = Tokens::new(reader)?;
+    let names: Vec<String> = (0..tokens.next_usize()?)
+        .map(|_| tokens.next_string())
+        .collect::<Result<_>>()?;
+    let elements = elements.iter()
+        .map(|element| names.iter().position(|name| name == element)
+            .ok_or_else(|| invalid_data(&format!("No element {element} in EAM file"))))
+        .collect::<Result<_>>()?;
+    let grids = Grids::read(&mut tokens)?;
+    let mut embedding = vec![];
+    let mut density = vec![];
+    for _ in 0..names.len() {
+        tokens.next_values(3)?;
+        tokens.next_string()?;
+        embedding.push(grids.read_embedding(&mut tokens)?);
+        let values = grids.read_distance_function(&mut tokens, 1.0)?;
+        density.push(CubicSpline::new(grids.r.clone(), values, None));
+    }
+    let factor = LammpsUnits::Metal.energy() * ANGSTROM;
+    let pair = (0..names.len()).map(|i| (0..=i).map(|_| {
+        let values = grids.read_distance_function(&mut tokens, factor)?;
+        Ok(CubicSpline::new(grids.r.clone(), values, None))
+    }).collect()).collect::<Result<_>>()?;
+    Ok(Eam {
+        embedding,
+        density,
+        pair,
+        r_cut: grids.r_cut,
+        elements,
+    })
+}
+
+/// Reads DYNAMO `funcfl` file of one element: comment line, line
+/// `number mass lattice_constant lattice`, `Nrho drho Nr dr cutoff` and tables of embedding
+/// energy `F(rho)`, effective charge `Z(r)` and density `rho(r)`. Pair potential is
+/// `27.2 * 0.529 * Z(r)^2 / r`. Units are eV and Å. The element is used for
+/// `particle_types_count` particle types.
+pub fn read_funcfl(reader: &mut impl BufRead, particle_types_count: usize) -> Result<Eam> {
+    reader.read_line(&mut String::new())?;
+    let mut tokens = Tokens::new(reader)?;
+    tokens.next_values(3)?;
+    tokens.next_string()?;
+    let grids = Grids::read(&mut tokens)?;
+    let embedding = grids.read_embedding(&mut tokens)?;
+    let factor = FUNCFL_CHARGE_FACTOR * LammpsUnits::Metal.energy() * ANGSTROM;
+    let charge = grids.read_distance_function(&mut tokens, 1.0)?;
+    let pair = charge.iter().map(|z| factor * z * z).collect();
+    let density = grids.read_distance_function(&mut tokens, 1.0)?;
+    Ok(Eam {
+        embedding: vec![embedding],
+        density: vec![CubicSpline::new(grids.r.clone(), density, None)],
+        pair: vec![vec![CubicSpline::new(grids.r.clone(), pair, None)]],
+        r_cut: grids.r_cut,
+        elements: vec![0; particle_types_count],
+    })
+}
+
+/// Two passes over neighbors of every particle: the first one sums electron densities, the
+/// second one adds pair forces `phi'(r) + F'(rho_i) rho_j'(r) + F'(rho_j) rho_i'(r)`. Every pair
+/// is evaluated from both sides, so Newton's third law setting isn't used.
+pub(crate) fn update_force_eam(eam: &Eam, state: &mut State, neighbor_list: Option<&NeighborList>) {
+    let r_cut = eam.get_radius_cut();
+    let bb = &state.boundary_box;
+    let tilt = &state.tilt;
+    let boundary_conditions = &state.boundary_conditions;
+    let mut offsets = vec![0; state.particles.len()];
+    for particle_type in 1..state.particles.len() {
+        offsets[particle_type] = offsets[particle_type - 1] + state.particles[particle_type - 1].len();
+    }
+    let mut types = vec![];
+    let mut elements = vec![];
+    let mut positions = vec![];
+    state.particles.iter().enumerate().for_each(|(particle_type, particles)| {
+        let element = if particles.is_empty() { 0 } else { eam.get_element(particle_type) };
+        particles.iter().for_each(|particle| {
+            types.push(particle_type);
+            elements.push(element);
+            positions.push(particle.position);
+        });
+    });
+    let particles_count = positions.len();
+    // Neighbors of particle `i` closer than cutoff radius with vectors to them
+    let neighbors = |i: usize| -> Vec<(usize, Vector3<f64>, f64)> {
+        let others: Box<dyn Iterator<Item = usize>> = match neighbor_list {
+            Some(neighbor_list) => Box::new(
+                neighbor_list.get_neighbors(types[i], i - offsets[types[i]]).iter()
+                    .map(|&(other_type, other_index)| offsets[other_type] + other_index)),
+            None => Box::new((0..particles_count).filter(move |j| *j != i)),
+        };
+        others.filter_map(|j| {
+            let mut r = positions[j] - positions[i];
+            minimum_image(&mut r, bb, tilt, boundary_conditions);
+            let r_abs = r.norm();
+            (r_abs <= r_cut).then_some((j, r, r_abs))
+        }).collect()
+    };
+    let embedding: Vec<(f64, f64)> = (0..particles_count).into_par_iter().map(|i| {
+        let rho = neighbors(i).iter()
+            .map(|(j, _, r_abs)| eam.get_density(elements[*j], *r_abs).0)
+            .sum();
+        eam.get_embedding_energy(elements[i], rho)
+    }).collect();
+    let result: Vec<(Vector3<f64>, f64, f64, Matrix3<f64>)> = (0..particles_count).into_par_iter().map(|i| {
+        let mut force = Vector3::zeros();
+        let mut potential = 2.0 * embedding[i].0;
+        let mut temp = 0.0;
+        let mut virial = Matrix3::zeros();
+        for (j, r, r_abs) in neighbors(i) {
+            let (phi, phi_derivative) = eam.get_pair_potential(elements[i], elements[j], r_abs);
+            let (_, density_j) = eam.get_density(elements[j], r_abs);
+            let (_, density_i) = eam.get_density(elements[i], r_abs);
+            let derivative = phi_derivative + embedding[i].1 * density_j + embedding[j].1 * density_i;
+            let force_vec = r / r_abs * derivative;
+            force += force_vec;
+            potential += phi;
+            temp += force_vec.dot(&r);
+            virial += force_vec * r.transpose();
+        }
+        (force, potential, temp, virial)
+    }).collect();
+    state.particles.iter_mut().enumerate().for_each(|(particle_type, particles)| {
+        particles.iter_mut().enumerate().for_each(|(index, particle)| {
+            let (force, potential, temp, virial) = result[offsets[particle_type] + index];
+            particle.force = force;
+            particle.potential = potential;
+            particle.temp = temp;
+            particle.virial = virial;
+        });
+    });
+}
@@ -1,30 +1,123 @@
 use moldyn_core::State;
+use na::Vector3;
+use serde::{Deserialize, Serialize};
 use crate::initializer::{Barostat, Thermostat};
-use crate::solver::{PotentialsDatabase, update_force};
+use crate::solver::{PotentialsDatabase, update_force, update_short_range_force};
 
+#[derive(Serialize, Deserialize)]
 pub enum Integrator {
     /// <https://doi.org/10.1103/PhysRev.159.98>
     VerletMethod,
+    /// Leapfrog method. Velocities are kept on half steps, `particle.velocity` keeps
+    /// on-step estimation `v(t + dt/2) + a(t + dt) * dt / 2`
+    Leapfrog {
+        half_step_velocities: Vec<Vec<Vector3<f64>>>,
+    },
+    /// <https://doi.org/10.1016/0021-9991(76)90059-0>
+    Beeman {
+        previous_forces: Vec<Vec<Vector3<f64>>>,
+    },
+    /// Multiple time step r-RESPA: <https://doi.org/10.1063/1.463137>.
+    /// Short-range forces (pairs closer than `r_inner`, switched off smoothly up to `r_outer`)
+    /// are integrated `inner_steps` times per one step of long-range forces.
+    Respa {
+        inner_steps: usize,
+        r_inner: f64,
+        r_outer: f64,
+        short_range_forces: Vec<Vec<Vector3<f64>>>,
+        long_range_forces: Vec<Vec<Vector3<f64>>>,
+    },
     /// Doesn't implemented now
     Custom(String),
 }
 
+fn same_shape(data: &[Vec<Vector3<f64>>], state: &State) -> bool {
+    data.len() == state.particles.len()
+        && data.iter().zip(state.particles.iter()).all(|(d, p)| d.len() == p.len())
+}
+
+fn get_forces(state: &State) -> Vec<Vec<Vector3<f64>>> {
+    state.particles.iter().map(|particle_type| {
+        particle_type.iter().map(|particle| particle.force).collect()
+    }).collect()
+}
+
 impl Integrator {
+    /// Creates leapfrog integrator. Half step velocities are initialized on the first iteration.
+    pub fn new_leapfrog() -> Self {
+        Integrator::Leapfrog {
+            half_step_velocities: vec![],
+        }
+    }
+
+    /// Creates Beeman integrator. On the first iteration previous forces are equal to current.
+    pub fn new_beeman() -> Self {
+        Integrator::Beeman {
+            previous_forces: vec![],
+        }
+    }
+
+    /// Creates r-RESPA integrator with `inner_steps` short-range steps per iteration. Short-range
+    /// forces are switched off between `r_inner` and `r_outer` (nm).
+    ///
+    /// # Panics
+    ///
+    /// If `inner_steps` is zero or `0 < r_inner < r_outer` doesn't hold
+    pub fn new_respa(inner_steps: usize, r_inner: f64, r_outer: f64) -> Self {
+        assert!(inner_steps >= 1, "RESPA needs at least one inner step");
+        assert!(0.0 < r_inner && r_inner < r_outer, "RESPA needs 0 < r_inner < r_outer");
+        Integrator::Respa {
+            inner_steps,
+            r_inner,
+            r_outer,
+            short_range_forces: vec![],
+            long_range_forces: vec![],
+        }
+    }
+
+    fn calculate_coefficients(state: &mut State, delta_time: f64,
+                              barostat: &mut Option<(&mut Barostat, f64)>,
+                              thermostat: &mut Option<(&mut Thermostat, f64)>) {
+        if let Some((barostat, target_pressure)) = barostat.as_mut() {
+            barostat.calculate_myu(state, delta_time, *target_pressure);
+            barostat.scale_velocities(state, delta_time / 2.0);
+        }
+        if let Some((thermostat, target_temperature)) = thermostat.as_mut() {
+            (0..state.particles.len()).for_each(|particle_type| {
+                thermostat.calculate_lambda(state, delta_time, particle_type as u16, *target_temperature);
+            });
+        }
+    }
+
+    fn apply_thermostat(state: &mut State, delta_time: f64,
+                        thermostat: &mut Option<(&mut Thermostat, f64)>) {
+        if let Some((thermostat, target_temperature)) = thermostat.as_mut() {
+            (0..state.particles.len()).for_each(|particle_type| {
+                thermostat.update(state, delta_time, particle_type as u16, *target_temperature);
+            });
+        }
+    }
+
+    fn apply_barostat(state: &mut State, delta_time: f64,
+                      barostat: &mut Option<(&mut Barostat, f64)>) {
+        if let Some((barostat, target_pressure)) = barostat.as_mut() {
+            barostat.update(state, delta_time, *target_pressure);
+        }
+    }
+
+    /// Box scaling by barostat. It is made for half of `delta_time` before and after position step.
+    fn scale_box(state: &mut State, delta_time: f64, barostat: &Option<(&mut Barostat, f64)>) {
+        if let Some((barostat, _)) = barostat.as_ref() {
+            barostat.scale_positions(state, delta_time / 2.0);
+        }
+    }
+
     /// Just integrator iteration
-    pub fn calculate(&self, potentials_database: &PotentialsDatabase, state: &mut State, delta_time: f64,
+    pub fn calculate(&mut self, potentials_database: &PotentialsDatabase, state: &mut State, delta_time: f64,
                      barostat: &mut Option<(&mut Barostat, f64)>, thermostat: &mut Option<(&mut Thermostat, f64)>) {
         match self {
             Integrator::VerletMethod => {
-                if let Some((barostat, target_pressure)) = barostat.as_mut() {
-                    (0..state.particles.len()).for_each(|particle_type| {
-                        barostat.calculate_myu(&state, delta_time, particle_type as u16, *target_pressure);
-                    });
-                }
-                if let Some((thermostat, target_temperature)) = thermostat.as_mut() {
-                    (0..state.particles.len()).for_each(|particle_type| {
-                        thermostat.calculate_lambda(&state, delta_time, particle_type as u16, *target_temperature);
-                    });
-                }
+                Self::calculate_coefficients(state, delta_time, barostat, thermostat);
                 state.particles.iter_mut().for_each(|particle_type| {
                     let mass = particle_type[0].mass;
                     let temp = delta_time / (2.0 * mass);
@@ -32,16 +125,14 @@ impl Integrator {
                         particle.velocity = particle.velocity + particle.force * temp;
                     });
                 });
-                if let Some((thermostat, target_temperature)) = thermostat.as_mut() {
-                    (0..state.particles.len()).for_each(|particle_type| {
-                        thermostat.update(state, delta_time, particle_type as u16, *target_temperature);
-                    });
-                }
+                Self::apply_thermostat(state, delta_time, thermostat);
+                Self::scale_box(state, delta_time, barostat);
                 state.particles.iter_mut().for_each(|particle_type| {
                     particle_type.iter_mut().for_each(|particle| {
                         particle.position += particle.velocity * delta_time;
                     });
                 });
+                Self::scale_box(state, delta_time, barostat);
                 state.apply_boundary_conditions();
                 update_force(potentials_database, state);
                 state.particles.iter_mut().for_each(|particle_type| {
@@ -51,15 +142,138 @@ impl Integrator {
                         particle.velocity += particle.force * temp;
                     });
                 });
-                if let Some((barostat, target_pressure)) = barostat.as_mut() {
-                    (0..state.particles.len()).for_each(|particle_type| {
-                        barostat.update(state, delta_time, particle_type as u16, *target_pressure);
+                Self::apply_barostat(state, delta_time, barostat);
+            }
+            Integrator::Leapfrog { half_step_velocities } => {
+                Self::calculate_coefficients(state, delta_time, barostat, thermostat);
+                // Barostat scales on-step velocities, so half step ones are recalculated
+                if !same_shape(half_step_velocities, state) || barostat.is_some() {
+                    *half_step_velocities = state.particles.iter().map(|particle_type| {
+                        particle_type.iter().map(|particle| {
+                            particle.velocity - particle.force * (delta_time / (2.0 * particle.mass))
+                        }).collect()
+                    }).collect();
+                }
+                state.particles.iter_mut().zip(half_step_velocities.iter_mut())
+                    .for_each(|(particle_type, velocities)| {
+                        particle_type.iter_mut().zip(velocities.iter_mut())
+                            .for_each(|(particle, velocity)| {
+                                *velocity += particle.force * (delta_time / particle.mass);
+                                particle.velocity = *velocity;
+                            });
+                    });
+                Self::apply_thermostat(state, delta_time, thermostat);
+                Self::scale_box(state, delta_time, barostat);
+                state.particles.iter_mut().zip(half_step_velocities.iter_mut())
+                    .for_each(|(particle_type, velocities)| {
+                        particle_type.iter_mut().zip(velocities.iter_mut())
+                            .for_each(|(particle, velocity)| {
+                                *velocity = particle.velocity;
+                                particle.position += *velocity * delta_time;
+                            });
+                    });
+                Self::scale_box(state, delta_time, barostat);
+                state.apply_boundary_conditions();
+                update_force(potentials_database, state);
+                state.particles.iter_mut().zip(half_step_velocities.iter())
+                    .for_each(|(particle_type, velocities)| {
+                        particle_type.iter_mut().zip(velocities.iter())
+                            .for_each(|(particle, velocity)| {
+                                particle.velocity = velocity + particle.force * (delta_time / (2.0 * particle.mass));
+                            });
+                    });
+                Self::apply_barostat(state, delta_time, barostat);
+            }
+            Integrator::Beeman { previous_forces } => {
+                Self::calculate_coefficients(state, delta_time, barostat, thermostat);
+                if !same_shape(previous_forces, state) {
+                    *previous_forces = get_forces(state);
+                }
+                Self::apply_thermostat(state, delta_time, thermostat);
+                Self::scale_box(state, delta_time, barostat);
+                state.particles.iter_mut().zip(previous_forces.iter())
+                    .for_each(|(particle_type, forces)| {
+                        particle_type.iter_mut().zip(forces.iter())
+                            .for_each(|(particle, previous_force)| {
+                                let acceleration = (particle.force * 4.0 - previous_force) / (6.0 * particle.mass);
+                                particle.position += particle.velocity * delta_time
+                                    + acceleration * delta_time * delta_time;
+                            });
                     });
+                Self::scale_box(state, delta_time, barostat);
+                state.apply_boundary_conditions();
+                let forces = get_forces(state);
+                update_force(potentials_database, state);
+                state.particles.iter_mut().zip(forces.iter().zip(previous_forces.iter()))
+                    .for_each(|(particle_type, (forces, previous_forces))| {
+                        particle_type.iter_mut().zip(forces.iter().zip(previous_forces.iter()))
+                            .for_each(|(particle, (force, previous_force))| {
+                                let acceleration = (particle.force * 2.0 + force * 5.0 - previous_force)
+                                    / (6.0 * particle.mass);
+                                particle.velocity += acceleration * delta_time;
+                            });
+                    });
+                *previous_forces = forces;
+                Self::apply_barostat(state, delta_time, barostat);
+            }
+            Integrator::Respa { inner_steps, r_inner, r_outer,
+                short_range_forces, long_range_forces } => {
+                Self::calculate_coefficients(state, delta_time, barostat, thermostat);
+                if !same_shape(long_range_forces, state) || !same_shape(short_range_forces, state) {
+                    let forces = get_forces(state);
+                    update_short_range_force(potentials_database, state, *r_inner, *r_outer);
+                    *short_range_forces = get_forces(state);
+                    *long_range_forces = forces.iter().zip(short_range_forces.iter())
+                        .map(|(forces, short_range)| {
+                            forces.iter().zip(short_range.iter()).map(|(f, s)| f - s).collect()
+                        }).collect();
                 }
+                let inner_delta_time = delta_time / *inner_steps as f64;
+                state.particles.iter_mut().zip(long_range_forces.iter())
+                    .for_each(|(particle_type, forces)| {
+                        particle_type.iter_mut().zip(forces.iter()).for_each(|(particle, force)| {
+                            particle.velocity += force * (delta_time / (2.0 * particle.mass));
+                        });
+                    });
+                Self::apply_thermostat(state, delta_time, thermostat);
+                for _ in 0..*inner_steps {
+                    state.particles.iter_mut().zip(short_range_forces.iter())
+                        .for_each(|(particle_type, forces)| {
+                            particle_type.iter_mut().zip(forces.iter()).for_each(|(particle, force)| {
+                                particle.velocity += force * (inner_delta_time / (2.0 * particle.mass));
+                            });
+                        });
+                    Self::scale_box(state, inner_delta_time, barostat);
+                    state.particles.iter_mut().for_each(|particle_type| {
+                        particle_type.iter_mut().for_each(|particle| {
+                            particle.position += particle.velocity * inner_delta_time;
+                        });
+                    });
+                    Self::scale_box(state, inner_delta_time, barostat);
+                    state.apply_boundary_conditions();
+                    update_short_range_force(potentials_database, state, *r_inner, *r_outer);
+                    state.particles.iter_mut().for_each(|particle_type| {
+                        particle_type.iter_mut().for_each(|particle| {
+                            particle.velocity += particle.force * (inner_delta_time / (2.0 * particle.mass));
+                        });
+                    });
+                    *short_range_forces = get_forces(state);
+                }
+                update_force(potentials_database, state);
+                state.particles.iter_mut()
+                    .zip(short_range_forces.iter().zip(long_range_forces.iter_mut()))
+                    .for_each(|(particle_type, (short_range, long_range))| {
+                        particle_type.iter_mut().zip(short_range.iter().zip(long_range.iter_mut()))
+                            .for_each(|(particle, (short_range, long_range))| {
+                                *long_range = particle.force - short_range;
+                                particle.velocity += *long_range * (delta_time / (2.0 * particle.mass));
+                            });
+                    });
+                Self::apply_barostat(state, delta_time, barostat);
             }
             Integrator::Custom(_) => {
                 todo!()
             }
         }
     }
-}
\ No newline at end of file
+}
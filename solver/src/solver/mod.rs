@@ -1,5 +1,13 @@
+mod eam;
 mod integrator;
+mod neighbor_list;
 mod potential;
+mod simulation;
+mod table;
 
+pub use eam::*;
 pub use integrator::*;
-pub use potential::*;
\ No newline at end of file
+pub use neighbor_list::*;
+pub use potential::*;
+pub use simulation::*;
+pub use table::*;
\ No newline at end of file
@@ -0,0 +1,183 @@
+use moldyn_core::{BoundaryCondition, State};
+use na::{Matrix3, Vector3};
+use serde::{Deserialize, Serialize};
+use crate::solver::{minimum_image, PotentialsDatabase};
+
+/// Verlet neighbor list built with linked-cell grid.
+///
+/// Every particle keeps all particles closer than `r_cut + skin`, where `r_cut` is the largest
+/// cutoff radius in [PotentialsDatabase]. The list is rebuilt only when some particle has moved
+/// more than half of the skin since the last build.
+///
+/// Positions of the last build are kept in fractional coordinates, so they follow the cell when
+/// barostat changes it. Deformation of cell can bring particles that were farther than
+/// `r_cut + skin` closer, so the skin left for displacements shrinks by the smallest stretch of
+/// the cell since the last build.
+#[derive(Serialize, Deserialize)]
+pub struct NeighborList {
+    /// Additional distance (nm) added to the largest cutoff radius
+    pub skin: f64,
+    /// `neighbors[type][i]` keeps pairs `(type, index)` of neighbors for particle `i` of `type`
+    neighbors: Vec<Vec<Vec<(usize, usize)>>>,
+    /// Fractional positions of particles at the last build
+    positions: Vec<Vec<Vector3<f64>>>,
+    /// Cell matrix at the last build
+    cell: Matrix3<f64>,
+    /// The largest cutoff radius (nm) at the last build
+    radius_cut: f64,
+    boundary_conditions: [BoundaryCondition; 3],
+    rebuild_count: usize,
+}
+
+impl NeighborList {
+    /// Creates empty neighbor list. It will be built on first update.
+    pub fn new(skin: f64) -> Self {
+        Self {
+            skin,
+            neighbors: vec![],
+            positions: vec![],
+            cell: Matrix3::zeros(),
+            radius_cut: 0.0,
+            boundary_conditions: Default::default(),
+            rebuild_count: 0,
+        }
+    }
+
+    /// Neighbors of particle with index `i` and type `particle_type`
+    pub fn get_neighbors(&self, particle_type: usize, i: usize) -> &[(usize, usize)] {
+        &self.neighbors[particle_type][i]
+    }
+
+    /// How many times the list was built
+    pub fn get_rebuild_count(&self) -> usize {
+        self.rebuild_count
+    }
+
+    /// Checks if list is outdated: particles were added or removed, boundary conditions were
+    /// changed or some particle has moved relative to the cell more than half of the skin left
+    /// after deformation of the cell.
+    pub fn needs_rebuild(&self, state: &State) -> bool {
+        if self.positions.len() != state.particles.len()
+            || self.boundary_conditions != state.boundary_conditions {
+            return true;
+        }
+        let h = state.get_cell_matrix();
+        // Pairs that were farther than `r_cut + skin` are not closer than that times the
+        // smallest singular value of deformation
+        let stretch = match self.cell.try_inverse() {
+            Some(inverse) => (h * inverse).singular_values().min(),
+            None => return true,
+        };
+        let max_displacement = (stretch * (self.radius_cut + self.skin) - self.radius_cut) / 2.0;
+        if max_displacement <= 0.0 {
+            return true;
+        }
+        let max_displacement_squared = max_displacement * max_displacement;
+        let bb = &state.boundary_box;
+        let tilt = &state.tilt;
+        let boundary_conditions = &state.boundary_conditions;
+        self.positions.iter().zip(state.particles.iter()).any(|(positions, particle_type)| {
+            positions.len() != particle_type.len()
+                || positions.iter().zip(particle_type.iter()).any(|(position, particle)| {
+                let mut dr = particle.position - h * position;
+                minimum_image(&mut dr, bb, tilt, boundary_conditions);
+                dr.magnitude_squared() > max_displacement_squared
+            })
+        })
+    }
+
+    /// Rebuilds list if it is needed
+    ///
+    /// # Returns
+    ///
+    /// true if list was rebuilt
+    pub fn update(&mut self, potentials_database: &PotentialsDatabase, state: &State) -> bool {
+        if self.needs_rebuild(state) {
+            self.build(potentials_database, state);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Builds list from scratch using linked-cell grid with cell edge not less than `r_cut + skin`.
+    /// Cells are built in fractional coordinates, so for triclinic cell distance between
+    /// opposite cell faces is not less than `r_cut + skin`.
+    pub fn build(&mut self, potentials_database: &PotentialsDatabase, state: &State) {
+        let bb = &state.boundary_box;
+        let tilt = &state.tilt;
+        let boundary_conditions = &state.boundary_conditions;
+        let radius_cut = potentials_database.get_max_radius_cut();
+        let r_list = radius_cut + self.skin;
+        let r_list_squared = r_list * r_list;
+        let h = state.get_cell_matrix();
+        let volume = state.get_volume();
+        let cells_count: Vec<usize> = (0..3).map(|axis| {
+            let face = h.column((axis + 1) % 3).cross(&h.column((axis + 2) % 3));
+            ((volume / face.norm() / r_list).floor() as usize).max(1)
+        }).collect();
+        let cell_index = |position: &Vector3<f64>| -> [usize; 3] {
+            let fractional = state.to_fractional(position);
+            let mut index = [0; 3];
+            for axis in 0..3 {
+                let cell = (fractional[axis] * cells_count[axis] as f64).floor() as isize;
+                index[axis] = if boundary_conditions[axis] == BoundaryCondition::Periodic {
+                    cell.rem_euclid(cells_count[axis] as isize) as usize
+                } else {
+                    // Particles outside of box along open axis are kept in border cells
+                    cell.clamp(0, cells_count[axis] as isize - 1) as usize
+                };
+            }
+            index
+        };
+        let mut cells: Vec<Vec<(usize, usize)>> =
+            vec![vec![]; cells_count[0] * cells_count[1] * cells_count[2]];
+        let flat = |index: [usize; 3]| -> usize {
+            (index[0] * cells_count[1] + index[1]) * cells_count[2] + index[2]
+        };
+        for (particle_type, particles) in state.particles.iter().enumerate() {
+            for (i, particle) in particles.iter().enumerate() {
+                cells[flat(cell_index(&particle.position))].push((particle_type, i));
+            }
+        }
+        // Neighbor cells along each axis without duplicates (grid could be smaller than 3 cells)
+        let neighbor_cells = |index: usize, count: usize| -> Vec<usize> {
+            let mut result: Vec<usize> = [count - 1, 0, 1].iter()
+                .map(|offset| (index + offset) % count)
+                .collect();
+            result.sort_unstable();
+            result.dedup();
+            result
+        };
+        self.neighbors = state.particles.iter().enumerate().map(|(particle_type, particles)| {
+            particles.iter().enumerate().map(|(i, particle)| {
+                let index = cell_index(&particle.position);
+                let mut neighbors = vec![];
+                for x in neighbor_cells(index[0], cells_count[0]) {
+                    for y in neighbor_cells(index[1], cells_count[1]) {
+                        for z in neighbor_cells(index[2], cells_count[2]) {
+                            for &(other_type, j) in &cells[flat([x, y, z])] {
+                                if other_type == particle_type && j == i {
+                                    continue;
+                                }
+                                let mut r = state.particles[other_type][j].position - particle.position;
+                                minimum_image(&mut r, bb, tilt, boundary_conditions);
+                                if r.magnitude_squared() <= r_list_squared {
+                                    neighbors.push((other_type, j));
+                                }
+                            }
+                        }
+                    }
+                }
+                neighbors
+            }).collect()
+        }).collect();
+        self.positions = state.particles.iter().map(|particles| {
+            particles.iter().map(|particle| state.to_fractional(&particle.position)).collect()
+        }).collect();
+        self.cell = h;
+        self.radius_cut = radius_cut;
+        self.boundary_conditions = state.boundary_conditions;
+        self.rebuild_count += 1;
+    }
+}
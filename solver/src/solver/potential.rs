@@ -1,13 +1,72 @@
-use moldyn_core::State;
+use moldyn_core::{BoundaryCondition, State};
 use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufWriter};
-use std::path::PathBuf;
+use std::io::{BufReader, BufWriter, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use na::{Matrix3, Vector3};
 use rand_distr::num_traits::Pow;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use crate::solver::{read_lammps_table, update_force_eam, CubicSpline, Eam, NeighborList};
 
-/// Enum to keep data for potential calculation
+/// Treatment of potential at cutoff radius `r_cut`. Potential is zero after `r_cut` for
+/// every scheme.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Cutoff {
+    /// Potential is just cut, energy and force are discontinuous at `r_cut`
+    Truncated,
+    /// Energy is shifted by its value at `r_cut` to be continuous, force is discontinuous
+    #[default]
+    Shifted,
+    /// Shifted-force potential `U(r) - U(r_cut) - (r - r_cut) U'(r_cut)`, energy and force are
+    /// continuous
+    ShiftedForce,
+    /// Potential is multiplied by CHARMM switching function that smoothly goes from 1 at `r_on`
+    /// to 0 at `r_cut`
+    Switched {
+        r_on: f64,
+    },
+}
+
+impl Cutoff {
+    /// Checks that switching of `Switched` scheme starts inside cutoff radius `r_cut`
+    fn check_radius_cut(&self, r_cut: f64) {
+        if let Cutoff::Switched { r_on } = self {
+            assert!(0.0 <= *r_on && *r_on < r_cut,
+                    "Need 0 <= r_on < r_cut for switched cutoff, got r_on = {r_on}, r_cut = {r_cut}");
+        }
+    }
+
+    /// Applies cutoff to unshifted potential `u` and its derivative `du` at distance
+    /// `r < r_cut`. `u_cut` and `du_cut` are unshifted potential and derivative at `r_cut`.
+    fn apply(&self, r: f64, (u, du): (f64, f64), r_cut: f64, u_cut: f64, du_cut: f64) -> (f64, f64) {
+        match self {
+            Cutoff::Truncated => (u, du),
+            Cutoff::Shifted => (u - u_cut, du),
+            Cutoff::ShiftedForce => (u - u_cut - (r - r_cut) * du_cut, du - du_cut),
+            Cutoff::Switched { r_on } => {
+                if r <= *r_on {
+                    return (u, du);
+                }
+                let (r2, r_cut2, r_on2) = (r * r, r_cut * r_cut, r_on * r_on);
+                let denominator = (r_cut2 - r_on2).powi(3);
+                let switch = (r_cut2 - r2).powi(2) * (r_cut2 + 2.0 * r2 - 3.0 * r_on2) / denominator;
+                let switch_derivative = 12.0 * r * (r_cut2 - r2) * (r_on2 - r2) / denominator;
+                (u * switch, du * switch + u * switch_derivative)
+            }
+        }
+    }
+}
+
+/// `∫ r^2 exp(-k (r - r0)) dr` from `r_cut` to infinity
+fn exp_tail_integral(k: f64, r0: f64, r_cut: f64) -> f64 {
+    (-k * (r_cut - r0)).exp() * (r_cut * r_cut / k + 2.0 * r_cut / (k * k) + 2.0 / (k * k * k))
+}
+
+/// Enum to keep data for potential calculation. Analytic potentials are zero after `r_cut`,
+/// `cutoff` defines how they are made continuous there (energy is shifted by default).
+/// `u_cut` and `du_cut` are unshifted potential and its derivative at `r_cut`.
 #[derive(Clone, Serialize, Deserialize)]
 pub enum Potential {
     LennardJones {
@@ -15,6 +74,73 @@ pub enum Potential {
         eps: f64,
         r_cut: f64,
         u_cut: f64,
+        #[serde(default)]
+        du_cut: f64,
+        #[serde(default)]
+        cutoff: Cutoff,
+    },
+    /// `U = d ((1 - exp(-a (r - r0)))^2 - 1)`
+    Morse {
+        d: f64,
+        a: f64,
+        r0: f64,
+        r_cut: f64,
+        u_cut: f64,
+        #[serde(default)]
+        du_cut: f64,
+        #[serde(default)]
+        cutoff: Cutoff,
+    },
+    /// Exp-6 potential `U = a exp(-r / rho) - c / r^6`. It goes to minus infinity at small `r`,
+    /// so particles shouldn't get closer than its maximum.
+    Buckingham {
+        a: f64,
+        rho: f64,
+        c: f64,
+        r_cut: f64,
+        u_cut: f64,
+        #[serde(default)]
+        du_cut: f64,
+        #[serde(default)]
+        cutoff: Cutoff,
+    },
+    /// Born-Mayer-Huggins potential `U = a exp((sigma - r) / rho) - c / r^6 - d / r^8`
+    BornMayerHuggins {
+        a: f64,
+        rho: f64,
+        sigma: f64,
+        c: f64,
+        d: f64,
+        r_cut: f64,
+        u_cut: f64,
+        #[serde(default)]
+        du_cut: f64,
+        #[serde(default)]
+        cutoff: Cutoff,
+    },
+    /// Inverse-power soft sphere `U = eps (sigma / r)^n`
+    SoftSphere {
+        sigma: f64,
+        eps: f64,
+        n: f64,
+        r_cut: f64,
+        u_cut: f64,
+        #[serde(default)]
+        du_cut: f64,
+        #[serde(default)]
+        cutoff: Cutoff,
+    },
+    /// Weeks-Chandler-Andersen potential: repulsive part of Lennard-Jones potential cut at its
+    /// minimum `2^(1/6) sigma` and shifted by `eps`
+    Wca {
+        sigma: f64,
+        eps: f64,
+    },
+    /// Potential interpolated by cubic splines of energy and force tables. It is zero after the
+    /// last point of table and isn't shifted.
+    Tabulated {
+        energy: CubicSpline,
+        force: CubicSpline,
     },
     Custom {
         name: String,
@@ -23,61 +149,229 @@ pub enum Potential {
 }
 
 impl Potential {
-    /// Creates Lennard-Jones potential object
+    /// Creates Lennard-Jones potential object cut and shifted at `2.5 sigma`
     pub fn new_lennard_jones(sigma: f64, eps: f64) -> Potential {
-        let r_cut = sigma * 2.5;
-        let mut potential = Potential::LennardJones {
-            sigma,
-            eps,
-            r_cut,
-            u_cut: 0.0,
-        };
-        potential = match potential {
-            Potential::LennardJones {
-                sigma,
-                eps,
-                r_cut,
-                ..
-            } => {
-                let (u_cut, _) = potential.get_potential_and_force(r_cut.clone());
-                Potential::LennardJones {
-                    sigma,
-                    eps,
-                    r_cut,
-                    u_cut,
-                }
+        Potential::LennardJones { sigma, eps, r_cut: 0.0, u_cut: 0.0, du_cut: 0.0, cutoff: Cutoff::Shifted }
+            .with_cutoff(sigma * 2.5, Cutoff::Shifted)
+    }
+
+    /// Creates Morse potential with well depth `d` (10^-21 J), width `a` (1/nm) and equilibrium
+    /// distance `r0` (nm)
+    pub fn new_morse(d: f64, a: f64, r0: f64, r_cut: f64) -> Potential {
+        Potential::Morse { d, a, r0, r_cut, u_cut: 0.0, du_cut: 0.0, cutoff: Cutoff::Shifted }
+            .with_cutoff(r_cut, Cutoff::Shifted)
+    }
+
+    /// Creates Buckingham potential
+    pub fn new_buckingham(a: f64, rho: f64, c: f64, r_cut: f64) -> Potential {
+        Potential::Buckingham { a, rho, c, r_cut, u_cut: 0.0, du_cut: 0.0, cutoff: Cutoff::Shifted }
+            .with_cutoff(r_cut, Cutoff::Shifted)
+    }
+
+    /// Creates Born-Mayer-Huggins potential
+    pub fn new_born_mayer_huggins(a: f64, rho: f64, sigma: f64, c: f64, d: f64, r_cut: f64) -> Potential {
+        Potential::BornMayerHuggins { a, rho, sigma, c, d, r_cut, u_cut: 0.0, du_cut: 0.0, cutoff: Cutoff::Shifted }
+            .with_cutoff(r_cut, Cutoff::Shifted)
+    }
+
+    /// Creates soft sphere potential with power `n`
+    pub fn new_soft_sphere(sigma: f64, eps: f64, n: f64, r_cut: f64) -> Potential {
+        Potential::SoftSphere { sigma, eps, n, r_cut, u_cut: 0.0, du_cut: 0.0, cutoff: Cutoff::Shifted }
+            .with_cutoff(r_cut, Cutoff::Shifted)
+    }
+
+    /// Creates Weeks-Chandler-Andersen potential
+    pub fn new_wca(sigma: f64, eps: f64) -> Potential {
+        Potential::Wca { sigma, eps }
+    }
+
+    /// Creates tabulated potential from distances `r` (increasing), energies and forces
+    /// `-dU/dr` in these points
+    pub fn new_tabulated(r: Vec<f64>, energy: Vec<f64>, force: Vec<f64>) -> Potential {
+        let end_derivatives = (-force[0], -force[force.len() - 1]);
+        Potential::Tabulated {
+            energy: CubicSpline::new(r.clone(), energy, Some(end_derivatives)),
+            force: CubicSpline::new(r, force, None),
+        }
+    }
+
+    /// Loads section `keyword` of table file in LAMMPS format (see [read_lammps_table])
+    pub fn load_table(path: &Path, keyword: &str) -> Result<Potential> {
+        read_lammps_table(&mut BufReader::new(File::open(path)?), keyword)
+    }
+
+    /// Tabulates potential in `points` evenly spaced distances from `r_min` to cutoff radius.
+    /// Spline interpolation of table is faster than expensive analytic potentials.
+    pub fn tabulate(&self, r_min: f64, points: usize) -> Potential {
+        let r_cut = self.get_radius_cut();
+        let r: Vec<f64> = (0..points)
+            .map(|i| r_min + (r_cut - r_min) * i as f64 / (points - 1) as f64)
+            .collect();
+        let (energy, force) = r.iter().map(|r| {
+            let (energy, derivative) = self.get_potential_and_force(*r);
+            (energy, -derivative)
+        }).unzip();
+        Potential::new_tabulated(r, energy, force)
+    }
+
+    /// Sets cutoff radius and cutoff scheme of analytic potential. WCA, tabulated and custom
+    /// potentials are not changed.
+    pub fn with_cutoff(mut self, radius_cut: f64, scheme: Cutoff) -> Potential {
+        scheme.check_radius_cut(radius_cut);
+        match &mut self {
+            Potential::LennardJones { r_cut, cutoff, .. }
+            | Potential::Morse { r_cut, cutoff, .. }
+            | Potential::Buckingham { r_cut, cutoff, .. }
+            | Potential::BornMayerHuggins { r_cut, cutoff, .. }
+            | Potential::SoftSphere { r_cut, cutoff, .. } => {
+                *r_cut = radius_cut;
+                *cutoff = scheme;
             }
-            _ => {
-                panic!("It's impossible to get another potential")
+            _ => return self,
+        }
+        let (u, du) = self.get_unshifted_potential_and_force(radius_cut);
+        match &mut self {
+            Potential::LennardJones { u_cut, du_cut, .. }
+            | Potential::Morse { u_cut, du_cut, .. }
+            | Potential::Buckingham { u_cut, du_cut, .. }
+            | Potential::BornMayerHuggins { u_cut, du_cut, .. }
+            | Potential::SoftSphere { u_cut, du_cut, .. } => {
+                *u_cut = u;
+                *du_cut = du;
             }
-        };
-        potential
+            _ => {}
+        }
+        self
     }
 
+    /// Analytic potential and its derivative without cutoff
+    fn get_unshifted_potential_and_force(&self, r: f64) -> (f64, f64) {
+        match self {
+            Potential::LennardJones { sigma, eps, .. } => {
+                let sigma_r = *sigma / r;
+                let sigma_r_6 = sigma_r.pow(6);
+                let sigma_r_12 = sigma_r_6 * sigma_r_6;
+                (
+                    4.0f64 * eps * (sigma_r_12 - sigma_r_6),
+                    (24.0f64 * eps / r) * (sigma_r_6 - 2.0f64 * sigma_r_12),
+                )
+            }
+            Potential::Morse { d, a, r0, .. } => {
+                let exp = (-a * (r - r0)).exp();
+                (
+                    d * ((1.0 - exp) * (1.0 - exp) - 1.0),
+                    2.0 * d * a * exp * (1.0 - exp),
+                )
+            }
+            Potential::Buckingham { a, rho, c, .. } => {
+                let repulsion = a * (-r / rho).exp();
+                let r_6 = r.powi(6);
+                (
+                    repulsion - c / r_6,
+                    -repulsion / rho + 6.0 * c / (r_6 * r),
+                )
+            }
+            Potential::BornMayerHuggins { a, rho, sigma, c, d, .. } => {
+                let repulsion = a * ((sigma - r) / rho).exp();
+                let r_6 = r.powi(6);
+                let r_8 = r_6 * r * r;
+                (
+                    repulsion - c / r_6 - d / r_8,
+                    -repulsion / rho + 6.0 * c / (r_6 * r) + 8.0 * d / (r_8 * r),
+                )
+            }
+            Potential::SoftSphere { sigma, eps, n, .. } => {
+                let u = eps * (sigma / r).powf(*n);
+                (u, -n * u / r)
+            }
+            _ => self.get_potential_and_force(r),
+        }
+    }
+
+    /// Potential and its derivative `dU/dr` at distance `r` (so force acting along `r` is
+    /// negative second value)
     pub fn get_potential_and_force(&self, r: f64) -> (f64, f64) {
         match self {
-            Potential::LennardJones { sigma, eps, r_cut, u_cut } => {
+            Potential::LennardJones { r_cut, u_cut, du_cut, cutoff, .. }
+            | Potential::Morse { r_cut, u_cut, du_cut, cutoff, .. }
+            | Potential::Buckingham { r_cut, u_cut, du_cut, cutoff, .. }
+            | Potential::BornMayerHuggins { r_cut, u_cut, du_cut, cutoff, .. }
+            | Potential::SoftSphere { r_cut, u_cut, du_cut, cutoff, .. } => {
                 if r > *r_cut {
                     return (0.0, 0.0);
                 }
-                let sigma_r = *sigma / r;
-                let sigma_r_6 = sigma_r.pow(6);
+                cutoff.apply(r, self.get_unshifted_potential_and_force(r), *r_cut, *u_cut, *du_cut)
+            }
+            Potential::Wca { sigma, eps } => {
+                if r > self.get_radius_cut() {
+                    return (0.0, 0.0);
+                }
+                let sigma_r_6 = (sigma / r).powi(6);
                 let sigma_r_12 = sigma_r_6 * sigma_r_6;
                 (
-                    4.0f64 * eps * (sigma_r_12 - sigma_r_6) - u_cut,
-                    (24.0f64 * eps / r) * (sigma_r_6 - 2.0f64 * sigma_r_12),
+                    4.0 * eps * (sigma_r_12 - sigma_r_6) + eps,
+                    (24.0 * eps / r) * (sigma_r_6 - 2.0 * sigma_r_12),
                 )
             }
+            Potential::Tabulated { energy, force } => {
+                if r > self.get_radius_cut() {
+                    return (0.0, 0.0);
+                }
+                (energy.get_value_and_derivative(r).0, -force.get_value_and_derivative(r).0)
+            }
             Potential::Custom { .. } => {
                 todo!()
             }
         }
     }
 
+    /// Integrals `∫ r^2 U dr` and `∫ r^3 dU/dr dr` of unshifted potential from `r_cut` to
+    /// infinity for long-range tail corrections. They are zero for WCA and tabulated potentials
+    /// which are zero after cutoff and for custom potentials. Soft sphere potential needs
+    /// `n > 3`.
+    pub fn get_tail_integrals(&self) -> (f64, f64) {
+        let energy = match self {
+            Potential::LennardJones { sigma, eps, r_cut, .. } => {
+                let sigma_r_3 = (sigma / r_cut).powi(3);
+                4.0 * eps * sigma.powi(3) * (sigma_r_3.powi(3) / 9.0 - sigma_r_3 / 3.0)
+            }
+            Potential::Morse { d, a, r0, r_cut, .. } => {
+                d * (exp_tail_integral(2.0 * a, *r0, *r_cut) - 2.0 * exp_tail_integral(*a, *r0, *r_cut))
+            }
+            Potential::Buckingham { a, rho, c, r_cut, .. } => {
+                a * exp_tail_integral(1.0 / rho, 0.0, *r_cut) - c / (3.0 * r_cut.powi(3))
+            }
+            Potential::BornMayerHuggins { a, rho, sigma, c, d, r_cut, .. } => {
+                a * exp_tail_integral(1.0 / rho, *sigma, *r_cut) - c / (3.0 * r_cut.powi(3))
+                    - d / (5.0 * r_cut.powi(5))
+            }
+            Potential::SoftSphere { sigma, eps, n, r_cut, .. } => {
+                eps * sigma.powf(*n) * r_cut.powf(3.0 - n) / (n - 3.0)
+            }
+            Potential::Wca { .. } | Potential::Tabulated { .. } | Potential::Custom { .. } => {
+                return (0.0, 0.0)
+            }
+        };
+        // Integration by parts: ∫ r^3 U' dr = -r_cut^3 U(r_cut) - 3 ∫ r^2 U dr
+        let r_cut = self.get_radius_cut();
+        let (u_cut, _) = self.get_unshifted_potential_and_force(r_cut);
+        (energy, -r_cut.powi(3) * u_cut - 3.0 * energy)
+    }
+
     pub fn get_radius_cut(&self) -> f64 {
         match self {
-            Potential::LennardJones { r_cut, .. } => {
-                r_cut.clone()
+            Potential::LennardJones { r_cut, .. }
+            | Potential::Morse { r_cut, .. }
+            | Potential::Buckingham { r_cut, .. }
+            | Potential::BornMayerHuggins { r_cut, .. }
+            | Potential::SoftSphere { r_cut, .. } => {
+                *r_cut
+            }
+            Potential::Wca { sigma, .. } => {
+                sigma * 2.0f64.powf(1.0 / 6.0)
+            }
+            Potential::Tabulated { energy, .. } => {
+                energy.get_range().1
             }
             Potential::Custom { .. } => {
                 todo!()
@@ -86,9 +380,83 @@ impl Potential {
     }
 }
 
+/// Rule to get Lennard-Jones parameters of pair of different species from their own parameters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum MixingRule {
+    /// `sigma = (sigma_i + sigma_j) / 2`, `eps = sqrt(eps_i eps_j)`
+    #[default]
+    LorentzBerthelot,
+    /// `sigma = sqrt(sigma_i sigma_j)`, `eps = sqrt(eps_i eps_j)`
+    Geometric,
+    /// `sigma = ((sigma_i^6 + sigma_j^6) / 2)^(1/6)`,
+    /// `eps = 2 sqrt(eps_i eps_j) sigma_i^3 sigma_j^3 / (sigma_i^6 + sigma_j^6)`
+    WaldmanHagler,
+}
+
+impl MixingRule {
+    /// Mixes Lennard-Jones parameters `(sigma, eps)` of two species
+    pub fn mix(&self, (sigma1, eps1): (f64, f64), (sigma2, eps2): (f64, f64)) -> (f64, f64) {
+        let eps = (eps1 * eps2).sqrt();
+        match self {
+            MixingRule::LorentzBerthelot => ((sigma1 + sigma2) / 2.0, eps),
+            MixingRule::Geometric => ((sigma1 * sigma2).sqrt(), eps),
+            MixingRule::WaldmanHagler => {
+                let (sigma1_3, sigma2_3) = (sigma1.powi(3), sigma2.powi(3));
+                let sigma_6 = (sigma1_3 * sigma1_3 + sigma2_3 * sigma2_3) / 2.0;
+                (sigma_6.powf(1.0 / 6.0), eps * sigma1_3 * sigma2_3 / sigma_6)
+            }
+        }
+    }
+}
+
+/// Content of `potentials.json`
+#[derive(Serialize, Deserialize)]
+struct PotentialsFile {
+    potentials: HashMap<String, Potential>,
+    #[serde(default)]
+    species: HashMap<String, (f64, f64)>,
+    #[serde(default)]
+    mixing_rule: MixingRule,
+    #[serde(default)]
+    mixed_radius_cut: Option<f64>,
+    #[serde(default)]
+    mixed_cutoff: Cutoff,
+    #[serde(default)]
+    strict: bool,
+    #[serde(default)]
+    eam: Option<Eam>,
+}
+
+/// `potentials.json` of older versions has only map of potentials
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedPotentials {
+    File(PotentialsFile),
+    Potentials(HashMap<String, Potential>),
+}
+
+/// Pair potentials for every pair of particle types. Potential of pair is the one that was set
+/// explicitly ([PotentialsDatabase::set_potential]), or Lennard-Jones potential with parameters
+/// mixed from parameters of both species ([PotentialsDatabase::set_species_lennard_jones]),
+/// or default potential (Lennard-Jones for argon). Many-body EAM potential
+/// ([PotentialsDatabase::set_eam]) replaces all of them.
+#[derive(Serialize, Deserialize)]
 pub struct PotentialsDatabase {
     potentials: HashMap<(u16, u16), Potential>,
     default_potential: Potential,
+    neighbor_list: Option<Mutex<NeighborList>>,
+    newton_third_law: bool,
+    /// Lennard-Jones parameters `(sigma, eps)` of species
+    species: HashMap<u16, (f64, f64)>,
+    mixing_rule: MixingRule,
+    /// Cutoff radius (nm) of mixed potentials, `2.5 sigma` of pair if not set
+    mixed_radius_cut: Option<f64>,
+    /// Cutoff scheme of mixed potentials
+    mixed_cutoff: Cutoff,
+    /// Potentials generated from parameters of species
+    mixed_potentials: HashMap<(u16, u16), Potential>,
+    strict: bool,
+    eam: Option<Eam>,
 }
 
 impl PotentialsDatabase {
@@ -97,9 +465,135 @@ impl PotentialsDatabase {
         Self {
             potentials: HashMap::new(),
             default_potential,
+            neighbor_list: None,
+            newton_third_law: false,
+            species: HashMap::new(),
+            mixing_rule: MixingRule::default(),
+            mixed_radius_cut: None,
+            mixed_cutoff: Cutoff::default(),
+            mixed_potentials: HashMap::new(),
+            strict: false,
+            eam: None,
         }
     }
 
+    /// Makes [update_force] use Verlet neighbor list with `skin` (nm) instead of checking
+    /// every pair of particles.
+    pub fn use_neighbor_list(&mut self, skin: f64) {
+        self.neighbor_list = Some(Mutex::new(NeighborList::new(skin)));
+    }
+
+    /// How many times neighbor list was built ([NeighborList::get_rebuild_count]), if it is used
+    pub fn get_neighbor_list_rebuild_count(&self) -> Option<usize> {
+        self.neighbor_list.as_ref()
+            .map(|neighbor_list| neighbor_list.lock().expect("Can't lock mutex").get_rebuild_count())
+    }
+
+    /// Makes [update_force] evaluate each pair of particles once and add equal and opposite
+    /// forces to both of them (Newton's third law).
+    pub fn use_newton_third_law(&mut self, enabled: bool) {
+        self.newton_third_law = enabled;
+    }
+
+    /// Sets Lennard-Jones parameters of species `id`: `sigma` (nm) and `eps` (10^-21 J).
+    /// Potentials of pairs with this species that are not set explicitly are generated by
+    /// mixing rule.
+    pub fn set_species_lennard_jones(&mut self, id: u16, sigma: f64, eps: f64) {
+        self.species.insert(id, (sigma, eps));
+        self.update_mixed_potentials();
+    }
+
+    /// Sets rule to mix parameters of different species (Lorentz-Berthelot by default)
+    pub fn set_mixing_rule(&mut self, mixing_rule: MixingRule) {
+        self.mixing_rule = mixing_rule;
+        self.update_mixed_potentials();
+    }
+
+    pub fn get_mixing_rule(&self) -> MixingRule {
+        self.mixing_rule
+    }
+
+    /// Sets cutoff radius (nm) and cutoff scheme of potentials generated by mixing rule. Radius
+    /// is `2.5 sigma` of pair if it is not set, scheme is shifted energy by default.
+    pub fn set_mixed_cutoff(&mut self, radius_cut: Option<f64>, cutoff: Cutoff) {
+        // Without radius cut it is checked for every pair by `with_cutoff`
+        cutoff.check_radius_cut(radius_cut.unwrap_or(f64::INFINITY));
+        self.mixed_radius_cut = radius_cut;
+        self.mixed_cutoff = cutoff;
+        self.update_mixed_potentials();
+    }
+
+    pub fn get_mixed_cutoff(&self) -> (Option<f64>, Cutoff) {
+        (self.mixed_radius_cut, self.mixed_cutoff)
+    }
+
+    /// In strict mode default potential shouldn't be used, every pair of species should have
+    /// its potential (see [PotentialsDatabase::get_undefined_pairs])
+    pub fn use_strict_mode(&mut self, enabled: bool) {
+        self.strict = enabled;
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Sets EAM potential that is used instead of pair potentials for all particle types
+    pub fn set_eam(&mut self, eam: Eam) {
+        self.eam = Some(eam);
+    }
+
+    pub fn get_eam(&self) -> Option<&Eam> {
+        self.eam.as_ref()
+    }
+
+    fn update_mixed_potentials(&mut self) {
+        self.mixed_potentials.clear();
+        for (id0, parameters0) in self.species.iter() {
+            for (id1, parameters1) in self.species.iter().filter(|(id1, _)| *id1 >= id0) {
+                let (sigma, eps) = self.mixing_rule.mix(*parameters0, *parameters1);
+                let radius_cut = self.mixed_radius_cut.unwrap_or(sigma * 2.5);
+                self.mixed_potentials.insert((*id0, *id1), Potential::new_lennard_jones(sigma, eps)
+                    .with_cutoff(radius_cut, self.mixed_cutoff));
+            }
+        }
+    }
+
+    /// Pairs of particle types from `0` to `particle_types_count - 1` that don't have
+    /// potential and use default one
+    pub fn get_undefined_pairs(&self, particle_types_count: usize) -> Vec<(u16, u16)> {
+        if self.eam.is_some() {
+            return vec![];
+        }
+        let count = particle_types_count as u16;
+        (0..count).flat_map(|id0| (id0..count).map(move |id1| (id0, id1)))
+            .filter(|key| !self.potentials.contains_key(key) && !self.mixed_potentials.contains_key(key))
+            .collect()
+    }
+
+    /// Get the largest cutoff radius of all potentials in database (including default one),
+    /// or cutoff radius of EAM potential if it is set
+    pub fn get_max_radius_cut(&self) -> f64 {
+        if let Some(eam) = &self.eam {
+            return eam.get_radius_cut();
+        }
+        self.potentials.values().chain(self.mixed_potentials.values())
+            .map(|potential| potential.get_radius_cut())
+            .fold(self.default_potential.get_radius_cut(), f64::max)
+    }
+
+    /// Replaces every potential (including default and mixed ones) with its table of `points`
+    /// distances from `r_min` to cutoff radius ([Potential::tabulate]). Tabulated potentials
+    /// are kept. Mixed potentials are generated again if species are changed after it.
+    pub fn tabulate_potentials(&mut self, r_min: f64, points: usize) {
+        let tabulate = |potential: &Potential| match potential {
+            Potential::Tabulated { .. } => potential.clone(),
+            _ => potential.tabulate(r_min, points),
+        };
+        self.default_potential = tabulate(&self.default_potential);
+        self.potentials.values_mut().chain(self.mixed_potentials.values_mut())
+            .for_each(|potential| *potential = tabulate(potential));
+    }
+
     /// Save potentials database to file
     pub fn save_potentials_to_file(&self, path: &PathBuf) {
         let mut new_db: HashMap<String, Potential> = HashMap::new();
@@ -117,7 +611,18 @@ impl PotentialsDatabase {
             File::create(path).expect("Can't create file")
         };
         let mut buf_writer = BufWriter::new(file);
-        serde_json::ser::to_writer_pretty(&mut buf_writer, &new_db)
+        let data = PotentialsFile {
+            potentials: new_db,
+            species: self.species.iter()
+                .map(|(id, parameters)| (id.to_string(), *parameters))
+                .collect(),
+            mixing_rule: self.mixing_rule,
+            mixed_radius_cut: self.mixed_radius_cut,
+            mixed_cutoff: self.mixed_cutoff,
+            strict: self.strict,
+            eam: self.eam.clone(),
+        };
+        serde_json::ser::to_writer_pretty(&mut buf_writer, &data)
             .expect("Can't save potential settings");
     }
 
@@ -126,16 +631,38 @@ impl PotentialsDatabase {
         let path = path.join("potentials.json");
         let file = File::open(path).expect("Can't open file");
         let buf_reader = BufReader::new(file);
-        let data: HashMap<String, Potential> = serde_json::de::from_reader(buf_reader)
+        let data: SavedPotentials = serde_json::de::from_reader(buf_reader)
             .expect("Can't load data from file");
+        let data = match data {
+            SavedPotentials::File(data) => data,
+            SavedPotentials::Potentials(potentials) => PotentialsFile {
+                potentials,
+                species: HashMap::new(),
+                mixing_rule: self.mixing_rule,
+                mixed_radius_cut: self.mixed_radius_cut,
+                mixed_cutoff: self.mixed_cutoff,
+                strict: self.strict,
+                eam: None,
+            },
+        };
 
-        for (id, potential) in data {
+        for (id, potential) in data.potentials {
             let key: Vec<u16> = id.split(",").map(|x|
                 x.parse::<u16>()
                     .expect(format!("Can't convert {} to i16", x).as_str()))
                 .collect();
             let _ = self.potentials.insert((key[0], key[1]), potential);
         }
+        for (id, parameters) in data.species {
+            let id = id.parse::<u16>().unwrap_or_else(|_| panic!("Can't convert {id} to u16"));
+            self.species.insert(id, parameters);
+        }
+        self.mixing_rule = data.mixing_rule;
+        self.mixed_radius_cut = data.mixed_radius_cut;
+        self.mixed_cutoff = data.mixed_cutoff;
+        self.strict = data.strict;
+        self.eam = data.eam;
+        self.update_mixed_potentials();
     }
 
     pub fn set_potential (&mut self, id0: u16, id1: u16, potential: Potential) {
@@ -143,21 +670,85 @@ impl PotentialsDatabase {
         self.potentials.insert(key, potential);
     }
 
-    /// Get potential object from potentials database
+    /// Get potential object from potentials database: explicitly set, mixed or default one
     pub fn get_potential(&self, id0: u16, id1: u16) -> &Potential {
         let key = if id0 > id1 { (id1, id0) } else { (id0, id1) };
-        if self.potentials.contains_key(&key) {
-            self.potentials.get(&key).unwrap()
-        } else {
-            &self.default_potential
+        self.potentials.get(&key)
+            .or_else(|| self.mixed_potentials.get(&key))
+            .unwrap_or(&self.default_potential)
+    }
+}
+
+/// Applies minimum image convention for periodic boundary box `bb` with `tilt` (see
+/// [State::tilt]) to vector `r` between particles. For triclinic cell it is exact while tilt
+/// factors are not greater than half of corresponding box lengths, [update_force] reduces
+/// them with [State::reduce_tilt]. Axes without periodic `boundary_conditions` are left as is.
+pub fn minimum_image(r: &mut Vector3<f64>, bb: &Vector3<f64>, tilt: &Vector3<f64>,
+                     boundary_conditions: &[BoundaryCondition; 3]) {
+    if boundary_conditions[2] == BoundaryCondition::Periodic {
+        if r.z < -bb.z / 2.0 {
+            r.z += bb.z;
+            r.y += tilt.z;
+            r.x += tilt.y;
+        } else if r.z > bb.z / 2.0 {
+            r.z -= bb.z;
+            r.y -= tilt.z;
+            r.x -= tilt.y;
+        }
+    }
+    if boundary_conditions[1] == BoundaryCondition::Periodic {
+        if r.y < -bb.y / 2.0 {
+            r.y += bb.y;
+            r.x += tilt.x;
+        } else if r.y > bb.y / 2.0 {
+            r.y -= bb.y;
+            r.x -= tilt.x;
+        }
+    }
+    if boundary_conditions[0] == BoundaryCondition::Periodic {
+        if r.x < -bb.x / 2.0 {
+            r.x += bb.x;
+        } else if r.x > bb.x / 2.0 {
+            r.x -= bb.x;
         }
     }
 }
 
-/// Setup potentials and forces for each particle in `state`
+/// Setup potentials and forces for each particle in `state`.
+/// If neighbor list is enabled in `potentials_database` only neighbors are checked.
+/// Tilt of triclinic cell is reduced first ([State::reduce_tilt]).
+/// EAM potential is used instead of pair potentials if it is set.
 pub fn update_force(potentials_database: &PotentialsDatabase, state: &mut State) {
-    let particle_type_count = state.particles.len();
-    let bb = &state.boundary_box;
+    update_force_with(potentials_database, state,
+                      &|potential: &Potential, r: f64| potential.get_potential_and_force(r));
+}
+
+/// Setup only short-range part of potentials and forces for r-RESPA integration.
+/// Every pair potential is multiplied by switching function that is 1.0 below `r_inner` and
+/// smoothly goes to 0.0 at `r_outer`. Long-range part is the rest of full force.
+/// Many-body EAM force isn't split, all of it is short-range.
+pub fn update_short_range_force(potentials_database: &PotentialsDatabase, state: &mut State,
+                                r_inner: f64, r_outer: f64) {
+    let width = r_outer - r_inner;
+    update_force_with(potentials_database, state, &|potential: &Potential, r: f64| {
+        let (potential, force) = potential.get_potential_and_force(r);
+        if r <= r_inner {
+            (potential, force)
+        } else if r >= r_outer {
+            (0.0, 0.0)
+        } else {
+            let x = (r - r_inner) / width;
+            let switch = 1.0 - 3.0 * x * x + 2.0 * x * x * x;
+            let switch_derivative = 6.0 * x * (x - 1.0) / width;
+            (potential * switch, force * switch + potential * switch_derivative)
+        }
+    });
+}
+
+fn update_force_with<F>(potentials_database: &PotentialsDatabase, state: &mut State,
+                        pair_potential_and_force: &F)
+    where F: Fn(&Potential, f64) -> (f64, f64) + Sync {
+    state.reduce_tilt();
     state.particles.iter_mut().for_each(|particle_type| {
         particle_type.iter_mut().for_each(|particle| {
             particle.force.x = 0.0;
@@ -165,50 +756,220 @@ pub fn update_force(potentials_database: &PotentialsDatabase, state: &mut State)
             particle.force.z = 0.0;
             particle.potential = 0.0;
             particle.temp = 0.0;
+            particle.virial = Matrix3::zeros();
         });
     });
-    let old_particles = state.particles.clone();
+    if let Some(neighbor_list) = &potentials_database.neighbor_list {
+        let mut neighbor_list = neighbor_list.lock().expect("Can't lock mutex");
+        neighbor_list.update(potentials_database, state);
+        if let Some(eam) = &potentials_database.eam {
+            update_force_eam(eam, state, Some(&neighbor_list));
+        } else if potentials_database.newton_third_law {
+            update_force_pairs_once(potentials_database, state, Some(&neighbor_list),
+                                    pair_potential_and_force);
+        } else {
+            update_force_neighbor_list(potentials_database, state, &neighbor_list,
+                                       pair_potential_and_force);
+        }
+    } else if let Some(eam) = &potentials_database.eam {
+        update_force_eam(eam, state, None);
+    } else if potentials_database.newton_third_law {
+        update_force_pairs_once(potentials_database, state, None, pair_potential_and_force);
+    } else {
+        update_force_all_pairs(potentials_database, state, pair_potential_and_force);
+    }
+}
+
+/// Per-thread sums of forces, potentials and virials for every particle
+struct PairAccumulator {
+    force: Vec<Vector3<f64>>,
+    potential: Vec<f64>,
+    temp: Vec<f64>,
+    virial: Vec<Matrix3<f64>>,
+}
+
+impl PairAccumulator {
+    fn new(particles_count: usize) -> Self {
+        Self {
+            force: vec![Vector3::zeros(); particles_count],
+            potential: vec![0.0; particles_count],
+            temp: vec![0.0; particles_count],
+            virial: vec![Matrix3::zeros(); particles_count],
+        }
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        for i in 0..self.force.len() {
+            self.force[i] += other.force[i];
+            self.potential[i] += other.potential[i];
+            self.temp[i] += other.temp[i];
+            self.virial[i] += other.virial[i];
+        }
+        self
+    }
+}
+
+/// Particles are split into this count of chunks of equal length for
+/// [update_force_pairs_once]. It doesn't depend on count of threads, so sums are the same in
+/// every run.
+const PAIRS_ONCE_CHUNKS: usize = 32;
+
+/// Evaluates each pair once (j > i in flat order) and accumulates it to both particles.
+/// Every chunk of particles sums into its own [PairAccumulator], then they are added in order
+/// of chunks, so forces are the same bits whatever rayon jobs were stolen.
+fn update_force_pairs_once<F>(potentials_database: &PotentialsDatabase, state: &mut State,
+                              neighbor_list: Option<&NeighborList>, pair_potential_and_force: &F)
+    where F: Fn(&Potential, f64) -> (f64, f64) + Sync {
+    let particle_type_count = state.particles.len();
+    let bb = &state.boundary_box;
+    let tilt = &state.tilt;
+    let boundary_conditions = &state.boundary_conditions;
+    let potentials: Vec<Vec<&Potential>> = (0..particle_type_count).map(|particle_type1| {
+        (0..particle_type_count).map(|particle_type2| {
+            potentials_database.get_potential(particle_type1 as u16, particle_type2 as u16)
+        }).collect()
+    }).collect();
+    let mut offsets = vec![0; particle_type_count];
+    for particle_type in 1..particle_type_count {
+        offsets[particle_type] = offsets[particle_type - 1] + state.particles[particle_type - 1].len();
+    }
+    let mut types = vec![];
+    let mut positions = vec![];
+    state.particles.iter().enumerate().for_each(|(particle_type, particles)| {
+        particles.iter().for_each(|particle| {
+            types.push(particle_type);
+            positions.push(particle.position);
+        });
+    });
+    let particles_count = positions.len();
+    let add_pair = |accumulator: &mut PairAccumulator, i: usize, j: usize| {
+        let potential = potentials[types[i]][types[j]];
+        let mut r = positions[j] - positions[i];
+        minimum_image(&mut r, bb, tilt, boundary_conditions);
+        let r_abs = r.norm();
+        if r_abs > potential.get_radius_cut() {
+            return;
+        }
+        let (potential, force) = pair_potential_and_force(potential, r_abs);
+        let force_vec = r / r_abs * force;
+        let t = force_vec.x * r.x + force_vec.y * r.y + force_vec.z * r.z;
+        let virial = force_vec * r.transpose();
+        accumulator.force[i] += force_vec;
+        accumulator.force[j] -= force_vec;
+        accumulator.potential[i] += potential;
+        accumulator.potential[j] += potential;
+        accumulator.temp[i] += t;
+        accumulator.temp[j] += t;
+        accumulator.virial[i] += virial;
+        accumulator.virial[j] += virial;
+    };
+    let indices: Vec<usize> = (0..particles_count).collect();
+    let chunk_length = particles_count.div_ceil(PAIRS_ONCE_CHUNKS).max(1);
+    let accumulators: Vec<PairAccumulator> = indices.par_chunks(chunk_length).map(|chunk| {
+        let mut accumulator = PairAccumulator::new(particles_count);
+        for &i in chunk {
+            if let Some(neighbor_list) = neighbor_list {
+                let particle_type = types[i];
+                let index = i - offsets[particle_type];
+                for &(other_type, other_index) in neighbor_list.get_neighbors(particle_type, index) {
+                    let j = offsets[other_type] + other_index;
+                    if j > i {
+                        add_pair(&mut accumulator, i, j);
+                    }
+                }
+            } else {
+                for j in (i + 1)..particles_count {
+                    add_pair(&mut accumulator, i, j);
+                }
+            }
+        }
+        accumulator
+    }).collect();
+    let result = accumulators.into_iter()
+        .fold(PairAccumulator::new(particles_count), PairAccumulator::merge);
+    state.particles.iter_mut().enumerate().for_each(|(particle_type, particles)| {
+        particles.iter_mut().enumerate().for_each(|(index, particle)| {
+            let i = offsets[particle_type] + index;
+            particle.force = result.force[i];
+            particle.potential = result.potential[i];
+            particle.temp = result.temp[i];
+            particle.virial = result.virial[i];
+        });
+    });
+}
+
+fn update_force_neighbor_list<F>(potentials_database: &PotentialsDatabase, state: &mut State,
+                                 neighbor_list: &NeighborList, pair_potential_and_force: &F)
+    where F: Fn(&Potential, f64) -> (f64, f64) + Sync {
+    let particle_type_count = state.particles.len();
+    let bb = &state.boundary_box;
+    let tilt = &state.tilt;
+    let boundary_conditions = &state.boundary_conditions;
+    let potentials: Vec<Vec<&Potential>> = (0..particle_type_count).map(|particle_type1| {
+        (0..particle_type_count).map(|particle_type2| {
+            potentials_database.get_potential(particle_type1 as u16, particle_type2 as u16)
+        }).collect()
+    }).collect();
+    let positions: Vec<Vec<Vector3<f64>>> = state.particles.iter().map(|particle_type| {
+        particle_type.iter().map(|particle| particle.position).collect()
+    }).collect();
+    for (particle_type1, particle_type) in state.particles.iter_mut().enumerate() {
+        particle_type.par_iter_mut().enumerate().for_each(|(i, particle)| {
+            for &(particle_type2, j) in neighbor_list.get_neighbors(particle_type1, i) {
+                let potential = potentials[particle_type1][particle_type2];
+                let mut r = positions[particle_type2][j] - positions[particle_type1][i];
+                minimum_image(&mut r, bb, tilt, boundary_conditions);
+                let r_abs = r.norm();
+                if r_abs > potential.get_radius_cut() {
+                    continue;
+                }
+
+                let (potential, force) = pair_potential_and_force(potential, r_abs);
+                let force_vec = r / r_abs * force;
+                let t = force_vec.x * r.x + force_vec.y * r.y + force_vec.z * r.z;
+                particle.force += force_vec;
+                particle.potential += potential;
+                particle.temp += t;
+                particle.virial += force_vec * r.transpose();
+            }
+        });
+    }
+}
+
+fn update_force_all_pairs<F>(potentials_database: &PotentialsDatabase, state: &mut State,
+                             pair_potential_and_force: &F)
+    where F: Fn(&Potential, f64) -> (f64, f64) + Sync {
+    let particle_type_count = state.particles.len();
+    let bb = &state.boundary_box;
+    let tilt = &state.tilt;
+    let boundary_conditions = &state.boundary_conditions;
+    let positions: Vec<Vec<Vector3<f64>>> = state.particles.iter().map(|particle_type| {
+        particle_type.iter().map(|particle| particle.position).collect()
+    }).collect();
     for particle_type1 in 0..particle_type_count {
-        for particle_type2 in particle_type1..particle_type_count {
+        for particle_type2 in 0..particle_type_count {
             let potential = potentials_database.get_potential(particle_type1 as u16, particle_type2 as u16);
             let r_cut = potential.get_radius_cut();
             let slice = &mut state.particles[particle_type1][..];
             slice.par_iter_mut().enumerate().for_each(|(i, particle)| {
-                for j in 0..old_particles[particle_type2].len() {
+                for j in 0..positions[particle_type2].len() {
                     if particle_type1 == particle_type2 && i == j {
                         continue;
                     }
-                    let mut r = {
-                        let p1 = &old_particles[particle_type1][i];
-                        let p2 = &old_particles[particle_type2][j];
-                        p2.position - p1.position
-                    };
-                    if r.x < -bb.x / 2.0 {
-                        r.x += bb.x;
-                    } else if r.x > bb.x / 2.0 {
-                        r.x -= bb.x;
-                    }
-                    if r.y < -bb.y / 2.0 {
-                        r.y += bb.y;
-                    } else if r.y > bb.y / 2.0 {
-                        r.y -= bb.y;
-                    }
-                    if r.z < -bb.z / 2.0 {
-                        r.z += bb.z;
-                    } else if r.z > bb.z / 2.0 {
-                        r.z -= bb.z;
-                    }
+                    let mut r = positions[particle_type2][j] - positions[particle_type1][i];
+                    minimum_image(&mut r, bb, tilt, boundary_conditions);
                     let r_abs = r.norm();
                     if r_abs > r_cut {
                         continue;
                     }
 
-                    let (potential, force) = potential.get_potential_and_force(r_abs);
+                    let (potential, force) = pair_potential_and_force(potential, r_abs);
                     let force_vec = r / r_abs * force;
                     let t = force_vec.x * r.x + force_vec.y * r.y + force_vec.z * r.z;
                     particle.force += force_vec;
                     particle.potential += potential;
                     particle.temp += t;
+                    particle.virial += force_vec * r.transpose();
                 }
             });
         }
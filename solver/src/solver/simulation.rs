@@ -0,0 +1,117 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::path::Path;
+use moldyn_core::{State, StateToSave};
+use serde::{Deserialize, Serialize};
+use crate::analysis::GreenKubo;
+use crate::initializer::{Barostat, Thermostat};
+use crate::solver::{update_force, Integrator, PotentialsDatabase};
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"MOLDYNCP";
+const CHECKPOINT_VERSION: u32 = 8;
+
+/// Everything that is needed to make steps of simulation: state with forces, integrator with
+/// its history, thermostat and barostat with their targets and random generators, potentials
+/// with neighbor list and time step.
+///
+/// Checkpoint of simulation ([Simulation::save_checkpoint]) keeps all of it exactly, so
+/// simulation continued from checkpoint is bit-identical to the one that wasn't stopped. It
+/// doesn't depend on amount of threads, also with Newton's third law.
+#[derive(Serialize, Deserialize)]
+pub struct Simulation {
+    pub state: State,
+    pub potentials_database: PotentialsDatabase,
+    pub integrator: Integrator,
+    /// Thermostat and target temperature (K)
+    pub thermostat: Option<(Thermostat, f64)>,
+    /// Barostat and target pressure
+    pub barostat: Option<(Barostat, f64)>,
+    /// Time step (ps)
+    pub delta_time: f64,
+    /// Steps made since simulation was created
+    pub iteration: usize,
+    /// Green-Kubo correlators that are updated after every step
+    pub green_kubo: Option<GreenKubo>,
+}
+
+impl Simulation {
+    /// Creates simulation and calculates forces for `state`
+    pub fn new(mut state: State, potentials_database: PotentialsDatabase, integrator: Integrator,
+               thermostat: Option<(Thermostat, f64)>, barostat: Option<(Barostat, f64)>,
+               delta_time: f64) -> Self {
+        update_force(&potentials_database, &mut state);
+        Self {
+            state,
+            potentials_database,
+            integrator,
+            thermostat,
+            barostat,
+            delta_time,
+            iteration: 0,
+            green_kubo: None,
+        }
+    }
+
+    /// Makes one step of integrator
+    pub fn step(&mut self) {
+        let mut thermostat = self.thermostat.as_mut()
+            .map(|(thermostat, temperature)| (thermostat, *temperature));
+        let mut barostat = self.barostat.as_mut()
+            .map(|(barostat, pressure)| (barostat, *pressure));
+        self.integrator.calculate(&self.potentials_database, &mut self.state, self.delta_time,
+                                  &mut barostat, &mut thermostat);
+        if let Some(green_kubo) = self.green_kubo.as_mut() {
+            green_kubo.add_state(&self.state);
+        }
+        self.iteration += 1;
+    }
+
+    /// Current state with thermostat and barostat variables to save as frame
+    pub fn to_save(&self) -> StateToSave {
+        let mut data = StateToSave::from(&self.state);
+        if let Some((thermostat, temperature)) = &self.thermostat {
+            data.thermostat = thermostat.to_save(*temperature);
+        }
+        if let Some((barostat, pressure)) = &self.barostat {
+            data.barostat = barostat.to_save(*pressure);
+        }
+        data
+    }
+
+    /// Writes checkpoint: header `MOLDYNCP`, version (u32) and simulation in bincode format
+    pub fn write_checkpoint(&self, writer: &mut impl Write) -> Result<()> {
+        writer.write_all(CHECKPOINT_MAGIC)?;
+        writer.write_all(&CHECKPOINT_VERSION.to_le_bytes())?;
+        bincode::serialize_into(writer, self).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Reads checkpoint written by [Simulation::write_checkpoint]
+    pub fn read_checkpoint(reader: &mut impl Read) -> Result<Self> {
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header)?;
+        if &header[..8] != CHECKPOINT_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "It isn't moldyn checkpoint"));
+        }
+        let version = u32::from_le_bytes(header[8..].try_into().unwrap());
+        if version != CHECKPOINT_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData,
+                                  format!("Unsupported checkpoint version {version}")));
+        }
+        bincode::deserialize_from(reader).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Saves checkpoint to file `path`
+    pub fn save_checkpoint(&self, path: &Path) -> Result<()> {
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_checkpoint(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Loads checkpoint from file `path`
+    pub fn load_checkpoint(path: &Path) -> Result<Self> {
+        Self::read_checkpoint(&mut BufReader::new(File::open(path)?))
+    }
+}
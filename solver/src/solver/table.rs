@@ -0,0 +1,145 @@
+use std::io::{BufRead, Error, ErrorKind, Result};
+use serde::{Deserialize, Serialize};
+use crate::solver::Potential;
+
+/// Cubic spline through points `(x, y)` with increasing `x`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CubicSpline {
+    x: Vec<f64>,
+    y: Vec<f64>,
+    /// Second derivatives in points
+    y2: Vec<f64>,
+}
+
+impl CubicSpline {
+    /// Creates spline with given first derivatives at the ends, or natural spline (zero second
+    /// derivatives at the ends) if they are not set. Needs at least two points.
+    pub fn new(x: Vec<f64>, y: Vec<f64>, end_derivatives: Option<(f64, f64)>) -> Self {
+        let n = x.len();
+        assert!(n >= 2 && y.len() == n, "Spline needs at least two points and value for every point");
+        // Tridiagonal system for second derivatives, solved by forward sweep and back substitution
+        let mut y2 = vec![0.0; n];
+        let mut u = vec![0.0; n];
+        if let Some((first, _)) = end_derivatives {
+            y2[0] = -0.5;
+            u[0] = 3.0 / (x[1] - x[0]) * ((y[1] - y[0]) / (x[1] - x[0]) - first);
+        }
+        for i in 1..n - 1 {
+            let sig = (x[i] - x[i - 1]) / (x[i + 1] - x[i - 1]);
+            let p = sig * y2[i - 1] + 2.0;
+            y2[i] = (sig - 1.0) / p;
+            let slope = (y[i + 1] - y[i]) / (x[i + 1] - x[i]) - (y[i] - y[i - 1]) / (x[i] - x[i - 1]);
+            u[i] = (6.0 * slope / (x[i + 1] - x[i - 1]) - sig * u[i - 1]) / p;
+        }
+        let (qn, un) = if let Some((_, last)) = end_derivatives {
+            let h = x[n - 1] - x[n - 2];
+            (0.5, 3.0 / h * (last - (y[n - 1] - y[n - 2]) / h))
+        } else {
+            (0.0, 0.0)
+        };
+        y2[n - 1] = (un - qn * u[n - 2]) / (qn * y2[n - 2] + 1.0);
+        for i in (0..n - 1).rev() {
+            y2[i] = y2[i] * y2[i + 1] + u[i];
+        }
+        Self { x, y, y2 }
+    }
+
+    /// First and last `x` of spline
+    pub fn get_range(&self) -> (f64, f64) {
+        (self.x[0], self.x[self.x.len() - 1])
+    }
+
+    /// Value and first derivative of spline at `x`. Outside of range the first or the last
+    /// segment is extrapolated.
+    pub fn get_value_and_derivative(&self, x: f64) -> (f64, f64) {
+        let k = self.x.partition_point(|xi| *xi <= x).clamp(1, self.x.len() - 1) - 1;
+        let h = self.x[k + 1] - self.x[k];
+        let a = (self.x[k + 1] - x) / h;
+        let b = (x - self.x[k]) / h;
+        let (y2_k, y2_k1) = (self.y2[k], self.y2[k + 1]);
+        let value = a * self.y[k] + b * self.y[k + 1]
+            + ((a * a * a - a) * y2_k + (b * b * b - b) * y2_k1) * h * h / 6.0;
+        let derivative = (self.y[k + 1] - self.y[k]) / h
+            - (3.0 * a * a - 1.0) / 6.0 * h * y2_k + (3.0 * b * b - 1.0) / 6.0 * h * y2_k1;
+        (value, derivative)
+    }
+}
+
+pub(crate) fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Reads section `keyword` of table in LAMMPS `pair_style table` format and creates
+/// tabulated potential ([Potential::new_tabulated]). Section is the keyword line, parameters
+/// line (`N n` with optional `R rlo rhi` or `RSQ rlo rhi` to recompute distances and `FP`
+/// which is ignored) and `n` lines `index r energy force`. Values should be in units of program
+/// (nm and 10^-21 J), force is `-dU/dr`.
+pub fn read_lammps_table(reader: &mut impl BufRead, keyword: &str) -> Result<Potential> {
+    let mut lines = reader.lines()
+        .filter(|line| line.as_ref().map_or(true, |line| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        }));
+    loop {
+        let line = lines.next()
+            .ok_or_else(|| invalid_data(&format!("No section {keyword} in table")))??;
+        if line.split_whitespace().next() == Some(keyword) {
+            break;
+        }
+    }
+    let parameters = lines.next().ok_or_else(|| invalid_data("No parameters of table"))??;
+    let parameters: Vec<&str> = parameters.split_whitespace().collect();
+    let parse = |value: Option<&&str>| -> Result<f64> {
+        value.and_then(|value| value.parse().ok())
+            .ok_or_else(|| invalid_data("Can't parse parameters of table"))
+    };
+    let mut count = None;
+    let mut distances = None;
+    let mut i = 0;
+    while i < parameters.len() {
+        match parameters[i] {
+            "N" => {
+                count = Some(parse(parameters.get(i + 1))? as usize);
+                i += 2;
+            }
+            "R" | "RSQ" => {
+                distances = Some((parameters[i] == "RSQ", parse(parameters.get(i + 1))?,
+                                  parse(parameters.get(i + 2))?));
+                i += 3;
+            }
+            "FP" => i += 3,
+            _ => return Err(invalid_data("Unknown parameter of table")),
+        }
+    }
+    let count = count.ok_or_else(|| invalid_data("No N parameter of table"))?;
+    if count < 2 {
+        return Err(invalid_data("Table should have at least two points"));
+    }
+    let (mut r, mut energy, mut force) = (vec![], vec![], vec![]);
+    for _ in 0..count {
+        let line = lines.next().ok_or_else(|| invalid_data("Unexpected end of table"))??;
+        let values: Vec<f64> = line.split_whitespace().skip(1).take(3)
+            .map(|value| value.parse().map_err(|_| invalid_data("Can't parse number in table")))
+            .collect::<Result<_>>()?;
+        if values.len() != 3 {
+            return Err(invalid_data("Not enough columns in table"));
+        }
+        r.push(values[0]);
+        energy.push(values[1]);
+        force.push(values[2]);
+    }
+    if let Some((squared, low, high)) = distances {
+        r.iter_mut().enumerate().for_each(|(i, r)| {
+            let t = i as f64 / (count - 1) as f64;
+            *r = if squared {
+                (low * low + (high * high - low * low) * t).sqrt()
+            } else {
+                low + (high - low) * t
+            };
+        });
+    }
+    if r.windows(2).any(|pair| pair[1] <= pair[0]) {
+        return Err(invalid_data("Distances in table should increase"));
+    }
+    Ok(Potential::new_tabulated(r, energy, force))
+}